@@ -1,4 +1,5 @@
 use clap::Parser;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug, Default)]
 pub struct AgentOptions {
@@ -13,3 +14,74 @@ pub struct AgentOptions {
 pub enum AgentCommands {
     Did {},
 }
+
+#[derive(Parser, Debug)]
+pub struct SidetreeNodeOptions {
+    /// 待ち受けるアドレス（複数のエージェントから共有する場合は 0.0.0.0:4000 などを指定）
+    #[clap(long, default_value = "127.0.0.1:4000")]
+    pub bind: String,
+
+    /// DIDの状態を保存するJSONファイル（データベースを利用しない場合）
+    #[clap(long, default_value = "sidetree_node.json")]
+    pub store: PathBuf,
+
+    /// DIDの状態を保存するPostgresのURL（未指定の場合は環境変数 DATABASE_URL を参照）
+    #[clap(long)]
+    pub database_url: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct ImportMnemonicOptions {
+    /// 鍵を復元するDID（未指定の場合は設定ファイルのDID）
+    #[clap(long)]
+    pub did: Option<String>,
+
+    /// リカバリ鍵を導出するインデックス（export-mnemonicで出力したrecovery index）
+    #[clap(long, default_value_t = 0)]
+    pub recovery_index: u32,
+}
+
+#[derive(Parser, Debug)]
+pub struct RotateKeysOptions {
+    /// 署名鍵をローテーションする（--sign・--encryptを省略した場合は両方）
+    #[clap(long)]
+    pub sign: bool,
+
+    /// 暗号化鍵をローテーションする
+    #[clap(long)]
+    pub encrypt: bool,
+
+    /// 置き換えた暗号化鍵で復号できる猶予期間（秒）
+    #[clap(long, default_value_t = crate::services::miax::DEFAULT_GRACE_PERIOD_SECS)]
+    pub grace_period: i64,
+}
+
+#[derive(Parser, Debug)]
+pub struct ExportRecoveryKeyOptions {
+    /// リカバリ鍵の復元に必要なシェアの数
+    #[clap(long)]
+    pub threshold: u8,
+
+    /// 生成するシェアの数
+    #[clap(long)]
+    pub shares: u8,
+
+    /// エクスポート後も鍵ストアにリカバリ鍵を残す
+    #[clap(long)]
+    pub keep: bool,
+
+    /// リカバリ鍵を導出できるニーモニックも鍵ストアから削除する（鍵ストアにニーモニックがある場合は必須）
+    #[clap(long, conflicts_with = "keep")]
+    pub remove_mnemonic: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct RecoveryKeySharesOptions {
+    /// リカバリ鍵のシェア（複数指定する）
+    #[clap(long = "share")]
+    pub shares: Vec<String>,
+
+    /// シェアを標準入力から1行に1つずつ読み込む（シェアをコマンド履歴に残さない場合に利用する）
+    #[clap(long)]
+    pub stdin: bool,
+}
@@ -1,14 +1,14 @@
 use home_config::HomeConfig;
 use protocol::keyring::keypair::{
-    K256KeyPair, KeyPair, KeyPairHex, KeyPairingError, X25519KeyPair,
+    K256KeyPair, KeyPair, KeyPairHex, KeyPairingError, SignKeyPair, SignKeyType, X25519KeyPair,
 };
 use serde::{Deserialize, Serialize};
 use std::{
     env,
     fs::{self, OpenOptions},
     io::{self, Write},
-    path::Path,
-    sync::{Arc, Mutex, MutexGuard, Once},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
 };
 use thiserror::Error;
 
@@ -18,7 +18,7 @@ pub struct DidCommConfig {
     pub http_body_size_limit: usize,
 }
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Deserialize, Serialize)]
 pub struct KeyPairsConfig {
     /// 署名鍵ペア
     sign: Option<KeyPairHex>,
@@ -30,7 +30,46 @@ pub struct KeyPairsConfig {
     encrypt: Option<KeyPairHex>,
 }
 
+/// 鍵の保存先
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyStoreBackend {
+    /// ファイル。鍵ストアの秘密が設定されている場合は暗号化したファイル（keystore.json）、それ以外は設定ファイル
+    #[default]
+    File,
+    /// PKCS#11トークン（HSM）。`pkcs11`フィーチャーを有効にしてビルドする必要がある
+    Pkcs11,
+    /// Linuxのカーネルキーリング。再起動に備え、暗号化したファイル（keystore.json）にも保存する
+    KernelKeyring,
+}
+
+/// カーネルキーリングの種類
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KernelKeyringType {
+    /// ユーザーキーリング（同じユーザーのプロセスで共有する）
+    #[default]
+    User,
+    /// セッションキーリング（ログインセッション内で共有する）
+    Session,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct KeyStoreConfig {
+    /// 鍵の保存先
+    #[serde(default)]
+    pub backend: KeyStoreBackend,
+    /// カーネルキーリングを利用する場合のキーリング
+    #[serde(default)]
+    pub kernel_keyring: KernelKeyringType,
+    /// 鍵ストアで生成する署名鍵の種類（DIDの作成・ローテーション・リカバリで利用する）
+    #[serde(default)]
+    pub sign_key_type: SignKeyType,
+}
+
+// 作成直後の設定ファイル（{}）など、項目が存在しない場合は既定値とする
 #[derive(Deserialize, Serialize)]
+#[serde(default)]
 pub struct ConfigRoot {
     /// DID
     did: Option<String>,
@@ -40,6 +79,8 @@ pub struct ConfigRoot {
     // metrics
     /// DIDComm設定
     didcomm: DidCommConfig,
+    /// 鍵ストア設定（既存の設定ファイルには存在しないため、省略時は既定値とする）
+    keystore: KeyStoreConfig,
     /// 初期化済みフラグ
     is_initialized: bool,
     /// 設定スキーマバージョン
@@ -65,6 +106,7 @@ impl Default for ConfigRoot {
                     .and_then(|v| v.parse().ok())
                     .unwrap_or(3 * 1024 * 1024),
             },
+            keystore: KeyStoreConfig::default(),
             is_initialized: false,
             schema_version: 1,
         }
@@ -83,6 +125,8 @@ pub enum AppConfigError<E: std::error::Error> {
     DecoreFailed(E),
     #[error("failed to write config file")]
     WriteError(home_config::JsonError),
+    #[error("failed to read config file")]
+    ReadError(home_config::JsonError),
 }
 
 /// KeyPairHex設定からKeyPair型へ変換する関数
@@ -113,7 +157,10 @@ impl AppConfig {
     const APP_NAME: &'static str = "miax";
     const CONFIG_FILE: &'static str = "config.json";
 
-    pub fn new() -> Self {
+    /// 設定ファイルを読み込む。設定ファイルが存在しない場合は作成する
+    ///
+    /// 設定ファイルを解釈できない場合は、既定値で上書きして鍵を失わないようエラーとする
+    pub fn new() -> Result<Self, AppConfigError<KeyPairingError>> {
         let config = HomeConfig::with_config_dir(AppConfig::APP_NAME, AppConfig::CONFIG_FILE);
         let config_dir = config.path().parent().unwrap();
 
@@ -122,9 +169,20 @@ impl AppConfig {
             Self::touch(config.path()).unwrap(); // TODO: unwrap_logの適用
         }
 
-        let root = ConfigRoot::default();
+        let root = config
+            .json::<ConfigRoot>()
+            .map_err(AppConfigError::ReadError)?;
 
-        AppConfig { root, config }
+        Ok(AppConfig { root, config })
+    }
+
+    /// 設定ファイルを保存するディレクトリ
+    pub fn config_dir(&self) -> PathBuf {
+        self.config
+            .path()
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
     }
 
     pub fn write(&self) -> Result<(), AppConfigError<KeyPairingError>> {
@@ -133,11 +191,15 @@ impl AppConfig {
             .map_err(AppConfigError::WriteError)
     }
 
-    pub fn load_sign_key_pair(&self) -> Option<K256KeyPair> {
-        load_key_pair(&self.root.key_pairs.sign)
+    pub fn load_sign_key_pair(&self) -> Option<SignKeyPair> {
+        self.root.key_pairs.sign.as_ref().and_then(|key| {
+            SignKeyPair::from_hex_key_pair(key)
+                .map_err(|e| log::error!("{:?}", e))
+                .ok()
+        })
     }
 
-    pub fn save_sign_key_pair(&mut self, value: &K256KeyPair) {
+    pub fn save_sign_key_pair(&mut self, value: &SignKeyPair) {
         self.root.key_pairs.sign = Some(value.to_hex_key_pair());
         self.write().unwrap();
     }
@@ -160,6 +222,11 @@ impl AppConfig {
         self.write().unwrap();
     }
 
+    pub fn clear_recovery_key_pair(&mut self) {
+        self.root.key_pairs.recovery = None;
+        self.write().unwrap() // TODO: unwrap_log
+    }
+
     pub fn load_encrypt_key_pair(&self) -> Option<X25519KeyPair> {
         load_key_pair(&self.root.key_pairs.encrypt)
     }
@@ -169,7 +236,27 @@ impl AppConfig {
         self.write().unwrap();
     }
 
+    pub fn keystore_config(&self) -> KeyStoreConfig {
+        self.root.keystore.clone()
+    }
+
+    /// 平文で保存された鍵ペアを全て削除する
+    /// 暗号化した鍵ストアへ移行した後に利用する
+    pub fn clear_key_pairs(&mut self) {
+        self.root.key_pairs = KeyPairsConfig {
+            sign: None,
+            update: None,
+            recovery: None,
+            encrypt: None,
+        };
+        self.write().unwrap() // TODO: unwrap_log
+    }
+
+    /// 作成済みのDID。DIDを作成して初期化済みとなるまではNone
     pub fn get_did(&self) -> Option<String> {
+        if !self.root.is_initialized {
+            return None;
+        }
         self.root.did.clone()
     }
 
@@ -196,23 +283,23 @@ impl SingletonAppConfig {
     }
 }
 
-#[allow(static_mut_refs)]
-pub fn app_config() -> Box<SingletonAppConfig> {
-    static mut SINGLETON: Option<Box<SingletonAppConfig>> = None;
-    static ONCE: Once = Once::new();
-
-    unsafe {
-        // 初期化時は競合を防ぐため、Onceで他スレッドを待機
-        ONCE.call_once(|| {
-            let singleton = SingletonAppConfig {
-                inner: Arc::new(Mutex::new(AppConfig::new())),
-            };
-
-            SINGLETON = Some(Box::new(singleton))
-        });
-
-        SINGLETON.clone().unwrap()
+/// 設定ファイルを一度だけ読み込み、以降は同じ設定を返す
+///
+/// 設定ファイルが壊れている場合は、保存済みの鍵やDIDを上書きしないようエラーとする
+/// 読み込みに失敗した場合は、次回の呼び出しで再度読み込む
+pub fn app_config() -> Result<Box<SingletonAppConfig>, AppConfigError<KeyPairingError>> {
+    static SINGLETON: Mutex<Option<Box<SingletonAppConfig>>> = Mutex::new(None);
+
+    // 初期化時は競合を防ぐため、Mutexで他スレッドを待機
+    let mut singleton = SINGLETON.lock().unwrap();
+    if let Some(config) = singleton.as_ref() {
+        return Ok(config.clone());
     }
+    let config = Box::new(SingletonAppConfig {
+        inner: Arc::new(Mutex::new(AppConfig::new()?)),
+    });
+    *singleton = Some(config.clone());
+    Ok(config)
 }
 
 #[derive(Debug)]
@@ -220,6 +307,7 @@ pub struct ServerConfig {
     did_http_endpoint: String,
     did_attachment_link: String,
     studio_http_endpoint: String,
+    did_cache_ttl: u64,
 }
 
 impl Default for ServerConfig {
@@ -236,10 +324,15 @@ impl ServerConfig {
             env::var("MIAX_DID_ATTACHMENT_LINK").unwrap_or("https://did.miacross.io".to_string());
         let studio_endpoint = env::var("MIAX_STUDIO_HTTP_ENDPOINT")
             .unwrap_or("https://http.hub.miacross.io".to_string());
+        let did_cache_ttl = env::var("MIAX_DID_CACHE_TTL")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(300);
         ServerConfig {
             did_http_endpoint: did_endpoint,
             did_attachment_link: link,
             studio_http_endpoint: studio_endpoint,
+            did_cache_ttl,
         }
     }
 
@@ -254,6 +347,11 @@ impl ServerConfig {
     pub fn studio_http_endpoint(&self) -> String {
         self.studio_http_endpoint.clone()
     }
+
+    /// DIDの解決結果をキャッシュする秒数
+    pub fn did_cache_ttl(&self) -> u64 {
+        self.did_cache_ttl
+    }
 }
 
 pub fn server_config() -> ServerConfig {
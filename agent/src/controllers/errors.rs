@@ -5,6 +5,10 @@ use thiserror::Error;
 pub enum MiaXErrorCode {
     #[error("Internal Server Error")]
     CreateIdentifierInternal = 5004,
+    #[error("Internal Server Error")]
+    ResolveIdentifierInternal = 5005,
+    #[error("Internal Server Error")]
+    RotateKeysInternal = 5006,
 }
 
 impl From<MiaXErrorCode> for StatusCode {
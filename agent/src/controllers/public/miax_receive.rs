@@ -1,15 +1,15 @@
 use std::time::Duration;
 
-use crate::{
-    miax::utils::did_accessor::{DidAccessor, DidAccessorImpl},
-    services::{
-        miax::MiaX,
-        studio::{MessageResponse, Studio},
-    },
+use crate::services::{
+    miax::MiaX,
+    studio::{MessageResponse, Studio},
 };
 use anyhow::anyhow;
 use controller::validator::network::can_connect_to_download_server;
-use protocol::didcomm::encrypted::DidCommEncryptedService;
+use protocol::didcomm::encrypted::{DidCommEncryptedService, DidCommEncryptedServiceVerifyError};
+use protocol::didcomm::types::DidCommMessage;
+use protocol::keyring::keypair::X25519KeyPair;
+use protocol::verifiable_credentials::types::VerifiedContainer;
 use serde::Deserialize;
 use tokio_util::sync::CancellationToken;
 
@@ -54,20 +54,40 @@ impl MessageReceiveUsecase {
         Err(anyhow::anyhow!("Invalid Json: {:?}", e))
     }
 
+    // 現在の暗号化鍵で復号できない場合は、ローテーション後の猶予期間中の鍵で復号を試みる
+    // 復号以外のエラー（送信者のDIDを解決できないなど）は鍵を変えても解決しないため、そのまま返す
+    async fn verify(
+        &self,
+        keys: &[X25519KeyPair],
+        message: &DidCommMessage,
+    ) -> anyhow::Result<VerifiedContainer> {
+        let mut decrypt_error = None;
+        for key in keys {
+            match DidCommEncryptedService::verify(self.agent.did_repository(), key, message).await {
+                Ok(verified) => return Ok(verified),
+                Err(e @ DidCommEncryptedServiceVerifyError::DecryptFailed(_)) => {
+                    decrypt_error.get_or_insert(e);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(decrypt_error.map_or(anyhow!("no encryption key is available"), Into::into))
+    }
+
     pub async fn receive_message(&self) -> anyhow::Result<()> {
-        for m in self.studio.get_message(&self.project_did).await? {
+        let messages = self.studio.get_message(&self.project_did).await?;
+        if messages.is_empty() {
+            return Ok(());
+        }
+        // 鍵ストアを開く（パスフレーズの場合はArgon2で鍵を導出する）のは、受信ごとに一度とする
+        let keys = self.agent.decryption_keys()?;
+        for m in messages {
             let json_message = match serde_json::from_str(&m.raw_message) {
                 Ok(msg) => msg,
                 Err(e) => return self.handle_invalid_json(&m, e).await,
             };
             log::info!("Receive message, message_id = {:?}", m.id);
-            match DidCommEncryptedService::verify(
-                self.agent.did_repository(),
-                &DidAccessorImpl {}.get_my_keyring(),
-                &json_message,
-            )
-            .await
-            {
+            match self.verify(&keys, &json_message).await {
                 Ok(verified) => {
                     log::info!(
                         "Verify success. message_id = {}, from = {}",
@@ -107,8 +127,8 @@ impl MessageReceiveUsecase {
                         log::error!("Not supported")
                     }
                 }
-                Err(_) => {
-                    log::error!("Verify failed : message_id = {}", m.id);
+                Err(e) => {
+                    log::error!("Verify failed : message_id = {}, {:?}", m.id, e);
                     self.studio
                         .ack_message(&self.project_did, m.id, false)
                         .await?;
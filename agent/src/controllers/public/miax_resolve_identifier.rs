@@ -0,0 +1,34 @@
+use crate::controllers::errors::MiaXErrorCode;
+use axum::{
+    extract::{Json, Path},
+    http::StatusCode,
+};
+use protocol::did::resolution::{DidResolutionResult, ResolutionError};
+
+// DID ResolutionのHTTP(S) Bindingに従い、エラーコードに対応するステータスコードを返す
+// https://w3c-ccg.github.io/did-resolution/#bindings-https
+fn status_code(result: &DidResolutionResult) -> StatusCode {
+    match result.did_resolution_metadata.error {
+        Some(ResolutionError::InvalidDid) => StatusCode::BAD_REQUEST,
+        Some(ResolutionError::NotFound) => StatusCode::NOT_FOUND,
+        Some(ResolutionError::RepresentationNotSupported) => StatusCode::NOT_ACCEPTABLE,
+        Some(ResolutionError::MethodNotSupported) => StatusCode::NOT_IMPLEMENTED,
+        Some(ResolutionError::InternalError) => StatusCode::INTERNAL_SERVER_ERROR,
+        None if result.is_deactivated() => StatusCode::GONE,
+        None => StatusCode::OK,
+    }
+}
+
+pub async fn handler(
+    did: Path<String>,
+) -> Result<(StatusCode, Json<DidResolutionResult>), StatusCode> {
+    let service = crate::services::miax::MiaX::new();
+
+    match service.resolve_identifier(&did).await {
+        Ok(v) => Ok((status_code(&v), Json(v))),
+        Err(e) => {
+            log::error!("{:?}", e);
+            Err(MiaXErrorCode::ResolveIdentifierInternal)?
+        }
+    }
+}
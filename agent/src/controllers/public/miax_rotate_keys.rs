@@ -0,0 +1,46 @@
+use crate::{
+    controllers::errors::MiaXErrorCode,
+    services::miax::{KeyRotation, MiaX, RotatedKeys, DEFAULT_GRACE_PERIOD_SECS},
+};
+use axum::{http::StatusCode, response::Json};
+use chrono::Duration;
+use serde::Deserialize;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_grace_period_secs() -> i64 {
+    DEFAULT_GRACE_PERIOD_SECS
+}
+
+/// 省略した場合は署名鍵・暗号化鍵の両方をローテーションする
+#[derive(Deserialize)]
+pub struct RotateKeysRequest {
+    #[serde(default = "default_true")]
+    sign: bool,
+    #[serde(default = "default_true")]
+    encrypt: bool,
+    #[serde(default = "default_grace_period_secs")]
+    grace_period_secs: i64,
+}
+
+pub async fn handler(Json(json): Json<RotateKeysRequest>) -> Result<Json<RotatedKeys>, StatusCode> {
+    if json.grace_period_secs < 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let rotation = KeyRotation {
+        sign: json.sign,
+        encrypt: json.encrypt,
+        grace_period: Duration::seconds(json.grace_period_secs),
+    };
+
+    let service = MiaX::new();
+    match service.rotate_keys(rotation).await {
+        Ok(v) => Ok(Json(v)),
+        Err(e) => {
+            log::error!("{:?}", e);
+            Err(MiaXErrorCode::RotateKeysInternal)?
+        }
+    }
+}
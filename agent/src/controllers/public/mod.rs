@@ -1,4 +1,6 @@
 pub mod miax_create_identifier;
 pub mod miax_find_identifier;
 pub mod miax_receive;
+pub mod miax_resolve_identifier;
+pub mod miax_rotate_keys;
 pub mod utils;
@@ -8,6 +8,7 @@ mod services;
 pub use crate::config::app_config;
 pub mod controllers;
 pub mod server;
+pub mod sidetree_node;
 pub use crate::config::server_config;
 pub use network::network_config;
 
@@ -23,3 +24,102 @@ pub async fn run(controlled: bool, options: &cli::AgentOptions) -> std::io::Resu
     axum::serve(listener, app).await?;
     Ok(())
 }
+
+/// 鍵を導出したニーモニックと、最後に鍵を導出したインデックスを出力する
+pub fn export_mnemonic() -> anyhow::Result<()> {
+    dotenv().ok();
+    let state = services::miax::MiaX::new().export_mnemonic()?;
+    println!("{}", state.mnemonic);
+    println!("index: {}", state.index);
+    println!("recovery index: {}", state.recovery_index);
+    Ok(())
+}
+
+/// 標準入力から読み込んだニーモニックで、DIDの鍵を鍵ストアに復元する
+/// ニーモニックをコマンド履歴に残さないよう、引数では受け取らない
+pub async fn import_mnemonic(options: &cli::ImportMnemonicOptions) -> anyhow::Result<()> {
+    dotenv().ok();
+    let mut mnemonic = zeroize::Zeroizing::new(String::new());
+    std::io::stdin().read_line(&mut mnemonic)?;
+    let did = services::miax::MiaX::new()
+        .import_mnemonic(
+            options.did.as_deref(),
+            mnemonic.trim(),
+            options.recovery_index,
+        )
+        .await?;
+    println!("Imported keys of {}", did);
+    Ok(())
+}
+
+/// 署名鍵・暗号化鍵をローテーションし、結果をJSONで出力する
+pub async fn rotate_keys(options: &cli::RotateKeysOptions) -> anyhow::Result<()> {
+    dotenv().ok();
+    if options.grace_period < 0 {
+        anyhow::bail!("grace period must not be negative");
+    }
+    let both = !options.sign && !options.encrypt;
+    let rotation = services::miax::KeyRotation {
+        sign: options.sign || both,
+        encrypt: options.encrypt || both,
+        grace_period: chrono::Duration::seconds(options.grace_period),
+    };
+    let rotated = services::miax::MiaX::new().rotate_keys(rotation).await?;
+    println!("{}", serde_json::to_string_pretty(&rotated)?);
+    Ok(())
+}
+
+/// リカバリ鍵をシェアに分割して、1行に1つずつ出力する
+/// `--keep`を指定しない場合は、鍵ストアからリカバリ鍵を削除する
+pub fn export_recovery_key(options: &cli::ExportRecoveryKeyOptions) -> anyhow::Result<()> {
+    dotenv().ok();
+    let shares = services::miax::MiaX::new().export_recovery_key(
+        options.threshold,
+        options.shares,
+        !options.keep,
+        options.remove_mnemonic,
+    )?;
+    for share in shares {
+        println!("{}", share.as_str());
+    }
+    Ok(())
+}
+
+/// シェアから復元したリカバリ鍵を鍵ストアに保存する
+pub fn import_recovery_key(options: &cli::RecoveryKeySharesOptions) -> anyhow::Result<()> {
+    dotenv().ok();
+    let shares = read_shares(options)?;
+    if shares.is_empty() {
+        anyhow::bail!("no shares given");
+    }
+    services::miax::MiaX::new().import_recovery_key(&shares)?;
+    println!("Imported recovery key");
+    Ok(())
+}
+
+/// recover操作でDIDの鍵を置き換え、結果をJSONで出力する
+/// シェアを指定しない場合は、鍵ストアのリカバリ鍵を利用する
+pub async fn recover_identifier(options: &cli::RecoveryKeySharesOptions) -> anyhow::Result<()> {
+    dotenv().ok();
+    let shares = read_shares(options)?;
+    let res = services::miax::MiaX::new()
+        .recover_identifier(&shares)
+        .await?;
+    println!("{}", serde_json::to_string_pretty(&res)?);
+    Ok(())
+}
+
+fn read_shares(
+    options: &cli::RecoveryKeySharesOptions,
+) -> std::io::Result<zeroize::Zeroizing<Vec<String>>> {
+    let mut shares = zeroize::Zeroizing::new(options.shares.clone());
+    if options.stdin {
+        for line in std::io::stdin().lines() {
+            let line = zeroize::Zeroizing::new(line?);
+            if !line.trim().is_empty() {
+                shares.push(line.trim().to_owned());
+            }
+        }
+    }
+    Ok(shares)
+}
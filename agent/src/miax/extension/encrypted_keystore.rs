@@ -0,0 +1,982 @@
+// 鍵を暗号化して保存するファイルベースの鍵ストア
+//
+// 秘密鍵はXChaCha20-Poly1305で暗号化し、設定ディレクトリのkeystore.jsonに保存する
+// 暗号化鍵は、オペレーターのパスフレーズ（Argon2id）またはマシン固有の秘密ファイル（HKDF-SHA256）から導出する
+// 秘密ファイルを鍵ストアと同じディレクトリに置くと暗号化の意味がないため、設定ディレクトリの外のパスを明示的に指定する
+// 平文の設定ファイル（config.json）に保存された鍵は、鍵ストアを開く際に暗号化して移行する
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use data_encoding::BASE64;
+use hkdf::Hkdf;
+use protocol::keyring::keypair::{
+    K256KeyPair, KeyPair, KeyPairHex, KeyPairingError, SignKeyPair, X25519KeyPair,
+};
+use protocol::rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::secure_keystore::{
+    read_signer, FileBaseKeyStore, HdState, KeyHistory, PendingKeyPairs, SecureKeyStore,
+    SecureKeyStoreError, SecureKeyStoreKey, SecureKeyStoreType,
+};
+use crate::config::SingletonAppConfig;
+
+const KEYSTORE_FILE: &str = "keystore.json";
+const KEYSTORE_VERSION: u8 = 1;
+
+// 暗号化鍵の導出元を指定する環境変数
+const PASSPHRASE_ENV: &str = "MIAX_KEYSTORE_PASSPHRASE";
+const MACHINE_KEY_ENV: &str = "MIAX_KEYSTORE_MACHINE_KEY";
+
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+const HKDF_INFO: &[u8] = b"miax keystore v1";
+
+// 暗号化鍵が正しいかを、鍵ストアを開く際に確認するための値
+const CHECK_AAD: &[u8] = b"check";
+const CHECK_PLAINTEXT: &[u8] = b"miax keystore";
+const HD_AAD: &[u8] = b"hd";
+const HISTORY_AAD: &[u8] = b"history";
+const PENDING_AAD: &[u8] = b"pending";
+
+#[derive(Error, Debug)]
+pub enum EncryptedKeyStoreError {
+    #[error("failed to access keystore file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed to serialize/deserialize keystore file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("failed to decode keystore file: {0}")]
+    Decode(#[from] data_encoding::DecodeError),
+    #[error("unsupported keystore version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("keystore secret does not match the kdf of keystore file")]
+    SecretMismatch,
+    #[error("failed to derive key: {0}")]
+    Kdf(String),
+    #[error("invalid machine key file: {0}")]
+    InvalidMachineKey(PathBuf),
+    #[error("keystore secret is not configured. set MIAX_KEYSTORE_PASSPHRASE or MIAX_KEYSTORE_MACHINE_KEY")]
+    SecretNotConfigured,
+    #[error("machine key file must be outside the config directory: {0}")]
+    MachineKeyInConfigDir(PathBuf),
+    #[error("failed to encrypt key")]
+    EncryptFailed,
+    #[error("failed to decrypt keystore. wrong passphrase or machine key")]
+    DecryptFailed,
+}
+
+impl From<EncryptedKeyStoreError> for SecureKeyStoreError {
+    fn from(e: EncryptedKeyStoreError) -> Self {
+        SecureKeyStoreError::WriteFailed(e.to_string())
+    }
+}
+
+/// 鍵ストアの暗号化鍵の導出元
+pub enum KeyStoreSecret {
+    /// オペレーターのパスフレーズ
+    Passphrase(Zeroizing<String>),
+    /// マシン固有の秘密ファイル（存在しない場合は生成する）
+    MachineKey(PathBuf),
+}
+
+impl KeyStoreSecret {
+    /// 環境変数から暗号化鍵の導出元を決める
+    ///
+    /// `MIAX_KEYSTORE_PASSPHRASE`があればパスフレーズ、なければ`MIAX_KEYSTORE_MACHINE_KEY`の秘密ファイルを利用する
+    /// どちらも未設定の場合はNoneを返す。秘密ファイルが設定ディレクトリ（`config_dir`）の中にある場合はエラーとする
+    pub fn from_env(config_dir: &Path) -> Result<Option<Self>, EncryptedKeyStoreError> {
+        if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+            if !passphrase.is_empty() {
+                return Ok(Some(KeyStoreSecret::Passphrase(Zeroizing::new(passphrase))));
+            }
+        }
+        let Some(path) = std::env::var(MACHINE_KEY_ENV)
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+        else {
+            return Ok(None);
+        };
+        if is_in_dir(&path, config_dir) {
+            return Err(EncryptedKeyStoreError::MachineKeyInConfigDir(path));
+        }
+        Ok(Some(KeyStoreSecret::MachineKey(path)))
+    }
+
+    // 新しく鍵ストアを作成する際のKDF
+    fn default_kdf(&self) -> Kdf {
+        match self {
+            KeyStoreSecret::Passphrase(_) => {
+                let params = Params::default();
+                Kdf::Argon2id {
+                    m_cost: params.m_cost(),
+                    t_cost: params.t_cost(),
+                    p_cost: params.p_cost(),
+                }
+            }
+            KeyStoreSecret::MachineKey(_) => Kdf::HkdfSha256,
+        }
+    }
+
+    // 暗号化鍵を導出する
+    fn derive_key(
+        &self,
+        kdf: &Kdf,
+        salt: &[u8],
+    ) -> Result<Zeroizing<[u8; KEY_LEN]>, EncryptedKeyStoreError> {
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        match (self, kdf) {
+            (
+                KeyStoreSecret::Passphrase(passphrase),
+                Kdf::Argon2id {
+                    m_cost,
+                    t_cost,
+                    p_cost,
+                },
+            ) => {
+                let params = Params::new(*m_cost, *t_cost, *p_cost, Some(KEY_LEN))
+                    .map_err(|e| EncryptedKeyStoreError::Kdf(e.to_string()))?;
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(passphrase.as_bytes(), salt, key.as_mut())
+                    .map_err(|e| EncryptedKeyStoreError::Kdf(e.to_string()))?;
+            }
+            // マシン固有の秘密ファイルは十分なエントロピーを持つため、HKDFで導出する
+            (KeyStoreSecret::MachineKey(path), Kdf::HkdfSha256) => {
+                let machine_key = load_or_create_machine_key(path)?;
+                Hkdf::<Sha256>::new(Some(salt), machine_key.as_ref())
+                    .expand(HKDF_INFO, key.as_mut())
+                    .map_err(|e| EncryptedKeyStoreError::Kdf(e.to_string()))?;
+            }
+            _ => return Err(EncryptedKeyStoreError::SecretMismatch),
+        }
+        Ok(key)
+    }
+}
+
+/// 暗号化鍵の導出方式。既存の鍵ストアを開けるよう、パラメータも保存する
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "kebab-case")]
+enum Kdf {
+    Argon2id {
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+    HkdfSha256,
+}
+
+/// 暗号化した値
+#[derive(Clone, Serialize, Deserialize)]
+struct SealedBox {
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct SealedKeyPairs {
+    sign: Option<SealedBox>,
+    update: Option<SealedBox>,
+    recovery: Option<SealedBox>,
+    encrypt: Option<SealedBox>,
+}
+
+/// 鍵ストアのファイル形式
+#[derive(Clone, Serialize, Deserialize)]
+struct KeyStoreFile {
+    version: u8,
+    kdf: Kdf,
+    salt: String,
+    check: SealedBox,
+    key_pairs: SealedKeyPairs,
+    // HD鍵の状態（HdState）。HD鍵に対応する前の鍵ストアには存在しない
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hd: Option<SealedBox>,
+    // 鍵の履歴（KeyHistory）。ローテーション前の鍵ストアには存在しない
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    history: Option<SealedBox>,
+    // 反映待ちの鍵ペア（PendingKeyPairs）。ローテーションの途中でのみ存在する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pending: Option<SealedBox>,
+}
+
+// 鍵の種類ごとの名前。暗号文を他の種類の鍵として復号できないよう、AADにも利用する
+fn key_type_name(key_type: &SecureKeyStoreType) -> &'static str {
+    match key_type {
+        SecureKeyStoreType::Sign => "sign",
+        SecureKeyStoreType::Update => "update",
+        SecureKeyStoreType::Recovery => "recovery",
+        SecureKeyStoreType::Encrypt => "encrypt",
+    }
+}
+
+// 所有者のみが読み書きできるファイルとして書き込む
+fn write_private_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.create(true).truncate(true).write(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+// ファイルがディレクトリ（またはその配下）にあるか
+// シンボリックリンクや相対パスは、存在する最も近い親ディレクトリまで解決して比較する
+fn is_in_dir(path: &Path, dir: &Path) -> bool {
+    let canonicalize = |path: &Path| {
+        for ancestor in path.ancestors() {
+            if let Ok(canonical) = fs::canonicalize(ancestor) {
+                let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+                let mut resolved = canonical;
+                for component in rest.components() {
+                    match component {
+                        Component::ParentDir => {
+                            resolved.pop();
+                        }
+                        Component::CurDir => {}
+                        component => resolved.push(component),
+                    }
+                }
+                return resolved;
+            }
+        }
+        path.to_path_buf()
+    };
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        Path::new(".").join(path)
+    };
+    canonicalize(&path).starts_with(canonicalize(dir))
+}
+
+fn load_or_create_machine_key(path: &Path) -> Result<Zeroizing<Vec<u8>>, EncryptedKeyStoreError> {
+    if !path.exists() {
+        let mut machine_key = Zeroizing::new([0u8; KEY_LEN]);
+        OsRng.fill_bytes(machine_key.as_mut());
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_private_file(path, machine_key.as_ref())?;
+        log::info!("created keystore machine key: {}", path.display());
+    }
+    let machine_key = Zeroizing::new(fs::read(path)?);
+    if machine_key.len() < KEY_LEN {
+        return Err(EncryptedKeyStoreError::InvalidMachineKey(
+            path.to_path_buf(),
+        ));
+    }
+    Ok(machine_key)
+}
+
+/// 暗号化したファイルベースの鍵ストア実装
+#[derive(Clone)]
+pub struct EncryptedFileKeyStore {
+    path: PathBuf,
+    key: Arc<Zeroizing<[u8; KEY_LEN]>>,
+    file: Arc<Mutex<KeyStoreFile>>,
+}
+
+impl EncryptedFileKeyStore {
+    /// 鍵ストアを開く。鍵ストアが存在しない場合は作成する
+    ///
+    /// 設定ファイルに平文で保存された鍵があれば、鍵ストアに暗号化して移行し、設定ファイルから削除する
+    pub fn new(
+        config: Box<SingletonAppConfig>,
+        secret: KeyStoreSecret,
+    ) -> Result<Self, EncryptedKeyStoreError> {
+        let path = config.lock().config_dir().join(KEYSTORE_FILE);
+        let keystore = Self::open_or_create(path, &secret)?;
+        // 平文の鍵はFileBaseKeyStoreが設定ファイルに保存している
+        keystore.migrate(&FileBaseKeyStore::new(config))?;
+        Ok(keystore)
+    }
+
+    /// パスを指定して鍵ストアを開く。鍵ストアが存在しない場合は作成する
+    pub(crate) fn open_or_create(
+        path: PathBuf,
+        secret: &KeyStoreSecret,
+    ) -> Result<Self, EncryptedKeyStoreError> {
+        if path.exists() {
+            Self::open(path, secret)
+        } else {
+            Self::create(path, secret)
+        }
+    }
+
+    /// 鍵ストアのファイルが存在するか
+    pub fn exists(config: &SingletonAppConfig) -> bool {
+        config.lock().config_dir().join(KEYSTORE_FILE).exists()
+    }
+
+    /// 鍵ストアのファイルを削除する。鍵を他の鍵ストアに移行した後に利用する
+    #[cfg_attr(not(feature = "pkcs11"), allow(dead_code))]
+    pub fn remove(&self) -> Result<(), EncryptedKeyStoreError> {
+        fs::remove_file(&self.path)?;
+        log::info!("removed encrypted keystore: {}", self.path.display());
+        Ok(())
+    }
+
+    fn open(path: PathBuf, secret: &KeyStoreSecret) -> Result<Self, EncryptedKeyStoreError> {
+        let file: KeyStoreFile = serde_json::from_slice(&fs::read(&path)?)?;
+        if file.version != KEYSTORE_VERSION {
+            return Err(EncryptedKeyStoreError::UnsupportedVersion(file.version));
+        }
+        let salt = BASE64.decode(file.salt.as_bytes())?;
+        let key = secret.derive_key(&file.kdf, &salt)?;
+        let keystore = EncryptedFileKeyStore {
+            path,
+            key: Arc::new(key),
+            file: Arc::new(Mutex::new(file.clone())),
+        };
+        // パスフレーズやマシン固有の秘密ファイルが異なる場合は、ここで復号に失敗する
+        keystore.decrypt(&file.check, CHECK_AAD)?;
+        Ok(keystore)
+    }
+
+    fn create(path: PathBuf, secret: &KeyStoreSecret) -> Result<Self, EncryptedKeyStoreError> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let kdf = secret.default_kdf();
+        let key = Arc::new(secret.derive_key(&kdf, &salt)?);
+
+        let check = encrypt(&key, CHECK_PLAINTEXT, CHECK_AAD)?;
+        let file = KeyStoreFile {
+            version: KEYSTORE_VERSION,
+            kdf,
+            salt: BASE64.encode(&salt),
+            check,
+            key_pairs: SealedKeyPairs::default(),
+            hd: None,
+            history: None,
+            pending: None,
+        };
+        let keystore = EncryptedFileKeyStore {
+            path,
+            key,
+            file: Arc::new(Mutex::new(file)),
+        };
+        keystore.save(&keystore.file.lock().unwrap())?;
+        log::info!("created encrypted keystore: {}", keystore.path.display());
+        Ok(keystore)
+    }
+
+    // 平文で保存された鍵を移行する
+    fn migrate(&self, legacy: &FileBaseKeyStore) -> Result<(), EncryptedKeyStoreError> {
+        let plaintext = [
+            (
+                SecureKeyStoreType::Sign,
+                legacy.read_sign().map(|k| k.to_hex_key_pair()),
+            ),
+            (
+                SecureKeyStoreType::Update,
+                legacy.read_update().map(|k| k.to_hex_key_pair()),
+            ),
+            (
+                SecureKeyStoreType::Recovery,
+                legacy.read_recovery().map(|k| k.to_hex_key_pair()),
+            ),
+            (
+                SecureKeyStoreType::Encrypt,
+                legacy.read_encrypt().map(|k| k.to_hex_key_pair()),
+            ),
+        ];
+        if plaintext.iter().all(|(_, key_pair)| key_pair.is_none()) {
+            return Ok(());
+        }
+
+        let mut migrated = true;
+        for (key_type, key_pair) in plaintext {
+            let Some(key_pair) = key_pair else {
+                continue;
+            };
+            match self.read_key_pair(&key_type)? {
+                None => self.write_key_pair(&key_type, &key_pair)?,
+                Some(stored) => {
+                    // 鍵ストアに異なる鍵がある場合は、平文の鍵を失わないよう削除しない
+                    let stored = Zeroizing::new(serde_json::to_vec(&stored)?);
+                    let key_pair = Zeroizing::new(serde_json::to_vec(&key_pair)?);
+                    if stored != key_pair {
+                        log::warn!(
+                            "plaintext key pair differs from keystore (type: {:?})",
+                            key_type
+                        );
+                        migrated = false;
+                    }
+                }
+            }
+        }
+        if migrated {
+            legacy.clear();
+            log::info!("migrated plaintext key pairs to encrypted keystore");
+        }
+        Ok(())
+    }
+
+    fn decrypt(
+        &self,
+        sealed: &SealedBox,
+        aad: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, EncryptedKeyStoreError> {
+        let nonce = BASE64.decode(sealed.nonce.as_bytes())?;
+        let ciphertext = BASE64.decode(sealed.ciphertext.as_bytes())?;
+        if nonce.len() != NONCE_LEN {
+            return Err(EncryptedKeyStoreError::DecryptFailed);
+        }
+        cipher(&self.key)
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad,
+                },
+            )
+            .map(Zeroizing::new)
+            .map_err(|_| EncryptedKeyStoreError::DecryptFailed)
+    }
+
+    fn read_key_pair(
+        &self,
+        key_type: &SecureKeyStoreType,
+    ) -> Result<Option<KeyPairHex>, EncryptedKeyStoreError> {
+        let sealed = {
+            let file = self.file.lock().unwrap();
+            match key_type {
+                SecureKeyStoreType::Sign => file.key_pairs.sign.clone(),
+                SecureKeyStoreType::Update => file.key_pairs.update.clone(),
+                SecureKeyStoreType::Recovery => file.key_pairs.recovery.clone(),
+                SecureKeyStoreType::Encrypt => file.key_pairs.encrypt.clone(),
+            }
+        };
+        let Some(sealed) = sealed else {
+            return Ok(None);
+        };
+        let plaintext = self.decrypt(&sealed, key_type_name(key_type).as_bytes())?;
+        Ok(Some(serde_json::from_slice(&plaintext)?))
+    }
+
+    fn write_key_pair(
+        &self,
+        key_type: &SecureKeyStoreType,
+        key_pair: &KeyPairHex,
+    ) -> Result<(), EncryptedKeyStoreError> {
+        let plaintext = Zeroizing::new(serde_json::to_vec(key_pair)?);
+        let sealed = encrypt(&self.key, &plaintext, key_type_name(key_type).as_bytes())?;
+
+        let mut file = self.file.lock().unwrap();
+        let slot = match key_type {
+            SecureKeyStoreType::Sign => &mut file.key_pairs.sign,
+            SecureKeyStoreType::Update => &mut file.key_pairs.update,
+            SecureKeyStoreType::Recovery => &mut file.key_pairs.recovery,
+            SecureKeyStoreType::Encrypt => &mut file.key_pairs.encrypt,
+        };
+        *slot = Some(sealed);
+        self.save(&file)
+    }
+
+    fn remove_key_pair(&self, key_type: &SecureKeyStoreType) -> Result<(), EncryptedKeyStoreError> {
+        let mut file = self.file.lock().unwrap();
+        let slot = match key_type {
+            SecureKeyStoreType::Sign => &mut file.key_pairs.sign,
+            SecureKeyStoreType::Update => &mut file.key_pairs.update,
+            SecureKeyStoreType::Recovery => &mut file.key_pairs.recovery,
+            SecureKeyStoreType::Encrypt => &mut file.key_pairs.encrypt,
+        };
+        *slot = None;
+        self.save(&file)
+    }
+
+    fn read_hd(&self) -> Result<Option<HdState>, EncryptedKeyStoreError> {
+        let sealed = self.file.lock().unwrap().hd.clone();
+        let Some(sealed) = sealed else {
+            return Ok(None);
+        };
+        let plaintext = self.decrypt(&sealed, HD_AAD)?;
+        Ok(Some(serde_json::from_slice(&plaintext)?))
+    }
+
+    fn write_hd(&self, state: Option<&HdState>) -> Result<(), EncryptedKeyStoreError> {
+        let sealed = match state {
+            Some(state) => {
+                let plaintext = Zeroizing::new(serde_json::to_vec(state)?);
+                Some(encrypt(&self.key, &plaintext, HD_AAD)?)
+            }
+            None => None,
+        };
+
+        let mut file = self.file.lock().unwrap();
+        file.hd = sealed;
+        self.save(&file)
+    }
+
+    fn read_history(&self) -> Result<KeyHistory, EncryptedKeyStoreError> {
+        let sealed = self.file.lock().unwrap().history.clone();
+        let Some(sealed) = sealed else {
+            return Ok(KeyHistory::default());
+        };
+        let plaintext = self.decrypt(&sealed, HISTORY_AAD)?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
+    fn write_history(&self, history: &KeyHistory) -> Result<(), EncryptedKeyStoreError> {
+        let plaintext = Zeroizing::new(serde_json::to_vec(history)?);
+        let sealed = encrypt(&self.key, &plaintext, HISTORY_AAD)?;
+
+        let mut file = self.file.lock().unwrap();
+        file.history = Some(sealed);
+        self.save(&file)
+    }
+
+    fn read_pending_keys(&self) -> Result<Option<PendingKeyPairs>, EncryptedKeyStoreError> {
+        let sealed = self.file.lock().unwrap().pending.clone();
+        let Some(sealed) = sealed else {
+            return Ok(None);
+        };
+        let plaintext = self.decrypt(&sealed, PENDING_AAD)?;
+        Ok(Some(serde_json::from_slice(&plaintext)?))
+    }
+
+    fn write_pending_keys(
+        &self,
+        pending: Option<&PendingKeyPairs>,
+    ) -> Result<(), EncryptedKeyStoreError> {
+        let sealed = match pending {
+            Some(pending) => {
+                let plaintext = Zeroizing::new(serde_json::to_vec(pending)?);
+                Some(encrypt(&self.key, &plaintext, PENDING_AAD)?)
+            }
+            None => None,
+        };
+
+        let mut file = self.file.lock().unwrap();
+        file.pending = sealed;
+        self.save(&file)
+    }
+
+    // 書き込み途中で鍵ストアが壊れないよう、一時ファイルに書き込んでから置き換える
+    fn save(&self, file: &KeyStoreFile) -> Result<(), EncryptedKeyStoreError> {
+        let tmp = self.path.with_extension("json.tmp");
+        write_private_file(&tmp, &serde_json::to_vec_pretty(file)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    fn read<T>(
+        &self,
+        key_type: SecureKeyStoreType,
+        decode: fn(&KeyPairHex) -> Result<T, KeyPairingError>,
+    ) -> Option<T> {
+        log::debug!("Called: read_internal (type: {:?})", key_type);
+        self.read_key_pair(&key_type)
+            .map_err(|e| log::error!("{:?}", e))
+            .ok()
+            .flatten()
+            .and_then(|key| decode(&key).map_err(|e| log::error!("{:?}", e)).ok())
+    }
+}
+
+fn cipher(key: &[u8; KEY_LEN]) -> XChaCha20Poly1305 {
+    XChaCha20Poly1305::new(key.into())
+}
+
+// ランダムなnonceで暗号化する
+fn encrypt(
+    key: &[u8; KEY_LEN],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<SealedBox, EncryptedKeyStoreError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = cipher(key)
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| EncryptedKeyStoreError::EncryptFailed)?;
+    Ok(SealedBox {
+        nonce: BASE64.encode(&nonce),
+        ciphertext: BASE64.encode(&ciphertext),
+    })
+}
+
+impl SecureKeyStore for EncryptedFileKeyStore {
+    type Signer = SignKeyPair;
+
+    fn write(&self, key_pair: &SecureKeyStoreKey) -> Result<(), SecureKeyStoreError> {
+        let (key_type, hex) = match key_pair {
+            SecureKeyStoreKey::Sign(k) => (SecureKeyStoreType::Sign, k.to_hex_key_pair()),
+            SecureKeyStoreKey::Update(k) => (SecureKeyStoreType::Update, k.to_hex_key_pair()),
+            SecureKeyStoreKey::Recovery(k) => (SecureKeyStoreType::Recovery, k.to_hex_key_pair()),
+            SecureKeyStoreKey::Encrypt(k) => (SecureKeyStoreType::Encrypt, k.to_hex_key_pair()),
+        };
+        log::info!("Called: write_internal (type {:?}", key_type);
+        Ok(self.write_key_pair(&key_type, &hex)?)
+    }
+
+    fn read_sign(&self) -> Option<SignKeyPair> {
+        self.read(SecureKeyStoreType::Sign, SignKeyPair::from_hex_key_pair)
+    }
+
+    fn read_update(&self) -> Option<K256KeyPair> {
+        self.read(SecureKeyStoreType::Update, K256KeyPair::from_hex_key_pair)
+    }
+
+    fn read_recovery(&self) -> Option<K256KeyPair> {
+        self.read(SecureKeyStoreType::Recovery, K256KeyPair::from_hex_key_pair)
+    }
+
+    fn read_encrypt(&self) -> Option<X25519KeyPair> {
+        self.read(
+            SecureKeyStoreType::Encrypt,
+            X25519KeyPair::from_hex_key_pair,
+        )
+    }
+
+    fn remove_recovery(&self) -> Result<(), SecureKeyStoreError> {
+        log::info!("Called: remove_internal (type: recovery)");
+        Ok(self.remove_key_pair(&SecureKeyStoreType::Recovery)?)
+    }
+
+    fn signer(&self, key_type: SecureKeyStoreType) -> Option<SignKeyPair> {
+        read_signer(self, key_type)
+    }
+
+    fn read_hd_state(&self) -> Option<HdState> {
+        log::debug!("Called: read_internal (type: hd)");
+        self.read_hd()
+            .map_err(|e| log::error!("{:?}", e))
+            .ok()
+            .flatten()
+    }
+
+    fn write_hd_state(&self, state: Option<&HdState>) -> Result<(), SecureKeyStoreError> {
+        log::info!("Called: write_internal (type hd)");
+        Ok(self.write_hd(state)?)
+    }
+
+    fn read_key_history(&self) -> KeyHistory {
+        log::debug!("Called: read_internal (type: history)");
+        self.read_history()
+            .map_err(|e| log::error!("{:?}", e))
+            .unwrap_or_default()
+    }
+
+    fn write_key_history(&self, history: &KeyHistory) -> Result<(), SecureKeyStoreError> {
+        log::info!("Called: write_internal (type history)");
+        Ok(self.write_history(history)?)
+    }
+
+    fn read_pending_key_pairs(&self) -> Option<PendingKeyPairs> {
+        log::debug!("Called: read_internal (type: pending)");
+        self.read_pending_keys()
+            .map_err(|e| log::error!("{:?}", e))
+            .ok()
+            .flatten()
+    }
+
+    fn write_pending_key_pairs(
+        &self,
+        pending: Option<&PendingKeyPairs>,
+    ) -> Result<(), SecureKeyStoreError> {
+        log::info!("Called: write_internal (type pending)");
+        Ok(self.write_pending_keys(pending)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::keyring::keypair::KeyPairing;
+
+    // テストごとに空の一時ディレクトリを作成する
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "miax-encrypted-keystore-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn small_argon2() -> Kdf {
+        Kdf::Argon2id {
+            m_cost: 8,
+            t_cost: 1,
+            p_cost: 1,
+        }
+    }
+
+    #[test]
+    fn test_is_in_dir() {
+        let dir = temp_dir("is-in-dir");
+        let config_dir = dir.join("config");
+        fs::create_dir_all(&config_dir).unwrap();
+
+        assert!(is_in_dir(&config_dir.join("machine.key"), &config_dir));
+        assert!(is_in_dir(
+            &dir.join("other/../config/machine.key"),
+            &config_dir
+        ));
+        assert!(!is_in_dir(&dir.join("machine.key"), &config_dir));
+        assert!(!is_in_dir(&config_dir.join("../machine.key"), &config_dir));
+
+        // シンボリックリンクを経由したパスも、設定ディレクトリの中として扱う
+        #[cfg(unix)]
+        {
+            let link = dir.join("link");
+            std::os::unix::fs::symlink(&config_dir, &link).unwrap();
+            assert!(is_in_dir(&link.join("machine.key"), &config_dir));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_derive_key() {
+        let salt = [1u8; SALT_LEN];
+        let passphrase = KeyStoreSecret::Passphrase(Zeroizing::new("passphrase".to_string()));
+        let key = passphrase.derive_key(&small_argon2(), &salt).unwrap();
+        assert_eq!(key, passphrase.derive_key(&small_argon2(), &salt).unwrap());
+        let other = KeyStoreSecret::Passphrase(Zeroizing::new("other".to_string()));
+        assert_ne!(key, other.derive_key(&small_argon2(), &salt).unwrap());
+        assert_ne!(
+            key,
+            passphrase
+                .derive_key(&small_argon2(), &[2u8; SALT_LEN])
+                .unwrap()
+        );
+        assert!(matches!(
+            passphrase.derive_key(&Kdf::HkdfSha256, &salt),
+            Err(EncryptedKeyStoreError::SecretMismatch)
+        ));
+
+        // マシン固有の秘密ファイルは、存在しない場合に作成する
+        let dir = temp_dir("derive-key");
+        let path = dir.join("machine.key");
+        let machine_key = KeyStoreSecret::MachineKey(path.clone());
+        let key = machine_key.derive_key(&Kdf::HkdfSha256, &salt).unwrap();
+        assert_eq!(fs::read(&path).unwrap().len(), KEY_LEN);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert_eq!(
+            key,
+            machine_key.derive_key(&Kdf::HkdfSha256, &salt).unwrap()
+        );
+        assert!(matches!(
+            machine_key.derive_key(&small_argon2(), &salt),
+            Err(EncryptedKeyStoreError::SecretMismatch)
+        ));
+
+        fs::write(&path, [0u8; KEY_LEN - 1]).unwrap();
+        assert!(matches!(
+            machine_key.derive_key(&Kdf::HkdfSha256, &salt),
+            Err(EncryptedKeyStoreError::InvalidMachineKey(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_create_and_open() {
+        let dir = temp_dir("create-and-open");
+        let path = dir.join(KEYSTORE_FILE);
+        let secret = KeyStoreSecret::MachineKey(dir.join("machine.key"));
+        let keyring = KeyPairing::create_keyring(OsRng);
+
+        let keystore = EncryptedFileKeyStore::create(path.clone(), &secret).unwrap();
+        keystore
+            .write(&SecureKeyStoreKey::Sign(keyring.sign()))
+            .unwrap();
+        keystore
+            .write(&SecureKeyStoreKey::Encrypt(keyring.encrypt()))
+            .unwrap();
+
+        // 秘密鍵は平文で保存しない
+        let contents = fs::read_to_string(&path).unwrap();
+        let hex = serde_json::to_value(keyring.sign().to_hex_key_pair()).unwrap();
+        assert!(!contents.contains(hex["secret_key"].as_str().unwrap()));
+
+        let reopened = EncryptedFileKeyStore::open(path.clone(), &secret).unwrap();
+        assert_eq!(
+            reopened.read_sign().unwrap().get_public_key(),
+            keyring.sign().get_public_key()
+        );
+        assert_eq!(
+            reopened.read_encrypt().unwrap().get_public_key(),
+            keyring.encrypt().get_public_key()
+        );
+        assert!(reopened.read_update().is_none());
+
+        let other = KeyStoreSecret::MachineKey(dir.join("other.key"));
+        assert!(matches!(
+            EncryptedFileKeyStore::open(path, &other),
+            Err(EncryptedKeyStoreError::DecryptFailed)
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reject_key_of_other_type() {
+        let dir = temp_dir("other-type");
+        let path = dir.join(KEYSTORE_FILE);
+        let secret = KeyStoreSecret::MachineKey(dir.join("machine.key"));
+        let keyring = KeyPairing::create_keyring(OsRng);
+
+        let keystore = EncryptedFileKeyStore::create(path, &secret).unwrap();
+        keystore
+            .write(&SecureKeyStoreKey::Update(keyring.update()))
+            .unwrap();
+
+        // 暗号文を他の種類の鍵の位置に移しても、復号できない
+        {
+            let mut file = keystore.file.lock().unwrap();
+            file.key_pairs.recovery = file.key_pairs.update.clone();
+        }
+        assert!(keystore.read_update().is_some());
+        assert!(matches!(
+            keystore.read_key_pair(&SecureKeyStoreType::Recovery),
+            Err(EncryptedKeyStoreError::DecryptFailed)
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_generate_and_signer() {
+        use protocol::keyring::keypair::SignKeyType;
+        use protocol::keyring::signer::Signer;
+
+        let dir = temp_dir("generate");
+        let secret = KeyStoreSecret::MachineKey(dir.join("machine.key"));
+        let keystore = EncryptedFileKeyStore::create(dir.join(KEYSTORE_FILE), &secret).unwrap();
+        assert!(keystore.read_public_keys().is_none());
+
+        let public_keys = keystore.generate(SignKeyType::P256).unwrap();
+        assert_eq!(
+            keystore.read_public_keys(),
+            Some(public_keys.clone().into())
+        );
+        let signer = keystore.signer(SecureKeyStoreType::Sign).unwrap();
+        assert_eq!(signer.public_key(), public_keys.sign);
+        let signer = keystore.signer(SecureKeyStoreType::Update).unwrap();
+        assert_eq!(signer.public_key(), public_keys.update.into());
+        assert!(keystore.signer(SecureKeyStoreType::Encrypt).is_none());
+
+        keystore.remove().unwrap();
+        assert!(!dir.join(KEYSTORE_FILE).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_generate_keeps_mnemonic() {
+        use protocol::keyring::hd::HdKeyring;
+        use protocol::keyring::keypair::SignKeyType;
+
+        let dir = temp_dir("hd");
+        let path = dir.join(KEYSTORE_FILE);
+        let secret = KeyStoreSecret::MachineKey(dir.join("machine.key"));
+        let keystore = EncryptedFileKeyStore::create(path.clone(), &secret).unwrap();
+        assert!(keystore.read_hd_state().is_none());
+
+        // 鍵はニーモニックのインデックス0から導出する
+        let public_keys = keystore.generate(SignKeyType::Ed25519).unwrap();
+        let state = keystore.read_hd_state().unwrap();
+        assert_eq!(state.index, 0);
+        let derived = HdKeyring::from_mnemonic(&state.mnemonic, "")
+            .unwrap()
+            .derive_keyring_with_sign_key(SignKeyType::Ed25519, 0)
+            .unwrap();
+        assert_eq!(derived.public_keys(), public_keys);
+
+        // ニーモニックも暗号化して保存する
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains(state.mnemonic.as_str()));
+        let reopened = EncryptedFileKeyStore::open(path, &secret).unwrap();
+        assert_eq!(reopened.read_hd_state().unwrap().mnemonic, state.mnemonic);
+
+        reopened.write_hd_state(None).unwrap();
+        assert!(reopened.read_hd_state().is_none());
+        assert_eq!(reopened.read_public_keys(), Some(public_keys.into()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pending_keys_and_history() {
+        use protocol::keyring::hd::HdKeyring;
+        use protocol::keyring::keypair::SignKeyType;
+
+        let dir = temp_dir("pending");
+        let path = dir.join(KEYSTORE_FILE);
+        let secret = KeyStoreSecret::MachineKey(dir.join("machine.key"));
+        let keystore = EncryptedFileKeyStore::create(path.clone(), &secret).unwrap();
+        let current = keystore.generate(SignKeyType::Secp256k1).unwrap();
+        assert!(keystore.read_pending().is_none());
+
+        // 反映待ちの鍵はHD鍵の次のインデックスから導出し、昇格するまで現在の鍵を置き換えない
+        let pending = keystore
+            .generate_pending(
+                &[SecureKeyStoreType::Update, SecureKeyStoreType::Encrypt],
+                SignKeyType::Secp256k1,
+            )
+            .unwrap();
+        let state = keystore.read_hd_state().unwrap();
+        let derived = HdKeyring::from_mnemonic(&state.mnemonic, "")
+            .unwrap()
+            .derive_keyring(1)
+            .unwrap()
+            .public_keys();
+        assert_eq!(pending.sign, None);
+        assert_eq!(pending.update, Some(derived.update));
+        assert_eq!(pending.encrypt, Some(derived.encrypt));
+        assert_eq!(state.index, 0);
+        assert_eq!(keystore.read_public_keys(), Some(current.clone().into()));
+
+        let reopened = EncryptedFileKeyStore::open(path.clone(), &secret).unwrap();
+        assert_eq!(reopened.read_pending(), Some(pending));
+        reopened.promote_pending().unwrap();
+        assert!(reopened.read_pending().is_none());
+        assert_eq!(reopened.read_hd_state().unwrap().index, 1);
+        let promoted = reopened.read_public_keys().unwrap();
+        assert_eq!(promoted.sign, current.sign);
+        assert_eq!(promoted.recovery, Some(current.recovery));
+        assert_eq!(promoted.update, derived.update);
+        assert_eq!(promoted.encrypt, derived.encrypt);
+        assert!(matches!(
+            reopened.promote_pending(),
+            Err(SecureKeyStoreError::PendingKeysNotFound)
+        ));
+
+        // 鍵の履歴も暗号化して保存する
+        assert!(reopened.read_key_history().keys.is_empty());
+        let mut history = KeyHistory::default();
+        history.retire(
+            SecureKeyStoreType::Update,
+            &current.update.into(),
+            chrono::Utc::now(),
+        );
+        reopened.write_key_history(&history).unwrap();
+        let reopened = EncryptedFileKeyStore::open(path, &secret).unwrap();
+        let keys = reopened.read_key_history().keys;
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].key_type, SecureKeyStoreType::Update);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
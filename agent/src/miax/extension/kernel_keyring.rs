@@ -0,0 +1,472 @@
+// Linuxのカーネルキーリングを利用する鍵ストア
+//
+// 鍵ペアはKeyPairHexのJSONとして、"user"タイプの鍵（説明 : miax:sign など）に保存する
+// 実行中の鍵はカーネルのメモリ上から読み出し、鍵を所有するプロセスのみが読み出せる権限を設定する
+// カーネルキーリングは再起動すると消去されるため、鍵は暗号化ファイル鍵ストア（keystore.json）にも保存し、
+// 鍵ストアを開く際に暗号化したコピーからカーネルキーリングへ読み込む
+// そのため、カーネルキーリングを利用するには鍵ストアの秘密（MIAX_KEYSTORE_PASSPHRASEなど）の設定が必要となる
+// カーネルキーリングを利用できない環境（seccompでkeyctlが禁止されたコンテナなど）では、ファイルの鍵ストアを利用する
+//
+// 保存した鍵は`keyctl show @u`（セッションキーリングの場合は`@s`）で確認できる
+use std::ffi::CString;
+use std::io;
+
+use protocol::keyring::keypair::{
+    K256KeyPair, KeyPair, KeyPairHex, KeyPairingError, SignKeyPair, X25519KeyPair,
+};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::encrypted_keystore::EncryptedFileKeyStore;
+use super::secure_keystore::{
+    read_signer, HdState, KeyHistory, PendingKeyPairs, SecureKeyStore, SecureKeyStoreError,
+    SecureKeyStoreKey, SecureKeyStoreType,
+};
+use crate::config::KernelKeyringType;
+
+const KEY_TYPE: &str = "user";
+
+// 鍵を所有するプロセス（possessor）のみに全ての操作を許可し、同じユーザーの他のプロセスには属性の参照のみを許可する
+// possessorは、自身のセッションキーリングなどからキーリングを辿れるプロセスに限られる
+const KEY_PERM: libc::c_long = 0x3f01_0000;
+
+const KEY_TYPES: [SecureKeyStoreType; 4] = [
+    SecureKeyStoreType::Sign,
+    SecureKeyStoreType::Update,
+    SecureKeyStoreType::Recovery,
+    SecureKeyStoreType::Encrypt,
+];
+
+// KEYCTL_READの最初のバッファサイズ。足りない場合は必要なサイズで読み直す
+const READ_BUFFER_LEN: usize = 512;
+
+type KeySerial = i32;
+
+#[derive(Error, Debug)]
+pub enum KernelKeyringError {
+    #[error("keyctl failed: {0}")]
+    Keyctl(#[from] io::Error),
+    #[error("failed to serialize/deserialize key pair: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("failed to decode key pair: {0}")]
+    Decode(String),
+    #[error("kernel keyring is cleared on reboot and requires an encrypted copy of keys. set MIAX_KEYSTORE_PASSPHRASE or MIAX_KEYSTORE_MACHINE_KEY")]
+    SealedCopyNotConfigured,
+}
+
+impl From<KernelKeyringError> for SecureKeyStoreError {
+    fn from(e: KernelKeyringError) -> Self {
+        SecureKeyStoreError::WriteFailed(e.to_string())
+    }
+}
+
+fn key_description(key_type: &SecureKeyStoreType) -> &'static str {
+    match key_type {
+        SecureKeyStoreType::Sign => "miax:sign",
+        SecureKeyStoreType::Update => "miax:update",
+        SecureKeyStoreType::Recovery => "miax:recovery",
+        SecureKeyStoreType::Encrypt => "miax:encrypt",
+    }
+}
+
+fn keyring_spec(keyring: KernelKeyringType) -> KeySerial {
+    match keyring {
+        KernelKeyringType::User => libc::KEY_SPEC_USER_KEYRING,
+        KernelKeyringType::Session => libc::KEY_SPEC_SESSION_KEYRING,
+    }
+}
+
+fn check(ret: libc::c_long) -> io::Result<libc::c_long> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+// キーリングのシリアル番号を取得する。キーリングが存在しない場合は作成する
+fn get_keyring_id(keyring: KeySerial) -> io::Result<KeySerial> {
+    // SAFETY: KEYCTL_GET_KEYRING_IDは整数の引数のみを受け取り、メモリを参照しない
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            libc::KEYCTL_GET_KEYRING_ID as libc::c_long,
+            keyring as libc::c_long,
+            1 as libc::c_long,
+        )
+    };
+    check(ret).map(|id| id as KeySerial)
+}
+
+// add_key(2)。同じ説明の鍵が存在する場合は、内容を置き換える
+fn add_key(keyring: KeySerial, description: &str, payload: &[u8]) -> io::Result<KeySerial> {
+    let key_type = CString::new(KEY_TYPE)?;
+    let description = CString::new(description)?;
+    // SAFETY: 文字列はNUL終端のCStringで、payloadはpayload.len()バイトの有効なスライス
+    // いずれもシステムコールの間は生存しており、カーネルは読み出すのみ
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_add_key,
+            key_type.as_ptr(),
+            description.as_ptr(),
+            payload.as_ptr() as *const libc::c_void,
+            payload.len(),
+            keyring as libc::c_long,
+        )
+    };
+    check(ret).map(|id| id as KeySerial)
+}
+
+fn set_perm(key: KeySerial, perm: libc::c_long) -> io::Result<()> {
+    // SAFETY: KEYCTL_SETPERMは整数の引数のみを受け取り、メモリを参照しない
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            libc::KEYCTL_SETPERM as libc::c_long,
+            key as libc::c_long,
+            perm,
+        )
+    };
+    check(ret).map(|_| ())
+}
+
+// キーリングから鍵を検索する。鍵が存在しない場合はNone
+fn search_key(keyring: KeySerial, description: &str) -> io::Result<Option<KeySerial>> {
+    let key_type = CString::new(KEY_TYPE)?;
+    let description = CString::new(description)?;
+    // SAFETY: 文字列はNUL終端のCStringで、システムコールの間は生存しており、カーネルは読み出すのみ
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            libc::KEYCTL_SEARCH as libc::c_long,
+            keyring as libc::c_long,
+            key_type.as_ptr(),
+            description.as_ptr(),
+            0 as libc::c_long,
+        )
+    };
+    match check(ret) {
+        Ok(id) => Ok(Some(id as KeySerial)),
+        Err(e) if e.raw_os_error() == Some(libc::ENOKEY) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// keyctl_invalidate(3)。鍵を無効化し、全てのキーリングから削除する
+fn invalidate_key(key: KeySerial) -> io::Result<()> {
+    // SAFETY: KEYCTL_INVALIDATEは整数の引数のみを受け取り、メモリを参照しない
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            libc::KEYCTL_INVALIDATE as libc::c_long,
+            key as libc::c_long,
+        )
+    };
+    check(ret).map(|_| ())
+}
+
+// keyctl_read(3)。鍵の内容を読み出す
+fn read_key(key: KeySerial) -> io::Result<Zeroizing<Vec<u8>>> {
+    let mut buffer = Zeroizing::new(vec![0u8; READ_BUFFER_LEN]);
+    loop {
+        // SAFETY: bufferはbuffer.len()バイトの書き込み可能な領域で、カーネルはその長さまでしか書き込まない
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                libc::KEYCTL_READ as libc::c_long,
+                key as libc::c_long,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+            )
+        };
+        let len = check(ret)? as usize;
+        if len <= buffer.len() {
+            buffer.truncate(len);
+            return Ok(buffer);
+        }
+        // 読み出している間に鍵が更新された場合に備え、再度読み出す
+        buffer = Zeroizing::new(vec![0u8; len]);
+    }
+}
+
+/// カーネルキーリングの鍵ストア実装
+#[derive(Clone)]
+pub struct KernelKeyringStore {
+    keyring: KeySerial,
+    // 再起動で消去された鍵を復元するための、暗号化したコピー
+    sealed: EncryptedFileKeyStore,
+}
+
+impl KernelKeyringStore {
+    /// カーネルキーリングを利用できるか
+    pub fn is_available(keyring: KernelKeyringType) -> bool {
+        get_keyring_id(keyring_spec(keyring))
+            .map_err(|e| log::warn!("kernel keyring is unavailable: {}", e))
+            .is_ok()
+    }
+
+    /// カーネルキーリングを開き、暗号化したコピーの鍵を読み込む
+    pub fn new(
+        keyring: KernelKeyringType,
+        sealed: EncryptedFileKeyStore,
+    ) -> Result<Self, KernelKeyringError> {
+        let id = get_keyring_id(keyring_spec(keyring))?;
+        log::info!("using kernel keyring: {:?} ({})", keyring, id);
+        Self::open(id, sealed)
+    }
+
+    fn open(keyring: KeySerial, sealed: EncryptedFileKeyStore) -> Result<Self, KernelKeyringError> {
+        let keystore = KernelKeyringStore { keyring, sealed };
+        keystore.load()?;
+        Ok(keystore)
+    }
+
+    // 暗号化したコピーを正として、カーネルキーリングの鍵を置き換える
+    // コピーにない鍵はカーネルキーリングからも削除する
+    fn load(&self) -> Result<(), KernelKeyringError> {
+        let key_pairs = [
+            self.sealed.read_sign().map(|k| k.to_hex_key_pair()),
+            self.sealed.read_update().map(|k| k.to_hex_key_pair()),
+            self.sealed.read_recovery().map(|k| k.to_hex_key_pair()),
+            self.sealed.read_encrypt().map(|k| k.to_hex_key_pair()),
+        ];
+        for (key_type, key_pair) in KEY_TYPES.iter().zip(key_pairs) {
+            match key_pair {
+                Some(key_pair) => self.write_key_pair(key_type, &key_pair)?,
+                None => self.remove_key_pair(key_type)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn read_key_pair(
+        &self,
+        key_type: &SecureKeyStoreType,
+    ) -> Result<Option<KeyPairHex>, KernelKeyringError> {
+        let Some(key) = search_key(self.keyring, key_description(key_type))? else {
+            return Ok(None);
+        };
+        let payload = read_key(key)?;
+        Ok(Some(serde_json::from_slice(&payload)?))
+    }
+
+    fn write_key_pair(
+        &self,
+        key_type: &SecureKeyStoreType,
+        key_pair: &KeyPairHex,
+    ) -> Result<(), KernelKeyringError> {
+        let payload = Zeroizing::new(serde_json::to_vec(key_pair)?);
+        let key = add_key(self.keyring, key_description(key_type), &payload)?;
+        set_perm(key, KEY_PERM)?;
+        Ok(())
+    }
+
+    fn remove_key_pair(&self, key_type: &SecureKeyStoreType) -> Result<(), KernelKeyringError> {
+        if let Some(key) = search_key(self.keyring, key_description(key_type))? {
+            invalidate_key(key)?;
+        }
+        Ok(())
+    }
+
+    fn read<T>(
+        &self,
+        key_type: SecureKeyStoreType,
+        decode: fn(&KeyPairHex) -> Result<T, KeyPairingError>,
+    ) -> Option<T> {
+        log::debug!("Called: read_internal (type: {:?})", key_type);
+        self.read_key_pair(&key_type)
+            .and_then(|key| {
+                key.map(|key| decode(&key).map_err(|e| KernelKeyringError::Decode(e.to_string())))
+                    .transpose()
+            })
+            .map_err(|e| log::error!("{:?}", e))
+            .ok()
+            .flatten()
+    }
+}
+
+impl SecureKeyStore for KernelKeyringStore {
+    type Signer = SignKeyPair;
+
+    // 暗号化したコピーに保存してから、カーネルキーリングに保存する
+    fn write(&self, key_pair: &SecureKeyStoreKey) -> Result<(), SecureKeyStoreError> {
+        self.sealed.write(key_pair)?;
+        let (key_type, hex) = match key_pair {
+            SecureKeyStoreKey::Sign(k) => (SecureKeyStoreType::Sign, k.to_hex_key_pair()),
+            SecureKeyStoreKey::Update(k) => (SecureKeyStoreType::Update, k.to_hex_key_pair()),
+            SecureKeyStoreKey::Recovery(k) => (SecureKeyStoreType::Recovery, k.to_hex_key_pair()),
+            SecureKeyStoreKey::Encrypt(k) => (SecureKeyStoreType::Encrypt, k.to_hex_key_pair()),
+        };
+        log::info!("Called: write_internal (type {:?}", key_type);
+        Ok(self.write_key_pair(&key_type, &hex)?)
+    }
+
+    fn read_sign(&self) -> Option<SignKeyPair> {
+        self.read(SecureKeyStoreType::Sign, SignKeyPair::from_hex_key_pair)
+    }
+
+    fn read_update(&self) -> Option<K256KeyPair> {
+        self.read(SecureKeyStoreType::Update, K256KeyPair::from_hex_key_pair)
+    }
+
+    fn read_recovery(&self) -> Option<K256KeyPair> {
+        self.read(SecureKeyStoreType::Recovery, K256KeyPair::from_hex_key_pair)
+    }
+
+    fn read_encrypt(&self) -> Option<X25519KeyPair> {
+        self.read(
+            SecureKeyStoreType::Encrypt,
+            X25519KeyPair::from_hex_key_pair,
+        )
+    }
+
+    // 暗号化したコピーからも削除し、次回の読み込みで復元されないようにする
+    fn remove_recovery(&self) -> Result<(), SecureKeyStoreError> {
+        self.sealed.remove_recovery()?;
+        log::info!("Called: remove_internal (type: recovery)");
+        Ok(self.remove_key_pair(&SecureKeyStoreType::Recovery)?)
+    }
+
+    fn signer(&self, key_type: SecureKeyStoreType) -> Option<SignKeyPair> {
+        read_signer(self, key_type)
+    }
+
+    // ニーモニックは鍵の利用時に必要ないため、カーネルキーリングには読み込まず暗号化したコピーのみに保存する
+    fn read_hd_state(&self) -> Option<HdState> {
+        self.sealed.read_hd_state()
+    }
+
+    fn write_hd_state(&self, state: Option<&HdState>) -> Result<(), SecureKeyStoreError> {
+        self.sealed.write_hd_state(state)
+    }
+
+    // 鍵の履歴と反映待ちの鍵も同様に、暗号化したコピーのみに保存する
+    // 反映待ちの鍵は昇格時にwriteでカーネルキーリングへ読み込まれる
+    fn read_key_history(&self) -> KeyHistory {
+        self.sealed.read_key_history()
+    }
+
+    fn write_key_history(&self, history: &KeyHistory) -> Result<(), SecureKeyStoreError> {
+        self.sealed.write_key_history(history)
+    }
+
+    fn read_pending_key_pairs(&self) -> Option<PendingKeyPairs> {
+        self.sealed.read_pending_key_pairs()
+    }
+
+    fn write_pending_key_pairs(
+        &self,
+        pending: Option<&PendingKeyPairs>,
+    ) -> Result<(), SecureKeyStoreError> {
+        self.sealed.write_pending_key_pairs(pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miax::extension::encrypted_keystore::KeyStoreSecret;
+    use protocol::keyring::keypair::KeyPairing;
+    use protocol::rand_core::OsRng;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "miax-kernel-keyring-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // テストごとに独立したスレッドキーリングを利用する
+    // カーネルキーリングを利用できない環境ではNoneを返し、テストをスキップする
+    fn thread_keyring() -> Option<KeySerial> {
+        get_keyring_id(libc::KEY_SPEC_THREAD_KEYRING)
+            .map_err(|e| eprintln!("kernel keyring is unavailable, skipped: {}", e))
+            .ok()
+    }
+
+    fn sealed(dir: &std::path::Path) -> EncryptedFileKeyStore {
+        let secret = KeyStoreSecret::MachineKey(dir.join("machine.key"));
+        EncryptedFileKeyStore::open_or_create(dir.join("keystore.json"), &secret).unwrap()
+    }
+
+    #[test]
+    fn test_restore_from_sealed_copy() {
+        let Some(keyring) = thread_keyring() else {
+            return;
+        };
+        let dir = temp_dir("restore");
+        let keystore = KernelKeyringStore::open(keyring, sealed(&dir)).unwrap();
+        let keyring_pairs = KeyPairing::create_keyring(OsRng);
+        keystore
+            .write(&SecureKeyStoreKey::Sign(keyring_pairs.sign()))
+            .unwrap();
+        keystore
+            .write(&SecureKeyStoreKey::Update(keyring_pairs.update()))
+            .unwrap();
+        keystore
+            .write(&SecureKeyStoreKey::Recovery(keyring_pairs.recovery()))
+            .unwrap();
+        keystore
+            .write(&SecureKeyStoreKey::Encrypt(keyring_pairs.encrypt()))
+            .unwrap();
+        let public_keys = keyring_pairs.public_keys();
+        assert_eq!(
+            keystore.read_public_keys(),
+            Some(public_keys.clone().into())
+        );
+
+        // 再起動でカーネルキーリングが消去された状態
+        for key_type in &KEY_TYPES {
+            keystore.remove_key_pair(key_type).unwrap();
+        }
+        assert!(keystore.read_sign().is_none());
+
+        // 暗号化したコピーから復元する
+        let keystore = KernelKeyringStore::open(keyring, sealed(&dir)).unwrap();
+        assert_eq!(keystore.read_public_keys(), Some(public_keys.into()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_replaces_keys_not_in_sealed_copy() {
+        let Some(keyring) = thread_keyring() else {
+            return;
+        };
+        let dir = temp_dir("replace");
+        let sealed = sealed(&dir);
+        let stored = KeyPairing::create_keyring(OsRng);
+        sealed
+            .write(&SecureKeyStoreKey::Update(stored.update()))
+            .unwrap();
+
+        // 暗号化したコピーにない鍵がカーネルキーリングに残っている
+        let stale = KeyPairing::create_keyring(OsRng);
+        let keystore = KernelKeyringStore {
+            keyring,
+            sealed: sealed.clone(),
+        };
+        keystore
+            .write_key_pair(&SecureKeyStoreType::Sign, &stale.sign().to_hex_key_pair())
+            .unwrap();
+        keystore
+            .write_key_pair(
+                &SecureKeyStoreType::Update,
+                &stale.update().to_hex_key_pair(),
+            )
+            .unwrap();
+
+        let keystore = KernelKeyringStore::open(keyring, sealed).unwrap();
+        assert!(keystore.read_sign().is_none());
+        assert_eq!(
+            keystore.read_update().unwrap().get_public_key(),
+            stored.update().get_public_key()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1 +1,6 @@
+pub mod encrypted_keystore;
+#[cfg(target_os = "linux")]
+pub mod kernel_keyring;
+#[cfg(feature = "pkcs11")]
+pub mod pkcs11_keystore;
 pub mod secure_keystore;
@@ -0,0 +1,1019 @@
+// PKCS#11トークン（HSM）を利用する鍵ストア
+//
+// 署名鍵（secp256k1またはP-256）・更新鍵・リカバリ鍵（secp256k1）はトークン上で生成し、取り出せない（CKA_EXTRACTABLE=false）鍵として保存する
+// エージェントは公開鍵のみを読み出し、署名はトークン上で行う
+// 暗号化鍵（X25519）は、didcomm-rsが秘密鍵のバイト列を要求するため、トークン上のプライベートなデータオブジェクト（CKO_DATA）として保存する
+// データオブジェクトはログインしたユーザーが読み出せるため、暗号化鍵の保護はトークンのPINに依存する
+//
+// 鍵ストアの設定（config.jsonの`keystore.backend`）を`pkcs11`とし、トークンを環境変数で指定する
+//
+// SoftHSMでの動作確認 :
+//
+//   softhsm2-util --init-token --free --label miax --pin 1234 --so-pin 5678
+//   export MIAX_PKCS11_MODULE=/usr/lib/softhsm/libsofthsm2.so
+//   export MIAX_PKCS11_TOKEN_LABEL=miax
+//   export MIAX_PKCS11_PIN=1234
+//   cargo run --features pkcs11
+//
+// 生成した鍵は`pkcs11-tool --module $MIAX_PKCS11_MODULE --login --pin 1234 --list-objects`で確認できる
+//
+// エージェントはトークン上の鍵を削除しない。同じラベルの鍵がトークンにある場合、鍵の生成・取り込みはエラーとなる
+// トークンに鍵がなく、ファイルの鍵ストアに鍵がある場合は、鍵をトークンに取り込んでからファイルの鍵を削除する
+//
+// ローテーションの新しい鍵は「miax-update-pending」などのラベルで生成し、DIDドキュメントへの反映後にラベルを付け替える
+// 置き換えた鍵は「miax-update-retired-<日時>」のラベルでトークンに残すため、不要になった鍵はオペレーターが削除する
+// 鍵の履歴は、プライベートなデータオブジェクト（ラベル : miax-history）として保存する
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, KeyType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use protocol::keyring::keypair::{
+    K256KeyPair, KeyPair, PublicKeyPairing, SignKeyPair, SignKeyType, SignPublicKey, X25519KeyPair,
+};
+use protocol::keyring::signer::Signer;
+use protocol::rand_core::OsRng;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::secure_keystore::{
+    KeyHistory, PendingPublicKeys, SecureKeyStore, SecureKeyStoreError, SecureKeyStoreKey,
+    SecureKeyStoreType, StoredPublicKeys,
+};
+
+// トークンの指定に利用する環境変数
+const MODULE_ENV: &str = "MIAX_PKCS11_MODULE";
+const TOKEN_LABEL_ENV: &str = "MIAX_PKCS11_TOKEN_LABEL";
+const PIN_ENV: &str = "MIAX_PKCS11_PIN";
+
+// データオブジェクトのCKA_APPLICATION
+const APPLICATION: &[u8] = b"miax";
+// 鍵の履歴（KeyHistory）を保存するデータオブジェクトのラベル
+const HISTORY_LABEL: &str = "miax-history";
+
+// secp256k1のOID（1.3.132.0.10）をDERエンコードしたECパラメータ
+const SECP256K1_EC_PARAMS: &[u8] = &[0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x0a];
+// P-256のOID（1.2.840.10045.3.1.7）をDERエンコードしたECパラメータ
+const P256_EC_PARAMS: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+#[derive(Error, Debug)]
+pub enum Pkcs11KeyStoreError {
+    #[error("PKCS#11 error: {0}")]
+    Pkcs11(#[from] cryptoki::error::Error),
+    #[error("PKCS#11 token is not configured. set {MODULE_ENV}, {TOKEN_LABEL_ENV} and {PIN_ENV}")]
+    NotConfigured,
+    #[error("token not found: {0}")]
+    TokenNotFound(String),
+    #[error("key not found: {0}")]
+    KeyNotFound(String),
+    #[error("key already exists: {0}. delete it from the token to generate a new key")]
+    KeyAlreadyExists(String),
+    #[error("invalid public key: {0}")]
+    InvalidPublicKey(String),
+    #[error("invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("invalid encrypt key: {0}")]
+    InvalidEncryptKey(String),
+    #[error("sign key type is not supported by PKCS#11 keystore: {0:?}")]
+    UnsupportedSignKeyType(SignKeyType),
+    #[error("keystore to migrate from has only some of the key pairs")]
+    IncompleteKeyPairs,
+    #[error("public keys on the token do not match the migrated key pairs")]
+    MigrationMismatch,
+    #[error("invalid key history: {0}")]
+    InvalidHistory(#[from] serde_json::Error),
+}
+
+impl From<Pkcs11KeyStoreError> for SecureKeyStoreError {
+    fn from(e: Pkcs11KeyStoreError) -> Self {
+        SecureKeyStoreError::WriteFailed(e.to_string())
+    }
+}
+
+/// トークンの設定
+pub struct Pkcs11Config {
+    /// PKCS#11モジュール（共有ライブラリ）のパス
+    pub module: String,
+    pub token_label: String,
+    pub pin: Zeroizing<String>,
+}
+
+impl Pkcs11Config {
+    /// 環境変数からトークンの設定を読み込む。`MIAX_PKCS11_MODULE`が未設定の場合はエラー
+    pub fn from_env() -> Result<Self, Pkcs11KeyStoreError> {
+        let module = std::env::var(MODULE_ENV)
+            .ok()
+            .filter(|v| !v.is_empty())
+            .ok_or(Pkcs11KeyStoreError::NotConfigured)?;
+        Ok(Pkcs11Config {
+            module,
+            token_label: std::env::var(TOKEN_LABEL_ENV).unwrap_or_default(),
+            pin: Zeroizing::new(std::env::var(PIN_ENV).unwrap_or_default()),
+        })
+    }
+}
+
+// トークン上の鍵のラベル
+fn key_label(key_type: &SecureKeyStoreType) -> &'static str {
+    match key_type {
+        SecureKeyStoreType::Sign => "miax-sign",
+        SecureKeyStoreType::Update => "miax-update",
+        SecureKeyStoreType::Recovery => "miax-recovery",
+        SecureKeyStoreType::Encrypt => "miax-encrypt",
+    }
+}
+
+// DIDドキュメントへの反映待ちの鍵のラベル
+fn pending_label(key_type: &SecureKeyStoreType) -> String {
+    format!("{}-pending", key_label(key_type))
+}
+
+// 鍵の種類に対応するECパラメータ。Ed25519の鍵はトークンに保存しない
+fn ec_params(key_type: SignKeyType) -> Result<&'static [u8], Pkcs11KeyStoreError> {
+    match key_type {
+        SignKeyType::Secp256k1 => Ok(SECP256K1_EC_PARAMS),
+        SignKeyType::P256 => Ok(P256_EC_PARAMS),
+        SignKeyType::Ed25519 => Err(Pkcs11KeyStoreError::UnsupportedSignKeyType(key_type)),
+    }
+}
+
+fn k256_public_key(public_key: SignPublicKey) -> Result<k256::PublicKey, Pkcs11KeyStoreError> {
+    match public_key {
+        SignPublicKey::Secp256k1(public_key) => Ok(public_key),
+        public_key => Err(Pkcs11KeyStoreError::InvalidPublicKey(format!(
+            "expected secp256k1 key: {:?}",
+            public_key.key_type()
+        ))),
+    }
+}
+
+// 非圧縮形式の公開鍵を、CKA_EC_POINTの形式（DERのOCTET STRING）でラップする
+fn ec_point(sec1: &[u8]) -> Vec<u8> {
+    let mut point = vec![0x04, sec1.len() as u8];
+    point.extend_from_slice(sec1);
+    point
+}
+
+/// PKCS#11トークンを利用する鍵ストア実装
+#[derive(Clone)]
+pub struct Pkcs11KeyStore {
+    session: Arc<Mutex<Session>>,
+}
+
+impl Pkcs11KeyStore {
+    /// トークンにログインする
+    pub fn new(config: &Pkcs11Config) -> Result<Self, Pkcs11KeyStoreError> {
+        let pkcs11 = Pkcs11::new(&config.module)?;
+        pkcs11.initialize(CInitializeArgs::OsThreads)?;
+
+        let mut slot = None;
+        for s in pkcs11.get_slots_with_token()? {
+            if pkcs11.get_token_info(s)?.label() == config.token_label {
+                slot = Some(s);
+                break;
+            }
+        }
+        let slot =
+            slot.ok_or_else(|| Pkcs11KeyStoreError::TokenNotFound(config.token_label.clone()))?;
+
+        let session = pkcs11.open_rw_session(slot)?;
+        session.login(
+            UserType::User,
+            Some(&AuthPin::new(config.pin.as_str().to_string())),
+        )?;
+        log::info!("logged in to PKCS#11 token: {}", config.token_label);
+
+        Ok(Pkcs11KeyStore {
+            session: Arc::new(Mutex::new(session)),
+        })
+    }
+
+    /// 鍵ストアに保存された鍵ペアをトークンに取り込む
+    ///
+    /// 取り込んだ場合はtrueを返す。移行元の鍵の削除は呼び出し側で行う
+    pub fn migrate<S: SecureKeyStore>(&self, legacy: &S) -> Result<bool, Pkcs11KeyStoreError> {
+        let key_pairs = (
+            legacy.read_sign(),
+            legacy.read_update(),
+            legacy.read_recovery(),
+            legacy.read_encrypt(),
+        );
+        let (sign, update, recovery, encrypt) = match key_pairs {
+            (Some(sign), Some(update), Some(recovery), Some(encrypt)) => {
+                (sign, update, recovery, encrypt)
+            }
+            (None, None, None, None) => return Ok(false),
+            _ => return Err(Pkcs11KeyStoreError::IncompleteKeyPairs),
+        };
+
+        self.import(&SecureKeyStoreKey::Sign(&sign))?;
+        self.import(&SecureKeyStoreKey::Update(&update))?;
+        self.import(&SecureKeyStoreKey::Recovery(&recovery))?;
+        self.import(&SecureKeyStoreKey::Encrypt(&encrypt))?;
+
+        // トークンから読み戻した公開鍵が一致しない場合は、移行元の鍵を削除させない
+        if self.read_public_keys() != legacy.read_public_keys() {
+            return Err(Pkcs11KeyStoreError::MigrationMismatch);
+        }
+        log::info!("migrated key pairs to PKCS#11 token");
+        Ok(true)
+    }
+
+    fn find_object(
+        session: &Session,
+        class: ObjectClass,
+        label: &str,
+    ) -> Result<Option<ObjectHandle>, Pkcs11KeyStoreError> {
+        let template = [
+            Attribute::Class(class),
+            Attribute::Label(label.as_bytes().to_vec()),
+        ];
+        Ok(session.find_objects(&template)?.into_iter().next())
+    }
+
+    // 鍵のラベル（CKA_LABEL・CKA_ID）を付け替える。付け替えた場合はtrueを返す
+    fn relabel(session: &Session, from: &str, to: &str) -> Result<bool, Pkcs11KeyStoreError> {
+        let mut relabeled = false;
+        for class in [
+            ObjectClass::PUBLIC_KEY,
+            ObjectClass::PRIVATE_KEY,
+            ObjectClass::DATA,
+        ] {
+            let template = [
+                Attribute::Class(class),
+                Attribute::Label(from.as_bytes().to_vec()),
+            ];
+            for handle in session.find_objects(&template)? {
+                let mut attributes = vec![Attribute::Label(to.as_bytes().to_vec())];
+                // データオブジェクトにはCKA_IDがない
+                if class != ObjectClass::DATA {
+                    attributes.push(Attribute::Id(to.as_bytes().to_vec()));
+                }
+                session.update_attributes(handle, &attributes)?;
+                relabeled = true;
+            }
+        }
+        Ok(relabeled)
+    }
+
+    // プライベートなデータオブジェクトの値を読み出す
+    fn read_data(
+        session: &Session,
+        label: &str,
+    ) -> Result<Option<Zeroizing<Vec<u8>>>, Pkcs11KeyStoreError> {
+        let Some(handle) = Self::find_object(session, ObjectClass::DATA, label)? else {
+            return Ok(None);
+        };
+        for attribute in session.get_attributes(handle, &[AttributeType::Value])? {
+            if let Attribute::Value(value) = attribute {
+                return Ok(Some(Zeroizing::new(value)));
+            }
+        }
+        Err(Pkcs11KeyStoreError::KeyNotFound(format!(
+            "{}: missing CKA_VALUE",
+            label
+        )))
+    }
+
+    // データオブジェクトを作成する
+    fn create_data(
+        session: &Session,
+        label: &str,
+        value: &[u8],
+    ) -> Result<(), Pkcs11KeyStoreError> {
+        let template = [
+            Attribute::Class(ObjectClass::DATA),
+            Attribute::Token(true),
+            Attribute::Private(true),
+            Attribute::Application(APPLICATION.to_vec()),
+            Attribute::Label(label.as_bytes().to_vec()),
+            Attribute::Value(value.to_vec()),
+        ];
+        session.create_object(&template)?;
+        Ok(())
+    }
+
+    // 同じラベルの鍵が存在する場合は、DIDの鍵を失わないよう削除せずにエラーとする
+    fn ensure_absent(session: &Session, label: &str) -> Result<(), Pkcs11KeyStoreError> {
+        for class in [
+            ObjectClass::PUBLIC_KEY,
+            ObjectClass::PRIVATE_KEY,
+            ObjectClass::DATA,
+        ] {
+            if Self::find_object(session, class, label)?.is_some() {
+                return Err(Pkcs11KeyStoreError::KeyAlreadyExists(label.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    // トークン上で鍵ペアを生成する
+    fn generate_key_pair(
+        &self,
+        label: &str,
+        curve: SignKeyType,
+    ) -> Result<SignPublicKey, Pkcs11KeyStoreError> {
+        let ec_params = ec_params(curve)?;
+        let session = self.session.lock().unwrap();
+        Self::ensure_absent(&session, label)?;
+        let label = label.as_bytes().to_vec();
+
+        let public_template = [
+            Attribute::Token(true),
+            Attribute::Verify(true),
+            Attribute::EcParams(ec_params.to_vec()),
+            Attribute::Label(label.clone()),
+            Attribute::Id(label.clone()),
+        ];
+        let private_template = [
+            Attribute::Token(true),
+            Attribute::Private(true),
+            Attribute::Sensitive(true),
+            Attribute::Extractable(false),
+            Attribute::Sign(true),
+            Attribute::KeyType(KeyType::EC),
+            Attribute::Label(label.clone()),
+            Attribute::Id(label),
+        ];
+        let (public_key, _) = session.generate_key_pair(
+            &Mechanism::EccKeyPairGen,
+            &public_template,
+            &private_template,
+        )?;
+        Self::read_ec_point(&session, public_key)
+    }
+
+    // トークン外で生成した鍵を、取り出せない鍵としてトークンに取り込む
+    fn import(&self, key_pair: &SecureKeyStoreKey) -> Result<(), Pkcs11KeyStoreError> {
+        match key_pair {
+            SecureKeyStoreKey::Sign(SignKeyPair::Secp256k1(k)) => {
+                self.import_k256(SecureKeyStoreType::Sign, k)
+            }
+            SecureKeyStoreKey::Sign(SignKeyPair::P256(k)) => self.import_ec(
+                SecureKeyStoreType::Sign,
+                P256_EC_PARAMS,
+                &Zeroizing::new(k.get_secret_key().to_bytes().to_vec()),
+                k.get_public_key().to_encoded_point(false).as_bytes(),
+            ),
+            SecureKeyStoreKey::Sign(k) => {
+                Err(Pkcs11KeyStoreError::UnsupportedSignKeyType(k.key_type()))
+            }
+            SecureKeyStoreKey::Update(k) => self.import_k256(SecureKeyStoreType::Update, k),
+            SecureKeyStoreKey::Recovery(k) => self.import_k256(SecureKeyStoreType::Recovery, k),
+            SecureKeyStoreKey::Encrypt(k) => {
+                self.write_encrypt(key_label(&SecureKeyStoreType::Encrypt), k)
+            }
+        }
+    }
+
+    fn import_k256(
+        &self,
+        key_type: SecureKeyStoreType,
+        key_pair: &K256KeyPair,
+    ) -> Result<(), Pkcs11KeyStoreError> {
+        self.import_ec(
+            key_type,
+            SECP256K1_EC_PARAMS,
+            &Zeroizing::new(key_pair.get_secret_key().to_bytes().to_vec()),
+            key_pair.get_public_key().to_encoded_point(false).as_bytes(),
+        )
+    }
+
+    fn import_ec(
+        &self,
+        key_type: SecureKeyStoreType,
+        ec_params: &[u8],
+        secret_key: &[u8],
+        public_key: &[u8],
+    ) -> Result<(), Pkcs11KeyStoreError> {
+        let label = key_label(&key_type);
+        let session = self.session.lock().unwrap();
+        Self::ensure_absent(&session, label)?;
+        let label = label.as_bytes().to_vec();
+
+        let public_template = [
+            Attribute::Class(ObjectClass::PUBLIC_KEY),
+            Attribute::KeyType(KeyType::EC),
+            Attribute::Token(true),
+            Attribute::Verify(true),
+            Attribute::EcParams(ec_params.to_vec()),
+            Attribute::EcPoint(ec_point(public_key)),
+            Attribute::Label(label.clone()),
+            Attribute::Id(label.clone()),
+        ];
+        let private_template = [
+            Attribute::Class(ObjectClass::PRIVATE_KEY),
+            Attribute::KeyType(KeyType::EC),
+            Attribute::Token(true),
+            Attribute::Private(true),
+            Attribute::Sensitive(true),
+            Attribute::Extractable(false),
+            Attribute::Sign(true),
+            Attribute::EcParams(ec_params.to_vec()),
+            Attribute::Value(secret_key.to_vec()),
+            Attribute::Label(label.clone()),
+            Attribute::Id(label),
+        ];
+        session.create_object(&public_template)?;
+        session.create_object(&private_template)?;
+        log::info!("imported key pair into PKCS#11 token (type {:?})", key_type);
+        Ok(())
+    }
+
+    // 暗号化鍵をプライベートなデータオブジェクトとして保存する
+    fn write_encrypt(
+        &self,
+        label: &str,
+        key_pair: &X25519KeyPair,
+    ) -> Result<(), Pkcs11KeyStoreError> {
+        let session = self.session.lock().unwrap();
+        Self::ensure_absent(&session, label)?;
+        let secret_key = Zeroizing::new(key_pair.get_secret_key().to_bytes());
+        Self::create_data(&session, label, secret_key.as_slice())
+    }
+
+    fn read_encrypt_key(&self, label: &str) -> Result<X25519KeyPair, Pkcs11KeyStoreError> {
+        let value = Self::read_data(&self.session.lock().unwrap(), label)?
+            .ok_or_else(|| Pkcs11KeyStoreError::KeyNotFound(label.to_string()))?;
+        let secret_key: [u8; 32] = value.as_slice().try_into().map_err(|_| {
+            Pkcs11KeyStoreError::InvalidEncryptKey(format!("invalid length: {}", value.len()))
+        })?;
+        let secret_key = Zeroizing::new(secret_key);
+        Ok(X25519KeyPair::new(x25519_dalek::StaticSecret::from(
+            *secret_key,
+        )))
+    }
+
+    // CKA_EC_PARAMSで曲線を判別し、CKA_EC_POINT（DERのOCTET STRINGでラップされたSEC1の公開鍵）を読み出す
+    fn read_ec_point(
+        session: &Session,
+        handle: ObjectHandle,
+    ) -> Result<SignPublicKey, Pkcs11KeyStoreError> {
+        let attributes =
+            session.get_attributes(handle, &[AttributeType::EcParams, AttributeType::EcPoint])?;
+        let (mut params, mut point) = (None, None);
+        for attribute in attributes {
+            match attribute {
+                Attribute::EcParams(value) => params = Some(value),
+                Attribute::EcPoint(value) => point = Some(value),
+                _ => {}
+            }
+        }
+        let (Some(params), Some(point)) = (params, point) else {
+            return Err(Pkcs11KeyStoreError::InvalidPublicKey(
+                "missing CKA_EC_PARAMS or CKA_EC_POINT".to_string(),
+            ));
+        };
+        // 非圧縮形式の公開鍵（65バイト）は、04 41のヘッダーでラップされている
+        let sec1 = match point.as_slice() {
+            [0x04, len, rest @ ..] if usize::from(*len) == rest.len() => rest,
+            raw => raw,
+        };
+        let invalid =
+            |e: k256::elliptic_curve::Error| Pkcs11KeyStoreError::InvalidPublicKey(e.to_string());
+        match params.as_slice() {
+            SECP256K1_EC_PARAMS => Ok(k256::PublicKey::from_sec1_bytes(sec1)
+                .map_err(invalid)?
+                .into()),
+            P256_EC_PARAMS => Ok(p256::PublicKey::from_sec1_bytes(sec1)
+                .map_err(invalid)?
+                .into()),
+            _ => Err(Pkcs11KeyStoreError::InvalidPublicKey(
+                "unsupported curve".to_string(),
+            )),
+        }
+    }
+
+    fn read_public_key(&self, label: &str) -> Result<SignPublicKey, Pkcs11KeyStoreError> {
+        let session = self.session.lock().unwrap();
+        let handle = Self::find_object(&session, ObjectClass::PUBLIC_KEY, label)?
+            .ok_or_else(|| Pkcs11KeyStoreError::KeyNotFound(label.to_string()))?;
+        Self::read_ec_point(&session, handle)
+    }
+
+    fn generate_keys(
+        &self,
+        sign_key_type: SignKeyType,
+    ) -> Result<PublicKeyPairing, Pkcs11KeyStoreError> {
+        // 一部の鍵のみを生成しないよう、生成する前に全てのラベルを確認する
+        ec_params(sign_key_type)?;
+        {
+            let session = self.session.lock().unwrap();
+            for key_type in [
+                SecureKeyStoreType::Sign,
+                SecureKeyStoreType::Update,
+                SecureKeyStoreType::Recovery,
+                SecureKeyStoreType::Encrypt,
+            ] {
+                Self::ensure_absent(&session, key_label(&key_type))?;
+            }
+        }
+        // 更新鍵・リカバリ鍵はSidetreeの仕様によりsecp256k1とする
+        let label = |key_type| key_label(&key_type);
+        let sign = self.generate_key_pair(label(SecureKeyStoreType::Sign), sign_key_type)?;
+        let update =
+            self.generate_key_pair(label(SecureKeyStoreType::Update), SignKeyType::Secp256k1)?;
+        let recovery =
+            self.generate_key_pair(label(SecureKeyStoreType::Recovery), SignKeyType::Secp256k1)?;
+
+        let encrypt = X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(OsRng));
+        self.write_encrypt(label(SecureKeyStoreType::Encrypt), &encrypt)?;
+
+        Ok(PublicKeyPairing {
+            sign,
+            update: k256_public_key(update)?,
+            recovery: k256_public_key(recovery)?,
+            encrypt: encrypt.get_public_key(),
+        })
+    }
+
+    // 反映待ちのラベルで鍵を生成する
+    // 前回の反映待ちの鍵が残っている場合は、update操作が後から反映される可能性があるため削除せずにエラーとする
+    fn generate_pending_keys(
+        &self,
+        key_types: &[SecureKeyStoreType],
+        sign_key_type: SignKeyType,
+    ) -> Result<PendingPublicKeys, Pkcs11KeyStoreError> {
+        if key_types.contains(&SecureKeyStoreType::Sign) {
+            ec_params(sign_key_type)?;
+        }
+        {
+            let session = self.session.lock().unwrap();
+            for key_type in key_types {
+                Self::ensure_absent(&session, &pending_label(key_type))?;
+            }
+        }
+        let mut pending = PendingPublicKeys::default();
+        for key_type in key_types {
+            let label = pending_label(key_type);
+            match key_type {
+                SecureKeyStoreType::Sign => {
+                    pending.sign = Some(self.generate_key_pair(&label, sign_key_type)?)
+                }
+                SecureKeyStoreType::Update => {
+                    pending.update = Some(k256_public_key(
+                        self.generate_key_pair(&label, SignKeyType::Secp256k1)?,
+                    )?)
+                }
+                SecureKeyStoreType::Recovery => {
+                    pending.recovery = Some(k256_public_key(
+                        self.generate_key_pair(&label, SignKeyType::Secp256k1)?,
+                    )?)
+                }
+                SecureKeyStoreType::Encrypt => {
+                    let encrypt =
+                        X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(OsRng));
+                    self.write_encrypt(&label, &encrypt)?;
+                    pending.encrypt = Some(encrypt.get_public_key());
+                }
+            }
+        }
+        Ok(pending)
+    }
+
+    // 現在の鍵を退役済みのラベルに付け替えてから、反映待ちの鍵を現在の鍵のラベルに付け替える
+    fn promote_pending_keys(&self) -> Result<bool, Pkcs11KeyStoreError> {
+        let retired_at = Utc::now().format("%Y%m%dT%H%M%SZ");
+        let session = self.session.lock().unwrap();
+        let mut promoted = false;
+        for key_type in [
+            SecureKeyStoreType::Sign,
+            SecureKeyStoreType::Update,
+            SecureKeyStoreType::Recovery,
+            SecureKeyStoreType::Encrypt,
+        ] {
+            let label = key_label(&key_type);
+            let pending = pending_label(&key_type);
+            let class = match key_type {
+                SecureKeyStoreType::Encrypt => ObjectClass::DATA,
+                _ => ObjectClass::PUBLIC_KEY,
+            };
+            if Self::find_object(&session, class, &pending)?.is_none() {
+                continue;
+            }
+            let retired = format!("{}-retired-{}", label, retired_at);
+            if Self::relabel(&session, label, &retired)? {
+                log::info!("retired PKCS#11 key: {}", retired);
+            }
+            Self::relabel(&session, &pending, label)?;
+            promoted = true;
+        }
+        Ok(promoted)
+    }
+
+    fn read_history(&self) -> Result<KeyHistory, Pkcs11KeyStoreError> {
+        match Self::read_data(&self.session.lock().unwrap(), HISTORY_LABEL)? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Ok(KeyHistory::default()),
+        }
+    }
+
+    // 既存のデータオブジェクトは削除せず、値を更新する
+    fn write_history(&self, history: &KeyHistory) -> Result<(), Pkcs11KeyStoreError> {
+        let value = Zeroizing::new(serde_json::to_vec(history)?);
+        let session = self.session.lock().unwrap();
+        match Self::find_object(&session, ObjectClass::DATA, HISTORY_LABEL)? {
+            Some(handle) => {
+                session.update_attributes(handle, &[Attribute::Value(value.to_vec())])?;
+                Ok(())
+            }
+            None => Self::create_data(&session, HISTORY_LABEL, &value),
+        }
+    }
+
+    fn pkcs11_signer(
+        &self,
+        key_type: &SecureKeyStoreType,
+    ) -> Result<Pkcs11Signer, Pkcs11KeyStoreError> {
+        let label = key_label(key_type);
+        let public_key = self.read_public_key(label)?;
+        let private_key = Self::find_object(
+            &self.session.lock().unwrap(),
+            ObjectClass::PRIVATE_KEY,
+            label,
+        )?
+        .ok_or_else(|| Pkcs11KeyStoreError::KeyNotFound(label.to_string()))?;
+        Ok(Pkcs11Signer {
+            session: self.session.clone(),
+            private_key,
+            public_key,
+        })
+    }
+}
+
+impl SecureKeyStore for Pkcs11KeyStore {
+    type Signer = Pkcs11Signer;
+
+    // 署名鍵・更新鍵・リカバリ鍵は、取り出せない鍵としてトークンに取り込む
+    fn write(&self, key_pair: &SecureKeyStoreKey) -> Result<(), SecureKeyStoreError> {
+        Ok(self.import(key_pair)?)
+    }
+
+    // 秘密鍵はトークンから取り出せない
+    fn read_sign(&self) -> Option<SignKeyPair> {
+        None
+    }
+
+    fn read_update(&self) -> Option<K256KeyPair> {
+        None
+    }
+
+    fn read_recovery(&self) -> Option<K256KeyPair> {
+        None
+    }
+
+    fn read_encrypt(&self) -> Option<X25519KeyPair> {
+        self.read_encrypt_key(key_label(&SecureKeyStoreType::Encrypt))
+            .map_err(|e| log::debug!("failed to read encrypt key: {}", e))
+            .ok()
+    }
+
+    fn signer(&self, key_type: SecureKeyStoreType) -> Option<Pkcs11Signer> {
+        if matches!(key_type, SecureKeyStoreType::Encrypt) {
+            return None;
+        }
+        self.pkcs11_signer(&key_type)
+            .map_err(|e| log::error!("failed to load signer (type {:?}): {}", key_type, e))
+            .ok()
+    }
+
+    fn generate(
+        &self,
+        sign_key_type: SignKeyType,
+    ) -> Result<PublicKeyPairing, SecureKeyStoreError> {
+        self.generate_keys(sign_key_type)
+            .map_err(|e| SecureKeyStoreError::GenerateFailed(e.to_string()))
+    }
+
+    fn read_key_history(&self) -> KeyHistory {
+        self.read_history()
+            .map_err(|e| log::error!("failed to read key history: {}", e))
+            .unwrap_or_default()
+    }
+
+    fn write_key_history(&self, history: &KeyHistory) -> Result<(), SecureKeyStoreError> {
+        Ok(self.write_history(history)?)
+    }
+
+    // 署名鍵・更新鍵・リカバリ鍵は反映待ちのラベルでトークン上に生成する
+    fn generate_pending(
+        &self,
+        key_types: &[SecureKeyStoreType],
+        sign_key_type: SignKeyType,
+    ) -> Result<PendingPublicKeys, SecureKeyStoreError> {
+        self.generate_pending_keys(key_types, sign_key_type)
+            .map_err(|e| SecureKeyStoreError::GenerateFailed(e.to_string()))
+    }
+
+    fn read_pending(&self) -> Option<PendingPublicKeys> {
+        let read = |key_type| {
+            self.read_public_key(&pending_label(&key_type))
+                .map_err(|e| log::debug!("failed to read pending key (type {:?}): {}", key_type, e))
+                .ok()
+        };
+        let read_k256 = |key_type| {
+            k256_public_key(read(key_type)?)
+                .map_err(|e| log::error!("{}", e))
+                .ok()
+        };
+        let pending = PendingPublicKeys {
+            sign: read(SecureKeyStoreType::Sign),
+            update: read_k256(SecureKeyStoreType::Update),
+            recovery: read_k256(SecureKeyStoreType::Recovery),
+            encrypt: self
+                .read_encrypt_key(&pending_label(&SecureKeyStoreType::Encrypt))
+                .ok()
+                .map(|key| key.get_public_key()),
+        };
+        (pending != PendingPublicKeys::default()).then_some(pending)
+    }
+
+    fn promote_pending(&self) -> Result<(), SecureKeyStoreError> {
+        if !self.promote_pending_keys()? {
+            return Err(SecureKeyStoreError::PendingKeysNotFound);
+        }
+        Ok(())
+    }
+
+    fn read_public_keys(&self) -> Option<StoredPublicKeys> {
+        let read = |key_type| {
+            self.read_public_key(key_label(&key_type))
+                .map_err(|e| log::debug!("failed to read public key (type {:?}): {}", key_type, e))
+                .ok()
+        };
+        let read_k256 = |key_type| {
+            k256_public_key(read(key_type)?)
+                .map_err(|e| log::error!("{}", e))
+                .ok()
+        };
+        Some(StoredPublicKeys {
+            sign: read(SecureKeyStoreType::Sign)?,
+            update: read_k256(SecureKeyStoreType::Update)?,
+            recovery: read_k256(SecureKeyStoreType::Recovery),
+            encrypt: self.read_encrypt()?.get_public_key(),
+        })
+    }
+}
+
+/// トークン上の秘密鍵で署名するSigner
+pub struct Pkcs11Signer {
+    session: Arc<Mutex<Session>>,
+    private_key: ObjectHandle,
+    public_key: SignPublicKey,
+}
+
+impl Signer for Pkcs11Signer {
+    type Error = Pkcs11KeyStoreError;
+
+    fn public_key(&self) -> SignPublicKey {
+        self.public_key
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        // CKM_ECDSAはハッシュ化しないため、ES256K・ES256に合わせてSHA-256のダイジェストに署名する
+        let digest = Sha256::digest(message);
+        let signature = self.session.lock().unwrap().sign(
+            &Mechanism::Ecdsa,
+            self.private_key,
+            digest.as_slice(),
+        )?;
+        // トークンはr||sの形式で返す
+        match self.public_key {
+            // k256の検証はsが大きい署名を受け付けないため正規化する
+            SignPublicKey::Secp256k1(_) => {
+                let signature = k256::ecdsa::Signature::from_slice(&signature)
+                    .map_err(|e| Pkcs11KeyStoreError::InvalidSignature(e.to_string()))?;
+                let signature = signature.normalize_s().unwrap_or(signature);
+                Ok(signature.to_vec())
+            }
+            _ => {
+                let signature = p256::ecdsa::Signature::from_slice(&signature)
+                    .map_err(|e| Pkcs11KeyStoreError::InvalidSignature(e.to_string()))?;
+                Ok(signature.to_vec())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::ecdsa::signature::Verifier;
+    use protocol::keyring::keypair::KeyPairing;
+
+    // 移行元の鍵ストア。鍵ペアはClone（暗黙の複製）を実装しないため、読み出すたびにKeyPairHexから復元する
+    struct MemoryKeyStore(KeyPairing);
+
+    impl SecureKeyStore for MemoryKeyStore {
+        type Signer = SignKeyPair;
+
+        fn write(&self, _key_pair: &SecureKeyStoreKey) -> Result<(), SecureKeyStoreError> {
+            unimplemented!()
+        }
+
+        fn read_sign(&self) -> Option<SignKeyPair> {
+            SignKeyPair::from_hex_key_pair(&self.0.sign().to_hex_key_pair()).ok()
+        }
+
+        fn read_update(&self) -> Option<K256KeyPair> {
+            K256KeyPair::from_hex_key_pair(&self.0.update().to_hex_key_pair()).ok()
+        }
+
+        fn read_recovery(&self) -> Option<K256KeyPair> {
+            K256KeyPair::from_hex_key_pair(&self.0.recovery().to_hex_key_pair()).ok()
+        }
+
+        fn read_encrypt(&self) -> Option<X25519KeyPair> {
+            X25519KeyPair::from_hex_key_pair(&self.0.encrypt().to_hex_key_pair()).ok()
+        }
+
+        fn signer(&self, _key_type: SecureKeyStoreType) -> Option<SignKeyPair> {
+            None
+        }
+    }
+
+    // SoftHSMのトークンにログインし、前回のテストで作成した鍵を削除する
+    //
+    //   export SOFTHSM2_CONF=/path/to/softhsm2.conf
+    //   softhsm2-util --init-token --free --label miax-test --pin 1234 --so-pin 5678
+    //   export MIAX_PKCS11_MODULE=/usr/lib/softhsm/libsofthsm2.so
+    //   export MIAX_PKCS11_TOKEN_LABEL=miax-test
+    //   export MIAX_PKCS11_PIN=1234
+    //   cargo test -p agent --features pkcs11 -- --ignored
+    fn softhsm_keystore() -> Pkcs11KeyStore {
+        let conf = std::env::var("SOFTHSM2_CONF").expect("SOFTHSM2_CONF is not set");
+        assert!(
+            std::path::Path::new(&conf).exists(),
+            "SOFTHSM2_CONF does not exist: {}",
+            conf
+        );
+        let config = Pkcs11Config::from_env().expect("PKCS#11 token is not configured");
+        let keystore = Pkcs11KeyStore::new(&config).unwrap();
+        clear(&keystore);
+        keystore
+    }
+
+    // 反映待ち・退役済みの鍵と鍵の履歴を含め、ラベルが「miax-」で始まるオブジェクトを削除する
+    fn clear(keystore: &Pkcs11KeyStore) {
+        let session = keystore.session.lock().unwrap();
+        for handle in session.find_objects(&[]).unwrap() {
+            let attributes = session
+                .get_attributes(handle, &[AttributeType::Label])
+                .unwrap();
+            let is_miax = attributes.iter().any(|attribute| {
+                matches!(attribute, Attribute::Label(label) if label.starts_with(b"miax-"))
+            });
+            if is_miax {
+                session.destroy_object(handle).unwrap();
+            }
+        }
+    }
+
+    fn verify(public_key: SignPublicKey, message: &[u8], signature: &[u8]) {
+        match public_key {
+            SignPublicKey::Secp256k1(public_key) => {
+                let signature = k256::ecdsa::Signature::from_slice(signature).unwrap();
+                k256::ecdsa::VerifyingKey::from(&public_key)
+                    .verify(message, &signature)
+                    .unwrap();
+            }
+            SignPublicKey::P256(public_key) => {
+                let signature = p256::ecdsa::Signature::from_slice(signature).unwrap();
+                p256::ecdsa::VerifyingKey::from(&public_key)
+                    .verify(message, &signature)
+                    .unwrap();
+            }
+            SignPublicKey::Ed25519(_) => panic!("unexpected key type"),
+        }
+    }
+
+    #[test]
+    fn test_ec_point() {
+        let public_key = KeyPairing::create_keyring(OsRng).update().get_public_key();
+        let sec1 = public_key.to_encoded_point(false);
+        let point = ec_point(sec1.as_bytes());
+        assert_eq!(&point[..2], &[0x04, 65]);
+        assert_eq!(&point[2..], sec1.as_bytes());
+    }
+
+    // PKCS#11の初期化はプロセスで一度のみのため、1つのテストで生成と移行を確認する
+    #[test]
+    #[ignore = "requires SoftHSM (SOFTHSM2_CONF)"]
+    fn test_softhsm() {
+        let keystore = softhsm_keystore();
+        let message = b"miax";
+
+        // トークン上での生成
+        assert!(keystore.read_public_keys().is_none());
+        let public_keys = keystore.generate(SignKeyType::P256).unwrap();
+        assert!(matches!(public_keys.sign, SignPublicKey::P256(_)));
+        assert_eq!(
+            keystore.read_public_keys(),
+            Some(public_keys.clone().into())
+        );
+        assert!(keystore.read_sign().is_none());
+        assert!(keystore.read_update().is_none());
+        assert_eq!(
+            keystore.read_encrypt().unwrap().get_public_key(),
+            public_keys.encrypt
+        );
+        for key_type in [
+            SecureKeyStoreType::Sign,
+            SecureKeyStoreType::Update,
+            SecureKeyStoreType::Recovery,
+        ] {
+            let signer = keystore.signer(key_type).unwrap();
+            verify(signer.public_key(), message, &signer.sign(message).unwrap());
+        }
+        assert!(keystore.signer(SecureKeyStoreType::Encrypt).is_none());
+
+        // 既存の鍵は削除しない
+        assert!(matches!(
+            keystore.generate(SignKeyType::Secp256k1),
+            Err(SecureKeyStoreError::GenerateFailed(_))
+        ));
+        assert_eq!(keystore.read_public_keys(), Some(public_keys.into()));
+
+        // ファイルの鍵ストアからの移行
+        clear(&keystore);
+        let legacy = MemoryKeyStore(KeyPairing::create_keyring(OsRng));
+        assert!(keystore.migrate(&legacy).unwrap());
+        assert_eq!(
+            keystore.read_public_keys(),
+            Some(legacy.0.public_keys().into())
+        );
+        let signer = keystore.signer(SecureKeyStoreType::Update).unwrap();
+        verify(
+            SignPublicKey::Secp256k1(legacy.0.update().get_public_key()),
+            message,
+            &signer.sign(message).unwrap(),
+        );
+        assert!(matches!(
+            keystore.migrate(&legacy),
+            Err(Pkcs11KeyStoreError::KeyAlreadyExists(_))
+        ));
+
+        // ローテーション : 反映待ちの鍵は昇格するまで現在の鍵を置き換えない
+        let current = keystore.read_public_keys().unwrap();
+        let pending = keystore
+            .generate_pending(
+                &[SecureKeyStoreType::Update, SecureKeyStoreType::Encrypt],
+                SignKeyType::Secp256k1,
+            )
+            .unwrap();
+        assert_eq!(keystore.read_pending(), Some(pending.clone()));
+        assert_eq!(keystore.read_public_keys(), Some(current.clone()));
+        assert!(keystore
+            .generate_pending(&[SecureKeyStoreType::Update], SignKeyType::Secp256k1)
+            .is_err());
+
+        keystore.promote_pending().unwrap();
+        assert!(keystore.read_pending().is_none());
+        let promoted = keystore.read_public_keys().unwrap();
+        assert_eq!(promoted.sign, current.sign);
+        assert_eq!(Some(promoted.update), pending.update);
+        assert_eq!(Some(promoted.encrypt), pending.encrypt);
+        assert!(matches!(
+            keystore.promote_pending(),
+            Err(SecureKeyStoreError::PendingKeysNotFound)
+        ));
+
+        // 置き換えた鍵は削除せず、退役済みのラベルで残す
+        let session = keystore.session.lock().unwrap();
+        let retired = session
+            .find_objects(&[Attribute::Class(ObjectClass::PUBLIC_KEY)])
+            .unwrap()
+            .into_iter()
+            .filter(|handle| {
+                session
+                    .get_attributes(*handle, &[AttributeType::Label])
+                    .unwrap()
+                    .iter()
+                    .any(|attribute| {
+                        matches!(attribute, Attribute::Label(label) if label.starts_with(b"miax-update-retired-"))
+                    })
+            })
+            .count();
+        assert_eq!(retired, 1);
+        drop(session);
+
+        // 鍵の履歴はデータオブジェクトとして保存し、上書きできる
+        let mut history = keystore.read_key_history();
+        assert!(history.keys.is_empty());
+        history.retire(
+            SecureKeyStoreType::Update,
+            &current.update.into(),
+            Utc::now(),
+        );
+        keystore.write_key_history(&history).unwrap();
+        keystore.write_key_history(&history).unwrap();
+        assert_eq!(keystore.read_key_history().keys.len(), 1);
+
+        clear(&keystore);
+    }
+}
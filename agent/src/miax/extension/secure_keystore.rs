@@ -1,10 +1,20 @@
-use protocol::keyring::keypair::{K256KeyPair, X25519KeyPair};
+use chrono::{DateTime, Utc};
+use protocol::keyring::hd::{HdKeyring, HdKeyringError};
+use protocol::keyring::keypair::{
+    K256KeyPair, KeyPair, KeyPairHex, KeyPairing, KeyPairingError, PublicKeyPairing, SignKeyPair,
+    SignKeyType, SignPublicKey, X25519KeyPair,
+};
+use protocol::keyring::signer::Signer;
+use protocol::rand_core::OsRng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use crate::config::SingletonAppConfig;
 
 pub enum SecureKeyStoreKey<'a> {
     /// 署名鍵
-    Sign(&'a K256KeyPair),
+    Sign(&'a SignKeyPair),
     /// 更新鍵
     Update(&'a K256KeyPair),
     /// リカバリ鍵
@@ -13,7 +23,8 @@ pub enum SecureKeyStoreKey<'a> {
     Encrypt(&'a X25519KeyPair),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SecureKeyStoreType {
     Sign,
     Update,
@@ -21,13 +32,430 @@ pub enum SecureKeyStoreType {
     Encrypt,
 }
 
+/// 鍵を導出するHD鍵のニーモニックと、最後に鍵を導出したインデックス
+///
+/// リカバリ鍵はrecover操作でのみ置き換えるため、導出したインデックスを`recovery_index`に別に保存する
+#[derive(Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct HdState {
+    pub mnemonic: String,
+    pub index: u32,
+    #[serde(default)]
+    pub recovery_index: u32,
+}
+
+impl HdState {
+    pub fn keyring(&self) -> Result<HdKeyring, HdKeyringError> {
+        HdKeyring::from_mnemonic(&self.mnemonic, "")
+    }
+}
+
+/// ローテーションで置き換えた鍵
+#[derive(Serialize, Deserialize)]
+pub struct RetiredKey {
+    pub key_type: SecureKeyStoreType,
+    /// 公開鍵（KeyPairHexのpublic_keyと同じ形式）
+    pub public_key: String,
+    /// 猶予期間中の暗号化鍵の鍵ペア。猶予期間を過ぎると削除する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_pair: Option<KeyPairHex>,
+    pub retired_at: DateTime<Utc>,
+    /// 猶予期間の終了日時（暗号化鍵のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// 鍵の履歴
+#[derive(Default, Serialize, Deserialize)]
+pub struct KeyHistory {
+    pub keys: Vec<RetiredKey>,
+}
+
+impl KeyHistory {
+    /// 置き換えた署名鍵・更新鍵の公開鍵を履歴に追加する
+    pub fn retire(
+        &mut self,
+        key_type: SecureKeyStoreType,
+        public_key: &SignPublicKey,
+        retired_at: DateTime<Utc>,
+    ) {
+        self.keys.push(RetiredKey {
+            key_type,
+            public_key: public_key.to_hex(),
+            key_pair: None,
+            retired_at,
+            expires_at: None,
+        });
+    }
+
+    /// 置き換えた暗号化鍵を履歴に追加する
+    ///
+    /// 猶予期間の終了日時までは、送信中のDIDCommメッセージを復号できるよう鍵ペアも保存する
+    pub fn retire_encrypt(
+        &mut self,
+        key_pair: KeyPairHex,
+        retired_at: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) {
+        self.keys.push(RetiredKey {
+            key_type: SecureKeyStoreType::Encrypt,
+            public_key: key_pair.public_key().to_string(),
+            key_pair: Some(key_pair),
+            retired_at,
+            expires_at: Some(expires_at),
+        });
+    }
+
+    /// 猶予期間中の暗号化鍵を、新しい順に返す
+    pub fn grace_encrypt_keys(&self, now: DateTime<Utc>) -> Vec<X25519KeyPair> {
+        self.keys
+            .iter()
+            .rev()
+            .filter(|key| key.key_type == SecureKeyStoreType::Encrypt)
+            .filter(|key| key.expires_at.is_some_and(|expires_at| now < expires_at))
+            .filter_map(|key| key.key_pair.as_ref())
+            .filter_map(|key_pair| {
+                X25519KeyPair::from_hex_key_pair(key_pair)
+                    .map_err(|e| log::error!("{:?}", e))
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// 猶予期間を過ぎた暗号化鍵の鍵ペアを削除する（公開鍵の履歴は残す）
+    /// 削除した場合はtrueを返す
+    pub fn prune(&mut self, now: DateTime<Utc>) -> bool {
+        let mut pruned = false;
+        for key in &mut self.keys {
+            if key.key_pair.is_some() && key.expires_at.is_none_or(|expires_at| expires_at <= now) {
+                key.key_pair = None;
+                pruned = true;
+            }
+        }
+        pruned
+    }
+}
+
+/// 反映待ちの鍵ペア
+///
+/// update操作でDIDドキュメントに反映されるまでは現在の鍵を置き換えないよう、
+/// 新しい鍵は反映待ちとして保存し、反映後に現在の鍵へ昇格する
+#[derive(Default, Serialize, Deserialize)]
+pub struct PendingKeyPairs {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sign: Option<KeyPairHex>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<KeyPairHex>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recovery: Option<KeyPairHex>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypt: Option<KeyPairHex>,
+    /// HD鍵から導出した場合のインデックス。昇格時に`HdState`のインデックスを進める
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hd_index: Option<u32>,
+}
+
+impl PendingKeyPairs {
+    pub fn public_keys(&self) -> Result<PendingPublicKeys, KeyPairingError> {
+        Ok(PendingPublicKeys {
+            sign: self
+                .sign
+                .as_ref()
+                .map(SignKeyPair::from_hex_key_pair)
+                .transpose()?
+                .map(|key| key.get_public_key()),
+            update: self
+                .update
+                .as_ref()
+                .map(K256KeyPair::from_hex_key_pair)
+                .transpose()?
+                .map(|key| key.get_public_key()),
+            recovery: self
+                .recovery
+                .as_ref()
+                .map(K256KeyPair::from_hex_key_pair)
+                .transpose()?
+                .map(|key| key.get_public_key()),
+            encrypt: self
+                .encrypt
+                .as_ref()
+                .map(X25519KeyPair::from_hex_key_pair)
+                .transpose()?
+                .map(|key| key.get_public_key()),
+        })
+    }
+}
+
+/// 反映待ちの鍵の公開鍵
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PendingPublicKeys {
+    pub sign: Option<SignPublicKey>,
+    pub update: Option<k256::PublicKey>,
+    pub recovery: Option<k256::PublicKey>,
+    pub encrypt: Option<x25519_dalek::PublicKey>,
+}
+
+impl PendingPublicKeys {
+    /// recover操作で送信する公開鍵の組。全ての種類の鍵がない場合はNone
+    pub fn to_pairing(&self) -> Option<PublicKeyPairing> {
+        Some(PublicKeyPairing {
+            sign: self.sign?,
+            update: self.update?,
+            recovery: self.recovery?,
+            encrypt: self.encrypt?,
+        })
+    }
+}
+
+/// 鍵ストアに保存された鍵の公開鍵
+///
+/// リカバリ鍵はエクスポートして鍵ストアから削除できるため、存在しない場合はNoneとする
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredPublicKeys {
+    pub sign: SignPublicKey,
+    pub update: k256::PublicKey,
+    pub recovery: Option<k256::PublicKey>,
+    pub encrypt: x25519_dalek::PublicKey,
+}
+
+impl StoredPublicKeys {
+    /// DIDの作成に利用する公開鍵の組。リカバリ鍵が存在しない場合はNone
+    pub fn to_pairing(&self) -> Option<PublicKeyPairing> {
+        Some(PublicKeyPairing {
+            sign: self.sign,
+            update: self.update,
+            recovery: self.recovery?,
+            encrypt: self.encrypt,
+        })
+    }
+}
+
+impl From<PublicKeyPairing> for StoredPublicKeys {
+    fn from(value: PublicKeyPairing) -> Self {
+        StoredPublicKeys {
+            sign: value.sign,
+            update: value.update,
+            recovery: Some(value.recovery),
+            encrypt: value.encrypt,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SecureKeyStoreError {
+    // 鍵をソフトウェアで生成する鍵ストアでは発生しない
+    #[cfg_attr(not(feature = "pkcs11"), allow(dead_code))]
+    #[error("failed to generate key pair: {0}")]
+    GenerateFailed(String),
+    #[error("failed to write key to the keystore: {0}")]
+    WriteFailed(String),
+    #[error("{0} is not supported by this keystore")]
+    Unsupported(&'static str),
+    #[error("failed to derive key pair: {0}")]
+    DeriveFailed(#[from] HdKeyringError),
+    #[error("invalid key pair: {0}")]
+    InvalidKeyPair(#[from] KeyPairingError),
+    #[error("pending keys are not found")]
+    PendingKeysNotFound,
+}
+
 /// セキュア鍵ストアのインターフェース
+///
+/// 秘密鍵を取り出せない鍵ストア（HSMなど）では、`read_*`はNoneを返し、
+/// `generate`・`read_public_keys`・`signer`を経由して鍵を利用する
 pub trait SecureKeyStore {
-    fn write(&self, key_pair: &SecureKeyStoreKey);
-    fn read_sign(&self) -> Option<K256KeyPair>;
+    /// 署名鍵・更新鍵・リカバリ鍵で署名するSigner
+    type Signer: Signer + Sync;
+
+    fn write(&self, key_pair: &SecureKeyStoreKey) -> Result<(), SecureKeyStoreError>;
+    fn read_sign(&self) -> Option<SignKeyPair>;
     fn read_update(&self) -> Option<K256KeyPair>;
     fn read_recovery(&self) -> Option<K256KeyPair>;
     fn read_encrypt(&self) -> Option<X25519KeyPair>;
+
+    /// 鍵ストアに保存された鍵で署名するSignerを返す。暗号化鍵の場合や鍵が存在しない場合はNone
+    fn signer(&self, key_type: SecureKeyStoreType) -> Option<Self::Signer>;
+
+    /// 指定した種類の署名鍵を含む新しい鍵ペアを生成して保存し、公開鍵を返す
+    ///
+    /// 鍵は新しいニーモニックのインデックス0から導出する
+    /// HD鍵の状態を保存できない鍵ストアでは、ニーモニックを保存せずに鍵のみを保存する
+    fn generate(
+        &self,
+        sign_key_type: SignKeyType,
+    ) -> Result<PublicKeyPairing, SecureKeyStoreError> {
+        let hd = HdKeyring::generate(OsRng);
+        let keyring = hd.derive_keyring_with_sign_key(sign_key_type, 0)?;
+        let state = HdState {
+            mnemonic: hd.mnemonic().as_str().to_owned(),
+            index: 0,
+            recovery_index: 0,
+        };
+        match self.write_hd_state(Some(&state)) {
+            Err(SecureKeyStoreError::Unsupported(_)) => {
+                log::warn!("HD keyring is not supported by this keystore, the mnemonic is not kept")
+            }
+            result => result?,
+        }
+        self.write(&SecureKeyStoreKey::Sign(keyring.sign()))?;
+        self.write(&SecureKeyStoreKey::Update(keyring.update()))?;
+        self.write(&SecureKeyStoreKey::Recovery(keyring.recovery()))?;
+        self.write(&SecureKeyStoreKey::Encrypt(keyring.encrypt()))?;
+        Ok(keyring.public_keys())
+    }
+
+    /// 保存された鍵ペアの公開鍵を返す。署名鍵・更新鍵・暗号化鍵のいずれかが存在しない場合はNone
+    fn read_public_keys(&self) -> Option<StoredPublicKeys> {
+        Some(StoredPublicKeys {
+            sign: self.read_sign()?.get_public_key(),
+            update: self.read_update()?.get_public_key(),
+            recovery: self.read_recovery().map(|key| key.get_public_key()),
+            encrypt: self.read_encrypt()?.get_public_key(),
+        })
+    }
+
+    /// リカバリ鍵を削除する
+    ///
+    /// シェアとしてエクスポートしたリカバリ鍵を、デバイスに残さないために利用する
+    fn remove_recovery(&self) -> Result<(), SecureKeyStoreError> {
+        Err(SecureKeyStoreError::Unsupported("removing recovery key"))
+    }
+
+    /// HD鍵の状態を読み出す。HD鍵の状態を保存できない鍵ストアでは常にNone
+    fn read_hd_state(&self) -> Option<HdState> {
+        None
+    }
+
+    /// HD鍵の状態を保存する。Noneの場合は削除する
+    fn write_hd_state(&self, _state: Option<&HdState>) -> Result<(), SecureKeyStoreError> {
+        Err(SecureKeyStoreError::Unsupported("HD keyring"))
+    }
+
+    /// 鍵の履歴を読み出す。履歴を保存できない鍵ストアでは空の履歴を返す
+    fn read_key_history(&self) -> KeyHistory {
+        KeyHistory::default()
+    }
+
+    /// 鍵の履歴を保存する
+    fn write_key_history(&self, _history: &KeyHistory) -> Result<(), SecureKeyStoreError> {
+        log::warn!("key history is not supported by this keystore");
+        Ok(())
+    }
+
+    /// 反映待ちの鍵ペアを読み出す。反映待ちの鍵を保存できない鍵ストアでは常にNone
+    fn read_pending_key_pairs(&self) -> Option<PendingKeyPairs> {
+        None
+    }
+
+    /// 反映待ちの鍵ペアを保存する。Noneの場合は削除する
+    fn write_pending_key_pairs(
+        &self,
+        _pending: Option<&PendingKeyPairs>,
+    ) -> Result<(), SecureKeyStoreError> {
+        Err(SecureKeyStoreError::Unsupported("pending keys"))
+    }
+
+    /// 指定した種類の新しい鍵を生成して反映待ちとして保存し、公開鍵を返す
+    ///
+    /// 既存の反映待ちの鍵は置き換える。現在の鍵は`promote_pending`を呼ぶまで変更しない
+    /// HD鍵の状態がある場合は、次のインデックスから導出する
+    fn generate_pending(
+        &self,
+        key_types: &[SecureKeyStoreType],
+        sign_key_type: SignKeyType,
+    ) -> Result<PendingPublicKeys, SecureKeyStoreError> {
+        let mut pending = PendingKeyPairs::default();
+        let keyring = match self.read_hd_state() {
+            Some(state) => {
+                let index = state.index + 1;
+                pending.hd_index = Some(index);
+                state
+                    .keyring()?
+                    .derive_keyring_with_sign_key(sign_key_type, index)?
+            }
+            None => KeyPairing::create_keyring_with_sign_key(OsRng, sign_key_type),
+        };
+        for key_type in key_types {
+            match key_type {
+                SecureKeyStoreType::Sign => pending.sign = Some(keyring.sign().to_hex_key_pair()),
+                SecureKeyStoreType::Update => {
+                    pending.update = Some(keyring.update().to_hex_key_pair())
+                }
+                SecureKeyStoreType::Recovery => {
+                    pending.recovery = Some(keyring.recovery().to_hex_key_pair())
+                }
+                SecureKeyStoreType::Encrypt => {
+                    pending.encrypt = Some(keyring.encrypt().to_hex_key_pair())
+                }
+            }
+        }
+        let public_keys = pending.public_keys()?;
+        self.write_pending_key_pairs(Some(&pending))?;
+        // 保存した鍵を読み戻せない場合は、DIDドキュメントに反映する前にエラーとする
+        if self.read_pending().as_ref() != Some(&public_keys) {
+            return Err(SecureKeyStoreError::PendingKeysNotFound);
+        }
+        Ok(public_keys)
+    }
+
+    /// 反映待ちの鍵の公開鍵を返す。反映待ちの鍵がない場合はNone
+    fn read_pending(&self) -> Option<PendingPublicKeys> {
+        self.read_pending_key_pairs().and_then(|pending| {
+            pending
+                .public_keys()
+                .map_err(|e| log::error!("{:?}", e))
+                .ok()
+        })
+    }
+
+    /// 反映待ちの鍵で現在の鍵を置き換え、反映待ちの鍵を削除する
+    ///
+    /// HD鍵から導出した鍵の場合は、HD鍵の状態のインデックスも進める
+    fn promote_pending(&self) -> Result<(), SecureKeyStoreError> {
+        let pending = self
+            .read_pending_key_pairs()
+            .ok_or(SecureKeyStoreError::PendingKeysNotFound)?;
+        if let Some(key) = &pending.sign {
+            self.write(&SecureKeyStoreKey::Sign(&SignKeyPair::from_hex_key_pair(
+                key,
+            )?))?;
+        }
+        if let Some(key) = &pending.update {
+            self.write(&SecureKeyStoreKey::Update(&K256KeyPair::from_hex_key_pair(
+                key,
+            )?))?;
+        }
+        if let Some(key) = &pending.recovery {
+            self.write(&SecureKeyStoreKey::Recovery(
+                &K256KeyPair::from_hex_key_pair(key)?,
+            ))?;
+        }
+        if let Some(key) = &pending.encrypt {
+            self.write(&SecureKeyStoreKey::Encrypt(
+                &X25519KeyPair::from_hex_key_pair(key)?,
+            ))?;
+        }
+        if let (Some(index), Some(mut state)) = (pending.hd_index, self.read_hd_state()) {
+            state.index = index;
+            if pending.recovery.is_some() {
+                state.recovery_index = index;
+            }
+            self.write_hd_state(Some(&state))?;
+        }
+        self.write_pending_key_pairs(None)
+    }
+}
+
+/// 秘密鍵を読み出せる鍵ストアのSigner
+pub(crate) fn read_signer<S: SecureKeyStore + ?Sized>(
+    keystore: &S,
+    key_type: SecureKeyStoreType,
+) -> Option<SignKeyPair> {
+    match key_type {
+        SecureKeyStoreType::Sign => keystore.read_sign(),
+        SecureKeyStoreType::Update => keystore.read_update().map(Into::into),
+        SecureKeyStoreType::Recovery => keystore.read_recovery().map(Into::into),
+        SecureKeyStoreType::Encrypt => None,
+    }
 }
 
 /// ファイルベースの鍵ストア実装
@@ -40,6 +468,12 @@ impl FileBaseKeyStore {
     pub fn new(config: Box<SingletonAppConfig>) -> Self {
         FileBaseKeyStore { config }
     }
+
+    /// 設定ファイルに保存した全ての鍵ペアを削除する
+    pub fn clear(&self) {
+        log::info!("Called: clear_internal");
+        self.config.lock().clear_key_pairs();
+    }
 }
 
 fn k2t(k: &SecureKeyStoreKey) -> SecureKeyStoreType {
@@ -52,7 +486,9 @@ fn k2t(k: &SecureKeyStoreKey) -> SecureKeyStoreType {
 }
 
 impl SecureKeyStore for FileBaseKeyStore {
-    fn write(&self, key_pair: &SecureKeyStoreKey) {
+    type Signer = SignKeyPair;
+
+    fn write(&self, key_pair: &SecureKeyStoreKey) -> Result<(), SecureKeyStoreError> {
         log::info!("Called: write_internal (type {:?}", k2t(key_pair));
 
         // 設定へのアクセスはMutexで保護
@@ -64,9 +500,10 @@ impl SecureKeyStore for FileBaseKeyStore {
             SecureKeyStoreKey::Recovery(k) => config.save_recovery_key_pair(k),
             SecureKeyStoreKey::Encrypt(k) => config.save_encrypt_key_pair(k),
         }
+        Ok(())
     }
 
-    fn read_sign(&self) -> Option<K256KeyPair> {
+    fn read_sign(&self) -> Option<SignKeyPair> {
         log::debug!("Called: read_internal (type: sign)");
         let config = self.config.lock();
         config.load_sign_key_pair()
@@ -89,4 +526,87 @@ impl SecureKeyStore for FileBaseKeyStore {
         let config = self.config.lock();
         config.load_encrypt_key_pair()
     }
+
+    fn remove_recovery(&self) -> Result<(), SecureKeyStoreError> {
+        log::info!("Called: remove_internal (type: recovery)");
+        self.config.lock().clear_recovery_key_pair();
+        Ok(())
+    }
+
+    fn signer(&self, key_type: SecureKeyStoreType) -> Option<SignKeyPair> {
+        read_signer(self, key_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_key_history() {
+        let now = Utc::now();
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let old = X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(OsRng));
+        let older = X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(OsRng));
+
+        let mut history = KeyHistory::default();
+        history.retire(
+            SecureKeyStoreType::Sign,
+            &keyring.sign().get_public_key(),
+            now,
+        );
+        history.retire_encrypt(
+            older.to_hex_key_pair(),
+            now - Duration::days(2),
+            now + Duration::days(1),
+        );
+        history.retire_encrypt(old.to_hex_key_pair(), now, now + Duration::days(7));
+        assert_eq!(
+            history.keys[0].public_key,
+            keyring.sign().to_hex_key_pair().public_key()
+        );
+        assert!(history.keys[0].key_pair.is_none());
+
+        // 猶予期間中の暗号化鍵は新しい順に返す
+        let keys = history.grace_encrypt_keys(now);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].get_public_key(), old.get_public_key());
+        assert_eq!(keys[1].get_public_key(), older.get_public_key());
+        assert!(!history.prune(now));
+
+        // 猶予期間を過ぎた鍵ペアは削除し、公開鍵の履歴は残す
+        let later = now + Duration::days(3);
+        assert!(history.prune(later));
+        assert!(!history.prune(later));
+        assert_eq!(history.keys.len(), 3);
+        assert!(history.keys[1].key_pair.is_none());
+        let keys = history.grace_encrypt_keys(later);
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].get_public_key(), old.get_public_key());
+        assert!(history
+            .grace_encrypt_keys(now + Duration::days(7))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_pending_public_keys() {
+        let keyring = KeyPairing::create_keyring_with_sign_key(OsRng, SignKeyType::P256);
+        let pending = PendingKeyPairs {
+            sign: Some(keyring.sign().to_hex_key_pair()),
+            update: Some(keyring.update().to_hex_key_pair()),
+            ..Default::default()
+        };
+        let public_keys = pending.public_keys().unwrap();
+        assert_eq!(public_keys.sign, Some(keyring.sign().get_public_key()));
+        assert_eq!(public_keys.update, Some(keyring.update().get_public_key()));
+        assert_eq!(public_keys.recovery, None);
+        assert_eq!(public_keys.encrypt, None);
+
+        // 保存した鍵ペアをJSONから復元できる
+        let json = serde_json::to_string(&pending).unwrap();
+        let restored: PendingKeyPairs = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.public_keys().unwrap(), public_keys);
+        assert!(!json.contains("recovery"));
+    }
 }
@@ -1,18 +1,14 @@
-use crate::miax::extension::secure_keystore::SecureKeyStoreKey;
+use crate::miax::extension::secure_keystore::{SecureKeyStoreError, StoredPublicKeys};
 use crate::{config::SingletonAppConfig, miax::extension::secure_keystore::SecureKeyStore};
-use protocol::keyring::keypair::{K256KeyPair, X25519KeyPair};
-use protocol::rand_core::OsRng;
+use protocol::keyring::keypair::PublicKeyPairing;
 use thiserror::Error;
 
 /// 設定とセキュア鍵ストアを統合した鍵ペア管理構造体
-/// 鍵ペアのロード、生成、永続化、DID識別子の管理などを担当
-pub struct KeyPairingWithConfig<S: SecureKeyStore> {
-    sign: K256KeyPair,
-    update: K256KeyPair,
-    recovery: K256KeyPair,
-    encrypt: X25519KeyPair,
+/// 鍵ペアのロード、生成、DID識別子の管理などを担当
+/// 秘密鍵はセキュア鍵ストアが管理し、この構造体は公開鍵のみを保持する
+pub struct KeyPairingWithConfig {
+    public_keys: StoredPublicKeys,
     config: Box<SingletonAppConfig>,
-    secure_keystore: S,
 }
 
 /// 鍵ペアリング処理に関連するエラー型
@@ -20,77 +16,69 @@ pub struct KeyPairingWithConfig<S: SecureKeyStore> {
 pub enum KeyPairingError {
     #[error("create keyring failed: {0}")]
     CreateKeyringFailed(#[from] protocol::keyring::keypair::KeyPairingError),
+    #[error("generate keyring failed: {0}")]
+    GenerateKeyringFailed(#[from] SecureKeyStoreError),
     #[error("key not found")]
     KeyNotFound,
+    #[error("recovery key not found")]
+    RecoveryKeyNotFound,
     #[error("DID not found")]
     DIDNotFound,
+    #[error("DID is already created: {0}")]
+    DIDAlreadyCreated(String),
 }
 
-impl<S: SecureKeyStore> KeyPairingWithConfig<S> {
-    pub fn load_keyring(
+impl KeyPairingWithConfig {
+    /// 鍵ストアに保存された鍵ペアを読み込む
+    ///
+    /// リカバリ鍵はエクスポートして削除されている場合があるため、読み込みには必要としない
+    pub fn load_keyring<S: SecureKeyStore>(
         config: Box<SingletonAppConfig>,
-        secure_keystore: S,
+        secure_keystore: &S,
     ) -> Result<Self, KeyPairingError> {
-        let sign = secure_keystore
-            .read_sign()
-            .ok_or(KeyPairingError::KeyNotFound)?;
-        let update = secure_keystore
-            .read_update()
-            .ok_or(KeyPairingError::KeyNotFound)?;
-
-        let recovery = secure_keystore
-            .read_recovery()
-            .ok_or(KeyPairingError::KeyNotFound)?;
-        let encrypt = secure_keystore
-            .read_encrypt()
+        let public_keys = secure_keystore
+            .read_public_keys()
             .ok_or(KeyPairingError::KeyNotFound)?;
 
         Ok(KeyPairingWithConfig {
-            sign,
-            update,
-            recovery,
-            encrypt,
+            public_keys,
             config,
-            secure_keystore,
         })
     }
 
-    pub fn create_keyring(config: Box<SingletonAppConfig>, secure_keystore: S) -> Self {
-        let keyring = protocol::keyring::keypair::KeyPairing::create_keyring(OsRng);
+    /// セキュア鍵ストアで、設定した種類の署名鍵を含む新しい鍵ペアを生成する
+    ///
+    /// 作成済みのDIDがある場合は、DIDの鍵を上書きしないよう生成しない
+    pub fn create_keyring<S: SecureKeyStore>(
+        config: Box<SingletonAppConfig>,
+        secure_keystore: &S,
+    ) -> Result<Self, KeyPairingError> {
+        let (did, keystore_config) = {
+            let config = config.lock();
+            (config.get_did(), config.keystore_config())
+        };
+        if let Some(did) = did {
+            return Err(KeyPairingError::DIDAlreadyCreated(did));
+        }
+        let public_keys = secure_keystore.generate(keystore_config.sign_key_type)?;
 
-        KeyPairingWithConfig {
-            sign: keyring.sign,
-            update: keyring.recovery,
-            recovery: keyring.update,
-            encrypt: keyring.encrypt,
+        Ok(KeyPairingWithConfig {
+            public_keys: public_keys.into(),
             config,
-            secure_keystore,
-        }
+        })
     }
 
-    pub fn get_keyring(&self) -> protocol::keyring::keypair::KeyPairing {
-        protocol::keyring::keypair::KeyPairing {
-            sign: self.sign.clone(),
-            update: self.update.clone(),
-            recovery: self.recovery.clone(),
-            encrypt: self.encrypt.clone(),
-        }
+    /// DIDの作成に利用する公開鍵の組
+    pub fn get_keyring(&self) -> Result<PublicKeyPairing, KeyPairingError> {
+        self.public_keys
+            .to_pairing()
+            .ok_or(KeyPairingError::RecoveryKeyNotFound)
     }
 
     pub fn save(&mut self, did: &str) {
-        self.secure_keystore
-            .write(&SecureKeyStoreKey::Sign(&self.sign));
-        self.secure_keystore
-            .write(&SecureKeyStoreKey::Update(&self.update));
-        self.secure_keystore
-            .write(&SecureKeyStoreKey::Recovery(&self.recovery));
-        self.secure_keystore
-            .write(&SecureKeyStoreKey::Encrypt(&self.encrypt));
-        {
-            let mut config = self.config.lock();
-            config.save_did(did);
-            config.save_is_initialized(true);
-        }
+        let mut config = self.config.lock();
+        config.save_did(did);
+        config.save_is_initialized(true);
     }
 
     pub fn get_identifier(&self) -> Result<String, KeyPairingError> {
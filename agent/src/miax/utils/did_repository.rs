@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use home_config::HomeConfig;
+use protocol::did::cached_did_repository::{CachedDidRepository, DidCacheConfig};
+use protocol::did::did_key::DidKeyResolver;
+use protocol::did::did_peer::DidPeerResolver;
+use protocol::did::did_repository::DidRepositoryImpl;
+use protocol::did::did_web::DidWebResolver;
+use protocol::did::resolver::DidResolverRouter;
+
+use crate::miax::utils::did_web_client::DidWebClient;
+use crate::miax::utils::sidetree_client::SideTreeClient;
+use crate::server_config;
+
+pub type AgentDidRepository = DidResolverRouter<
+    CachedDidRepository<DidRepositoryImpl<SideTreeClient>>,
+    (
+        DidKeyResolver,
+        (DidPeerResolver, DidWebResolver<DidWebClient>),
+    ),
+>;
+
+const APP_NAME: &str = "miax";
+const CACHE_FILE: &str = "did_cache.json";
+
+/// エージェントで利用するDidRepositoryを生成する
+/// 解決したDIDは設定ディレクトリに永続化し、Sidetreeに接続できない間も既知のDIDを検証できるようにする
+/// did:key・did:peer・did:webのDIDは、Sidetreeに問い合わせずに解決する
+pub fn did_repository() -> anyhow::Result<AgentDidRepository> {
+    let server_config = server_config();
+    let sidetree_client = SideTreeClient::new(&server_config.did_http_endpoint())?;
+    let cache_path = HomeConfig::with_config_dir(APP_NAME, CACHE_FILE)
+        .path()
+        .to_path_buf();
+    let config = DidCacheConfig {
+        ttl: Duration::from_secs(server_config.did_cache_ttl()),
+        persist_path: Some(cache_path),
+        ..Default::default()
+    };
+    let repository = CachedDidRepository::new(DidRepositoryImpl::new(sidetree_client), config);
+    let resolvers = (
+        DidKeyResolver,
+        (DidPeerResolver, DidWebResolver::new(DidWebClient::new())),
+    );
+    Ok(DidResolverRouter::new(repository, resolvers))
+}
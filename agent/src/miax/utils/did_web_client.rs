@@ -0,0 +1,131 @@
+use protocol::did::did_web::DidWebFetcher;
+
+#[derive(Clone, Default)]
+pub struct DidWebClient {
+    client: reqwest::Client,
+}
+
+impl DidWebClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DidWebClientError {
+    #[error("reqwest error: {0:?}")]
+    ReqwestError(#[from] reqwest::Error),
+    #[error("http error: {0}")]
+    HttpError(#[from] http::Error),
+}
+
+impl DidWebFetcher for DidWebClient {
+    type Error = DidWebClientError;
+
+    async fn fetch(&self, url: &str) -> Result<http::Response<String>, Self::Error> {
+        let response = self
+            .client
+            .get(url)
+            .header("Accept", "application/did+json, application/json")
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body = response.text().await?;
+
+        Ok(http::Response::builder().status(status).body(body)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::did::did_web::DidWebResolver;
+    use protocol::did::resolution::ResolutionError;
+    use protocol::did::resolver::DidResolver;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+
+    /// ディレクトリ内のファイルを返す静的ファイルサーバーを起動し、ポート番号を返す
+    fn serve_dir(root: PathBuf) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                // リクエストヘッダーを読み捨てる
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request_line.split(' ').nth(1).unwrap_or("/");
+                let (status, body) = match std::fs::read(root.join(path.trim_start_matches('/'))) {
+                    Ok(body) => ("200 OK", body),
+                    Err(_) => ("404 Not Found", vec![]),
+                };
+                let header = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/did+json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = stream.write_all(header.as_bytes());
+                let _ = stream.write_all(&body);
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_resolve_from_static_file_server() {
+        let root = std::env::temp_dir().join(format!("miax-did-web-test-{}", std::process::id()));
+        std::fs::create_dir_all(root.join(".well-known")).unwrap();
+        std::fs::create_dir_all(root.join("user/alice")).unwrap();
+        let port = serve_dir(root.clone());
+
+        let domain = format!("did:web:127.0.0.1%3A{}", port);
+        let alice = format!("{}:user:alice", domain);
+        for (did, path) in [
+            (&domain, root.join(".well-known/did.json")),
+            (&alice, root.join("user/alice/did.json")),
+        ] {
+            let document = serde_json::json!({
+                "@context": "https://www.w3.org/ns/did/v1",
+                "id": did,
+                "verificationMethod": [{
+                    "id": "#key-1",
+                    "type": "JsonWebKey2020",
+                    "controller": did,
+                    "publicKeyJwk": {
+                        "kty": "OKP",
+                        "crv": "X25519",
+                        "x": "bl_3kgKpz9jgsg350CNuHa_kQL3B60Gi-98WmdQW2h8",
+                    },
+                }],
+                "keyAgreement": ["#key-1"],
+            });
+            std::fs::write(path, document.to_string()).unwrap();
+        }
+
+        let resolver = DidWebResolver::with_http(DidWebClient::new());
+        for did in [&domain, &alice] {
+            let result = resolver.resolve(did).await.unwrap();
+            assert_eq!(&result.did_document.unwrap().id, did);
+        }
+        let result = resolver
+            .resolve(&format!("{}:user:bob", domain))
+            .await
+            .unwrap();
+        assert_eq!(
+            result.did_resolution_metadata.error,
+            Some(ResolutionError::NotFound)
+        );
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
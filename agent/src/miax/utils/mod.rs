@@ -1,3 +1,5 @@
 pub mod did_accessor;
+pub mod did_repository;
+pub mod did_web_client;
 pub mod sidetree_client;
 pub mod studio_client;
@@ -27,12 +27,12 @@ pub enum SideTreeClientError {
     ReqwestError(#[from] reqwest::Error),
 }
 
-impl SidetreeHttpClient for SideTreeClient {
-    type Error = SideTreeClientError;
-    async fn post_create_identifier(
+impl SideTreeClient {
+    // create/update等の全ての操作は、同一のエンドポイントに送信される
+    async fn post_operation(
         &self,
         body: &str,
-    ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+    ) -> Result<SidetreeHttpClientResponse, SideTreeClientError> {
         let url = self.base_url.join("/api/v1/operations")?;
 
         let response = self
@@ -50,6 +50,37 @@ impl SidetreeHttpClient for SideTreeClient {
 
         Ok(response)
     }
+}
+
+impl SidetreeHttpClient for SideTreeClient {
+    type Error = SideTreeClientError;
+    async fn post_create_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+        self.post_operation(body).await
+    }
+
+    async fn post_update_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+        self.post_operation(body).await
+    }
+
+    async fn post_recover_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+        self.post_operation(body).await
+    }
+
+    async fn post_deactivate_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+        self.post_operation(body).await
+    }
 
     async fn get_find_identifier(
         &self,
@@ -1,8 +1,7 @@
 use super::did_accessor::{DidAccessor, DidAccessorImpl};
-use crate::miax::utils::sidetree_client::SideTreeClient;
+use super::did_repository::{did_repository, AgentDidRepository};
 use crate::server_config;
 use chrono::Utc;
-use protocol::did::did_repository::DidRepositoryImpl;
 use protocol::didcomm::encrypted::{DidCommEncryptedService, DidCommServiceWithAttachment};
 use protocol::verifiable_credentials::types::VerifiableCredentials;
 use reqwest::{
@@ -18,7 +17,7 @@ pub struct StudioClientConfig {
 pub struct StudioClient {
     pub base_url: Url,
     pub instance: reqwest::Client,
-    pub didcomm_service: DidCommServiceWithAttachment<DidRepositoryImpl<SideTreeClient>>,
+    pub didcomm_service: DidCommServiceWithAttachment<AgentDidRepository>,
     pub did_accessor: DidAccessorImpl,
 }
 
@@ -27,8 +26,7 @@ impl StudioClient {
         let url = Url::parse(&_config.base_url.to_string())?;
         let client = reqwest::Client::new();
         let server_config = server_config();
-        let sidetree_client = SideTreeClient::new(&server_config.did_http_endpoint())?;
-        let did_repository = DidRepositoryImpl::new(sidetree_client);
+        let did_repository = did_repository()?;
         let didcomm_service =
             DidCommServiceWithAttachment::new(did_repository, server_config.did_attachment_link());
         let did_accessor = DidAccessorImpl {};
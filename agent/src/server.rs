@@ -7,8 +7,16 @@ pub fn make_router() -> Router {
             "/create_identifier",
             post(controllers::public::miax_create_identifier::handler),
         )
+        .route(
+            "/rotate_keys",
+            post(controllers::public::miax_rotate_keys::handler),
+        )
         .route(
             "/identifiers/:did",
             get(controllers::public::miax_find_identifier::handler),
         )
+        .route(
+            "/resolve/:did",
+            get(controllers::public::miax_resolve_identifier::handler),
+        )
 }
@@ -1,70 +1,812 @@
 use crate::app_config;
-use crate::config::server_config;
-use crate::miax::extension::secure_keystore::FileBaseKeyStore;
+#[cfg(target_os = "linux")]
+use crate::config::KernelKeyringType;
+use crate::config::{KeyStoreBackend, SingletonAppConfig};
+use crate::miax::extension::encrypted_keystore::{
+    EncryptedFileKeyStore, EncryptedKeyStoreError, KeyStoreSecret,
+};
+#[cfg(target_os = "linux")]
+use crate::miax::extension::kernel_keyring::{KernelKeyringError, KernelKeyringStore};
+#[cfg(feature = "pkcs11")]
+use crate::miax::extension::pkcs11_keystore::{Pkcs11Config, Pkcs11KeyStore};
+use crate::miax::extension::secure_keystore::{
+    FileBaseKeyStore, HdState, PendingPublicKeys, SecureKeyStore, SecureKeyStoreError,
+    SecureKeyStoreKey, SecureKeyStoreType,
+};
 use crate::miax::keyring;
-use crate::miax::utils::sidetree_client::SideTreeClient;
+use crate::miax::utils::did_repository::{did_repository, AgentDidRepository};
+use chrono::{DateTime, Duration, Utc};
 use controller::managers::{
     resource::ResourceManagerTrait,
     runtime::{RuntimeManagerImpl, RuntimeManagerWithoutAsync, State},
 };
 use controller::validator::storage::check_storage;
-use protocol::did::did_repository::{DidRepository, DidRepositoryImpl};
+use protocol::did::did_repository::{
+    encryption_key_payload, get_encrypt_key, get_sign_key, signing_key_payload, DidRepository,
+};
+use protocol::did::sidetree::patch::DidPatchBuilder;
+use protocol::keyring::hd::HdKeyring;
+use protocol::keyring::keypair::{K256KeyPair, KeyPair, X25519KeyPair};
+use protocol::keyring::shamir::{combine_key, split_key, KeyShare};
+use protocol::keyring::signer::Signer;
+use protocol::rand_core::OsRng;
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+use protocol::did::resolution::DidResolutionResult;
+use protocol::did::sidetree::payload::{DidDocument, MiaxDidResponse};
+use std::sync::Mutex;
+
+// ニーモニックから鍵を復元する際に、DIDドキュメントの公開鍵と照合するインデックスの上限
+const MNEMONIC_SCAN_LIMIT: u32 = 1024;
+
+/// 置き換えた暗号化鍵の猶予期間（秒）の既定値 : 7日
+pub const DEFAULT_GRACE_PERIOD_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// 鍵ローテーションの対象と、置き換えた暗号化鍵の猶予期間
+#[derive(Clone, Copy, Debug)]
+pub struct KeyRotation {
+    pub sign: bool,
+    pub encrypt: bool,
+    pub grace_period: Duration,
+}
+
+/// 鍵ローテーションの結果
+#[derive(Debug, Serialize)]
+pub struct RotatedKeys {
+    pub did: String,
+    pub rotated: Vec<SecureKeyStoreType>,
+    /// 置き換えた暗号化鍵の猶予期間の終了日時
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encrypt_key_expires_at: Option<DateTime<Utc>>,
+}
+
+// 設定に応じて選択した鍵ストア
+#[derive(Clone)]
+enum AgentKeyStore {
+    File(FileBaseKeyStore),
+    EncryptedFile(EncryptedFileKeyStore),
+    #[cfg(feature = "pkcs11")]
+    Pkcs11(Pkcs11KeyStore),
+    #[cfg(target_os = "linux")]
+    KernelKeyring(KernelKeyringStore),
+}
+
+// 開いた鍵ストア。パスフレーズからの暗号化鍵の導出（Argon2id）は重いため、プロセス内で一度だけ行う
+static KEYSTORE: Mutex<Option<AgentKeyStore>> = Mutex::new(None);
+
+fn open_keystore(config: Box<SingletonAppConfig>) -> anyhow::Result<AgentKeyStore> {
+    let mut cached = KEYSTORE.lock().unwrap();
+    if let Some(keystore) = cached.as_ref() {
+        return Ok(keystore.clone());
+    }
+
+    let keystore_config = config.lock().keystore_config();
+    let keystore = match keystore_config.backend {
+        KeyStoreBackend::File => open_file_keystore(config)?,
+        #[cfg(feature = "pkcs11")]
+        KeyStoreBackend::Pkcs11 => open_pkcs11_keystore(config)?,
+        #[cfg(not(feature = "pkcs11"))]
+        KeyStoreBackend::Pkcs11 => {
+            anyhow::bail!("PKCS#11 keystore is not supported. build with the pkcs11 feature")
+        }
+        #[cfg(target_os = "linux")]
+        KeyStoreBackend::KernelKeyring => {
+            open_kernel_keyring(config, keystore_config.kernel_keyring)?
+        }
+        #[cfg(not(target_os = "linux"))]
+        KeyStoreBackend::KernelKeyring => {
+            log::warn!("kernel keyring is only supported on Linux, using file keystore");
+            open_file_keystore(config)?
+        }
+    };
+    *cached = Some(keystore.clone());
+    Ok(keystore)
+}
+
+fn open_file_keystore(config: Box<SingletonAppConfig>) -> anyhow::Result<AgentKeyStore> {
+    let config_dir = config.lock().config_dir();
+    Ok(match KeyStoreSecret::from_env(&config_dir)? {
+        Some(secret) => AgentKeyStore::EncryptedFile(EncryptedFileKeyStore::new(config, secret)?),
+        // 暗号化した鍵ストアを作成済みの場合は、平文の鍵ストアで新しい鍵を生成しないようエラーとする
+        None if EncryptedFileKeyStore::exists(&config) => {
+            return Err(EncryptedKeyStoreError::SecretNotConfigured.into())
+        }
+        // 鍵ストアの秘密が設定されるまでは、既存の環境と同じく設定ファイルに保存する
+        None => {
+            log::warn!("keystore secret is not configured, key pairs are stored in plaintext");
+            AgentKeyStore::File(FileBaseKeyStore::new(config))
+        }
+    })
+}
+
+// トークンに鍵がない場合は、ファイルの鍵ストアの鍵をトークンに移行する
+// 暗号化したファイルの鍵ストアは、移行する場合のみ開くため、移行後は鍵ストアの秘密を必要としない
+#[cfg(feature = "pkcs11")]
+fn open_pkcs11_keystore(config: Box<SingletonAppConfig>) -> anyhow::Result<AgentKeyStore> {
+    let keystore = Pkcs11KeyStore::new(&Pkcs11Config::from_env()?)?;
+    if keystore.read_public_keys().is_none() {
+        if EncryptedFileKeyStore::exists(&config) {
+            let config_dir = config.lock().config_dir();
+            let secret = KeyStoreSecret::from_env(&config_dir)?
+                .ok_or(EncryptedKeyStoreError::SecretNotConfigured)?;
+            let legacy = EncryptedFileKeyStore::new(config, secret)?;
+            if keystore.migrate(&legacy)? {
+                legacy.remove()?;
+            }
+        } else {
+            let legacy = FileBaseKeyStore::new(config);
+            if keystore.migrate(&legacy)? {
+                legacy.clear();
+            }
+        }
+    }
+    Ok(AgentKeyStore::Pkcs11(keystore))
+}
+
+// カーネルキーリングは再起動で消去されるため、暗号化ファイル鍵ストアを永続化したコピーとして利用する
+// 鍵ストアの秘密が設定されていない場合は、コピーを保存できないため鍵を移行しない
+#[cfg(target_os = "linux")]
+fn open_kernel_keyring(
+    config: Box<SingletonAppConfig>,
+    keyring: KernelKeyringType,
+) -> anyhow::Result<AgentKeyStore> {
+    if !KernelKeyringStore::is_available(keyring) {
+        log::warn!("falling back to file keystore");
+        return open_file_keystore(config);
+    }
+    let config_dir = config.lock().config_dir();
+    let secret = KeyStoreSecret::from_env(&config_dir)?
+        .ok_or(KernelKeyringError::SealedCopyNotConfigured)?;
+    // 設定ファイルに平文で保存された鍵は、暗号化したコピーに移行してからカーネルキーリングに読み込む
+    let sealed = EncryptedFileKeyStore::new(config, secret)?;
+    Ok(AgentKeyStore::KernelKeyring(KernelKeyringStore::new(
+        keyring, sealed,
+    )?))
+}
+
+fn export_mnemonic_with<S: SecureKeyStore>(keystore: &S) -> anyhow::Result<HdState> {
+    keystore.read_hd_state().ok_or(anyhow::anyhow!(
+        "mnemonic is not in the keystore, keys are not derived from a mnemonic or it was removed"
+    ))
+}
+
+fn combine_recovery_key(shares: &[String]) -> anyhow::Result<K256KeyPair> {
+    let shares = shares
+        .iter()
+        .map(|share| KeyShare::decode(share))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(combine_key(&shares)?)
+}
+
+fn export_recovery_key_with<S: SecureKeyStore>(
+    keystore: &S,
+    threshold: u8,
+    shares: u8,
+    remove: bool,
+    remove_mnemonic: bool,
+) -> anyhow::Result<Vec<Zeroizing<String>>> {
+    let recovery_key = keystore
+        .read_recovery()
+        .ok_or(anyhow::anyhow!("recovery key is not available"))?;
+    // ニーモニックからリカバリ鍵を導出できるため、ニーモニックを残したままではリカバリ鍵を削除しても意味がない
+    // ニーモニックの削除は元に戻せないため、明示的に指定された場合のみ削除する
+    let has_mnemonic = keystore.read_hd_state().is_some();
+    if remove && has_mnemonic && !remove_mnemonic {
+        anyhow::bail!(
+            "the recovery key can be derived from the mnemonic in the keystore. export the mnemonic and pass --remove-mnemonic to remove both"
+        );
+    }
+    let shares = split_key(OsRng, &recovery_key, threshold, shares)?;
+
+    // 削除する前に、シェアから同じ鍵を復元できることを確かめる
+    let threshold = usize::from(threshold);
+    if combine_key(&shares[..threshold])?.get_public_key() != recovery_key.get_public_key() {
+        anyhow::bail!("failed to verify shares");
+    }
+    if remove {
+        // 以降にローテーションした鍵はニーモニックから導出しないため、事前にニーモニックをエクスポートしておくこと
+        if has_mnemonic {
+            keystore.write_hd_state(None)?;
+            log::warn!(
+                "removed the mnemonic from the keystore, keys rotated from now on are not derived from it"
+            );
+        }
+        keystore.remove_recovery()?;
+        if keystore.read_recovery().is_some() {
+            anyhow::bail!("failed to remove recovery key from the keystore");
+        }
+    }
 
-use protocol::did::sidetree::payload::MiaxDidResponse;
+    Ok(shares.iter().map(KeyShare::encode).collect())
+}
+
+fn import_recovery_key_with<S: SecureKeyStore>(
+    keystore: &S,
+    recovery_key: &K256KeyPair,
+) -> anyhow::Result<()> {
+    // 鍵ストアの公開鍵と一致しないリカバリ鍵は保存しない
+    if let Some(current) = keystore.read_recovery() {
+        if current.get_public_key() != recovery_key.get_public_key() {
+            anyhow::bail!("keystore already has a different recovery key");
+        }
+    }
+    keystore.write(&SecureKeyStoreKey::Recovery(recovery_key))?;
+    if keystore.read_recovery().is_none() {
+        anyhow::bail!("failed to import recovery key into the keystore");
+    }
+    Ok(())
+}
+
+// ニーモニックから導出した鍵を鍵ストアに保存する
+// 署名鍵・暗号化鍵は、ローテーションしたかどうかでインデックスが異なるため、DIDドキュメントの公開鍵と照合して求める
+// 更新鍵は全てのローテーション・recover操作で導出し直すため、署名鍵・暗号化鍵のうち新しい方のインデックスとなる
+// リカバリ鍵はDIDドキュメントから照合できないため、指定したインデックスから導出する
+fn import_mnemonic_with<S: SecureKeyStore>(
+    keystore: &S,
+    hd: &HdKeyring,
+    document: &DidDocument,
+    recovery_index: u32,
+) -> anyhow::Result<()> {
+    // 反映待ちの鍵がある場合、update操作・recover操作が後から反映されると復元した鍵が無効になる
+    if keystore.read_pending().is_some() {
+        anyhow::bail!("pending keys are in the keystore, retry the key rotation or recovery first");
+    }
+    let sign_key = get_sign_key(document)?;
+    let encrypt_key = get_encrypt_key(document)?;
+    let sign_index = (0..MNEMONIC_SCAN_LIMIT)
+        .find(|&index| {
+            hd.derive_sign(sign_key.key_type(), index)
+                .is_ok_and(|key| key.get_public_key() == sign_key)
+        })
+        .ok_or(anyhow::anyhow!(
+            "signing key of the DID is not derived from the mnemonic"
+        ))?;
+    let encrypt_index = (0..MNEMONIC_SCAN_LIMIT)
+        .find(|&index| {
+            hd.derive_encrypt(index)
+                .is_ok_and(|key| key.get_public_key() == encrypt_key)
+        })
+        .ok_or(anyhow::anyhow!(
+            "encryption key of the DID is not derived from the mnemonic"
+        ))?;
+    let index = sign_index.max(encrypt_index);
+    if recovery_index > index {
+        anyhow::bail!(
+            "recovery index must not be greater than the index of the latest keys ({})",
+            index
+        );
+    }
+
+    keystore.write(&SecureKeyStoreKey::Sign(
+        &hd.derive_sign(sign_key.key_type(), sign_index)?,
+    ))?;
+    keystore.write(&SecureKeyStoreKey::Update(&hd.derive_update(index)?))?;
+    keystore.write(&SecureKeyStoreKey::Recovery(
+        &hd.derive_recovery(recovery_index)?,
+    ))?;
+    keystore.write(&SecureKeyStoreKey::Encrypt(
+        &hd.derive_encrypt(encrypt_index)?,
+    ))?;
+    let state = HdState {
+        mnemonic: hd.mnemonic().as_str().to_owned(),
+        index,
+        recovery_index,
+    };
+    match keystore.write_hd_state(Some(&state)) {
+        Err(SecureKeyStoreError::Unsupported(_)) => {
+            log::warn!("HD keyring is not supported by this keystore, the mnemonic is not kept")
+        }
+        result => result?,
+    }
+    Ok(())
+}
+
+fn decryption_keys_with<S: SecureKeyStore>(keystore: &S) -> Vec<X25519KeyPair> {
+    let now = Utc::now();
+    let mut history = keystore.read_key_history();
+    // 猶予期間を過ぎた鍵ペアは、ここで鍵ストアから削除する
+    if history.prune(now) {
+        if let Err(e) = keystore.write_key_history(&history) {
+            log::error!("failed to prune key history: {}", e);
+        }
+    }
+    keystore
+        .read_encrypt()
+        .into_iter()
+        .chain(history.grace_encrypt_keys(now))
+        .collect()
+}
+
+// 反映待ちの署名鍵・暗号化鍵が、DIDドキュメントに登録されているか
+fn pending_keys_applied(pending: &PendingPublicKeys, document: &DidDocument) -> bool {
+    let sign_applied = pending
+        .sign
+        .is_some_and(|sign| get_sign_key(document).is_ok_and(|key| key == sign));
+    let encrypt_applied = pending
+        .encrypt
+        .is_some_and(|encrypt| get_encrypt_key(document).is_ok_and(|key| key == encrypt));
+    sign_applied || encrypt_applied
+}
+
+// 反映待ちの鍵で現在の鍵を置き換え、置き換えた鍵を履歴に保存する
+// 置き換えた鍵の種類と、置き換えた暗号化鍵の猶予期間の終了日時を返す
+fn promote_pending_keys<S: SecureKeyStore>(
+    keystore: &S,
+    pending: &PendingPublicKeys,
+    grace_period: Duration,
+) -> anyhow::Result<(Vec<SecureKeyStoreType>, Option<DateTime<Utc>>)> {
+    // 秘密鍵を取り出せない鍵ストアもあるため、署名鍵・更新鍵は公開鍵のみを履歴に残す
+    let public_key = |key_type| keystore.signer(key_type).map(|s| s.public_key());
+    let old_sign = public_key(SecureKeyStoreType::Sign);
+    let old_update = public_key(SecureKeyStoreType::Update);
+    let old_encrypt = keystore.read_encrypt();
+    keystore.promote_pending()?;
+
+    let now = Utc::now();
+    let mut history = keystore.read_key_history();
+    let mut rotated = vec![];
+    let mut encrypt_key_expires_at = None;
+    if pending.update.is_some() {
+        if let Some(old_update) = old_update {
+            history.retire(SecureKeyStoreType::Update, &old_update, now);
+        }
+        rotated.push(SecureKeyStoreType::Update);
+    }
+    if pending.sign.is_some() {
+        if let Some(old_sign) = old_sign {
+            history.retire(SecureKeyStoreType::Sign, &old_sign, now);
+        }
+        rotated.push(SecureKeyStoreType::Sign);
+    }
+    if pending.encrypt.is_some() {
+        if let Some(old_encrypt) = old_encrypt {
+            let expires_at = now + grace_period;
+            history.retire_encrypt(old_encrypt.to_hex_key_pair(), now, expires_at);
+            encrypt_key_expires_at = Some(expires_at);
+        }
+        rotated.push(SecureKeyStoreType::Encrypt);
+    }
+    history.prune(now);
+    keystore.write_key_history(&history)?;
+
+    Ok((rotated, encrypt_key_expires_at))
+}
 
 pub struct MiaX {
-    did_repository: DidRepositoryImpl<SideTreeClient>,
+    did_repository: AgentDidRepository,
 }
 
 impl MiaX {
     pub fn new() -> Self {
-        let server_config = server_config();
-        let sidetree_client = SideTreeClient::new(&server_config.did_http_endpoint()).unwrap();
-        let did_repository = DidRepositoryImpl::new(sidetree_client);
+        let did_repository = did_repository().unwrap();
 
         MiaX { did_repository }
     }
 
-    pub fn did_repository(&self) -> &DidRepositoryImpl<SideTreeClient> {
+    pub fn did_repository(&self) -> &AgentDidRepository {
         &self.did_repository
     }
 
     pub async fn create_identifier(&self) -> anyhow::Result<MiaxDidResponse> {
         //  設定とキーストアの準備
-        let config = app_config();
-        let keystore = FileBaseKeyStore::new(config.clone());
-
-        // 既存のDIDがあるかチェック
-        if let Some(did) =
-            keyring::keypair::KeyPairingWithConfig::load_keyring(config.clone(), keystore.clone())
-                .ok()
-                .and_then(|v| v.get_identifier().ok())
-        {
-            if let Some(json) = self.find_identifier(&did).await? {
-                return Ok(json);
+        let config = app_config()?;
+        match open_keystore(config.clone())? {
+            AgentKeyStore::File(keystore) => self.create_identifier_with(config, &keystore).await,
+            AgentKeyStore::EncryptedFile(keystore) => {
+                self.create_identifier_with(config, &keystore).await
+            }
+            #[cfg(feature = "pkcs11")]
+            AgentKeyStore::Pkcs11(keystore) => self.create_identifier_with(config, &keystore).await,
+            #[cfg(target_os = "linux")]
+            AgentKeyStore::KernelKeyring(keystore) => {
+                self.create_identifier_with(config, &keystore).await
             }
         }
+    }
+
+    async fn create_identifier_with<S: SecureKeyStore>(
+        &self,
+        config: Box<SingletonAppConfig>,
+        keystore: &S,
+    ) -> anyhow::Result<MiaxDidResponse> {
+        // 作成済みのDIDがある場合は、鍵ストアの状態に関わらず新しいDIDを作成しない
+        // リカバリ鍵をエクスポートして削除した後も、同じDIDを利用し続ける
+        let recorded_did = config.lock().get_did();
+        if recorded_did.is_some() {
+            let keyring_with_config = keyring::keypair::KeyPairingWithConfig::load_keyring(
+                config, keystore,
+            )
+            .map_err(|e| {
+                anyhow::anyhow!(
+                    "keys of the created DID are not in the keystore ({}), re-provision the keys",
+                    e
+                )
+            })?;
+            let did = keyring_with_config.get_identifier()?;
+            return self
+                .find_identifier(&did)
+                .await?
+                .ok_or(anyhow::anyhow!("DID is not found: {}", did));
+        }
 
         // 新規DIDを生成
         let mut keyring_with_config =
-            keyring::keypair::KeyPairingWithConfig::create_keyring(config, keystore);
+            keyring::keypair::KeyPairingWithConfig::create_keyring(config, keystore)?;
 
-        // キーペアを保存しDIDを返却
+        // DIDを保存し返却
         let res = self
             .did_repository
-            .create_identifier(keyring_with_config.get_keyring())
+            .create_identifier(keyring_with_config.get_keyring()?)
             .await?;
         keyring_with_config.save(&res.did_document.id);
 
         Ok(res)
     }
 
+    /// 鍵を導出したニーモニックと、最後に鍵を導出したインデックスを返す
+    pub fn export_mnemonic(&self) -> anyhow::Result<HdState> {
+        match open_keystore(app_config()?)? {
+            AgentKeyStore::File(keystore) => export_mnemonic_with(&keystore),
+            AgentKeyStore::EncryptedFile(keystore) => export_mnemonic_with(&keystore),
+            #[cfg(feature = "pkcs11")]
+            AgentKeyStore::Pkcs11(keystore) => export_mnemonic_with(&keystore),
+            #[cfg(target_os = "linux")]
+            AgentKeyStore::KernelKeyring(keystore) => export_mnemonic_with(&keystore),
+        }
+    }
+
+    /// ニーモニックからDIDの鍵を復元して鍵ストアに保存し、DIDを返す
+    ///
+    /// `did`を指定しない場合は、設定ファイルのDIDの鍵を復元する
+    /// リカバリ鍵は、recover操作の回数に応じた`recovery_index`から導出する
+    pub async fn import_mnemonic(
+        &self,
+        did: Option<&str>,
+        mnemonic: &str,
+        recovery_index: u32,
+    ) -> anyhow::Result<String> {
+        let hd = HdKeyring::from_mnemonic(mnemonic, "")?;
+        let config = app_config()?;
+        let recorded_did = config.lock().get_did();
+        let did = match (did, recorded_did) {
+            (Some(did), Some(recorded_did)) if did != recorded_did => {
+                anyhow::bail!("another DID is recorded: {}", recorded_did)
+            }
+            (Some(did), _) => did.to_string(),
+            (None, Some(recorded_did)) => recorded_did,
+            (None, None) => anyhow::bail!("DID is not given"),
+        };
+        let document = self
+            .find_identifier(&did)
+            .await?
+            .ok_or(anyhow::anyhow!("DID is not found: {}", did))?
+            .did_document;
+
+        match open_keystore(config.clone())? {
+            AgentKeyStore::File(keystore) => {
+                import_mnemonic_with(&keystore, &hd, &document, recovery_index)?
+            }
+            AgentKeyStore::EncryptedFile(keystore) => {
+                import_mnemonic_with(&keystore, &hd, &document, recovery_index)?
+            }
+            #[cfg(feature = "pkcs11")]
+            AgentKeyStore::Pkcs11(keystore) => {
+                import_mnemonic_with(&keystore, &hd, &document, recovery_index)?
+            }
+            #[cfg(target_os = "linux")]
+            AgentKeyStore::KernelKeyring(keystore) => {
+                import_mnemonic_with(&keystore, &hd, &document, recovery_index)?
+            }
+        }
+
+        let mut config = config.lock();
+        config.save_did(&did);
+        config.save_is_initialized(true);
+        Ok(did)
+    }
+
+    /// 署名鍵・暗号化鍵をローテーションし、Sidetreeのupdate操作でDIDドキュメントに反映する
+    ///
+    /// update操作では更新鍵のコミットメントも更新されるため、更新鍵は常にローテーションする
+    /// 置き換えた暗号化鍵は、送信中のDIDCommメッセージを復号できるよう猶予期間の間は保持する
+    /// 新しい鍵はupdate操作が成功するまで反映待ちとして保存し、成功後に現在の鍵と置き換える
+    pub async fn rotate_keys(&self, rotation: KeyRotation) -> anyhow::Result<RotatedKeys> {
+        if !rotation.sign && !rotation.encrypt {
+            anyhow::bail!("no key to rotate");
+        }
+        let config = app_config()?;
+        match open_keystore(config.clone())? {
+            AgentKeyStore::File(keystore) => {
+                self.rotate_keys_with(config, &keystore, rotation).await
+            }
+            AgentKeyStore::EncryptedFile(keystore) => {
+                self.rotate_keys_with(config, &keystore, rotation).await
+            }
+            #[cfg(feature = "pkcs11")]
+            AgentKeyStore::Pkcs11(keystore) => {
+                self.rotate_keys_with(config, &keystore, rotation).await
+            }
+            #[cfg(target_os = "linux")]
+            AgentKeyStore::KernelKeyring(keystore) => {
+                self.rotate_keys_with(config, &keystore, rotation).await
+            }
+        }
+    }
+
+    async fn rotate_keys_with<S: SecureKeyStore + Sync>(
+        &self,
+        config: Box<SingletonAppConfig>,
+        keystore: &S,
+        rotation: KeyRotation,
+    ) -> anyhow::Result<RotatedKeys> {
+        let did = config
+            .lock()
+            .get_did()
+            .ok_or(anyhow::anyhow!("DID is not created"))?;
+        let document = self
+            .find_identifier(&did)
+            .await?
+            .ok_or(anyhow::anyhow!("DID is not found: {}", did))?
+            .did_document;
+
+        // 前回のローテーションで反映待ちのまま残った鍵
+        // DIDドキュメントに反映済みであれば現在の鍵とする。未反映の場合、update操作が後から反映される
+        // 可能性があるため破棄せず、同じ鍵でupdate操作を送り直す
+        let pending = match keystore.read_pending() {
+            Some(pending) if pending_keys_applied(&pending, &document) => {
+                log::warn!("previous key rotation is applied, replacing keys with pending keys");
+                promote_pending_keys(keystore, &pending, rotation.grace_period)?;
+                None
+            }
+            // 全ての鍵を置き換えるrecover操作の鍵は、update操作では反映できない
+            Some(pending) if pending.recovery.is_some() => {
+                anyhow::bail!("previous recovery is not applied, retry the recovery first");
+            }
+            Some(pending) => {
+                log::warn!("previous key rotation is not applied, retrying with pending keys");
+                Some(pending)
+            }
+            None => None,
+        };
+
+        // PKCS#11トークンの鍵など、秘密鍵を取り出せない更新鍵でも署名できるよう鍵ストアのSignerを利用する
+        let update_key = keystore
+            .signer(SecureKeyStoreType::Update)
+            .ok_or(anyhow::anyhow!("update key is not available"))?;
+
+        // 新しい鍵は反映待ちとして保存し、update操作が成功するまで現在の鍵を置き換えない
+        let pending = match pending {
+            Some(pending) => pending,
+            None => {
+                let mut key_types = vec![SecureKeyStoreType::Update];
+                if rotation.sign {
+                    key_types.push(SecureKeyStoreType::Sign);
+                }
+                if rotation.encrypt {
+                    key_types.push(SecureKeyStoreType::Encrypt);
+                }
+                let sign_key_type = config.lock().keystore_config().sign_key_type;
+                keystore.generate_pending(&key_types, sign_key_type)?
+            }
+        };
+        let new_update_key = pending
+            .update
+            .ok_or(anyhow::anyhow!("pending update key is not found"))?;
+
+        // 同じIDの公開鍵を追加することで、既存の公開鍵を置き換える
+        let mut public_keys = vec![];
+        if let Some(sign) = &pending.sign {
+            public_keys.push(signing_key_payload(sign)?);
+        }
+        if let Some(encrypt) = &pending.encrypt {
+            public_keys.push(encryption_key_payload(encrypt)?);
+        }
+        let patches = DidPatchBuilder::new().add_public_keys(public_keys)?.build();
+
+        // 失敗した場合は反映待ちの鍵を残し、次回のローテーションで反映されたかを確かめる
+        self.did_repository
+            .update_identifier(&did, &update_key, new_update_key, patches)
+            .await?;
+
+        let (rotated, encrypt_key_expires_at) =
+            promote_pending_keys(keystore, &pending, rotation.grace_period)?;
+
+        Ok(RotatedKeys {
+            did,
+            rotated,
+            encrypt_key_expires_at,
+        })
+    }
+
+    /// DIDCommメッセージの復号に利用する暗号化鍵を返す
+    ///
+    /// 現在の暗号化鍵に続き、猶予期間中の暗号化鍵を新しい順に返す
+    pub fn decryption_keys(&self) -> anyhow::Result<Vec<X25519KeyPair>> {
+        Ok(match open_keystore(app_config()?)? {
+            AgentKeyStore::File(keystore) => decryption_keys_with(&keystore),
+            AgentKeyStore::EncryptedFile(keystore) => decryption_keys_with(&keystore),
+            #[cfg(feature = "pkcs11")]
+            AgentKeyStore::Pkcs11(keystore) => decryption_keys_with(&keystore),
+            #[cfg(target_os = "linux")]
+            AgentKeyStore::KernelKeyring(keystore) => decryption_keys_with(&keystore),
+        })
+    }
+
+    /// リカバリ鍵をShamirの秘密分散法で`shares`個のシェアに分割してエクスポートする
+    ///
+    /// 復元には`threshold`個のシェアが必要。`remove`を指定した場合は、エクスポート後に鍵ストアからリカバリ鍵を削除する
+    /// 鍵ストアにニーモニックがある場合、リカバリ鍵を削除するには`remove_mnemonic`でニーモニックの削除も指定する
+    pub fn export_recovery_key(
+        &self,
+        threshold: u8,
+        shares: u8,
+        remove: bool,
+        remove_mnemonic: bool,
+    ) -> anyhow::Result<Vec<Zeroizing<String>>> {
+        match open_keystore(app_config()?)? {
+            AgentKeyStore::File(keystore) => {
+                export_recovery_key_with(&keystore, threshold, shares, remove, remove_mnemonic)
+            }
+            AgentKeyStore::EncryptedFile(keystore) => {
+                export_recovery_key_with(&keystore, threshold, shares, remove, remove_mnemonic)
+            }
+            #[cfg(feature = "pkcs11")]
+            AgentKeyStore::Pkcs11(keystore) => {
+                export_recovery_key_with(&keystore, threshold, shares, remove, remove_mnemonic)
+            }
+            #[cfg(target_os = "linux")]
+            AgentKeyStore::KernelKeyring(keystore) => {
+                export_recovery_key_with(&keystore, threshold, shares, remove, remove_mnemonic)
+            }
+        }
+    }
+
+    /// シェアから復元したリカバリ鍵を鍵ストアに保存する
+    pub fn import_recovery_key(&self, shares: &[String]) -> anyhow::Result<()> {
+        let recovery_key = combine_recovery_key(shares)?;
+        match open_keystore(app_config()?)? {
+            AgentKeyStore::File(keystore) => import_recovery_key_with(&keystore, &recovery_key),
+            AgentKeyStore::EncryptedFile(keystore) => {
+                import_recovery_key_with(&keystore, &recovery_key)
+            }
+            #[cfg(feature = "pkcs11")]
+            AgentKeyStore::Pkcs11(keystore) => import_recovery_key_with(&keystore, &recovery_key),
+            #[cfg(target_os = "linux")]
+            AgentKeyStore::KernelKeyring(keystore) => {
+                import_recovery_key_with(&keystore, &recovery_key)
+            }
+        }
+    }
+
+    /// recover操作でDIDの全ての鍵を新しい鍵に置き換える
+    ///
+    /// `shares`を指定した場合は、鍵ストアのリカバリ鍵の代わりにシェアから復元したリカバリ鍵で署名する
+    /// 新しいリカバリ鍵は鍵ストアに保存されるため、デバイスに残さない場合は再度エクスポートすること
+    pub async fn recover_identifier(&self, shares: &[String]) -> anyhow::Result<MiaxDidResponse> {
+        let recovery_key = if shares.is_empty() {
+            None
+        } else {
+            Some(combine_recovery_key(shares)?)
+        };
+        let config = app_config()?;
+        match open_keystore(config.clone())? {
+            AgentKeyStore::File(keystore) => {
+                self.recover_identifier_with(config, &keystore, recovery_key)
+                    .await
+            }
+            AgentKeyStore::EncryptedFile(keystore) => {
+                self.recover_identifier_with(config, &keystore, recovery_key)
+                    .await
+            }
+            #[cfg(feature = "pkcs11")]
+            AgentKeyStore::Pkcs11(keystore) => {
+                self.recover_identifier_with(config, &keystore, recovery_key)
+                    .await
+            }
+            #[cfg(target_os = "linux")]
+            AgentKeyStore::KernelKeyring(keystore) => {
+                self.recover_identifier_with(config, &keystore, recovery_key)
+                    .await
+            }
+        }
+    }
+
+    async fn recover_identifier_with<S: SecureKeyStore + Sync>(
+        &self,
+        config: Box<SingletonAppConfig>,
+        keystore: &S,
+        recovery_key: Option<K256KeyPair>,
+    ) -> anyhow::Result<MiaxDidResponse> {
+        let did = config
+            .lock()
+            .get_did()
+            .ok_or(anyhow::anyhow!("DID is not created"))?;
+        let response = self
+            .find_identifier(&did)
+            .await?
+            .ok_or(anyhow::anyhow!("DID is not found: {}", did))?;
+
+        // 前回のrecover操作の鍵が反映待ちのまま残っている場合、DIDドキュメントに反映済みであれば現在の鍵とする
+        // 未反映の場合、recover操作が後から反映される可能性があるため破棄せず、同じ鍵で送り直す
+        // recover操作は鍵の漏洩時に利用するため、置き換えた鍵は履歴に残さない
+        let previous = keystore
+            .read_pending()
+            .filter(|pending| pending.to_pairing().is_some());
+        if let Some(pending) = &previous {
+            if pending_keys_applied(pending, &response.did_document) {
+                log::warn!("previous recovery is applied, replacing keys with pending keys");
+                keystore.promote_pending()?;
+                return Ok(response);
+            }
+        }
+
+        // シェアから復元したリカバリ鍵がない場合は、鍵ストアのリカバリ鍵のSignerで署名する
+        // 新しい鍵を生成する前に、リカバリ鍵を利用できることを確かめる
+        if recovery_key.is_none() && keystore.signer(SecureKeyStoreType::Recovery).is_none() {
+            anyhow::bail!("recovery key is not available");
+        }
+        let pending = match previous {
+            Some(pending) => {
+                log::warn!("previous recovery is not applied, retrying with pending keys");
+                pending
+            }
+            // 新しい鍵は反映待ちとして保存し、recover操作が成功するまで現在の鍵を置き換えない
+            None => {
+                let sign_key_type = config.lock().keystore_config().sign_key_type;
+                keystore.generate_pending(
+                    &[
+                        SecureKeyStoreType::Sign,
+                        SecureKeyStoreType::Update,
+                        SecureKeyStoreType::Recovery,
+                        SecureKeyStoreType::Encrypt,
+                    ],
+                    sign_key_type,
+                )?
+            }
+        };
+        let new_keyring = pending
+            .to_pairing()
+            .ok_or(anyhow::anyhow!("pending keys are incomplete"))?;
+
+        match &recovery_key {
+            Some(recovery_key) => {
+                self.did_repository
+                    .recover_identifier(&did, recovery_key, new_keyring)
+                    .await?
+            }
+            None => {
+                let recovery_key = keystore
+                    .signer(SecureKeyStoreType::Recovery)
+                    .ok_or(anyhow::anyhow!("recovery key is not available"))?;
+                self.did_repository
+                    .recover_identifier(&did, &recovery_key, new_keyring)
+                    .await?
+            }
+        };
+
+        keystore.promote_pending()?;
+        log::warn!(
+            "new recovery key is stored in the keystore, export it to remove from the device"
+        );
+
+        self.find_identifier(&did)
+            .await?
+            .ok_or(anyhow::anyhow!("DID is not found: {}", did))
+    }
+
     pub async fn find_identifier(&self, did: &str) -> anyhow::Result<Option<MiaxDidResponse>> {
         let res = self.did_repository.find_identifier(did).await?;
 
         Ok(res)
     }
 
+    pub async fn resolve_identifier(&self, did: &str) -> anyhow::Result<DidResolutionResult> {
+        let res = self.did_repository.resolve(did).await?;
+
+        Ok(res)
+    }
+
     pub async fn update_version(&self, binary_url: &str) -> anyhow::Result<()> {
         #[cfg(windows)]
         {
@@ -107,3 +849,189 @@ impl MiaX {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miax::extension::encrypted_keystore::KeyStoreSecret;
+    use protocol::did::sidetree::payload::DidPublicKey;
+    use protocol::keyring::jwk::Jwk;
+    use protocol::keyring::keypair::SignKeyType;
+    use protocol::rand_core::OsRng;
+
+    fn public_key(id: &str, r#type: &str, jwk: Jwk) -> DidPublicKey {
+        DidPublicKey {
+            id: id.to_string(),
+            controller: String::new(),
+            r#type: r#type.to_string(),
+            public_key_jwk: jwk,
+        }
+    }
+
+    #[test]
+    fn test_import_mnemonic() {
+        let dir = std::env::temp_dir().join(format!("miax-import-mnemonic-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let secret = KeyStoreSecret::MachineKey(dir.join("machine.key"));
+        let keystore =
+            EncryptedFileKeyStore::open_or_create(dir.join("keystore.json"), &secret).unwrap();
+
+        // 署名鍵を2回、暗号化鍵を1回ローテーションしたDID
+        let hd = HdKeyring::generate(OsRng);
+        let sign = hd.derive_sign(SignKeyType::P256, 2).unwrap();
+        let encrypt = hd.derive_encrypt(1).unwrap();
+        let document = DidDocument {
+            id: "did:miax:test".to_string(),
+            public_key: Some(vec![
+                public_key(
+                    "#signingKey",
+                    "JsonWebKey2020",
+                    Jwk::try_from(sign.get_public_key()).unwrap(),
+                ),
+                public_key(
+                    "#encryptionKey",
+                    "X25519KeyAgreementKey2019",
+                    Jwk::from(encrypt.get_public_key()),
+                ),
+            ]),
+            authentication: None,
+        };
+
+        // リカバリ鍵のインデックスは、最後に鍵を導出したインデックスを超えない
+        assert!(import_mnemonic_with(&keystore, &hd, &document, 3).is_err());
+        import_mnemonic_with(&keystore, &hd, &document, 1).unwrap();
+        assert_eq!(
+            keystore.read_sign().unwrap().get_public_key(),
+            sign.get_public_key()
+        );
+        assert_eq!(
+            keystore.read_update().unwrap().get_public_key(),
+            hd.derive_update(2).unwrap().get_public_key()
+        );
+        assert_eq!(
+            keystore.read_recovery().unwrap().get_public_key(),
+            hd.derive_recovery(1).unwrap().get_public_key()
+        );
+        assert_eq!(
+            keystore.read_encrypt().unwrap().get_public_key(),
+            encrypt.get_public_key()
+        );
+        let state = export_mnemonic_with(&keystore).unwrap();
+        assert_eq!(state.mnemonic, *hd.mnemonic());
+        assert_eq!(state.index, 2);
+        assert_eq!(state.recovery_index, 1);
+
+        // 他のニーモニックから導出した鍵のDIDは復元できない
+        let other = HdKeyring::generate(OsRng);
+        assert!(import_mnemonic_with(&keystore, &other, &document, 0).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_promote_pending_keys() {
+        let dir = std::env::temp_dir().join(format!("miax-promote-pending-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let secret = KeyStoreSecret::MachineKey(dir.join("machine.key"));
+        let keystore =
+            EncryptedFileKeyStore::open_or_create(dir.join("keystore.json"), &secret).unwrap();
+        let current = keystore.generate(SignKeyType::Secp256k1).unwrap();
+        let old_encrypt = keystore.read_encrypt().unwrap();
+
+        let pending = keystore
+            .generate_pending(
+                &[SecureKeyStoreType::Update, SecureKeyStoreType::Encrypt],
+                SignKeyType::Secp256k1,
+            )
+            .unwrap();
+        assert_eq!(decryption_keys_with(&keystore).len(), 1);
+
+        let (rotated, expires_at) =
+            promote_pending_keys(&keystore, &pending, Duration::days(7)).unwrap();
+        assert_eq!(
+            rotated,
+            vec![SecureKeyStoreType::Update, SecureKeyStoreType::Encrypt]
+        );
+        assert!(expires_at.unwrap() > Utc::now());
+        assert_eq!(
+            keystore.read_encrypt().unwrap().get_public_key(),
+            pending.encrypt.unwrap()
+        );
+
+        // 猶予期間中は、置き換えた暗号化鍵でも復号できる
+        let keys = decryption_keys_with(&keystore);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].get_public_key(), pending.encrypt.unwrap());
+        assert_eq!(keys[1].get_public_key(), old_encrypt.get_public_key());
+
+        let history = keystore.read_key_history();
+        assert_eq!(history.keys.len(), 2);
+        assert_eq!(
+            history.keys[0].public_key,
+            protocol::keyring::keypair::SignPublicKey::from(current.update).to_hex()
+        );
+
+        // 猶予期間が0の場合は、置き換えた暗号化鍵を保持しない
+        let pending = keystore
+            .generate_pending(&[SecureKeyStoreType::Encrypt], SignKeyType::Secp256k1)
+            .unwrap();
+        promote_pending_keys(&keystore, &pending, Duration::zero()).unwrap();
+        let keys = decryption_keys_with(&keystore);
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].get_public_key(), pending.encrypt.unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_and_import_recovery_key() {
+        let dir = std::env::temp_dir().join(format!("miax-recovery-key-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("keystore.json");
+        let secret = KeyStoreSecret::MachineKey(dir.join("machine.key"));
+        let keystore = EncryptedFileKeyStore::open_or_create(path.clone(), &secret).unwrap();
+        let public_keys = keystore.generate(SignKeyType::Secp256k1).unwrap();
+
+        // ニーモニックからリカバリ鍵を導出できるため、ニーモニックの削除を指定しない場合は削除しない
+        assert!(export_recovery_key_with(&keystore, 2, 3, true, false).is_err());
+        assert!(keystore.read_recovery().is_some());
+        assert!(keystore.read_hd_state().is_some());
+
+        // 鍵ストアに残す場合は、ニーモニックも残す
+        let shares = export_recovery_key_with(&keystore, 2, 3, false, false).unwrap();
+        assert_eq!(shares.len(), 3);
+        let shares: Vec<String> = shares.iter().map(|share| share.to_string()).collect();
+        assert_eq!(
+            combine_recovery_key(&shares[1..]).unwrap().get_public_key(),
+            public_keys.recovery
+        );
+        assert!(keystore.read_hd_state().is_some());
+
+        let shares = export_recovery_key_with(&keystore, 2, 3, true, true).unwrap();
+        let shares: Vec<String> = shares.iter().map(|share| share.to_string()).collect();
+        let reopened = EncryptedFileKeyStore::open_or_create(path, &secret).unwrap();
+        assert!(reopened.read_recovery().is_none());
+        assert!(reopened.read_hd_state().is_none());
+        // リカバリ鍵を削除しても、DIDの作成に利用した他の鍵は読み込める
+        let stored = reopened.read_public_keys().unwrap();
+        assert_eq!(stored.recovery, None);
+        assert_eq!(stored.update, public_keys.update);
+
+        // 閾値に満たないシェアからは復元できない
+        assert!(combine_recovery_key(&shares[..1]).is_err());
+        import_recovery_key_with(&reopened, &combine_recovery_key(&shares[..2]).unwrap()).unwrap();
+        assert_eq!(
+            reopened.read_recovery().unwrap().get_public_key(),
+            public_keys.recovery
+        );
+        // 鍵ストアのリカバリ鍵と異なる鍵はインポートしない
+        let other = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        assert!(import_recovery_key_with(&reopened, &other).is_err());
+        assert_eq!(
+            reopened.read_recovery().unwrap().get_public_key(),
+            public_keys.recovery
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,2 @@
+pub mod postgres_store;
+pub mod server;
@@ -0,0 +1,71 @@
+use protocol::did::sidetree::node::{DidState, SidetreeNodeStore};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PostgresNodeStoreError {
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("failed serialize/deserialize: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Postgresを利用したSidetreeノードのストア実装
+/// 複数のエージェントから共有されるDIDレジストリとして、開発環境のPostgres（docker-compose.dev.yml）を利用する
+pub struct PostgresNodeStore {
+    pool: PgPool,
+}
+
+impl PostgresNodeStore {
+    pub async fn connect(database_url: &str) -> Result<Self, PostgresNodeStoreError> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(database_url)
+            .await?;
+
+        // db/init/02-sidetree.sql を適用していないデータベースでも利用できるように、テーブルを作成しておく
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS sidetree_did_states (
+                did_suffix TEXT PRIMARY KEY,
+                state JSONB NOT NULL,
+                created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+            )",
+        )
+        .execute(&pool)
+        .await?;
+
+        Ok(Self { pool })
+    }
+}
+
+impl SidetreeNodeStore for PostgresNodeStore {
+    type Error = PostgresNodeStoreError;
+
+    async fn load(&self, did_suffix: &str) -> Result<Option<DidState>, Self::Error> {
+        let state: Option<(String,)> =
+            sqlx::query_as("SELECT state::text FROM sidetree_did_states WHERE did_suffix = $1")
+                .bind(did_suffix)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        match state {
+            Some((state,)) => Ok(Some(serde_json::from_str(&state)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn save(&self, did_suffix: &str, state: &DidState) -> Result<(), Self::Error> {
+        let state = serde_json::to_string(state)?;
+        sqlx::query(
+            "INSERT INTO sidetree_did_states (did_suffix, state) VALUES ($1, $2::jsonb)
+             ON CONFLICT (did_suffix)
+             DO UPDATE SET state = EXCLUDED.state, updated_at = CURRENT_TIMESTAMP",
+        )
+        .bind(did_suffix)
+        .bind(state)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
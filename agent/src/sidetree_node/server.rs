@@ -0,0 +1,85 @@
+use super::postgres_store::PostgresNodeStore;
+use crate::cli::SidetreeNodeOptions;
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use protocol::did::sidetree::{
+    client::SidetreeHttpClientResponse,
+    node::{LocalSidetreeNode, SidetreeNodeStore},
+};
+
+fn into_response<E: std::error::Error>(
+    response: Result<SidetreeHttpClientResponse, E>,
+) -> Response {
+    match response {
+        Ok(response) => (
+            response.status_code(),
+            [(header::CONTENT_TYPE, "application/json")],
+            response.body().to_string(),
+        )
+            .into_response(),
+        Err(e) => {
+            log::error!("{:?}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+async fn post_operation<S: SidetreeNodeStore + 'static>(
+    State(node): State<LocalSidetreeNode<S>>,
+    body: String,
+) -> Response {
+    into_response(node.handle_operation(&body).await)
+}
+
+async fn get_identifier<S: SidetreeNodeStore + 'static>(
+    State(node): State<LocalSidetreeNode<S>>,
+    Path(did): Path<String>,
+) -> Response {
+    into_response(node.resolve(&did).await)
+}
+
+/// SideTreeClientが利用するエンドポイント（/api/v1/operations, /api/v1/identifiers/:did）を公開する
+pub fn make_router<S: SidetreeNodeStore + 'static>(node: LocalSidetreeNode<S>) -> Router {
+    Router::new()
+        .route("/api/v1/operations", post(post_operation::<S>))
+        .route("/api/v1/identifiers/:did", get(get_identifier::<S>))
+        .with_state(node)
+}
+
+async fn serve(router: Router, bind: &str) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(bind).await?;
+    println!("Sidetree node running on http://{}", bind);
+
+    axum::serve(listener, router).await
+}
+
+/// ローカルのSidetreeノードを起動する
+/// データベースのURLが指定された場合はPostgresに、それ以外の場合はJSONファイルにDIDの状態を保存する
+pub async fn run(options: &SidetreeNodeOptions) -> std::io::Result<()> {
+    println!("Starting Sidetree node...");
+
+    let database_url = options
+        .database_url
+        .clone()
+        .or_else(|| std::env::var("DATABASE_URL").ok());
+
+    match database_url {
+        Some(database_url) => {
+            let store = PostgresNodeStore::connect(&database_url)
+                .await
+                .map_err(std::io::Error::other)?;
+            println!("Using postgres store");
+            serve(make_router(LocalSidetreeNode::new(store)), &options.bind).await
+        }
+        None => {
+            let node = LocalSidetreeNode::open(&options.store).map_err(std::io::Error::other)?;
+            println!("Using file store: {}", options.store.display());
+            serve(make_router(node), &options.bind).await
+        }
+    }
+}
@@ -23,6 +23,20 @@ struct Cli {
 enum Commands {
     Controller,
     Controlled,
+    /// 開発用のSidetreeノードを起動する
+    SidetreeNode(agent::cli::SidetreeNodeOptions),
+    /// 鍵を導出したニーモニックをエクスポートする
+    ExportMnemonic,
+    /// 標準入力から読み込んだニーモニックで鍵を復元する
+    ImportMnemonic(agent::cli::ImportMnemonicOptions),
+    /// 署名鍵・暗号化鍵をローテーションする
+    RotateKeys(agent::cli::RotateKeysOptions),
+    /// リカバリ鍵をシェアに分割してエクスポートする
+    ExportRecoveryKey(agent::cli::ExportRecoveryKeyOptions),
+    /// シェアから復元したリカバリ鍵をインポートする
+    ImportRecoveryKey(agent::cli::RecoveryKeySharesOptions),
+    /// リカバリ鍵でDIDの全ての鍵を置き換える
+    RecoverIdentifier(agent::cli::RecoveryKeySharesOptions),
 }
 
 fn log_init() {
@@ -54,6 +68,41 @@ async fn main() {
         let _ = controller::run();
         #[cfg(not(unix))]
         log::error!("Controller is not supported on this platform.");
+    } else if let Some(Commands::SidetreeNode(options)) = &cli.command {
+        if let Err(e) = agent::sidetree_node::server::run(options).await {
+            log::error!("Failed to run sidetree node: {:?}", e);
+            std::process::exit(1);
+        }
+    } else if let Some(Commands::ExportMnemonic) = &cli.command {
+        if let Err(e) = agent::export_mnemonic() {
+            log::error!("Failed to export mnemonic: {:?}", e);
+            std::process::exit(1);
+        }
+    } else if let Some(Commands::ImportMnemonic(options)) = &cli.command {
+        if let Err(e) = agent::import_mnemonic(options).await {
+            log::error!("Failed to import mnemonic: {:?}", e);
+            std::process::exit(1);
+        }
+    } else if let Some(Commands::RotateKeys(options)) = &cli.command {
+        if let Err(e) = agent::rotate_keys(options).await {
+            log::error!("Failed to rotate keys: {:?}", e);
+            std::process::exit(1);
+        }
+    } else if let Some(Commands::ExportRecoveryKey(options)) = &cli.command {
+        if let Err(e) = agent::export_recovery_key(options) {
+            log::error!("Failed to export recovery key: {:?}", e);
+            std::process::exit(1);
+        }
+    } else if let Some(Commands::ImportRecoveryKey(options)) = &cli.command {
+        if let Err(e) = agent::import_recovery_key(options) {
+            log::error!("Failed to import recovery key: {:?}", e);
+            std::process::exit(1);
+        }
+    } else if let Some(Commands::RecoverIdentifier(options)) = &cli.command {
+        if let Err(e) = agent::recover_identifier(options).await {
+            log::error!("Failed to recover identifier: {:?}", e);
+            std::process::exit(1);
+        }
     } else {
         let controlled = cli.command.map(|_| true).unwrap_or(false);
         let options = if cli.agent_options.config || cli.agent_options.command.is_some() {
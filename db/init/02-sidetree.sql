@@ -0,0 +1,9 @@
+\c miax_dev;
+
+-- ローカルSidetreeノード（miax-agent sidetree-node）のDID状態テーブル
+CREATE TABLE IF NOT EXISTS sidetree_did_states (
+    did_suffix TEXT PRIMARY KEY,
+    state JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
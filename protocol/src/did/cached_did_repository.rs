@@ -0,0 +1,617 @@
+// DidRepositoryのキャッシュ付きデコレータ
+// DIDComm・VCの検証の度にSidetreeへ問い合わせることを避け、Sidetreeに一時的に接続できない場合も既知のDIDを解決できるようにする
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::did_repository::DidRepository;
+use super::resolution::DidResolutionResult;
+use super::sidetree::payload::{DidAction, MiaxDidResponse};
+use crate::keyring::keypair::PublicKeyPairing;
+use crate::keyring::signer::Signer;
+
+#[derive(Clone, Debug)]
+pub struct DidCacheConfig {
+    /// 解決結果を再利用する期間
+    pub ttl: Duration,
+    /// 存在しないDIDの結果を再利用する期間（Noneの場合は、存在しないDIDの結果をキャッシュしない）
+    pub negative_ttl: Option<Duration>,
+    /// キャッシュするDIDの最大数（超えた場合は、最も古い結果から破棄する）
+    pub max_entries: usize,
+    /// キャッシュを永続化するファイル（Noneの場合は、メモリ上にのみ保持する）
+    pub persist_path: Option<PathBuf>,
+}
+
+impl Default for DidCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(5 * 60),
+            negative_ttl: Some(Duration::from_secs(30)),
+            max_entries: 1024,
+            persist_path: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    // Noneの場合は、DIDが存在しなかったことを表す
+    response: Option<MiaxDidResponse>,
+    fetched_at: DateTime<Utc>,
+}
+
+/// find_identifierの結果をキャッシュするDidRepository
+///
+/// - TTL内の結果はSidetreeに問い合わせずに返す
+/// - Sidetreeへの問い合わせに失敗した場合、TTLを過ぎていても既知のDIDであればキャッシュの結果を返す
+/// - update/recover/deactivate操作を行ったDIDのキャッシュは破棄する
+/// - 永続化はベストエフォートであり、ファイルの読み書きに失敗してもDIDの解決は継続する
+/// - ファイルへの書き込みは専用のスレッドで行い、書き込み待ちの間に更新があった場合は最新の内容のみを書き込む
+pub struct CachedDidRepository<R: DidRepository> {
+    inner: R,
+    config: DidCacheConfig,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    writer: Option<mpsc::Sender<HashMap<String, CacheEntry>>>,
+}
+
+impl<R: DidRepository> CachedDidRepository<R> {
+    pub fn new(inner: R, config: DidCacheConfig) -> Self {
+        // 永続化したキャッシュが読み込めない場合は、空のキャッシュから開始する
+        let entries = config
+            .persist_path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        // スレッドを起動できない場合は、永続化せずにメモリ上にのみ保持する
+        let writer = config.persist_path.clone().and_then(|path| {
+            let (tx, rx) = mpsc::channel();
+            thread::Builder::new()
+                .name("did-cache-writer".to_string())
+                .spawn(move || write_loop(&path, rx))
+                .ok()
+                .map(|_| tx)
+        });
+        Self {
+            inner,
+            config,
+            entries: Mutex::new(entries),
+            writer,
+        }
+    }
+
+    pub fn inner(&self) -> &R {
+        &self.inner
+    }
+
+    /// 指定したDIDのキャッシュを破棄する
+    pub fn invalidate(&self, did: &str) {
+        let snapshot = {
+            let mut entries = self.lock();
+            if entries.remove(did).is_none() {
+                return;
+            }
+            self.snapshot(&entries)
+        };
+        self.persist(snapshot);
+    }
+
+    /// 全てのキャッシュを破棄する
+    pub fn clear(&self) {
+        let snapshot = {
+            let mut entries = self.lock();
+            entries.clear();
+            self.snapshot(&entries)
+        };
+        self.persist(snapshot);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, CacheEntry>> {
+        self.entries.lock().unwrap()
+    }
+
+    fn is_fresh(&self, entry: &CacheEntry, now: DateTime<Utc>) -> bool {
+        let ttl = match entry.response {
+            Some(_) => Some(self.config.ttl),
+            None => self.config.negative_ttl,
+        };
+        let elapsed = (now - entry.fetched_at).to_std().unwrap_or_default();
+        ttl.is_some_and(|ttl| elapsed < ttl)
+    }
+
+    fn store(&self, did: &str, response: Option<MiaxDidResponse>) {
+        let snapshot = {
+            let mut entries = self.lock();
+            if response.is_none() && self.config.negative_ttl.is_none() {
+                entries.remove(did);
+            } else {
+                entries.insert(
+                    did.to_string(),
+                    CacheEntry {
+                        response,
+                        fetched_at: Utc::now(),
+                    },
+                );
+                self.evict(&mut entries);
+            }
+            self.snapshot(&entries)
+        };
+        self.persist(snapshot);
+    }
+
+    fn evict(&self, entries: &mut HashMap<String, CacheEntry>) {
+        while entries.len() > self.config.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.fetched_at)
+                .map(|(did, _)| did.clone());
+            match oldest {
+                Some(did) => entries.remove(&did),
+                None => break,
+            };
+        }
+    }
+
+    // 永続化する内容をロックを保持している間に複製する
+    fn snapshot(
+        &self,
+        entries: &HashMap<String, CacheEntry>,
+    ) -> Option<HashMap<String, CacheEntry>> {
+        self.writer.as_ref()?;
+        // 存在しないDIDの結果は一時的なものであるため、永続化しない
+        Some(
+            entries
+                .iter()
+                .filter(|(_, entry)| entry.response.is_some())
+                .map(|(did, entry)| (did.clone(), entry.clone()))
+                .collect(),
+        )
+    }
+
+    // ファイルへの書き込みは書き込み用のスレッドに任せ、呼び出し元をブロックしない
+    fn persist(&self, snapshot: Option<HashMap<String, CacheEntry>>) {
+        if let (Some(writer), Some(snapshot)) = (&self.writer, snapshot) {
+            let _ = writer.send(snapshot);
+        }
+    }
+}
+
+// CachedDidRepositoryが破棄されて送信側が閉じるまで、受け取った内容をファイルに書き込む
+fn write_loop(path: &Path, rx: mpsc::Receiver<HashMap<String, CacheEntry>>) {
+    while let Ok(mut snapshot) = rx.recv() {
+        // 書き込み待ちの内容が複数ある場合は、最新の内容のみを書き込む
+        while let Ok(newer) = rx.try_recv() {
+            snapshot = newer;
+        }
+        if let Ok(bytes) = serde_json::to_vec(&snapshot) {
+            let tmp = path.with_extension("tmp");
+            let _ = fs::write(&tmp, bytes).and_then(|_| fs::rename(&tmp, path));
+        }
+    }
+}
+
+impl<R: DidRepository> DidRepository for CachedDidRepository<R> {
+    type CreateIdentifierError = R::CreateIdentifierError;
+    type UpdateIdentifierError = R::UpdateIdentifierError;
+    type RecoverIdentifierError = R::RecoverIdentifierError;
+    type DeactivateIdentifierError = R::DeactivateIdentifierError;
+    type FindIdentifierError = R::FindIdentifierError;
+
+    async fn create_identifier<K: Into<PublicKeyPairing> + Send>(
+        &self,
+        keyring: K,
+    ) -> Result<MiaxDidResponse, Self::CreateIdentifierError> {
+        let response = self.inner.create_identifier(keyring).await?;
+        self.store(&response.did_document.id, Some(response.clone()));
+        Ok(response)
+    }
+
+    async fn update_identifier<S: Signer + Sync>(
+        &self,
+        did: &str,
+        update_key: &S,
+        new_update_key: k256::PublicKey,
+        patches: Vec<DidAction>,
+    ) -> Result<(), Self::UpdateIdentifierError> {
+        let result = self
+            .inner
+            .update_identifier(did, update_key, new_update_key, patches)
+            .await;
+        self.invalidate(did);
+        result
+    }
+
+    async fn recover_identifier<S: Signer + Sync, K: Into<PublicKeyPairing> + Send>(
+        &self,
+        did: &str,
+        recovery_key: &S,
+        new_keyring: K,
+    ) -> Result<(), Self::RecoverIdentifierError> {
+        let result = self
+            .inner
+            .recover_identifier(did, recovery_key, new_keyring)
+            .await;
+        self.invalidate(did);
+        result
+    }
+
+    async fn deactivate_identifier<S: Signer + Sync>(
+        &self,
+        did: &str,
+        recovery_key: &S,
+    ) -> Result<(), Self::DeactivateIdentifierError> {
+        let result = self.inner.deactivate_identifier(did, recovery_key).await;
+        self.invalidate(did);
+        result
+    }
+
+    async fn find_identifier(
+        &self,
+        did: &str,
+    ) -> Result<Option<MiaxDidResponse>, Self::FindIdentifierError> {
+        // ロックを保持したままSidetreeへ問い合わせないように、キャッシュの確認とは別に問い合わせる
+        let cached = self.lock().get(did).cloned();
+        if let Some(entry) = &cached {
+            if self.is_fresh(entry, Utc::now()) {
+                return Ok(entry.response.clone());
+            }
+        }
+
+        match self.inner.find_identifier(did).await {
+            Ok(response) => {
+                self.store(did, response.clone());
+                Ok(response)
+            }
+            // 問い合わせに失敗した場合は、期限切れであっても既知のDIDの結果を返す
+            Err(e) => match cached.and_then(|entry| entry.response) {
+                Some(response) => Ok(Some(response)),
+                None => Err(e),
+            },
+        }
+    }
+
+    // メタデータ（更新日時・無効化の有無）を含む結果は、常にSidetreeに問い合わせる
+    async fn resolve(&self, did: &str) -> Result<DidResolutionResult, Self::FindIdentifierError> {
+        self.inner.resolve(did).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::did_repository::DidRepositoryImpl;
+    use crate::did::sidetree::node::LocalSidetreeNode;
+    use crate::keyring::keypair::{KeyPair, KeyPairing};
+    use futures::executor::block_on;
+    use rand_core::OsRng;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    enum TestError {
+        #[error("sidetree is offline")]
+        Offline,
+        #[error("sidetree error: {0}")]
+        Sidetree(String),
+    }
+
+    /// find_identifierの呼び出し回数を数え、Sidetreeへの接続断を再現できるDidRepository
+    struct CountingRepository {
+        inner: DidRepositoryImpl<LocalSidetreeNode>,
+        finds: AtomicUsize,
+        offline: AtomicBool,
+    }
+
+    impl CountingRepository {
+        fn new(node: LocalSidetreeNode) -> Self {
+            Self {
+                inner: DidRepositoryImpl::new(node),
+                finds: AtomicUsize::new(0),
+                offline: AtomicBool::new(false),
+            }
+        }
+
+        fn finds(&self) -> usize {
+            self.finds.load(Ordering::SeqCst)
+        }
+
+        fn set_offline(&self, offline: bool) {
+            self.offline.store(offline, Ordering::SeqCst);
+        }
+    }
+
+    fn sidetree_error(e: impl std::error::Error) -> TestError {
+        TestError::Sidetree(e.to_string())
+    }
+
+    impl DidRepository for CountingRepository {
+        type CreateIdentifierError = TestError;
+        type UpdateIdentifierError = TestError;
+        type RecoverIdentifierError = TestError;
+        type DeactivateIdentifierError = TestError;
+        type FindIdentifierError = TestError;
+
+        async fn create_identifier<K: Into<PublicKeyPairing> + Send>(
+            &self,
+            keyring: K,
+        ) -> Result<MiaxDidResponse, TestError> {
+            self.inner
+                .create_identifier(keyring)
+                .await
+                .map_err(sidetree_error)
+        }
+
+        async fn update_identifier<S: Signer + Sync>(
+            &self,
+            did: &str,
+            update_key: &S,
+            new_update_key: k256::PublicKey,
+            patches: Vec<DidAction>,
+        ) -> Result<(), TestError> {
+            self.inner
+                .update_identifier(did, update_key, new_update_key, patches)
+                .await
+                .map_err(sidetree_error)
+        }
+
+        async fn recover_identifier<S: Signer + Sync, K: Into<PublicKeyPairing> + Send>(
+            &self,
+            did: &str,
+            recovery_key: &S,
+            new_keyring: K,
+        ) -> Result<(), TestError> {
+            self.inner
+                .recover_identifier(did, recovery_key, new_keyring)
+                .await
+                .map_err(sidetree_error)
+        }
+
+        async fn deactivate_identifier<S: Signer + Sync>(
+            &self,
+            did: &str,
+            recovery_key: &S,
+        ) -> Result<(), TestError> {
+            self.inner
+                .deactivate_identifier(did, recovery_key)
+                .await
+                .map_err(sidetree_error)
+        }
+
+        async fn find_identifier(&self, did: &str) -> Result<Option<MiaxDidResponse>, TestError> {
+            self.finds.fetch_add(1, Ordering::SeqCst);
+            if self.offline.load(Ordering::SeqCst) {
+                return Err(TestError::Offline);
+            }
+            self.inner
+                .find_identifier(did)
+                .await
+                .map_err(sidetree_error)
+        }
+
+        async fn resolve(
+            &self,
+            did: &str,
+        ) -> Result<crate::did::resolution::DidResolutionResult, TestError> {
+            self.inner.resolve(did).await.map_err(sidetree_error)
+        }
+    }
+
+    /// Sidetreeに直接DIDを作成し、DIDを返す（キャッシュを経由しない）
+    fn create(node: &LocalSidetreeNode) -> String {
+        let repository = DidRepositoryImpl::new(node.clone());
+        block_on(repository.create_identifier(KeyPairing::create_keyring(OsRng)))
+            .unwrap()
+            .did_document
+            .id
+    }
+
+    fn cached(
+        node: &LocalSidetreeNode,
+        config: DidCacheConfig,
+    ) -> CachedDidRepository<CountingRepository> {
+        CachedDidRepository::new(CountingRepository::new(node.clone()), config)
+    }
+
+    const UNKNOWN_DID: &str = "did:miax:EiAunknownunknownunknownunknownunknownunknown";
+
+    #[test]
+    fn test_fresh_entry_is_reused() {
+        let node = LocalSidetreeNode::in_memory();
+        let did = create(&node);
+        let repository = cached(&node, DidCacheConfig::default());
+
+        for _ in 0..3 {
+            let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+            assert_eq!(found.did_document.id, did);
+        }
+        assert_eq!(repository.inner().finds(), 1);
+    }
+
+    #[test]
+    fn test_expired_entry_is_refetched() {
+        let node = LocalSidetreeNode::in_memory();
+        let did = create(&node);
+        let repository = cached(
+            &node,
+            DidCacheConfig {
+                ttl: Duration::ZERO,
+                ..Default::default()
+            },
+        );
+
+        block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(repository.inner().finds(), 2);
+    }
+
+    #[test]
+    fn test_negative_caching() {
+        let node = LocalSidetreeNode::in_memory();
+
+        let repository = cached(&node, DidCacheConfig::default());
+        assert!(block_on(repository.find_identifier(UNKNOWN_DID))
+            .unwrap()
+            .is_none());
+        assert!(block_on(repository.find_identifier(UNKNOWN_DID))
+            .unwrap()
+            .is_none());
+        assert_eq!(repository.inner().finds(), 1);
+
+        // negative_ttlがNoneの場合は、存在しないDIDの結果をキャッシュしない
+        let repository = cached(
+            &node,
+            DidCacheConfig {
+                negative_ttl: None,
+                ..Default::default()
+            },
+        );
+        block_on(repository.find_identifier(UNKNOWN_DID)).unwrap();
+        block_on(repository.find_identifier(UNKNOWN_DID)).unwrap();
+        assert_eq!(repository.inner().finds(), 2);
+    }
+
+    #[test]
+    fn test_stale_entry_is_returned_when_offline() {
+        let node = LocalSidetreeNode::in_memory();
+        let did = create(&node);
+        let repository = cached(
+            &node,
+            DidCacheConfig {
+                ttl: Duration::ZERO,
+                negative_ttl: Some(Duration::ZERO),
+                ..Default::default()
+            },
+        );
+        block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        block_on(repository.find_identifier(UNKNOWN_DID)).unwrap();
+
+        repository.inner().set_offline(true);
+        let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(found.did_document.id, did);
+        // 存在しないDIDの結果は、問い合わせに失敗した場合に返さない
+        assert!(matches!(
+            block_on(repository.find_identifier(UNKNOWN_DID)),
+            Err(TestError::Offline)
+        ));
+    }
+
+    #[test]
+    fn test_update_invalidates_entry() {
+        let node = LocalSidetreeNode::in_memory();
+        let repository = cached(&node, DidCacheConfig::default());
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = block_on(repository.create_identifier(&keyring))
+            .unwrap()
+            .did_document
+            .id;
+        // 作成したDIDはキャッシュから返す
+        block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(repository.inner().finds(), 0);
+
+        let new_update_key = KeyPairing::create_keyring(OsRng).update().get_public_key();
+        block_on(repository.update_identifier(
+            &did,
+            keyring.update(),
+            new_update_key,
+            vec![DidAction::RemovePublicKeys {
+                ids: vec!["encryptionKey".to_string()],
+            }],
+        ))
+        .unwrap();
+
+        let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(repository.inner().finds(), 1);
+        assert_eq!(found.did_document.public_key.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_invalidate_and_clear() {
+        let node = LocalSidetreeNode::in_memory();
+        let did = create(&node);
+        let repository = cached(&node, DidCacheConfig::default());
+
+        block_on(repository.find_identifier(&did)).unwrap();
+        repository.invalidate(&did);
+        block_on(repository.find_identifier(&did)).unwrap();
+        assert_eq!(repository.inner().finds(), 2);
+
+        repository.clear();
+        block_on(repository.find_identifier(&did)).unwrap();
+        assert_eq!(repository.inner().finds(), 3);
+    }
+
+    #[test]
+    fn test_oldest_entry_is_evicted() {
+        let node = LocalSidetreeNode::in_memory();
+        let dids: Vec<String> = (0..3).map(|_| create(&node)).collect();
+        let repository = cached(
+            &node,
+            DidCacheConfig {
+                max_entries: 2,
+                ..Default::default()
+            },
+        );
+
+        for did in &dids {
+            block_on(repository.find_identifier(did)).unwrap();
+        }
+        assert_eq!(repository.inner().finds(), 3);
+        // 最も古い結果のみが破棄されている
+        block_on(repository.find_identifier(&dids[2])).unwrap();
+        block_on(repository.find_identifier(&dids[1])).unwrap();
+        assert_eq!(repository.inner().finds(), 3);
+        block_on(repository.find_identifier(&dids[0])).unwrap();
+        assert_eq!(repository.inner().finds(), 4);
+    }
+
+    #[test]
+    fn test_persisted_cache_is_loaded() {
+        let path =
+            std::env::temp_dir().join(format!("miax-did-cache-test-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let config = DidCacheConfig {
+            persist_path: Some(path.clone()),
+            ..Default::default()
+        };
+
+        let node = LocalSidetreeNode::in_memory();
+        let did = create(&node);
+        {
+            let repository = cached(&node, config.clone());
+            block_on(repository.find_identifier(&did)).unwrap().unwrap();
+            block_on(repository.find_identifier(UNKNOWN_DID)).unwrap();
+        }
+
+        // 書き込みは別スレッドで行われるため、書き込まれるまで待つ
+        let mut persisted = HashMap::<String, CacheEntry>::new();
+        for _ in 0..100 {
+            if let Some(entries) = fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            {
+                persisted = entries;
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        // 存在しないDIDの結果は永続化しない
+        assert_eq!(persisted.keys().collect::<Vec<_>>(), [&did]);
+
+        // Sidetreeに接続できなくても、永続化したキャッシュから解決できる
+        let repository = cached(&LocalSidetreeNode::in_memory(), config);
+        repository.inner().set_offline(true);
+        let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(found.did_document.id, did);
+        assert_eq!(repository.inner().finds(), 0);
+
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,442 @@
+// did:keyメソッド
+// 公開鍵そのものをDIDとして表現する（did:key:z<base58btc(multicodec + 公開鍵)>）ため、問い合わせなしで解決できる
+//
+// 参考 : https://w3c-ccg.github.io/did-method-key/
+use std::convert::Infallible;
+
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use thiserror::Error;
+
+use super::document::{DidCoreDocument, VerificationMethod, VerificationRelationship};
+use super::resolution::{DidDocumentMetadata, DidResolutionResult, ResolutionError};
+use super::resolver::DidResolver;
+use crate::keyring::keypair::SignPublicKey;
+
+pub const DID_KEY_METHOD: &str = "key";
+
+// multicodecのコード（https://github.com/multiformats/multicodec/blob/master/table.csv）
+const SECP256K1_PUB: u64 = 0xe7;
+const X25519_PUB: u64 = 0xec;
+const ED25519_PUB: u64 = 0xed;
+const P256_PUB: u64 = 0x1200;
+
+// multibaseのプレフィックス（base58btc）
+const BASE58BTC_PREFIX: char = 'z';
+
+#[derive(Debug, Error)]
+pub enum DidKeyError {
+    #[error("invalid did:key: {0}")]
+    InvalidDid(String),
+    #[error("failed to decode multibase: {0}")]
+    Decode(#[from] bs58::decode::Error),
+    #[error("unsupported multicodec: {0:#x}")]
+    UnsupportedCodec(u64),
+    #[error("invalid public key")]
+    InvalidKey,
+}
+
+/// did:keyで表現できる公開鍵
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DidKeyPublicKey {
+    Secp256k1(k256::PublicKey),
+    X25519(x25519_dalek::PublicKey),
+    Ed25519(ed25519_dalek::VerifyingKey),
+    P256(p256::PublicKey),
+}
+
+// unsigned varintをエンコードする
+fn encode_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// unsigned varintをデコードし、値と残りのバイト列を返す
+fn decode_varint(bytes: &[u8]) -> Option<(u64, &[u8])> {
+    let mut value = 0u64;
+    for (i, byte) in bytes.iter().enumerate().take(9) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, &bytes[i + 1..]));
+        }
+    }
+    None
+}
+
+impl DidKeyPublicKey {
+    fn codec(&self) -> u64 {
+        match self {
+            DidKeyPublicKey::Secp256k1(_) => SECP256K1_PUB,
+            DidKeyPublicKey::X25519(_) => X25519_PUB,
+            DidKeyPublicKey::Ed25519(_) => ED25519_PUB,
+            DidKeyPublicKey::P256(_) => P256_PUB,
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            // secp256k1・P-256は圧縮形式（33バイト）で表現する
+            DidKeyPublicKey::Secp256k1(key) => key.to_encoded_point(true).as_bytes().to_vec(),
+            DidKeyPublicKey::P256(key) => key.to_encoded_point(true).as_bytes().to_vec(),
+            DidKeyPublicKey::X25519(key) => key.as_bytes().to_vec(),
+            DidKeyPublicKey::Ed25519(key) => key.as_bytes().to_vec(),
+        }
+    }
+
+    /// multicodecのプレフィックスを付与し、multibase（base58btc）でエンコードする
+    pub fn to_multibase(&self) -> String {
+        let mut bytes = vec![];
+        encode_varint(self.codec(), &mut bytes);
+        bytes.extend(self.to_bytes());
+        format!("{}{}", BASE58BTC_PREFIX, bs58::encode(bytes).into_string())
+    }
+
+    pub fn from_multibase(value: &str) -> Result<Self, DidKeyError> {
+        let encoded = value
+            .strip_prefix(BASE58BTC_PREFIX)
+            .ok_or_else(|| DidKeyError::InvalidDid(value.to_string()))?;
+        let bytes = bs58::decode(encoded).into_vec()?;
+        let (codec, key) =
+            decode_varint(&bytes).ok_or_else(|| DidKeyError::InvalidDid(value.to_string()))?;
+        match codec {
+            SECP256K1_PUB => k256::PublicKey::from_sec1_bytes(key)
+                .map(DidKeyPublicKey::Secp256k1)
+                .map_err(|_| DidKeyError::InvalidKey),
+            X25519_PUB => {
+                let key: [u8; 32] = key.try_into().map_err(|_| DidKeyError::InvalidKey)?;
+                Ok(DidKeyPublicKey::X25519(key.into()))
+            }
+            ED25519_PUB => {
+                let key: [u8; 32] = key.try_into().map_err(|_| DidKeyError::InvalidKey)?;
+                ed25519_dalek::VerifyingKey::from_bytes(&key)
+                    .map(DidKeyPublicKey::Ed25519)
+                    .map_err(|_| DidKeyError::InvalidKey)
+            }
+            P256_PUB => p256::PublicKey::from_sec1_bytes(key)
+                .map(DidKeyPublicKey::P256)
+                .map_err(|_| DidKeyError::InvalidKey),
+            codec => Err(DidKeyError::UnsupportedCodec(codec)),
+        }
+    }
+
+    /// 公開鍵からdid:keyを生成する
+    pub fn to_did(&self) -> String {
+        format!("did:{}:{}", DID_KEY_METHOD, self.to_multibase())
+    }
+
+    pub fn from_did(did: &str) -> Result<Self, DidKeyError> {
+        let prefix = format!("did:{}:", DID_KEY_METHOD);
+        let multibase = did
+            .strip_prefix(&prefix)
+            // DID URL（did:key:z...#z...）の場合は、DID部分のみを利用する
+            .map(|id| id.split(['#', '?', '/']).next().unwrap_or(id))
+            .ok_or_else(|| DidKeyError::InvalidDid(did.to_string()))?;
+        Self::from_multibase(multibase)
+    }
+}
+
+impl From<SignPublicKey> for DidKeyPublicKey {
+    fn from(value: SignPublicKey) -> Self {
+        match value {
+            SignPublicKey::Secp256k1(key) => DidKeyPublicKey::Secp256k1(key),
+            SignPublicKey::P256(key) => DidKeyPublicKey::P256(key),
+            SignPublicKey::Ed25519(key) => DidKeyPublicKey::Ed25519(key),
+        }
+    }
+}
+
+/// 公開鍵から、指定したidのverificationMethodを生成する
+pub(crate) fn verification_method(
+    id: String,
+    controller: &str,
+    key: &DidKeyPublicKey,
+) -> VerificationMethod {
+    let (r#type, jwk) = match key {
+        DidKeyPublicKey::Secp256k1(key) => (
+            "EcdsaSecp256k1VerificationKey2019",
+            // 検証済みの公開鍵は、必ずJWKに変換できる
+            (*key).try_into().expect("failed to convert to JWK"),
+        ),
+        DidKeyPublicKey::X25519(key) => ("X25519KeyAgreementKey2019", (*key).into()),
+        DidKeyPublicKey::Ed25519(key) => ("Ed25519VerificationKey2018", (*key).into()),
+        DidKeyPublicKey::P256(key) => (
+            "JsonWebKey2020",
+            (*key).try_into().expect("failed to convert to JWK"),
+        ),
+    };
+    VerificationMethod {
+        id,
+        r#type: r#type.to_string(),
+        controller: controller.to_string(),
+        public_key_jwk: Some(jwk),
+        public_key_multibase: None,
+    }
+}
+
+/// did:keyからDIDドキュメントを生成する
+///
+/// - secp256k1・P-256・Ed25519 : authentication・assertionMethod・capabilityInvocation・capabilityDelegation
+///   （Ed25519の場合は、鍵から導出したX25519の公開鍵をkeyAgreementとする）
+/// - X25519 : keyAgreement
+pub fn did_key_document(did: &str) -> Result<DidCoreDocument, DidKeyError> {
+    let key = DidKeyPublicKey::from_did(did)?;
+    let did = key.to_did();
+    let mut document = DidCoreDocument::new(did.clone());
+
+    let method = verification_method(format!("{}#{}", did, key.to_multibase()), &did, &key);
+    let reference = VerificationRelationship::Reference(method.id.clone());
+    document.verification_method.push(method);
+    match key {
+        DidKeyPublicKey::X25519(_) => document.key_agreement.push(reference),
+        DidKeyPublicKey::Secp256k1(_) | DidKeyPublicKey::P256(_) | DidKeyPublicKey::Ed25519(_) => {
+            document.authentication.push(reference.clone());
+            document.assertion_method.push(reference.clone());
+            document.capability_invocation.push(reference.clone());
+            document.capability_delegation.push(reference);
+        }
+    }
+
+    if let DidKeyPublicKey::Ed25519(key) = key {
+        let x25519 = DidKeyPublicKey::X25519(key.to_montgomery().to_bytes().into());
+        let method =
+            verification_method(format!("{}#{}", did, x25519.to_multibase()), &did, &x25519);
+        document
+            .key_agreement
+            .push(VerificationRelationship::Reference(method.id.clone()));
+        document.verification_method.push(method);
+    }
+
+    Ok(document)
+}
+
+/// did:keyのResolver
+/// DIDに公開鍵が含まれるため、ネットワークへの問い合わせは発生しない
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DidKeyResolver;
+
+impl DidResolver for DidKeyResolver {
+    type Error = Infallible;
+
+    fn supports(&self, method: &str) -> bool {
+        method == DID_KEY_METHOD
+    }
+
+    async fn resolve(&self, did: &str) -> Result<DidResolutionResult, Self::Error> {
+        Ok(match did_key_document(did) {
+            Ok(document) => DidResolutionResult::resolved(document, DidDocumentMetadata::default()),
+            Err(_) => DidResolutionResult::error(ResolutionError::InvalidDid),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::document::Relationship;
+    use crate::keyring::jwk::Jwk;
+    use crate::keyring::keypair::{KeyPair, KeyPairing, SignKeyPair, SignKeyType};
+    use data_encoding::BASE64URL_NOPAD;
+    use futures::executor::block_on;
+    use rand_core::OsRng;
+
+    // did:key仕様のテストベクター
+    // https://w3c-ccg.github.io/did-method-key/#test-vectors
+    const ED25519_DID: &str = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+    const ED25519_X: &str = "Lm_M42cB3HkUiODQsXRcweM6TByfzEHGO9ND274JcOY";
+    const ED25519_X25519_KEY: &str = "z6LSj72tK8brWgZja8NLRwPigth2T9QRiG1uH9oKZuKjdh9p";
+    const SECP256K1_DID: &str = "did:key:zQ3shokFTS3brHcDQrn82RUDfCZESWL1ZdCEJwekUDPQiYBme";
+    const SECP256K1_X: &str = "h0wVx_2iDlOcblulc8E5iEw1EYh5n1RYtLQfeSTyNc0";
+    const SECP256K1_Y: &str = "O2EATIGbu6DezKFptj5scAIRntgfecanVNXxat1rnwE";
+    const P256_DID: &str = "did:key:zDnaerDaTF5BXEavCrfRZEk316dpbLsfPDZ3WJ5hRTPFU2169";
+    const P256_X: &str = "fyNYMN0976ci7xqiSdag3buk-ZCwgXU4kz9XNkBlNUI";
+    const P256_Y: &str = "hW2ojTNfH7Jbi8--CJUo3OCbH3y5n91g-IMA9MLMbTU";
+
+    fn ids(document: &DidCoreDocument, relationship: Relationship) -> Vec<&str> {
+        document
+            .relationship(relationship)
+            .iter()
+            .map(|r| r.id())
+            .collect()
+    }
+
+    #[test]
+    fn test_varint() {
+        for value in [
+            0u64,
+            0x7f,
+            0x80,
+            SECP256K1_PUB,
+            ED25519_PUB,
+            0x1200,
+            u32::MAX as u64,
+        ] {
+            let mut bytes = vec![];
+            encode_varint(value, &mut bytes);
+            bytes.push(0xff);
+            assert_eq!(decode_varint(&bytes), Some((value, &[0xff][..])));
+        }
+        let mut bytes = vec![];
+        encode_varint(ED25519_PUB, &mut bytes);
+        assert_eq!(bytes, [0xed, 0x01]);
+        assert_eq!(decode_varint(&[0x80]), None);
+    }
+
+    #[test]
+    fn test_ed25519_vector() {
+        let key = DidKeyPublicKey::from_did(ED25519_DID).unwrap();
+        let DidKeyPublicKey::Ed25519(verifying_key) = key else {
+            panic!("unexpected key type: {:?}", key);
+        };
+        assert_eq!(
+            verifying_key.as_bytes()[..],
+            BASE64URL_NOPAD.decode(ED25519_X.as_bytes()).unwrap()
+        );
+        assert_eq!(key.to_did(), ED25519_DID);
+
+        let document = did_key_document(ED25519_DID).unwrap();
+        let signing_key_id = format!("{}#{}", ED25519_DID, &ED25519_DID[8..]);
+        let key_agreement_id = format!("{}#{}", ED25519_DID, ED25519_X25519_KEY);
+        assert_eq!(document.id, ED25519_DID);
+        for relationship in [
+            Relationship::Authentication,
+            Relationship::AssertionMethod,
+            Relationship::CapabilityInvocation,
+            Relationship::CapabilityDelegation,
+        ] {
+            assert_eq!(ids(&document, relationship), [signing_key_id.as_str()]);
+        }
+        // Ed25519の公開鍵から導出したX25519の公開鍵を、keyAgreementとする
+        assert_eq!(
+            ids(&document, Relationship::KeyAgreement),
+            [key_agreement_id.as_str()]
+        );
+        let method = document.find_verification_method(&signing_key_id).unwrap();
+        assert_eq!(method.r#type, "Ed25519VerificationKey2018");
+        assert_eq!(method.public_key_jwk, Some(Jwk::from(verifying_key)));
+    }
+
+    #[test]
+    fn test_secp256k1_vector() {
+        let key = DidKeyPublicKey::from_did(SECP256K1_DID).unwrap();
+        let DidKeyPublicKey::Secp256k1(public_key) = key else {
+            panic!("unexpected key type: {:?}", key);
+        };
+        let jwk: serde_json::Value =
+            serde_json::to_value(Jwk::try_from(public_key).unwrap()).unwrap();
+        assert_eq!(jwk["crv"], "secp256k1");
+        assert_eq!(jwk["x"], SECP256K1_X);
+        assert_eq!(jwk["y"], SECP256K1_Y);
+        assert_eq!(key.to_did(), SECP256K1_DID);
+
+        let document = did_key_document(SECP256K1_DID).unwrap();
+        assert_eq!(document.verification_method.len(), 1);
+        assert_eq!(
+            document.verification_method[0].r#type,
+            "EcdsaSecp256k1VerificationKey2019"
+        );
+        assert!(document.key_agreement.is_empty());
+    }
+
+    #[test]
+    fn test_p256_vector() {
+        let key = DidKeyPublicKey::from_did(P256_DID).unwrap();
+        let DidKeyPublicKey::P256(public_key) = key else {
+            panic!("unexpected key type: {:?}", key);
+        };
+        let jwk: serde_json::Value =
+            serde_json::to_value(Jwk::try_from(public_key).unwrap()).unwrap();
+        assert_eq!(jwk["crv"], "P-256");
+        assert_eq!(jwk["x"], P256_X);
+        assert_eq!(jwk["y"], P256_Y);
+        assert_eq!(key.to_did(), P256_DID);
+
+        let document = did_key_document(P256_DID).unwrap();
+        assert_eq!(document.verification_method.len(), 1);
+        assert_eq!(document.verification_method[0].r#type, "JsonWebKey2020");
+        assert!(document.key_agreement.is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+
+        let sign = DidKeyPublicKey::from(keyring.sign().get_public_key());
+        assert!(sign.to_did().starts_with("did:key:zQ3s"));
+        assert_eq!(DidKeyPublicKey::from_did(&sign.to_did()).unwrap(), sign);
+        let p256 = DidKeyPublicKey::from(
+            SignKeyPair::random(&mut OsRng, SignKeyType::P256).get_public_key(),
+        );
+        assert!(p256.to_did().starts_with("did:key:zDn"));
+        assert_eq!(DidKeyPublicKey::from_did(&p256.to_did()).unwrap(), p256);
+
+        let encrypt = DidKeyPublicKey::X25519(keyring.encrypt().get_public_key());
+        assert!(encrypt.to_did().starts_with("did:key:z6LS"));
+        assert_eq!(
+            DidKeyPublicKey::from_did(&encrypt.to_did()).unwrap(),
+            encrypt
+        );
+        let document = did_key_document(&encrypt.to_did()).unwrap();
+        assert!(document.authentication.is_empty());
+        assert_eq!(document.key_agreement.len(), 1);
+
+        // DID URLの場合は、DID部分のみを利用する
+        let did_url = format!("{}#{}", sign.to_did(), sign.to_multibase());
+        assert_eq!(DidKeyPublicKey::from_did(&did_url).unwrap(), sign);
+        assert_eq!(did_key_document(&did_url).unwrap().id, sign.to_did());
+    }
+
+    #[test]
+    fn test_invalid_did_key() {
+        assert!(matches!(
+            DidKeyPublicKey::from_did("did:miax:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK"),
+            Err(DidKeyError::InvalidDid(_))
+        ));
+        // base58btc以外のmultibase
+        assert!(matches!(
+            DidKeyPublicKey::from_did("did:key:u7QE"),
+            Err(DidKeyError::InvalidDid(_))
+        ));
+        assert!(matches!(
+            DidKeyPublicKey::from_did("did:key:z0OIl"),
+            Err(DidKeyError::Decode(_))
+        ));
+
+        // 未対応のmulticodec（rsa-pub）
+        let mut bytes = vec![];
+        encode_varint(0x1205, &mut bytes);
+        bytes.extend([0u8; 32]);
+        let multibase = format!("z{}", bs58::encode(bytes).into_string());
+        assert!(matches!(
+            DidKeyPublicKey::from_multibase(&multibase),
+            Err(DidKeyError::UnsupportedCodec(0x1205))
+        ));
+
+        // 公開鍵の長さが不正
+        let mut bytes = vec![];
+        encode_varint(ED25519_PUB, &mut bytes);
+        bytes.extend([0u8; 31]);
+        let multibase = format!("z{}", bs58::encode(bytes).into_string());
+        assert!(matches!(
+            DidKeyPublicKey::from_multibase(&multibase),
+            Err(DidKeyError::InvalidKey)
+        ));
+    }
+
+    #[test]
+    fn test_resolver() {
+        assert!(DidKeyResolver.supports("key"));
+        assert!(!DidKeyResolver.supports("miax"));
+
+        let result = block_on(DidKeyResolver.resolve(ED25519_DID)).unwrap();
+        assert_eq!(result.did_document.unwrap().id, ED25519_DID);
+
+        let result = block_on(DidKeyResolver.resolve("did:key:invalid")).unwrap();
+        assert_eq!(
+            result.did_resolution_metadata.error,
+            Some(ResolutionError::InvalidDid)
+        );
+        assert!(result.did_document.is_none());
+    }
+}
@@ -0,0 +1,374 @@
+// did:peerメソッド（numalgo 2）
+// 公開鍵とサービスエンドポイントをDIDに埋め込むため、公開のレジストリに登録せずにデバイス間の通信に利用できる
+//
+// did:peer:2.E<暗号化用の公開鍵>.V<署名用の公開鍵>.S<サービス>
+//
+// 参考 : https://identity.foundation/peer-did-method-spec/#method-2-multiple-inception-key-without-doc
+use std::convert::Infallible;
+
+use data_encoding::{BASE64URL, BASE64URL_NOPAD};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use super::did_key::{verification_method, DidKeyError, DidKeyPublicKey};
+use super::document::{DidCoreDocument, Service, VerificationRelationship};
+use super::resolution::{DidDocumentMetadata, DidResolutionResult, ResolutionError};
+use super::resolver::DidResolver;
+use crate::keyring::keypair::{KeyPair, KeyPairing};
+
+pub const DID_PEER_METHOD: &str = "peer";
+
+const NUMALGO_2: char = '2';
+const DIDCOMM_MESSAGING: &str = "DIDCommMessaging";
+
+// サービスのJSONで利用する省略形（キー・値）
+const ABBREVIATIONS: [(&str, &str); 4] = [
+    ("type", "t"),
+    ("serviceEndpoint", "s"),
+    ("routingKeys", "r"),
+    ("accept", "a"),
+];
+const DIDCOMM_MESSAGING_ABBREVIATION: &str = "dm";
+
+#[derive(Debug, Error)]
+pub enum DidPeerError {
+    #[error("invalid did:peer: {0}")]
+    InvalidDid(String),
+    #[error("unsupported numalgo: {0}")]
+    UnsupportedNumalgo(String),
+    #[error("unsupported purpose code: {0}")]
+    InvalidPurpose(char),
+    #[error("invalid public key: {0}")]
+    Key(#[from] DidKeyError),
+    #[error("failed to decode service: {0}")]
+    Decode(#[from] data_encoding::DecodeError),
+    #[error("failed to parse service: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+// サービスのJSONを省略形に変換する
+fn abbreviate(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let key = ABBREVIATIONS
+                        .iter()
+                        .find(|(long, _)| *long == key)
+                        .map_or(key, |(_, short)| short.to_string());
+                    let value = match value {
+                        Value::String(s) if s == DIDCOMM_MESSAGING => {
+                            Value::String(DIDCOMM_MESSAGING_ABBREVIATION.to_string())
+                        }
+                        value => abbreviate(value),
+                    };
+                    (key, value)
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(abbreviate).collect()),
+        value => value,
+    }
+}
+
+// 省略形のサービスのJSONを元に戻す
+fn expand(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let key = ABBREVIATIONS
+                        .iter()
+                        .find(|(_, short)| *short == key)
+                        .map_or(key, |(long, _)| long.to_string());
+                    let value = match value {
+                        Value::String(s)
+                            if key == "type" && s == DIDCOMM_MESSAGING_ABBREVIATION =>
+                        {
+                            Value::String(DIDCOMM_MESSAGING.to_string())
+                        }
+                        value => expand(value),
+                    };
+                    (key, value)
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(expand).collect()),
+        value => value,
+    }
+}
+
+/// 鍵ペアの署名鍵・暗号化鍵と、DIDCommのサービスエンドポイントからdid:peer（numalgo 2）を生成する
+///
+/// 生成したDIDは公開のレジストリに登録されないため、通信相手に直接伝える必要がある
+pub fn did_peer_identifier(keyring: &KeyPairing, service_endpoint: Option<&str>) -> String {
+    let encrypt = DidKeyPublicKey::X25519(keyring.encrypt().get_public_key());
+    let sign = DidKeyPublicKey::from(keyring.sign().get_public_key());
+
+    let mut did = format!(
+        "did:{}:{}.E{}.V{}",
+        DID_PEER_METHOD,
+        NUMALGO_2,
+        encrypt.to_multibase(),
+        sign.to_multibase()
+    );
+    if let Some(endpoint) = service_endpoint {
+        let mut service = Map::new();
+        service.insert("type".to_string(), DIDCOMM_MESSAGING.into());
+        service.insert("serviceEndpoint".to_string(), endpoint.into());
+        // JCSで正規化し、同じ内容からは常に同じDIDを生成する
+        let service = serde_jcs::to_string(&abbreviate(Value::Object(service)))
+            .expect("failed to serialize service");
+        did.push_str(".S");
+        did.push_str(&BASE64URL_NOPAD.encode(service.as_bytes()));
+    }
+    did
+}
+
+/// did:peer（numalgo 2）からDIDドキュメントを生成する
+///
+/// - E : keyAgreement
+/// - V : authentication
+/// - A : assertionMethod
+/// - I : capabilityInvocation
+/// - D : capabilityDelegation
+/// - S : service
+pub fn did_peer_document(did: &str) -> Result<DidCoreDocument, DidPeerError> {
+    let prefix = format!("did:{}:", DID_PEER_METHOD);
+    let id = did
+        .strip_prefix(&prefix)
+        .ok_or_else(|| DidPeerError::InvalidDid(did.to_string()))?;
+    let mut elements = id.split('.');
+    match elements.next() {
+        Some("2") => {}
+        Some(numalgo) => return Err(DidPeerError::UnsupportedNumalgo(numalgo.to_string())),
+        None => return Err(DidPeerError::InvalidDid(did.to_string())),
+    }
+
+    let mut document = DidCoreDocument::new(did);
+    for element in elements {
+        let mut chars = element.chars();
+        let purpose = chars
+            .next()
+            .ok_or_else(|| DidPeerError::InvalidDid(did.to_string()))?;
+        let value = chars.as_str();
+
+        if purpose == 'S' {
+            // パディングの有無のどちらも受け付ける
+            let decoded = BASE64URL_NOPAD
+                .decode(value.as_bytes())
+                .or_else(|_| BASE64URL.decode(value.as_bytes()))?;
+            let mut service = expand(serde_json::from_slice(&decoded)?);
+            let index = document.service.len();
+            let id = match index {
+                0 => format!("{}#service", did),
+                _ => format!("{}#service-{}", did, index),
+            };
+            let object = service
+                .as_object_mut()
+                .ok_or_else(|| DidPeerError::InvalidDid(did.to_string()))?;
+            object.entry("id").or_insert_with(|| id.into());
+            let mut service: Service = serde_json::from_value(service)?;
+            // サービスのidは相対DID URLで記述されることがある
+            if service.id.starts_with('#') {
+                service.id = format!("{}{}", did, service.id);
+            }
+            document.service.push(service);
+            continue;
+        }
+
+        let key = DidKeyPublicKey::from_multibase(value)?;
+        let method_id = format!("{}#key-{}", did, document.verification_method.len() + 1);
+        let reference = VerificationRelationship::Reference(method_id.clone());
+        let relationship = match purpose {
+            'E' => &mut document.key_agreement,
+            'V' => &mut document.authentication,
+            'A' => &mut document.assertion_method,
+            'I' => &mut document.capability_invocation,
+            'D' => &mut document.capability_delegation,
+            purpose => return Err(DidPeerError::InvalidPurpose(purpose)),
+        };
+        relationship.push(reference);
+        document
+            .verification_method
+            .push(verification_method(method_id, did, &key));
+    }
+
+    Ok(document)
+}
+
+/// did:peerのResolver
+/// DIDに公開鍵とサービスが含まれるため、ネットワークへの問い合わせは発生しない
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DidPeerResolver;
+
+impl DidResolver for DidPeerResolver {
+    type Error = Infallible;
+
+    fn supports(&self, method: &str) -> bool {
+        method == DID_PEER_METHOD
+    }
+
+    async fn resolve(&self, did: &str) -> Result<DidResolutionResult, Self::Error> {
+        Ok(match did_peer_document(did) {
+            Ok(document) => DidResolutionResult::resolved(document, DidDocumentMetadata::default()),
+            Err(DidPeerError::UnsupportedNumalgo(_)) => {
+                DidResolutionResult::error(ResolutionError::MethodNotSupported)
+            }
+            Err(_) => DidResolutionResult::error(ResolutionError::InvalidDid),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::did_repository::{get_encrypt_key, get_sign_key};
+    use crate::did::document::Relationship;
+    use crate::did::sidetree::payload::MiaxDidResponse;
+    use futures::executor::block_on;
+    use rand_core::OsRng;
+
+    // did:peer仕様の例（numalgo 2）
+    // https://identity.foundation/peer-did-method-spec/#example-2
+    const SPEC_DID: &str = "did:peer:2.Ez6LSbysY2xFMRpGMhb7tFTLMpeuPRaqaWM1yECx2AtzE3KCc.Vz6MkqRYqQiSgvZQdnBytw86Qbs2ZWUkGv22od935YF4s8M7V.Vz6MkgoLTnTypo3tDRwCkZXSccTPHRLhF4ZnjhueYAFpEX6vg.SeyJ0IjoiZG0iLCJzIjoiaHR0cHM6Ly9leGFtcGxlLmNvbS9lbmRwb2ludCIsInIiOlsiZGlkOmV4YW1wbGU6c29tZW1lZGlhdG9yI3NvbWVrZXkiXSwiYSI6WyJkaWRjb21tL3YyIiwiZGlkY29tbS9haXAyO2Vudj1yZmM1ODciXX0";
+
+    fn ids(document: &DidCoreDocument, relationship: Relationship) -> Vec<String> {
+        document
+            .relationship(relationship)
+            .iter()
+            .map(|r| r.id().trim_start_matches(SPEC_DID).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_spec_example() {
+        let document = did_peer_document(SPEC_DID).unwrap();
+        assert_eq!(document.id, SPEC_DID);
+        assert_eq!(ids(&document, Relationship::KeyAgreement), ["#key-1"]);
+        assert_eq!(
+            ids(&document, Relationship::Authentication),
+            ["#key-2", "#key-3"]
+        );
+        assert_eq!(
+            document
+                .verification_method
+                .iter()
+                .map(|method| method.r#type.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "X25519KeyAgreementKey2019",
+                "Ed25519VerificationKey2018",
+                "Ed25519VerificationKey2018"
+            ]
+        );
+
+        assert_eq!(document.service.len(), 1);
+        let service = &document.service[0];
+        assert_eq!(service.id, format!("{}#service", SPEC_DID));
+        assert_eq!(service.r#type, DIDCOMM_MESSAGING);
+        assert_eq!(service.service_endpoint, "https://example.com/endpoint");
+    }
+
+    #[test]
+    fn test_abbreviation() {
+        let service = serde_json::json!({
+            "type": "DIDCommMessaging",
+            "serviceEndpoint": "https://example.com/endpoint",
+            "routingKeys": ["did:example:somemediator#somekey"],
+            "accept": ["didcomm/v2"],
+        });
+        let abbreviated = abbreviate(service.clone());
+        assert_eq!(
+            abbreviated,
+            serde_json::json!({
+                "t": "dm",
+                "s": "https://example.com/endpoint",
+                "r": ["did:example:somemediator#somekey"],
+                "a": ["didcomm/v2"],
+            })
+        );
+        assert_eq!(expand(abbreviated), service);
+    }
+
+    #[test]
+    fn test_identifier_round_trip() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let endpoint = "https://alice.example/didcomm";
+        let did = did_peer_identifier(&keyring, Some(endpoint));
+        assert!(did.starts_with("did:peer:2.Ez6LS"));
+        // 同じ内容からは常に同じDIDを生成する
+        assert_eq!(did_peer_identifier(&keyring, Some(endpoint)), did);
+
+        let document = did_peer_document(&did).unwrap();
+        assert_eq!(document.service[0].service_endpoint, endpoint);
+        let response = MiaxDidResponse::from(document);
+        assert_eq!(
+            get_sign_key(&response.did_document).unwrap(),
+            keyring.sign().get_public_key()
+        );
+        assert_eq!(
+            get_encrypt_key(&response.did_document).unwrap(),
+            keyring.encrypt().get_public_key()
+        );
+
+        let did = did_peer_identifier(&keyring, None);
+        assert!(!did.contains(".S"));
+        assert!(did_peer_document(&did).unwrap().service.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_did_peer() {
+        assert!(matches!(
+            did_peer_document("did:key:z6Mk"),
+            Err(DidPeerError::InvalidDid(_))
+        ));
+        assert!(matches!(
+            did_peer_document("did:peer:0z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK"),
+            Err(DidPeerError::UnsupportedNumalgo(_))
+        ));
+        assert!(matches!(
+            did_peer_document("did:peer:2.Xz6LSbysY2xFMRpGMhb7tFTLMpeuPRaqaWM1yECx2AtzE3KCc"),
+            Err(DidPeerError::InvalidPurpose('X'))
+        ));
+        assert!(matches!(
+            did_peer_document("did:peer:2..Vz6MkqRYqQiSgvZQdnBytw86Qbs2ZWUkGv22od935YF4s8M7V"),
+            Err(DidPeerError::InvalidDid(_))
+        ));
+        assert!(matches!(
+            did_peer_document("did:peer:2.Vz6MkqRYqQ"),
+            Err(DidPeerError::Key(_))
+        ));
+        assert!(matches!(
+            did_peer_document("did:peer:2.S!!!"),
+            Err(DidPeerError::Decode(_))
+        ));
+        // サービスがJSONオブジェクトでない
+        let service = BASE64URL_NOPAD.encode(b"[]");
+        assert!(matches!(
+            did_peer_document(&format!("did:peer:2.S{}", service)),
+            Err(DidPeerError::InvalidDid(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolver() {
+        assert!(DidPeerResolver.supports("peer"));
+        let result = block_on(DidPeerResolver.resolve(SPEC_DID)).unwrap();
+        assert_eq!(result.did_document.unwrap().id, SPEC_DID);
+
+        let result = block_on(
+            DidPeerResolver.resolve("did:peer:0z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK"),
+        )
+        .unwrap();
+        assert_eq!(
+            result.did_resolution_metadata.error,
+            Some(ResolutionError::MethodNotSupported)
+        );
+        let result = block_on(DidPeerResolver.resolve("did:peer:2.X")).unwrap();
+        assert_eq!(
+            result.did_resolution_metadata.error,
+            Some(ResolutionError::InvalidDid)
+        );
+    }
+}
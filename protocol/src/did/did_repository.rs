@@ -1,20 +1,39 @@
+use std::convert::Infallible;
+
 use http::StatusCode;
 
+use super::did_url::{dereference_document, DereferencedResource, DidUrl};
+use super::document::{absolute_id, DidCoreDocument, Relationship};
+use super::resolution::{DidDocumentMetadata, DidResolutionResult, ResolutionError};
 use super::sidetree::{
     client::SidetreeHttpClient,
-    payload::{did_create_payload, DidDocument, DidPatchDocument, MiaxDidResponse, ToPublicKey},
+    long_form::{long_form_did, resolve_long_form, split_long_form, LongFormDidError},
+    payload::{
+        did_create_payload, did_create_suffix, did_deactivate_payload, did_recover_payload,
+        did_update_payload, DidAction, DidCreatePayloadError, DidDocument, DidPatchDocument,
+        MiaxDidResponse, PublicKeyPayload, ToPublicKey, DID_METHOD,
+    },
 };
 use crate::keyring::{
-    jwk::Jwk,
-    keypair::{KeyPair, KeyPairing},
+    jwk::{Jwk, SignPublicKeyToJwkError},
+    keypair::{KeyPairing, PublicKeyPairing, SignKeyType, SignPublicKey},
+    signer::Signer,
 };
 
+/// DIDドキュメントに登録する署名鍵のid
+pub const SIGNING_KEY_ID: &str = "signingKey";
+/// DIDドキュメントに登録する暗号化鍵のid
+pub const ENCRYPTION_KEY_ID: &str = "encryptionKey";
+const LEGACY_SIGNING_KEY_ID: &str = "signingkey";
+
 // ”protocol”クレートはライブラリとして利用されることを想定しているため、anyhowは使用しない
 
 #[derive(Debug, thiserror::Error)]
 pub enum CreateIdentifierError<StudioClientError: std::error::Error> {
     #[error("Failed to convert to JWK: {0}")]
     Jwk(#[from] crate::keyring::jwk::K256ToJwkError),
+    #[error("Failed to convert to JWK: {0}")]
+    SignKeyJwk(#[from] SignPublicKeyToJwkError),
     #[error("Failed to build operation payload: {0}")]
     PayloadBuildFailed(#[from] crate::did::sidetree::payload::DidCreatePayloadError),
     #[error("Failed to parse body: {0}")]
@@ -23,6 +42,52 @@ pub enum CreateIdentifierError<StudioClientError: std::error::Error> {
     SidetreeRequestFailed(String),
     #[error("Failed to send request: {0}")]
     SidetreeHttpClient(StudioClientError),
+    #[error("DID mismatch. expected: {expected}, actual: {actual}")]
+    DidMismatch { expected: String, actual: String },
+    #[error("Public key mismatch: {0}")]
+    PublicKeyMismatch(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum UpdateIdentifierError<StudioClientError: std::error::Error> {
+    #[error("Invalid DID: {0}")]
+    InvalidDid(String),
+    #[error("Invalid patch: {0}")]
+    InvalidPatch(#[from] crate::did::sidetree::patch::DidPatchError),
+    #[error("Failed to build operation payload: {0}")]
+    PayloadBuildFailed(#[from] crate::did::sidetree::payload::DidUpdatePayloadError),
+    #[error("Failed to update identifier. response: {0}")]
+    SidetreeRequestFailed(String),
+    #[error("Failed to send request: {0}")]
+    SidetreeHttpClient(StudioClientError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RecoverIdentifierError<StudioClientError: std::error::Error> {
+    #[error("Invalid DID: {0}")]
+    InvalidDid(String),
+    #[error("Failed to convert to JWK: {0}")]
+    Jwk(#[from] crate::keyring::jwk::K256ToJwkError),
+    #[error("Failed to convert to JWK: {0}")]
+    SignKeyJwk(#[from] SignPublicKeyToJwkError),
+    #[error("Failed to build operation payload: {0}")]
+    PayloadBuildFailed(#[from] crate::did::sidetree::payload::DidRecoverPayloadError),
+    #[error("Failed to recover identifier. response: {0}")]
+    SidetreeRequestFailed(String),
+    #[error("Failed to send request: {0}")]
+    SidetreeHttpClient(StudioClientError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DeactivateIdentifierError<StudioClientError: std::error::Error> {
+    #[error("Invalid DID: {0}")]
+    InvalidDid(String),
+    #[error("Failed to build operation payload: {0}")]
+    PayloadBuildFailed(#[from] crate::did::sidetree::payload::DidDeactivatePayloadError),
+    #[error("Failed to deactivate identifier. response: {0}")]
+    SidetreeRequestFailed(String),
+    #[error("Failed to send request: {0}")]
+    SidetreeHttpClient(StudioClientError),
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -31,6 +96,8 @@ pub enum FindIdentifierError<StudioClientError: std::error::Error> {
     SidetreeRequestFailed(String),
     #[error("Failed to parse body: {0}")]
     BodyParse(#[from] serde_json::Error),
+    #[error("Failed to resolve long-form DID: {0}")]
+    LongForm(#[from] crate::did::sidetree::long_form::LongFormDidError),
     #[error("Failed to send request: {0}")]
     SidetreeHttpClient(StudioClientError),
 }
@@ -43,28 +110,232 @@ pub enum GetPublicKeyError {
     JwkToK256(#[from] crate::keyring::jwk::JwkToK256Error),
     #[error("Failed to convert from JWK: {0}")]
     JwkToX25519(#[from] crate::keyring::jwk::JwkToX25519Error),
+    #[error("Failed to convert from JWK: {0}")]
+    JwkToSignPublicKey(#[from] crate::keyring::jwk::JwkToSignPublicKeyError),
+    #[error("Invalid verification method: {0}")]
+    InvalidVerificationMethod(#[from] crate::did::did_url::DidUrlError),
+    #[error("Unsupported proof purpose: {0}")]
+    UnsupportedProofPurpose(String),
+    #[error("Public key {id} is not authorized for {proof_purpose}")]
+    NotAuthorized { id: String, proof_purpose: String },
+}
+
+/// DID（did:<method>:<suffix>）からsuffix部分を取り出す
+/// long-form DID（did:<method>:<suffix>:<initial-state>）の場合も、suffix部分のみを返す
+fn did_suffix(did: &str) -> Option<&str> {
+    let mut parts = did.splitn(4, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("did"), Some(_), Some(suffix)) if !suffix.is_empty() => Some(suffix),
+        _ => None,
+    }
+}
+
+/// 鍵ペアから、long-form DID（did:miax:<suffix>:<initial-state>）を生成する
+///
+/// long-form DIDはSidetreeへの登録前から解決できるため、鍵を生成した直後からメッセージの送受信に利用できる
+/// 同じ鍵ペアでcreate_identifierを行うと、suffixが一致するshort-form DIDとして登録される
+pub fn long_form_identifier(keyring: &KeyPairing) -> Result<String, LongFormDidError> {
+    let keyring = keyring.public_keys();
+    let document = keyring_to_document(&keyring).map_err(DidCreatePayloadError::from)?;
+    long_form_did(document, keyring.update, keyring.recovery)
+}
+
+// 署名鍵の種類に対応する、DIDドキュメント上の公開鍵のtype
+fn sign_key_type(key_type: SignKeyType) -> &'static str {
+    match key_type {
+        SignKeyType::Secp256k1 => "EcdsaSecp256k1VerificationKey2019",
+        SignKeyType::P256 => "JsonWebKey2020",
+        SignKeyType::Ed25519 => "Ed25519VerificationKey2018",
+    }
+}
+
+/// DIDドキュメントに登録する署名鍵（#signingKey）の公開鍵
+/// 公開鍵のtypeは、署名鍵の種類（secp256k1・P-256・Ed25519）に従う
+pub fn signing_key_payload(
+    public_key: &SignPublicKey,
+) -> Result<PublicKeyPayload, SignPublicKeyToJwkError> {
+    (*public_key).to_public_key(
+        sign_key_type(public_key.key_type()).to_string(),
+        SIGNING_KEY_ID.to_string(),
+        vec!["auth".to_string(), "general".to_string()],
+    )
 }
 
-fn get_key(key_type: &str, did_document: &DidDocument) -> Result<Jwk, GetPublicKeyError> {
-    let did = &did_document.id;
-    let public_key = did_document
-        .public_key
-        .clone()
-        .and_then(|pks| pks.into_iter().find(|pk| pk.id == key_type))
-        .ok_or(GetPublicKeyError::PublicKeyNotFound(did.to_string()))?;
-    Ok(public_key.public_key_jwk)
+/// DIDドキュメントに登録する暗号化鍵（#encryptionKey）の公開鍵
+/// X25519の公開鍵は常にJWKに変換できるため、エラーにはならない
+pub fn encryption_key_payload(
+    public_key: &x25519_dalek::PublicKey,
+) -> Result<PublicKeyPayload, Infallible> {
+    (*public_key).to_public_key(
+        "X25519KeyAgreementKey2019".to_string(),
+        ENCRYPTION_KEY_ID.to_string(),
+        vec!["auth".to_string(), "general".to_string()],
+    )
 }
 
-pub fn get_sign_key(did_document: &DidDocument) -> Result<k256::PublicKey, GetPublicKeyError> {
-    let public_key = get_key("#signingKey", did_document)?;
-    Ok(public_key.try_into()?)
+/// 鍵ペアから、DIDドキュメントに登録する公開鍵（署名鍵・暗号化鍵）を組み立てる
+fn keyring_to_document(
+    keyring: &PublicKeyPairing,
+) -> Result<DidPatchDocument, SignPublicKeyToJwkError> {
+    let Ok(enc) = encryption_key_payload(&keyring.encrypt);
+    Ok(DidPatchDocument {
+        public_keys: vec![signing_key_payload(&keyring.sign)?, enc],
+        service_endpoints: vec![],
+    })
 }
 
+/// ノードが返したDIDドキュメントが、create操作で送信した内容と一致するかを検証する
+///
+/// - DIDは、送信したsuffix dataから計算したものと一致すること
+/// - 公開鍵は、送信したものと過不足なく一致すること（id・type・JWKの鍵の値）
+fn verify_created_document<E: std::error::Error>(
+    expected_did: &str,
+    submitted: &DidPatchDocument,
+    did_document: &DidDocument,
+) -> Result<(), CreateIdentifierError<E>> {
+    if did_document.id != expected_did {
+        return Err(CreateIdentifierError::DidMismatch {
+            expected: expected_did.to_string(),
+            actual: did_document.id.clone(),
+        });
+    }
+
+    let public_keys = did_document.public_key.as_deref().unwrap_or_default();
+    if public_keys.len() != submitted.public_keys.len() {
+        return Err(CreateIdentifierError::PublicKeyMismatch(format!(
+            "expected {} keys, got {}",
+            submitted.public_keys.len(),
+            public_keys.len()
+        )));
+    }
+    for expected in &submitted.public_keys {
+        // DIDドキュメント上のidは、"#signingKey"や"did:miax:xxx#signingKey"の形式となる
+        let actual = public_keys
+            .iter()
+            .find(|pk| pk.id.rsplit('#').next() == Some(expected.id.as_str()))
+            .ok_or_else(|| CreateIdentifierError::PublicKeyMismatch(expected.id.clone()))?;
+        if actual.r#type != expected.r#type || !actual.public_key_jwk.is_same_key(&expected.jwk) {
+            return Err(CreateIdentifierError::PublicKeyMismatch(
+                expected.id.clone(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// long-form DIDに埋め込まれた初期状態から、DID Resolutionの結果を生成する
+fn resolve_long_form_result(did: &str) -> DidResolutionResult {
+    match resolve_long_form(did) {
+        Ok(response) => DidResolutionResult::resolved(
+            DidCoreDocument::from(response),
+            DidDocumentMetadata::default(),
+        ),
+        Err(_) => DidResolutionResult::error(ResolutionError::InvalidDid),
+    }
+}
+
+/// verification relationshipに登録された公開鍵のうち、`key_id`（フラグメント）の公開鍵を返す
+/// `key_id`の公開鍵が登録されていない場合（他の実装で作成されたDIDドキュメントなど）は、
+/// 指定した鍵の種類に変換できる最初の公開鍵を返す
+/// relationshipsは優先度の高い順に指定する
+fn get_key<T>(
+    relationships: &[Relationship],
+    key_id: &str,
+    did_document: &DidDocument,
+) -> Result<T, GetPublicKeyError>
+where
+    T: TryFrom<Jwk>,
+    T::Error: Into<GetPublicKeyError>,
+{
+    let document = DidCoreDocument::from(did_document);
+    let key_id = format!("{}#{}", document.id, key_id);
+    let methods = || {
+        relationships
+            .iter()
+            .flat_map(|r| document.verification_methods(*r))
+    };
+
+    // 鍵を追加したDIDドキュメントでも、登録順に依存せずに同じ鍵を用いる
+    if let Some(method) = methods().find(|method| absolute_id(&document.id, &method.id) == key_id) {
+        let jwk = method
+            .public_key_jwk
+            .clone()
+            .ok_or(GetPublicKeyError::PublicKeyNotFound(key_id))?;
+        return T::try_from(jwk).map_err(Into::into);
+    }
+
+    let mut last_error = None;
+    for method in methods() {
+        let Some(jwk) = method.public_key_jwk.clone() else {
+            continue;
+        };
+        match T::try_from(jwk) {
+            Ok(key) => return Ok(key),
+            Err(e) => last_error = Some(e.into()),
+        }
+    }
+    Err(last_error.unwrap_or(GetPublicKeyError::PublicKeyNotFound(document.id)))
+}
+
+/// 署名検証用の公開鍵（assertionMethod、なければauthenticationの#signingKey）を返す
+pub fn get_sign_key(did_document: &DidDocument) -> Result<SignPublicKey, GetPublicKeyError> {
+    get_key(
+        &[Relationship::AssertionMethod, Relationship::Authentication],
+        SIGNING_KEY_ID,
+        did_document,
+    )
+}
+
+/// proofのverificationMethod（did:miax:xxx#signingKey）で指定された、署名検証用の公開鍵を返す
+///
+/// 公開鍵は、proofPurposeに対応するverification relationshipに登録されている必要がある
+/// verificationMethodのDIDがDIDドキュメントのDIDと一致するかは、呼び出し側で検証する
+pub fn get_verification_key(
+    did_document: &DidDocument,
+    verification_method: &str,
+    proof_purpose: &str,
+) -> Result<SignPublicKey, GetPublicKeyError> {
+    let mut did_url = DidUrl::parse(verification_method)?;
+    // 以前のバージョンは、DIDドキュメントと異なるid（#signingkey）でproofを生成していた
+    if did_url.fragment.as_deref() == Some(LEGACY_SIGNING_KEY_ID) {
+        did_url.fragment = Some(SIGNING_KEY_ID.to_string());
+    }
+    let relationship = Relationship::from_proof_purpose(proof_purpose)
+        .ok_or_else(|| GetPublicKeyError::UnsupportedProofPurpose(proof_purpose.to_string()))?;
+    let document = DidCoreDocument::from(did_document);
+
+    let method = match dereference_document(&document, &did_url) {
+        Some(DereferencedResource::VerificationMethod(method)) => method,
+        _ => {
+            return Err(GetPublicKeyError::PublicKeyNotFound(
+                verification_method.to_string(),
+            ))
+        }
+    };
+    if !document
+        .verification_methods(relationship)
+        .any(|m| absolute_id(&document.id, &m.id) == absolute_id(&document.id, &method.id))
+    {
+        return Err(GetPublicKeyError::NotAuthorized {
+            id: verification_method.to_string(),
+            proof_purpose: proof_purpose.to_string(),
+        });
+    }
+    let jwk = method
+        .public_key_jwk
+        .ok_or_else(|| GetPublicKeyError::PublicKeyNotFound(verification_method.to_string()))?;
+    Ok(SignPublicKey::try_from(jwk)?)
+}
+
+/// 暗号化用の公開鍵（keyAgreementの#encryptionKey）を返す
 pub fn get_encrypt_key(
     did_document: &DidDocument,
 ) -> Result<x25519_dalek::PublicKey, GetPublicKeyError> {
-    let public_key = get_key("#encryptionKey", did_document)?;
-    Ok(public_key.try_into()?)
+    get_key(
+        &[Relationship::KeyAgreement],
+        ENCRYPTION_KEY_ID,
+        did_document,
+    )
 }
 
 // Send: ある型Tが”スレッド間で安全に所有権を移動できること"を示す
@@ -74,15 +345,49 @@ pub fn get_encrypt_key(
 #[trait_variant::make(Send)] // トレイト自体がSendであり、トレイトオブジェクト（dyn Trait）が"Send + Sync + 'static"であることを保証
 pub trait DidRepository: Sync {
     type CreateIdentifierError: std::error::Error + Send + Sync;
+    type UpdateIdentifierError: std::error::Error + Send + Sync;
+    type RecoverIdentifierError: std::error::Error + Send + Sync;
+    type DeactivateIdentifierError: std::error::Error + Send + Sync;
     type FindIdentifierError: std::error::Error + Send + Sync;
-    async fn create_identifier(
+    /// 鍵ペアの公開鍵を登録したcreate操作を送信する
+    /// 署名鍵の種類は、`KeyPairing::create_keyring_with_sign_key`で鍵ペアを生成する際に選択する
+    /// 公開鍵のみを利用するため、秘密鍵を取り出せないキーストアの鍵は`PublicKeyPairing`で渡す
+    async fn create_identifier<K: Into<PublicKeyPairing> + Send>(
         &self,
-        keyring: KeyPairing,
+        keyring: K,
     ) -> Result<MiaxDidResponse, Self::CreateIdentifierError>;
+    /// 現在の更新鍵で署名したupdate操作を送信し、DIDドキュメントに変更内容（patches）を適用する
+    /// 更新鍵・リカバリ鍵はsecp256k1の`Signer`で渡すため、秘密鍵を取り出せないキーストアの鍵でも署名できる
+    /// 成功後は`new_update_key`が次回の更新鍵となるため、呼び出し側で永続化する必要がある
+    async fn update_identifier<S: Signer + Sync>(
+        &self,
+        did: &str,
+        update_key: &S,
+        new_update_key: k256::PublicKey,
+        patches: Vec<DidAction>,
+    ) -> Result<(), Self::UpdateIdentifierError>;
+    /// 現在のリカバリ鍵で署名したrecover操作を送信し、DIDドキュメントを`new_keyring`の鍵で置き換える
+    /// 成功後は`new_keyring`の全ての鍵（更新鍵・リカバリ鍵を含む）が有効となる
+    /// 公開鍵のみを利用するため、鍵ストアに反映待ちとして保存した鍵は`PublicKeyPairing`で渡す
+    async fn recover_identifier<S: Signer + Sync, K: Into<PublicKeyPairing> + Send>(
+        &self,
+        did: &str,
+        recovery_key: &S,
+        new_keyring: K,
+    ) -> Result<(), Self::RecoverIdentifierError>;
+    /// 現在のリカバリ鍵で署名したdeactivate操作を送信し、DIDを恒久的に無効化する
+    async fn deactivate_identifier<S: Signer + Sync>(
+        &self,
+        did: &str,
+        recovery_key: &S,
+    ) -> Result<(), Self::DeactivateIdentifierError>;
     async fn find_identifier(
         &self,
         did: &str,
     ) -> Result<Option<MiaxDidResponse>, Self::FindIdentifierError>;
+    /// DIDを解決し、DIDドキュメントとメタデータを含むDID Resolutionの結果を返す
+    /// 不正なDIDや存在しないDIDはエラーとせず、didResolutionMetadataのerrorとして返す
+    async fn resolve(&self, did: &str) -> Result<DidResolutionResult, Self::FindIdentifierError>;
 }
 
 // Sidetreeプロトコルとの通信
@@ -103,34 +408,19 @@ where
     C::Error: Send + Sync,
 {
     type CreateIdentifierError = CreateIdentifierError<C::Error>;
+    type UpdateIdentifierError = UpdateIdentifierError<C::Error>;
+    type RecoverIdentifierError = RecoverIdentifierError<C::Error>;
+    type DeactivateIdentifierError = DeactivateIdentifierError<C::Error>;
     type FindIdentifierError = FindIdentifierError<C::Error>;
 
-    async fn create_identifier(
+    async fn create_identifier<K: Into<PublicKeyPairing> + Send>(
         &self,
-        keyring: KeyPairing,
+        keyring: K,
     ) -> Result<MiaxDidResponse, CreateIdentifierError<C::Error>> {
-        let sign = keyring.sign.get_public_key().to_public_key(
-            "EcdsaSecp256k1VerificationKey2019".to_string(),
-            "signingKey".to_string(),
-            vec!["auth".to_string(), "general".to_string()],
-        )?;
-
-        let enc = keyring
-            .encrypt
-            .get_public_key()
-            .to_public_key(
-                "X25519KeyAgreementKey2019".to_string(),
-                "encryptionKey".to_string(),
-                vec!["auth".to_string(), "general".to_string()],
-            )
-            .unwrap();
-        let update = keyring.update.get_public_key();
-        let recovery = keyring.recovery.get_public_key();
-        let document = DidPatchDocument {
-            public_keys: vec![sign, enc],
-            service_endpoints: vec![],
-        };
-        let payload = did_create_payload(document, update, recovery)?;
+        let keyring = keyring.into();
+        let document = keyring_to_document(&keyring)?;
+        let payload = did_create_payload(document.clone(), keyring.update, keyring.recovery)?;
+        let expected_did = format!("did:{}:{}", DID_METHOD, did_create_suffix(&payload)?);
 
         let response = self
             .client
@@ -138,7 +428,9 @@ where
             .await
             .map_err(CreateIdentifierError::SidetreeHttpClient)?;
         if response.status_code.is_success() {
-            Ok(serde_json::from_str(&response.body)?)
+            let response: MiaxDidResponse = serde_json::from_str(&response.body)?;
+            verify_created_document(&expected_did, &document, &response.did_document)?;
+            Ok(response)
         } else {
             Err(CreateIdentifierError::SidetreeRequestFailed(format!(
                 "{:?}",
@@ -147,6 +439,92 @@ where
         }
     }
 
+    async fn update_identifier<S: Signer + Sync>(
+        &self,
+        did: &str,
+        update_key: &S,
+        new_update_key: k256::PublicKey,
+        patches: Vec<DidAction>,
+    ) -> Result<(), Self::UpdateIdentifierError> {
+        let suffix =
+            did_suffix(did).ok_or_else(|| UpdateIdentifierError::InvalidDid(did.to_string()))?;
+        for patch in &patches {
+            patch.validate()?;
+        }
+        let payload = did_update_payload(patches, suffix, update_key, new_update_key)?;
+
+        let response = self
+            .client
+            .post_update_identifier(&payload)
+            .await
+            .map_err(UpdateIdentifierError::SidetreeHttpClient)?;
+        if response.status_code.is_success() {
+            Ok(())
+        } else {
+            Err(UpdateIdentifierError::SidetreeRequestFailed(format!(
+                "{:?}",
+                response
+            )))
+        }
+    }
+
+    async fn recover_identifier<S: Signer + Sync, K: Into<PublicKeyPairing> + Send>(
+        &self,
+        did: &str,
+        recovery_key: &S,
+        new_keyring: K,
+    ) -> Result<(), Self::RecoverIdentifierError> {
+        let suffix =
+            did_suffix(did).ok_or_else(|| RecoverIdentifierError::InvalidDid(did.to_string()))?;
+        let new_keyring = new_keyring.into();
+        let document = keyring_to_document(&new_keyring)?;
+        let payload = did_recover_payload(
+            document,
+            suffix,
+            recovery_key,
+            new_keyring.update,
+            new_keyring.recovery,
+        )?;
+
+        let response = self
+            .client
+            .post_recover_identifier(&payload)
+            .await
+            .map_err(RecoverIdentifierError::SidetreeHttpClient)?;
+        if response.status_code.is_success() {
+            Ok(())
+        } else {
+            Err(RecoverIdentifierError::SidetreeRequestFailed(format!(
+                "{:?}",
+                response
+            )))
+        }
+    }
+
+    async fn deactivate_identifier<S: Signer + Sync>(
+        &self,
+        did: &str,
+        recovery_key: &S,
+    ) -> Result<(), Self::DeactivateIdentifierError> {
+        let suffix = did_suffix(did)
+            .ok_or_else(|| DeactivateIdentifierError::InvalidDid(did.to_string()))?;
+        let payload = did_deactivate_payload(suffix, recovery_key)?;
+
+        let response = self
+            .client
+            .post_deactivate_identifier(&payload)
+            .await
+            .map_err(DeactivateIdentifierError::SidetreeHttpClient)?;
+        if response.status_code.is_success() {
+            Ok(())
+        } else {
+            Err(DeactivateIdentifierError::SidetreeRequestFailed(format!(
+                "{:?}",
+                response
+            )))
+        }
+    }
+
     async fn find_identifier(
         &self,
         did: &str,
@@ -159,7 +537,69 @@ where
 
         match response.status_code {
             StatusCode::OK => Ok(Some(serde_json::from_str(&response.body)?)),
-            StatusCode::NOT_FOUND => Ok(None),
+            // long-form DIDは、Sidetreeに登録されていない場合に、埋め込まれた初期状態から解決する
+            // 登録後に鍵が更新されている可能性があるため、Sidetreeに接続できない場合やエラーの場合は初期状態を用いない
+            StatusCode::NOT_FOUND if split_long_form(did).is_some() => {
+                Ok(Some(resolve_long_form(did)?))
+            }
+            // 無効化されたDIDは、存在しないDIDと同様に扱う
+            StatusCode::NOT_FOUND | StatusCode::GONE => Ok(None),
+            _ => Err(FindIdentifierError::SidetreeRequestFailed(format!(
+                "{:?}",
+                response
+            ))),
+        }
+    }
+
+    async fn resolve(&self, did: &str) -> Result<DidResolutionResult, Self::FindIdentifierError> {
+        match did.split(':').nth(1) {
+            Some(DID_METHOD) => {}
+            Some(_) if did.starts_with("did:") => {
+                return Ok(DidResolutionResult::error(
+                    ResolutionError::MethodNotSupported,
+                ))
+            }
+            _ => return Ok(DidResolutionResult::error(ResolutionError::InvalidDid)),
+        }
+        if did_suffix(did).is_none() {
+            return Ok(DidResolutionResult::error(ResolutionError::InvalidDid));
+        }
+
+        let is_long_form = split_long_form(did).is_some();
+        let response = self
+            .client
+            .get_find_identifier(did)
+            .await
+            .map_err(FindIdentifierError::SidetreeHttpClient)?;
+
+        match response.status_code {
+            StatusCode::OK => {
+                let response: MiaxDidResponse = serde_json::from_str(&response.body)?;
+                let mut metadata = response.did_document_metadata.clone().unwrap_or_default();
+                // 登録済みのlong-form DIDは、short-form DIDのドキュメントとして返される
+                if response.did_document.id != did {
+                    metadata.canonical_id = Some(response.did_document.id.clone());
+                }
+                Ok(DidResolutionResult::resolved(
+                    DidCoreDocument::from(response),
+                    metadata,
+                ))
+            }
+            // long-form DIDは、Sidetreeに登録されていない場合に限り、埋め込まれた初期状態から解決する
+            StatusCode::NOT_FOUND if is_long_form => Ok(resolve_long_form_result(did)),
+            StatusCode::NOT_FOUND => Ok(DidResolutionResult::error(ResolutionError::NotFound)),
+            StatusCode::GONE => {
+                #[derive(serde::Deserialize)]
+                struct GoneBody {
+                    did_document_metadata: Option<DidDocumentMetadata>,
+                }
+                let metadata = serde_json::from_str::<GoneBody>(&response.body)
+                    .ok()
+                    .and_then(|body| body.did_document_metadata)
+                    .unwrap_or_default();
+                Ok(DidResolutionResult::deactivated(did, metadata))
+            }
+            StatusCode::BAD_REQUEST => Ok(DidResolutionResult::error(ResolutionError::InvalidDid)),
             _ => Err(FindIdentifierError::SidetreeRequestFailed(format!(
                 "{:?}",
                 response
@@ -167,3 +607,506 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::sidetree::client::SidetreeHttpClientResponse;
+    use crate::did::sidetree::node::{LocalSidetreeNode, MemoryNodeStore};
+    use crate::did::sidetree::patch::DidPatchBuilder;
+    use crate::keyring::keypair::KeyPair;
+    use futures::executor::block_on;
+    use rand_core::OsRng;
+
+    type NodeError = <MemoryNodeStore as crate::did::sidetree::node::SidetreeNodeStore>::Error;
+
+    /// create操作のレスポンスを書き換えるSidetreeHttpClient（不正なノードを再現する）
+    struct TamperingClient {
+        node: LocalSidetreeNode,
+        tamper: fn(&mut serde_json::Value),
+    }
+
+    impl SidetreeHttpClient for TamperingClient {
+        type Error = NodeError;
+
+        async fn post_create_identifier(
+            &self,
+            body: &str,
+        ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+            let response = self.node.post_create_identifier(body).await?;
+            let mut json: serde_json::Value = serde_json::from_str(response.body()).unwrap();
+            (self.tamper)(&mut json);
+            Ok(SidetreeHttpClientResponse::new(
+                response.status_code(),
+                json.to_string(),
+            ))
+        }
+
+        async fn post_update_identifier(
+            &self,
+            body: &str,
+        ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+            self.node.post_update_identifier(body).await
+        }
+
+        async fn post_recover_identifier(
+            &self,
+            body: &str,
+        ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+            self.node.post_recover_identifier(body).await
+        }
+
+        async fn post_deactivate_identifier(
+            &self,
+            body: &str,
+        ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+            self.node.post_deactivate_identifier(body).await
+        }
+
+        async fn get_find_identifier(
+            &self,
+            did: &str,
+        ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+            self.node.get_find_identifier(did).await
+        }
+    }
+
+    fn create_with(
+        tamper: fn(&mut serde_json::Value),
+    ) -> Result<MiaxDidResponse, CreateIdentifierError<NodeError>> {
+        let repository = DidRepositoryImpl::new(TamperingClient {
+            node: LocalSidetreeNode::in_memory(),
+            tamper,
+        });
+        block_on(repository.create_identifier(KeyPairing::create_keyring(OsRng)))
+    }
+
+    fn signing_key_jwk(json: &mut serde_json::Value) -> &mut serde_json::Value {
+        &mut json["did_document"]["publicKey"][0]["publicKeyJwk"]
+    }
+
+    #[test]
+    fn test_create_identifier_accepts_matching_document() {
+        let response = create_with(|_| {}).unwrap();
+        assert!(response.did_document.id.starts_with("did:miax:"));
+    }
+
+    #[test]
+    fn test_create_identifier_ignores_non_key_jwk_members() {
+        // 鍵の値以外のメンバーが付与されていても、同じ鍵であれば受け入れる
+        create_with(|json| {
+            signing_key_jwk(json)["kid"] = "signingKey".into();
+            signing_key_jwk(json)["use"] = "sig".into();
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn test_create_identifier_rejects_did_mismatch() {
+        let result = create_with(|json| {
+            json["did_document"]["id"] = "did:miax:EiAotherotherotherotherotherotherother".into();
+        });
+        assert!(matches!(
+            result,
+            Err(CreateIdentifierError::DidMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_create_identifier_rejects_public_key_mismatch() {
+        // 別の鍵に差し替えられている
+        let result = create_with(|json| {
+            let other = KeyPairing::create_keyring(OsRng).sign().get_public_key();
+            *signing_key_jwk(json) = serde_json::to_value(Jwk::try_from(other).unwrap()).unwrap();
+        });
+        assert!(matches!(
+            result,
+            Err(CreateIdentifierError::PublicKeyMismatch(id)) if id == "signingKey"
+        ));
+
+        // 鍵の種類が書き換えられている
+        let result = create_with(|json| {
+            json["did_document"]["publicKey"][0]["type"] = "JsonWebKey2020".into();
+        });
+        assert!(matches!(
+            result,
+            Err(CreateIdentifierError::PublicKeyMismatch(_))
+        ));
+
+        // 公開鍵が削除されている
+        let result = create_with(|json| {
+            json["did_document"]["publicKey"]
+                .as_array_mut()
+                .unwrap()
+                .pop();
+        });
+        assert!(matches!(
+            result,
+            Err(CreateIdentifierError::PublicKeyMismatch(_))
+        ));
+    }
+
+    fn public_key(id: &str, r#type: &str, jwk: Jwk) -> crate::did::sidetree::payload::DidPublicKey {
+        crate::did::sidetree::payload::DidPublicKey {
+            id: id.to_string(),
+            controller: String::new(),
+            r#type: r#type.to_string(),
+            public_key_jwk: jwk,
+        }
+    }
+
+    #[test]
+    fn test_get_key_prefers_key_id() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let other = KeyPairing::create_keyring(OsRng);
+        // 鍵を追加した場合でも、登録順に依存せずに#signingKey・#encryptionKeyを用いる
+        let did_document = DidDocument {
+            id: "did:miax:test".to_string(),
+            public_key: Some(vec![
+                public_key(
+                    "#addedKey",
+                    "EcdsaSecp256k1VerificationKey2019",
+                    Jwk::try_from(other.sign().get_public_key()).unwrap(),
+                ),
+                public_key(
+                    "#addedEncryptionKey",
+                    "X25519KeyAgreementKey2019",
+                    Jwk::from(other.encrypt().get_public_key()),
+                ),
+                public_key(
+                    "#signingKey",
+                    "EcdsaSecp256k1VerificationKey2019",
+                    Jwk::try_from(keyring.sign().get_public_key()).unwrap(),
+                ),
+                public_key(
+                    "#encryptionKey",
+                    "X25519KeyAgreementKey2019",
+                    Jwk::from(keyring.encrypt().get_public_key()),
+                ),
+            ]),
+            authentication: None,
+        };
+
+        assert_eq!(
+            get_sign_key(&did_document).unwrap(),
+            keyring.sign().get_public_key()
+        );
+        assert_eq!(
+            get_encrypt_key(&did_document).unwrap(),
+            keyring.encrypt().get_public_key()
+        );
+    }
+
+    #[test]
+    fn test_get_key_falls_back_to_relationship() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        // 他の実装で作成されたDIDドキュメントでは、鍵の用途から公開鍵を選択する
+        let did_document = DidDocument {
+            id: "did:miax:test".to_string(),
+            public_key: Some(vec![
+                public_key(
+                    "#key-1",
+                    "X25519KeyAgreementKey2019",
+                    Jwk::from(keyring.encrypt().get_public_key()),
+                ),
+                public_key(
+                    "#key-2",
+                    "EcdsaSecp256k1VerificationKey2019",
+                    Jwk::try_from(keyring.sign().get_public_key()).unwrap(),
+                ),
+            ]),
+            authentication: None,
+        };
+
+        assert_eq!(
+            get_sign_key(&did_document).unwrap(),
+            keyring.sign().get_public_key()
+        );
+        assert_eq!(
+            get_encrypt_key(&did_document).unwrap(),
+            keyring.encrypt().get_public_key()
+        );
+    }
+
+    #[test]
+    fn test_get_key_not_found() {
+        let did_document = DidDocument {
+            id: "did:miax:test".to_string(),
+            public_key: None,
+            authentication: None,
+        };
+        assert!(matches!(
+            get_sign_key(&did_document),
+            Err(GetPublicKeyError::PublicKeyNotFound(_))
+        ));
+        assert!(matches!(
+            get_encrypt_key(&did_document),
+            Err(GetPublicKeyError::PublicKeyNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_invalid_did() {
+        let repository = DidRepositoryImpl::new(LocalSidetreeNode::in_memory());
+        for (did, error) in [
+            ("not-a-did", ResolutionError::InvalidDid),
+            ("did:miax:", ResolutionError::InvalidDid),
+            ("did:example:123", ResolutionError::MethodNotSupported),
+            (
+                "did:miax:EiAunknownunknownunknownunknownunknownunknown",
+                ResolutionError::NotFound,
+            ),
+        ] {
+            let result = block_on(repository.resolve(did)).unwrap();
+            assert_eq!(result.did_resolution_metadata.error, Some(error), "{}", did);
+            assert!(result.did_document.is_none());
+        }
+    }
+
+    #[test]
+    fn test_resolve_metadata() {
+        let repository = DidRepositoryImpl::new(LocalSidetreeNode::in_memory());
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = block_on(repository.create_identifier(&keyring))
+            .unwrap()
+            .did_document
+            .id;
+
+        let created = block_on(repository.resolve(&did)).unwrap();
+        assert_eq!(
+            created.did_resolution_metadata.content_type.as_deref(),
+            Some(crate::did::resolution::DID_LD_JSON)
+        );
+        let document = created.did_document.as_ref().unwrap();
+        assert_eq!(document.id, did);
+        assert_eq!(document.assertion_method.len(), 1);
+        assert_eq!(document.key_agreement.len(), 1);
+        let metadata = &created.did_document_metadata;
+        assert!(metadata.created.is_some());
+        assert!(metadata.updated.is_none());
+        assert!(metadata.version_id.is_some());
+        assert_eq!(metadata.deactivated, None);
+
+        block_on(repository.update_identifier(
+            &did,
+            keyring.update(),
+            KeyPairing::create_keyring(OsRng).update().get_public_key(),
+            vec![],
+        ))
+        .unwrap();
+        let updated = block_on(repository.resolve(&did)).unwrap();
+        assert_eq!(
+            updated.did_document_metadata.created,
+            created.did_document_metadata.created
+        );
+        assert!(updated.did_document_metadata.updated.is_some());
+        assert_ne!(
+            updated.did_document_metadata.version_id,
+            created.did_document_metadata.version_id
+        );
+
+        block_on(repository.deactivate_identifier(&did, keyring.recovery())).unwrap();
+        let deactivated = block_on(repository.resolve(&did)).unwrap();
+        assert!(deactivated.is_deactivated());
+        assert_eq!(deactivated.did_resolution_metadata.error, None);
+        let document = deactivated.did_document.unwrap();
+        assert_eq!(document.id, did);
+        assert!(document.verification_method.is_empty());
+    }
+
+    #[test]
+    fn test_resolve_long_form() {
+        let repository = DidRepositoryImpl::new(LocalSidetreeNode::in_memory());
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = long_form_identifier(&keyring).unwrap();
+
+        // 未登録の場合は、埋め込まれた初期状態から解決する
+        let result = block_on(repository.resolve(&did)).unwrap();
+        assert_eq!(result.did_document.unwrap().id, did);
+        assert_eq!(result.did_document_metadata.canonical_id, None);
+
+        // 登録後は、short-form DIDをcanonicalIdとして返す
+        let short_form = block_on(repository.create_identifier(keyring))
+            .unwrap()
+            .did_document
+            .id;
+        let result = block_on(repository.resolve(&did)).unwrap();
+        assert_eq!(
+            result.did_document_metadata.canonical_id,
+            Some(short_form.clone())
+        );
+        assert_eq!(result.did_document.unwrap().id, short_form);
+    }
+
+    #[test]
+    fn test_resolution_result_json() {
+        let result = DidResolutionResult::error(ResolutionError::NotFound);
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            serde_json::json!({
+                "@context": crate::did::resolution::DID_RESOLUTION_CONTEXT,
+                "didDocument": null,
+                "didResolutionMetadata": { "error": "notFound" },
+                "didDocumentMetadata": {},
+            })
+        );
+    }
+
+    #[test]
+    fn test_get_verification_key() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did_document = DidDocument {
+            id: "did:miax:test".to_string(),
+            public_key: Some(vec![
+                public_key(
+                    "#signingKey",
+                    "EcdsaSecp256k1VerificationKey2019",
+                    Jwk::try_from(keyring.sign().get_public_key()).unwrap(),
+                ),
+                public_key(
+                    "#encryptionKey",
+                    "X25519KeyAgreementKey2019",
+                    Jwk::from(keyring.encrypt().get_public_key()),
+                ),
+            ]),
+            authentication: Some(vec!["#signingKey".to_string()]),
+        };
+
+        for (verification_method, proof_purpose) in [
+            ("did:miax:test#signingKey", "assertionMethod"),
+            ("did:miax:test#signingKey", "authentication"),
+            // 以前のバージョンで生成されたproofのid
+            ("did:miax:test#signingkey", "assertionMethod"),
+        ] {
+            assert_eq!(
+                get_verification_key(&did_document, verification_method, proof_purpose).unwrap(),
+                keyring.sign().get_public_key()
+            );
+        }
+
+        assert!(matches!(
+            get_verification_key(&did_document, "did:miax:test#signingKey", "signing"),
+            Err(GetPublicKeyError::UnsupportedProofPurpose(_))
+        ));
+        // 用途が異なる公開鍵は受け付けない
+        assert!(matches!(
+            get_verification_key(&did_document, "did:miax:test#signingKey", "keyAgreement"),
+            Err(GetPublicKeyError::NotAuthorized { .. })
+        ));
+        assert!(matches!(
+            get_verification_key(
+                &did_document,
+                "did:miax:test#encryptionKey",
+                "assertionMethod"
+            ),
+            Err(GetPublicKeyError::NotAuthorized { .. })
+        ));
+        assert!(matches!(
+            get_verification_key(&did_document, "did:miax:test#otherKey", "assertionMethod"),
+            Err(GetPublicKeyError::PublicKeyNotFound(_))
+        ));
+        assert!(matches!(
+            get_verification_key(&did_document, "signingKey", "assertionMethod"),
+            Err(GetPublicKeyError::InvalidVerificationMethod(_))
+        ));
+    }
+
+    #[test]
+    fn test_create_identifier_with_sign_key_types() {
+        let repository = DidRepositoryImpl::new(LocalSidetreeNode::in_memory());
+        for key_type in [
+            SignKeyType::Secp256k1,
+            SignKeyType::P256,
+            SignKeyType::Ed25519,
+        ] {
+            let keyring = KeyPairing::create_keyring_with_sign_key(OsRng, key_type);
+            let did = block_on(repository.create_identifier(&keyring))
+                .unwrap()
+                .did_document
+                .id;
+            let document = block_on(repository.find_identifier(&did))
+                .unwrap()
+                .unwrap()
+                .did_document;
+            let public_key = get_sign_key(&document).unwrap();
+            assert_eq!(public_key, keyring.sign().get_public_key());
+            assert_eq!(public_key.key_type(), key_type);
+        }
+    }
+
+    #[test]
+    fn test_create_identifier_with_public_keys() {
+        // 秘密鍵を取り出せない鍵ストアの鍵でも、公開鍵のみで同じDIDを作成できる
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let public_keys = keyring.public_keys();
+        let repository = DidRepositoryImpl::new(LocalSidetreeNode::in_memory());
+        let did = block_on(repository.create_identifier(public_keys.clone()))
+            .unwrap()
+            .did_document
+            .id;
+        let other = DidRepositoryImpl::new(LocalSidetreeNode::in_memory());
+        let expected = block_on(other.create_identifier(&keyring))
+            .unwrap()
+            .did_document
+            .id;
+        assert_eq!(did, expected);
+        assert_eq!(PublicKeyPairing::from(keyring), public_keys);
+    }
+
+    #[test]
+    fn test_rotate_keys_with_key_payloads() {
+        // 同じIDの公開鍵を追加すると、DIDドキュメントの署名鍵・暗号化鍵が置き換わる
+        let repository = DidRepositoryImpl::new(LocalSidetreeNode::in_memory());
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = block_on(repository.create_identifier(&keyring))
+            .unwrap()
+            .did_document
+            .id;
+
+        let new_keyring = KeyPairing::create_keyring_with_sign_key(OsRng, SignKeyType::P256);
+        let sign = signing_key_payload(&new_keyring.sign().get_public_key()).unwrap();
+        assert_eq!(sign.id, SIGNING_KEY_ID);
+        let Ok(encrypt) = encryption_key_payload(&new_keyring.encrypt().get_public_key());
+        assert_eq!(encrypt.id, ENCRYPTION_KEY_ID);
+        let patches = DidPatchBuilder::new()
+            .add_public_keys(vec![sign, encrypt])
+            .unwrap()
+            .build();
+        block_on(repository.update_identifier(
+            &did,
+            keyring.update(),
+            new_keyring.update().get_public_key(),
+            patches,
+        ))
+        .unwrap();
+
+        let document = block_on(repository.find_identifier(&did))
+            .unwrap()
+            .unwrap()
+            .did_document;
+        assert_eq!(
+            get_sign_key(&document).unwrap(),
+            new_keyring.sign().get_public_key()
+        );
+        assert_eq!(
+            get_encrypt_key(&document).unwrap(),
+            new_keyring.encrypt().get_public_key()
+        );
+
+        // 次のupdate操作には、新しい更新鍵で署名する
+        assert!(block_on(repository.update_identifier(
+            &did,
+            keyring.update(),
+            new_keyring.update().get_public_key(),
+            vec![],
+        ))
+        .is_err());
+        block_on(repository.update_identifier(
+            &did,
+            new_keyring.update(),
+            KeyPairing::create_keyring(OsRng).update().get_public_key(),
+            vec![],
+        ))
+        .unwrap();
+    }
+}
@@ -0,0 +1,395 @@
+// DID URLの解析と参照解決（dereferencing）
+// did:<method>:<method-specific-id>[/path][?query][#fragment]
+//
+// 参考 : https://www.w3.org/TR/did-core/#did-url-syntax
+//        https://w3c-ccg.github.io/did-resolution/#dereferencing
+use std::fmt;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use super::did_repository::DidRepository;
+use super::document::{absolute_id, DidCoreDocument, Service, VerificationMethod};
+use super::resolution::ResolutionError;
+use super::resolver::did_method;
+
+// DID URLのクエリパラメータ
+const SERVICE_PARAM: &str = "service";
+const VERSION_ID_PARAM: &str = "versionId";
+
+#[derive(Debug, Error)]
+pub enum DidUrlError {
+    #[error("invalid did url: {0}")]
+    InvalidDidUrl(String),
+}
+
+/// DID URL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DidUrl {
+    pub did: String,
+    pub path: Option<String>,
+    pub query: Option<String>,
+    pub fragment: Option<String>,
+}
+
+impl DidUrl {
+    pub fn parse(value: &str) -> Result<Self, DidUrlError> {
+        let invalid = || DidUrlError::InvalidDidUrl(value.to_string());
+
+        let (rest, fragment) = match value.split_once('#') {
+            Some((rest, fragment)) => (rest, Some(fragment)),
+            None => (value, None),
+        };
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query)),
+            None => (rest, None),
+        };
+        let (did, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], Some(&rest[index..])),
+            None => (rest, None),
+        };
+
+        // DIDメソッド名は小文字の英数字のみ
+        let method = did_method(did).ok_or_else(invalid)?;
+        if !method
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        if fragment.is_some_and(str::is_empty) {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            did: did.to_string(),
+            path: path.map(str::to_string),
+            query: query.map(str::to_string),
+            fragment: fragment.map(str::to_string),
+        })
+    }
+
+    /// クエリパラメータの値を返す
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .as_deref()?
+            .split('&')
+            .filter_map(|param| param.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    /// `?service=`で指定されたサービスのid
+    pub fn service(&self) -> Option<&str> {
+        self.query_param(SERVICE_PARAM)
+    }
+
+    /// `?versionId=`で指定されたDIDドキュメントのバージョン
+    pub fn version_id(&self) -> Option<&str> {
+        self.query_param(VERSION_ID_PARAM)
+    }
+}
+
+impl FromStr for DidUrl {
+    type Err = DidUrlError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for DidUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.did)?;
+        if let Some(path) = &self.path {
+            write!(f, "{}", path)?;
+        }
+        if let Some(query) = &self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(fragment) = &self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
+    }
+}
+
+/// DID URLの参照先
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DereferencedResource {
+    Document(DidCoreDocument),
+    VerificationMethod(VerificationMethod),
+    Service(Service),
+}
+
+#[derive(Debug, Error)]
+pub enum DereferenceError<FindIdentifierError: std::error::Error> {
+    #[error(transparent)]
+    InvalidDidUrl(#[from] DidUrlError),
+    #[error("failed to resolve identifier: {0}")]
+    Resolve(FindIdentifierError),
+    #[error("failed to resolve identifier: {did} ({error:?})")]
+    Resolution { did: String, error: ResolutionError },
+    #[error("did url path is not supported: {0}")]
+    PathNotSupported(String),
+    #[error("did document version not found: {0}")]
+    VersionNotFound(String),
+    #[error("resource not found: {0}")]
+    NotFound(String),
+}
+
+/// 解決済みのDIDドキュメントの中から、DID URLの参照先を返す
+///
+/// - `?service=<id>` : idに一致するservice
+/// - `#<fragment>` : idに一致するverificationMethod、なければservice
+/// - どちらもない場合 : DIDドキュメント
+///
+/// パスとバージョンは解決時に扱うため、ここでは参照しない
+/// フラグメントはドキュメントのidを基準に解決するため、long-form DIDとshort-form DIDのどちらで指定してもよい
+pub fn dereference_document(
+    document: &DidCoreDocument,
+    did_url: &DidUrl,
+) -> Option<DereferencedResource> {
+    let find_service = |id: &str| {
+        let id = absolute_id(&document.id, &format!("#{}", id));
+        document
+            .service
+            .iter()
+            .find(|service| absolute_id(&document.id, &service.id) == id)
+            .cloned()
+            .map(DereferencedResource::Service)
+    };
+
+    if let Some(service) = did_url.service() {
+        return find_service(service);
+    }
+    match &did_url.fragment {
+        Some(fragment) => document
+            .find_verification_method(&format!("#{}", fragment))
+            .cloned()
+            .map(DereferencedResource::VerificationMethod)
+            .or_else(|| find_service(fragment)),
+        None => Some(DereferencedResource::Document(document.clone())),
+    }
+}
+
+/// DID URLのDIDを解決し、参照先（DIDドキュメント、verificationMethod、service）を返す
+///
+/// `?versionId=`は、現在のDIDドキュメントのバージョンと一致する場合のみ受け付ける
+/// （過去のバージョンのDIDドキュメントは取得できないため）
+pub async fn dereference<R: DidRepository>(
+    did_repository: &R,
+    did_url: &str,
+) -> Result<DereferencedResource, DereferenceError<R::FindIdentifierError>> {
+    let did_url = DidUrl::parse(did_url)?;
+    if let Some(path) = &did_url.path {
+        return Err(DereferenceError::PathNotSupported(path.clone()));
+    }
+
+    let result = did_repository
+        .resolve(&did_url.did)
+        .await
+        .map_err(DereferenceError::Resolve)?;
+    if let Some(error) = result.did_resolution_metadata.error {
+        return Err(DereferenceError::Resolution {
+            did: did_url.did,
+            error,
+        });
+    }
+    let document = result
+        .did_document
+        .ok_or_else(|| DereferenceError::NotFound(did_url.to_string()))?;
+
+    if let Some(version_id) = did_url.version_id() {
+        if result.did_document_metadata.version_id.as_deref() != Some(version_id) {
+            return Err(DereferenceError::VersionNotFound(version_id.to_string()));
+        }
+    }
+
+    dereference_document(&document, &did_url)
+        .ok_or_else(|| DereferenceError::NotFound(did_url.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::did_repository::DidRepositoryImpl;
+    use crate::did::sidetree::node::LocalSidetreeNode;
+    use crate::keyring::keypair::{KeyPairing, SignPublicKey};
+    use futures::executor::block_on;
+    use rand_core::OsRng;
+
+    #[test]
+    fn test_parse() {
+        let url = DidUrl::parse("did:example:123/path/to?service=agent&versionId=1#key-1").unwrap();
+        assert_eq!(url.did, "did:example:123");
+        assert_eq!(url.path.as_deref(), Some("/path/to"));
+        assert_eq!(url.query.as_deref(), Some("service=agent&versionId=1"));
+        assert_eq!(url.fragment.as_deref(), Some("key-1"));
+        assert_eq!(url.service(), Some("agent"));
+        assert_eq!(url.version_id(), Some("1"));
+        assert_eq!(url.query_param("missing"), None);
+
+        let url: DidUrl = "did:miax:EiAsuffix:initialstate#signingKey"
+            .parse()
+            .unwrap();
+        assert_eq!(url.did, "did:miax:EiAsuffix:initialstate");
+        assert_eq!(url.path, None);
+        assert_eq!(url.query, None);
+
+        // フラグメント内の'?'や'/'は、フラグメントの一部として扱う
+        let url = DidUrl::parse("did:example:123#a/b?c").unwrap();
+        assert_eq!(url.fragment.as_deref(), Some("a/b?c"));
+        assert_eq!(url.path, None);
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for value in [
+            "did:example:123",
+            "did:example:123#key-1",
+            "did:example:123?service=agent",
+            "did:example:123/path?versionId=1#key-1",
+        ] {
+            assert_eq!(DidUrl::parse(value).unwrap().to_string(), value);
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for value in [
+            "",
+            "example:123",
+            "did:example",
+            "did::123",
+            "did:Example:123",
+            "did:ex-ample:123",
+            "did:example:123#",
+        ] {
+            assert!(
+                matches!(DidUrl::parse(value), Err(DidUrlError::InvalidDidUrl(_))),
+                "{}",
+                value
+            );
+        }
+    }
+
+    fn document() -> DidCoreDocument {
+        let mut document: DidCoreDocument = serde_json::from_value(serde_json::json!({
+            "@context": "https://www.w3.org/ns/did/v1",
+            "id": "did:example:123",
+            "verificationMethod": [{
+                "id": "#key-1",
+                "type": "JsonWebKey2020",
+                "controller": "did:example:123",
+            }],
+            "service": [
+                {
+                    "id": "did:example:123#agent",
+                    "type": "DIDCommMessaging",
+                    "serviceEndpoint": "https://agent.example",
+                },
+                {
+                    "id": "#key-2",
+                    "type": "LinkedDomains",
+                    "serviceEndpoint": "https://example.com",
+                },
+            ],
+        }))
+        .unwrap();
+        document.verification_method[0].public_key_multibase = Some("z6Mk".to_string());
+        document
+    }
+
+    fn dereference_str(value: &str) -> Option<DereferencedResource> {
+        dereference_document(&document(), &DidUrl::parse(value).unwrap())
+    }
+
+    #[test]
+    fn test_dereference_document() {
+        assert!(matches!(
+            dereference_str("did:example:123"),
+            Some(DereferencedResource::Document(document)) if document.id == "did:example:123"
+        ));
+        assert!(matches!(
+            dereference_str("did:example:123#key-1"),
+            Some(DereferencedResource::VerificationMethod(method)) if method.id == "#key-1"
+        ));
+        // verificationMethodがない場合は、serviceを返す
+        assert!(matches!(
+            dereference_str("did:example:123#agent"),
+            Some(DereferencedResource::Service(service)) if service.r#type == "DIDCommMessaging"
+        ));
+        assert!(matches!(
+            dereference_str("did:example:123#key-2"),
+            Some(DereferencedResource::Service(service)) if service.r#type == "LinkedDomains"
+        ));
+        assert!(matches!(
+            dereference_str("did:example:123?service=agent"),
+            Some(DereferencedResource::Service(service)) if service.id == "did:example:123#agent"
+        ));
+        // ?service=はverificationMethodを参照しない
+        assert_eq!(dereference_str("did:example:123?service=key-1"), None);
+        assert_eq!(dereference_str("did:example:123#key-3"), None);
+    }
+
+    #[test]
+    fn test_dereference() {
+        let repository = DidRepositoryImpl::new(LocalSidetreeNode::in_memory());
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = block_on(repository.create_identifier(&keyring))
+            .unwrap()
+            .did_document
+            .id;
+
+        let resource = block_on(dereference(&repository, &format!("{}#signingKey", did))).unwrap();
+        let DereferencedResource::VerificationMethod(method) = resource else {
+            panic!("unexpected resource: {:?}", resource);
+        };
+        assert_eq!(
+            SignPublicKey::try_from(method.public_key_jwk.unwrap()).unwrap(),
+            keyring.sign().get_public_key()
+        );
+
+        // 現在のバージョンのみ受け付ける
+        let version_id = block_on(repository.resolve(&did))
+            .unwrap()
+            .did_document_metadata
+            .version_id
+            .unwrap();
+        assert!(matches!(
+            block_on(dereference(
+                &repository,
+                &format!("{}?versionId={}", did, version_id)
+            )),
+            Ok(DereferencedResource::Document(_))
+        ));
+        assert!(matches!(
+            block_on(dereference(&repository, &format!("{}?versionId=old", did))),
+            Err(DereferenceError::VersionNotFound(version)) if version == "old"
+        ));
+
+        assert!(matches!(
+            block_on(dereference(&repository, &format!("{}/path", did))),
+            Err(DereferenceError::PathNotSupported(path)) if path == "/path"
+        ));
+        assert!(matches!(
+            block_on(dereference(&repository, &format!("{}#missing", did))),
+            Err(DereferenceError::NotFound(_))
+        ));
+        assert!(matches!(
+            block_on(dereference(
+                &repository,
+                "did:miax:EiAunknownunknownunknownunknownunknownunknown#signingKey"
+            )),
+            Err(DereferenceError::Resolution {
+                error: ResolutionError::NotFound,
+                ..
+            })
+        ));
+        assert!(matches!(
+            block_on(dereference(&repository, "not-a-did-url")),
+            Err(DereferenceError::InvalidDidUrl(_))
+        ));
+    }
+}
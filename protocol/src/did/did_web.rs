@@ -0,0 +1,308 @@
+// did:webメソッド
+// DIDをドメイン上のURL（https://<domain>/.well-known/did.json など）に対応付け、公開されたDIDドキュメントを取得する
+//
+// 参考 : https://w3c-ccg.github.io/did-method-web/
+use http::StatusCode;
+use thiserror::Error;
+
+use super::document::DidCoreDocument;
+use super::resolution::{DidDocumentMetadata, DidResolutionResult, ResolutionError};
+use super::resolver::DidResolver;
+
+pub const DID_WEB_METHOD: &str = "web";
+
+const WELL_KNOWN_PATH: &str = ".well-known";
+const DID_DOCUMENT_FILE: &str = "did.json";
+
+/// DIDドキュメントを取得するHTTPクライアントのインターフェース
+/// protocolクレートはHTTPクライアントに依存しないため、利用側で実装する
+#[trait_variant::make(Send)]
+pub trait DidWebFetcher: Sync {
+    type Error: std::error::Error + Send + Sync;
+    /// URLに対してGETリクエストを送信し、レスポンスを返す
+    async fn fetch(&self, url: &str) -> Result<http::Response<String>, Self::Error>;
+}
+
+#[derive(Debug, Error)]
+pub enum DidWebError<FetchError: std::error::Error> {
+    #[error("failed to fetch did document: {0}")]
+    Fetch(FetchError),
+    #[error("unexpected response. url: {url}, status: {status}")]
+    UnexpectedStatus { url: String, status: StatusCode },
+    #[error("failed to parse did document: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("did document id mismatch. expected: {expected}, actual: {actual}")]
+    DidMismatch { expected: String, actual: String },
+}
+
+// %XX形式のパーセントエンコーディングをデコードする
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// did:webのDIDを、DIDドキュメントを取得するURLに変換する
+///
+/// - did:web:example.com → https://example.com/.well-known/did.json
+/// - did:web:example.com%3A8443 → https://example.com:8443/.well-known/did.json
+/// - did:web:example.com:user:alice → https://example.com/user/alice/did.json
+pub fn did_web_url(did: &str, scheme: &str) -> Option<String> {
+    let prefix = format!("did:{}:", DID_WEB_METHOD);
+    let id = did.strip_prefix(&prefix)?;
+    let mut segments = id.split(':');
+
+    let domain = percent_decode(segments.next()?)?;
+    if domain.is_empty() || domain.contains(['/', '?', '#', '@']) {
+        return None;
+    }
+    let mut path = segments
+        .map(|segment| match percent_decode(segment) {
+            Some(segment) if !segment.is_empty() && !segment.contains(['/', '?', '#']) => {
+                Some(segment)
+            }
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if path.is_empty() {
+        path.push(WELL_KNOWN_PATH.to_string());
+    }
+
+    Some(format!(
+        "{}://{}/{}/{}",
+        scheme,
+        domain,
+        path.join("/"),
+        DID_DOCUMENT_FILE
+    ))
+}
+
+/// did:webのResolver
+pub struct DidWebResolver<F: DidWebFetcher> {
+    fetcher: F,
+    scheme: &'static str,
+}
+
+impl<F: DidWebFetcher> DidWebResolver<F> {
+    pub fn new(fetcher: F) -> Self {
+        Self {
+            fetcher,
+            scheme: "https",
+        }
+    }
+
+    /// HTTPでDIDドキュメントを取得するResolver
+    /// ローカルの静的ファイルサーバーを利用した検証用であり、本番環境では利用しないこと
+    pub fn with_http(fetcher: F) -> Self {
+        Self {
+            fetcher,
+            scheme: "http",
+        }
+    }
+}
+
+impl<F: DidWebFetcher> DidResolver for DidWebResolver<F> {
+    type Error = DidWebError<F::Error>;
+
+    fn supports(&self, method: &str) -> bool {
+        method == DID_WEB_METHOD
+    }
+
+    async fn resolve(&self, did: &str) -> Result<DidResolutionResult, Self::Error> {
+        let Some(url) = did_web_url(did, self.scheme) else {
+            return Ok(DidResolutionResult::error(ResolutionError::InvalidDid));
+        };
+
+        let response = self.fetcher.fetch(&url).await.map_err(DidWebError::Fetch)?;
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND | StatusCode::GONE => {
+                return Ok(DidResolutionResult::error(ResolutionError::NotFound))
+            }
+            status => return Err(DidWebError::UnexpectedStatus { url, status }),
+        }
+
+        let document: DidCoreDocument = serde_json::from_str(response.body())?;
+        // 別のDIDのドキュメントが返された場合は、なりすましの可能性があるため受け付けない
+        if document.id != did {
+            return Err(DidWebError::DidMismatch {
+                expected: did.to_string(),
+                actual: document.id,
+            });
+        }
+        Ok(DidResolutionResult::resolved(
+            document,
+            DidDocumentMetadata::default(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::document::Relationship;
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+
+    const DID: &str = "did:web:example.com%3A8443:user:alice";
+    const URL: &str = "https://example.com:8443/user/alice/did.json";
+
+    #[derive(Debug, Error)]
+    #[error("connection refused")]
+    struct FetchError;
+
+    /// URLごとに固定のレスポンスを返すDidWebFetcher
+    #[derive(Default)]
+    struct StaticFetcher {
+        files: HashMap<String, (StatusCode, String)>,
+    }
+
+    impl StaticFetcher {
+        fn with(mut self, url: &str, status: StatusCode, body: serde_json::Value) -> Self {
+            self.files
+                .insert(url.to_string(), (status, body.to_string()));
+            self
+        }
+    }
+
+    impl DidWebFetcher for StaticFetcher {
+        type Error = FetchError;
+
+        async fn fetch(&self, url: &str) -> Result<http::Response<String>, Self::Error> {
+            let (status, body) = self
+                .files
+                .get(url)
+                .cloned()
+                .unwrap_or((StatusCode::NOT_FOUND, String::new()));
+            Ok(http::Response::builder().status(status).body(body).unwrap())
+        }
+    }
+
+    fn document(did: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": did,
+            "verificationMethod": [{
+                "id": format!("{}#key-1", did),
+                "type": "JsonWebKey2020",
+                "controller": did,
+                "publicKeyJwk": {
+                    "kty": "OKP",
+                    "crv": "X25519",
+                    "x": "bl_3kgKpz9jgsg350CNuHa_kQL3B60Gi-98WmdQW2h8",
+                },
+            }],
+            "keyAgreement": ["#key-1"],
+        })
+    }
+
+    #[test]
+    fn test_did_web_url() {
+        for (did, url) in [
+            (
+                "did:web:w3c-ccg.github.io",
+                "https://w3c-ccg.github.io/.well-known/did.json",
+            ),
+            (
+                "did:web:w3c-ccg.github.io:user:alice",
+                "https://w3c-ccg.github.io/user/alice/did.json",
+            ),
+            (
+                "did:web:example.com%3A3000:user:alice",
+                "https://example.com:3000/user/alice/did.json",
+            ),
+            (
+                "did:web:example.com:user%20name",
+                "https://example.com/user name/did.json",
+            ),
+        ] {
+            assert_eq!(did_web_url(did, "https").as_deref(), Some(url), "{}", did);
+        }
+        assert_eq!(
+            did_web_url("did:web:localhost%3A8080", "http").as_deref(),
+            Some("http://localhost:8080/.well-known/did.json")
+        );
+    }
+
+    #[test]
+    fn test_invalid_did_web_url() {
+        for did in [
+            "did:web:",
+            "did:key:example.com",
+            "did:web:example.com::alice",
+            "did:web:example.com%2Fpath",
+            "did:web:user%40example.com",
+            "did:web:example.com:a%2Fb",
+            "did:web:example.com%3",
+            "did:web:example.com%ZZ",
+        ] {
+            assert_eq!(did_web_url(did, "https"), None, "{}", did);
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let resolver =
+            DidWebResolver::new(StaticFetcher::default().with(URL, StatusCode::OK, document(DID)));
+        assert!(resolver.supports("web"));
+
+        let result = block_on(resolver.resolve(DID)).unwrap();
+        let document = result.did_document.unwrap();
+        assert_eq!(document.id, DID);
+        // @contextを含まないDIDドキュメントも受け付ける
+        assert_eq!(document.context, [crate::did::document::DID_CORE_CONTEXT]);
+        assert_eq!(
+            document
+                .verification_methods(Relationship::KeyAgreement)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let other = "did:web:attacker.example";
+        let resolver = DidWebResolver::new(
+            StaticFetcher::default()
+                .with(URL, StatusCode::OK, document(other))
+                .with(
+                    "https://example.com/.well-known/did.json",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    serde_json::json!({}),
+                ),
+        );
+
+        // 別のDIDのドキュメントは受け付けない
+        assert!(matches!(
+            block_on(resolver.resolve(DID)),
+            Err(DidWebError::DidMismatch { actual, .. }) if actual == other
+        ));
+        assert!(matches!(
+            block_on(resolver.resolve("did:web:example.com")),
+            Err(DidWebError::UnexpectedStatus {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                ..
+            })
+        ));
+        let result = block_on(resolver.resolve("did:web:unknown.example")).unwrap();
+        assert_eq!(
+            result.did_resolution_metadata.error,
+            Some(ResolutionError::NotFound)
+        );
+        let result = block_on(resolver.resolve("did:web:")).unwrap();
+        assert_eq!(
+            result.did_resolution_metadata.error,
+            Some(ResolutionError::InvalidDid)
+        );
+    }
+}
@@ -0,0 +1,528 @@
+// W3C DID Core準拠のDIDドキュメント
+// https://www.w3.org/TR/did-core/#did-documents
+//
+// SidetreeノードのDIDドキュメント（payload::DidDocument）は旧形式のため、
+// 鍵の用途（verification relationship）はこのモデルに変換したうえで参照する
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::sidetree::payload::{DidDocument, DidPublicKey, MiaxDidResponse};
+use crate::keyring::jwk::Jwk;
+
+pub const DID_CORE_CONTEXT: &str = "https://www.w3.org/ns/did/v1";
+
+// 旧形式のDIDドキュメントで、鍵交換用の公開鍵に付与されるtype
+const KEY_AGREEMENT_TYPES: [&str; 2] = ["X25519KeyAgreementKey2019", "X25519KeyAgreementKey2020"];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerificationMethod {
+    #[serde(rename = "id")]
+    pub id: String,
+
+    #[serde(rename = "type")]
+    pub r#type: String,
+
+    #[serde(rename = "controller")]
+    pub controller: String,
+
+    #[serde(rename = "publicKeyJwk", skip_serializing_if = "Option::is_none")]
+    pub public_key_jwk: Option<Jwk>,
+
+    #[serde(rename = "publicKeyMultibase", skip_serializing_if = "Option::is_none")]
+    pub public_key_multibase: Option<String>,
+}
+
+/// verification relationshipの要素
+/// verificationMethodへの参照（DID URL）か、埋め込まれたverificationMethodのいずれか
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum VerificationRelationship {
+    Reference(String),
+    Embedded(Box<VerificationMethod>),
+}
+
+impl VerificationRelationship {
+    pub fn id(&self) -> &str {
+        match self {
+            VerificationRelationship::Reference(id) => id,
+            VerificationRelationship::Embedded(method) => &method.id,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Relationship {
+    Authentication,
+    AssertionMethod,
+    KeyAgreement,
+    CapabilityInvocation,
+    CapabilityDelegation,
+}
+
+impl Relationship {
+    /// proofPurpose（"authentication"、"assertionMethod"など）に対応するverification relationship
+    pub fn from_proof_purpose(proof_purpose: &str) -> Option<Self> {
+        match proof_purpose {
+            "authentication" => Some(Relationship::Authentication),
+            "assertionMethod" => Some(Relationship::AssertionMethod),
+            "keyAgreement" => Some(Relationship::KeyAgreement),
+            "capabilityInvocation" => Some(Relationship::CapabilityInvocation),
+            "capabilityDelegation" => Some(Relationship::CapabilityDelegation),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Service {
+    #[serde(rename = "id")]
+    pub id: String,
+
+    #[serde(rename = "type")]
+    pub r#type: String,
+
+    #[serde(rename = "serviceEndpoint")]
+    pub service_endpoint: serde_json::Value,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DidCoreDocument {
+    // application/jsonで提供されるDIDドキュメント（did:webなど）は、@contextを含まない場合がある
+    #[serde(
+        rename = "@context",
+        default = "default_context",
+        deserialize_with = "one_or_many"
+    )]
+    pub context: Vec<String>,
+
+    #[serde(rename = "id")]
+    pub id: String,
+
+    #[serde(
+        rename = "controller",
+        default,
+        deserialize_with = "one_or_many",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub controller: Vec<String>,
+
+    #[serde(
+        rename = "verificationMethod",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub verification_method: Vec<VerificationMethod>,
+
+    #[serde(
+        rename = "authentication",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub authentication: Vec<VerificationRelationship>,
+
+    #[serde(
+        rename = "assertionMethod",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub assertion_method: Vec<VerificationRelationship>,
+
+    #[serde(
+        rename = "keyAgreement",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub key_agreement: Vec<VerificationRelationship>,
+
+    #[serde(
+        rename = "capabilityInvocation",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub capability_invocation: Vec<VerificationRelationship>,
+
+    #[serde(
+        rename = "capabilityDelegation",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub capability_delegation: Vec<VerificationRelationship>,
+
+    #[serde(rename = "service", default, skip_serializing_if = "Vec::is_empty")]
+    pub service: Vec<Service>,
+}
+
+fn default_context() -> Vec<String> {
+    vec![DID_CORE_CONTEXT.to_string()]
+}
+
+// DID Coreでは、@contextやcontrollerは単一の値と配列のどちらでも記述できる
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+/// 相対DID URL（"#signingKey"）を、DIDを基準とした絶対DID URLに変換する
+pub(crate) fn absolute_id(did: &str, id: &str) -> String {
+    if id.starts_with('#') {
+        format!("{}{}", did, id)
+    } else {
+        id.to_string()
+    }
+}
+
+// 絶対DID URLを、旧形式のDIDドキュメントで利用される相対DID URLに変換する
+fn relative_id(did: &str, id: &str) -> String {
+    match id.strip_prefix(did) {
+        Some(fragment) if fragment.starts_with('#') => fragment.to_string(),
+        _ => id.to_string(),
+    }
+}
+
+impl DidCoreDocument {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            context: vec![DID_CORE_CONTEXT.to_string()],
+            id: id.into(),
+            controller: vec![],
+            verification_method: vec![],
+            authentication: vec![],
+            assertion_method: vec![],
+            key_agreement: vec![],
+            capability_invocation: vec![],
+            capability_delegation: vec![],
+            service: vec![],
+        }
+    }
+
+    pub fn relationship(&self, relationship: Relationship) -> &[VerificationRelationship] {
+        match relationship {
+            Relationship::Authentication => &self.authentication,
+            Relationship::AssertionMethod => &self.assertion_method,
+            Relationship::KeyAgreement => &self.key_agreement,
+            Relationship::CapabilityInvocation => &self.capability_invocation,
+            Relationship::CapabilityDelegation => &self.capability_delegation,
+        }
+    }
+
+    /// idに一致するverificationMethodを返す（相対DID URLも受け付ける）
+    pub fn find_verification_method(&self, id: &str) -> Option<&VerificationMethod> {
+        let id = absolute_id(&self.id, id);
+        self.verification_method
+            .iter()
+            .chain(self.all_relationships().filter_map(|r| match r {
+                VerificationRelationship::Embedded(method) => Some(method.as_ref()),
+                VerificationRelationship::Reference(_) => None,
+            }))
+            .find(|method| absolute_id(&self.id, &method.id) == id)
+    }
+
+    /// verification relationshipに登録されたverificationMethodを、登録順に返す
+    /// 参照先が存在しない要素は無視する
+    pub fn verification_methods(
+        &self,
+        relationship: Relationship,
+    ) -> impl Iterator<Item = &VerificationMethod> {
+        self.relationship(relationship)
+            .iter()
+            .filter_map(|r| match r {
+                VerificationRelationship::Reference(id) => self.find_verification_method(id),
+                VerificationRelationship::Embedded(method) => Some(method.as_ref()),
+            })
+    }
+
+    fn all_relationships(&self) -> impl Iterator<Item = &VerificationRelationship> {
+        self.authentication
+            .iter()
+            .chain(&self.assertion_method)
+            .chain(&self.key_agreement)
+            .chain(&self.capability_invocation)
+            .chain(&self.capability_delegation)
+    }
+}
+
+/// 旧形式のDIDドキュメントには鍵の用途が含まれないため、typeから用途を判定する
+/// - 鍵交換用の公開鍵 : keyAgreement
+/// - それ以外の公開鍵 : assertionMethod（authenticationは旧形式の記述に従う）
+impl From<&DidDocument> for DidCoreDocument {
+    fn from(value: &DidDocument) -> Self {
+        let did = &value.id;
+        let mut document = DidCoreDocument::new(did.clone());
+
+        for public_key in value.public_key.iter().flatten() {
+            let method = VerificationMethod {
+                id: absolute_id(did, &public_key.id),
+                r#type: public_key.r#type.clone(),
+                controller: public_key.controller.clone(),
+                public_key_jwk: Some(public_key.public_key_jwk.clone()),
+                public_key_multibase: None,
+            };
+            let reference = VerificationRelationship::Reference(method.id.clone());
+            if KEY_AGREEMENT_TYPES.contains(&method.r#type.as_str()) {
+                document.key_agreement.push(reference);
+            } else {
+                document.assertion_method.push(reference);
+            }
+            document.verification_method.push(method);
+        }
+
+        for id in value.authentication.iter().flatten() {
+            let id = absolute_id(did, id);
+            // 鍵交換用の公開鍵は署名に利用できないため、authenticationから除外する
+            let is_signing_key = document
+                .find_verification_method(&id)
+                .is_some_and(|method| !KEY_AGREEMENT_TYPES.contains(&method.r#type.as_str()));
+            if is_signing_key {
+                document
+                    .authentication
+                    .push(VerificationRelationship::Reference(id));
+            }
+        }
+
+        document
+    }
+}
+
+impl From<MiaxDidResponse> for DidCoreDocument {
+    fn from(value: MiaxDidResponse) -> Self {
+        DidCoreDocument::from(&value.did_document)
+    }
+}
+
+/// 旧形式のDIDドキュメントは公開鍵をJWKでのみ表現するため、JWKを持たないverificationMethodとserviceは含まれない
+impl From<&DidCoreDocument> for DidDocument {
+    fn from(value: &DidCoreDocument) -> Self {
+        let did = &value.id;
+        let mut public_key: Vec<DidPublicKey> = vec![];
+        let methods = value
+            .verification_method
+            .iter()
+            .chain(value.all_relationships().filter_map(|r| match r {
+                VerificationRelationship::Embedded(method) => Some(method.as_ref()),
+                VerificationRelationship::Reference(_) => None,
+            }));
+        for method in methods {
+            let Some(jwk) = &method.public_key_jwk else {
+                continue;
+            };
+            let id = relative_id(did, &method.id);
+            if public_key.iter().any(|pk| pk.id == id) {
+                continue;
+            }
+            public_key.push(DidPublicKey {
+                id,
+                controller: method.controller.clone(),
+                r#type: method.r#type.clone(),
+                public_key_jwk: jwk.clone(),
+            });
+        }
+        let authentication = value
+            .authentication
+            .iter()
+            .map(|r| relative_id(did, r.id()))
+            .collect();
+
+        DidDocument {
+            id: did.clone(),
+            public_key: Some(public_key),
+            authentication: Some(authentication),
+        }
+    }
+}
+
+impl From<DidCoreDocument> for MiaxDidResponse {
+    fn from(value: DidCoreDocument) -> Self {
+        MiaxDidResponse {
+            did_document: DidDocument::from(&value),
+            did_document_metadata: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyring::keypair::{KeyPair, KeyPairing};
+    use rand_core::OsRng;
+
+    const DID: &str = "did:example:123";
+
+    fn jwk() -> Jwk {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        Jwk::try_from(keyring.sign().get_public_key()).unwrap()
+    }
+
+    fn legacy_document() -> DidDocument {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        DidDocument {
+            id: DID.to_string(),
+            public_key: Some(vec![
+                DidPublicKey {
+                    id: "#signingKey".to_string(),
+                    controller: String::new(),
+                    r#type: "EcdsaSecp256k1VerificationKey2019".to_string(),
+                    public_key_jwk: Jwk::try_from(keyring.sign().get_public_key()).unwrap(),
+                },
+                DidPublicKey {
+                    id: "#encryptionKey".to_string(),
+                    controller: String::new(),
+                    r#type: "X25519KeyAgreementKey2019".to_string(),
+                    public_key_jwk: Jwk::from(keyring.encrypt().get_public_key()),
+                },
+            ]),
+            authentication: Some(vec![
+                "#signingKey".to_string(),
+                "#encryptionKey".to_string(),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_did_core_document() {
+        let json = serde_json::json!({
+            "@context": "https://www.w3.org/ns/did/v1",
+            "id": DID,
+            "controller": "did:example:controller",
+            "verificationMethod": [{
+                "id": "#key-1",
+                "type": "JsonWebKey2020",
+                "controller": DID,
+                "publicKeyJwk": jwk(),
+            }],
+            "authentication": [
+                "#key-1",
+                {
+                    "id": "did:example:123#key-2",
+                    "type": "Ed25519VerificationKey2020",
+                    "controller": DID,
+                    "publicKeyMultibase": "z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK",
+                },
+            ],
+            "keyAgreement": ["#missing"],
+        });
+        let document: DidCoreDocument = serde_json::from_value(json).unwrap();
+
+        assert_eq!(document.context, [DID_CORE_CONTEXT]);
+        assert_eq!(document.controller, ["did:example:controller"]);
+        assert_eq!(
+            document
+                .verification_methods(Relationship::Authentication)
+                .map(|method| method.id.as_str())
+                .collect::<Vec<_>>(),
+            ["#key-1", "did:example:123#key-2"]
+        );
+        // 参照先が存在しない要素は無視する
+        assert_eq!(
+            document
+                .verification_methods(Relationship::KeyAgreement)
+                .count(),
+            0
+        );
+        // 相対DID URL・絶対DID URLのどちらでも検索できる
+        assert!(document
+            .find_verification_method("did:example:123#key-1")
+            .is_some());
+        assert!(document.find_verification_method("#key-2").is_some());
+        assert!(document.find_verification_method("#key-3").is_none());
+    }
+
+    #[test]
+    fn test_serialize_omits_empty_members() {
+        let json = serde_json::to_value(DidCoreDocument::new(DID)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "@context": [DID_CORE_CONTEXT],
+                "id": DID,
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_legacy_document() {
+        let document = DidCoreDocument::from(&legacy_document());
+
+        assert_eq!(
+            document
+                .verification_method
+                .iter()
+                .map(|method| method.id.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "did:example:123#signingKey",
+                "did:example:123#encryptionKey"
+            ]
+        );
+        assert_eq!(
+            document.assertion_method,
+            [VerificationRelationship::Reference(
+                "did:example:123#signingKey".to_string()
+            )]
+        );
+        assert_eq!(
+            document.key_agreement,
+            [VerificationRelationship::Reference(
+                "did:example:123#encryptionKey".to_string()
+            )]
+        );
+        // 鍵交換用の公開鍵は、authenticationから除外する
+        assert_eq!(
+            document.authentication,
+            [VerificationRelationship::Reference(
+                "did:example:123#signingKey".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_to_legacy_document() {
+        let legacy = legacy_document();
+        let mut document = DidCoreDocument::from(&legacy);
+        // JWKを持たないverificationMethodは含まれない
+        document
+            .authentication
+            .push(VerificationRelationship::Embedded(Box::new(
+                VerificationMethod {
+                    id: "#multibaseKey".to_string(),
+                    r#type: "Ed25519VerificationKey2020".to_string(),
+                    controller: DID.to_string(),
+                    public_key_jwk: None,
+                    public_key_multibase: Some("z6Mk".to_string()),
+                },
+            )));
+        // verificationMethodと同じidの埋め込まれた公開鍵は重複させない
+        let signing_key = document.verification_method[0].clone();
+        document
+            .assertion_method
+            .push(VerificationRelationship::Embedded(Box::new(signing_key)));
+
+        let converted = DidDocument::from(&document);
+        assert_eq!(converted.id, DID);
+        let public_keys = converted.public_key.unwrap();
+        assert_eq!(
+            public_keys
+                .iter()
+                .map(|pk| pk.id.as_str())
+                .collect::<Vec<_>>(),
+            ["#signingKey", "#encryptionKey"]
+        );
+        for (converted, original) in public_keys.iter().zip(legacy.public_key.unwrap()) {
+            assert_eq!(converted.r#type, original.r#type);
+            assert_eq!(converted.public_key_jwk, original.public_key_jwk);
+        }
+        assert_eq!(
+            converted.authentication.unwrap(),
+            ["#signingKey", "#multibaseKey"]
+        );
+    }
+}
@@ -1,2 +1,10 @@
+pub mod cached_did_repository;
+pub mod did_key;
+pub mod did_peer;
 pub mod did_repository;
+pub mod did_url;
+pub mod did_web;
+pub mod document;
+pub mod resolution;
+pub mod resolver;
 pub mod sidetree;
@@ -0,0 +1,110 @@
+// DID Resolutionの結果
+// https://w3c-ccg.github.io/did-resolution/#did-resolution-result
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::document::DidCoreDocument;
+
+pub const DID_RESOLUTION_CONTEXT: &str = "https://w3id.org/did-resolution/v1";
+pub const DID_LD_JSON: &str = "application/did+ld+json";
+
+/// DID Resolutionのエラーコード
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResolutionError {
+    InvalidDid,
+    NotFound,
+    MethodNotSupported,
+    RepresentationNotSupported,
+    InternalError,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DidResolutionMetadata {
+    #[serde(rename = "contentType", skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+
+    #[serde(rename = "error", skip_serializing_if = "Option::is_none")]
+    pub error: Option<ResolutionError>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DidDocumentMetadata {
+    #[serde(rename = "created", default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<DateTime<Utc>>,
+
+    #[serde(rename = "updated", default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Utc>>,
+
+    #[serde(rename = "versionId", default, skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+
+    #[serde(
+        rename = "deactivated",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub deactivated: Option<bool>,
+
+    /// long-form DIDが登録済みの場合の、short-form DID
+    #[serde(
+        rename = "canonicalId",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub canonical_id: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DidResolutionResult {
+    #[serde(rename = "@context")]
+    pub context: String,
+
+    #[serde(rename = "didDocument")]
+    pub did_document: Option<DidCoreDocument>,
+
+    #[serde(rename = "didResolutionMetadata")]
+    pub did_resolution_metadata: DidResolutionMetadata,
+
+    #[serde(rename = "didDocumentMetadata")]
+    pub did_document_metadata: DidDocumentMetadata,
+}
+
+impl DidResolutionResult {
+    /// DIDドキュメントを解決できた場合の結果
+    pub fn resolved(did_document: DidCoreDocument, metadata: DidDocumentMetadata) -> Self {
+        Self {
+            context: DID_RESOLUTION_CONTEXT.to_string(),
+            did_document: Some(did_document),
+            did_resolution_metadata: DidResolutionMetadata {
+                content_type: Some(DID_LD_JSON.to_string()),
+                error: None,
+            },
+            did_document_metadata: metadata,
+        }
+    }
+
+    /// 無効化されたDIDの結果
+    /// DIDドキュメントは、公開鍵などを含まない空のドキュメントとなる
+    pub fn deactivated(did: &str, mut metadata: DidDocumentMetadata) -> Self {
+        metadata.deactivated = Some(true);
+        Self::resolved(DidCoreDocument::new(did), metadata)
+    }
+
+    /// DIDを解決できなかった場合の結果
+    pub fn error(error: ResolutionError) -> Self {
+        Self {
+            context: DID_RESOLUTION_CONTEXT.to_string(),
+            did_document: None,
+            did_resolution_metadata: DidResolutionMetadata {
+                content_type: None,
+                error: Some(error),
+            },
+            did_document_metadata: DidDocumentMetadata::default(),
+        }
+    }
+
+    pub fn is_deactivated(&self) -> bool {
+        self.did_document_metadata.deactivated == Some(true)
+    }
+}
@@ -0,0 +1,294 @@
+// DIDメソッドごとのResolverと、DIDメソッドに応じてResolverを切り替えるDidRepository
+//
+// did:miaxはSidetreeを利用するDidRepositoryで解決し、それ以外のDIDメソッド（did:keyなど）は
+// DidResolverを実装したResolverで解決する
+use std::convert::Infallible;
+
+use thiserror::Error;
+
+use super::did_repository::DidRepository;
+use super::resolution::{DidResolutionResult, ResolutionError};
+use super::sidetree::payload::{DidAction, MiaxDidResponse};
+use crate::keyring::keypair::PublicKeyPairing;
+use crate::keyring::signer::Signer;
+
+/// DIDメソッドごとのResolverのインターフェース
+#[trait_variant::make(Send)]
+pub trait DidResolver: Sync {
+    type Error: std::error::Error + Send + Sync;
+    /// 指定したDIDメソッド（did:<method>:...）を解決できるか
+    fn supports(&self, method: &str) -> bool;
+    /// 不正なDIDや存在しないDIDはエラーとせず、didResolutionMetadataのerrorとして返す
+    async fn resolve(&self, did: &str) -> Result<DidResolutionResult, Self::Error>;
+}
+
+/// DID（did:<method>:<method-specific-id>）からDIDメソッドを取り出す
+pub fn did_method(did: &str) -> Option<&str> {
+    let mut parts = did.splitn(3, ':');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some("did"), Some(method), Some(id)) if !method.is_empty() && !id.is_empty() => {
+            Some(method)
+        }
+        _ => None,
+    }
+}
+
+/// Resolverを持たない場合（全てのDIDメソッドをDidRepositoryで解決する）
+impl DidResolver for () {
+    type Error = Infallible;
+    fn supports(&self, _method: &str) -> bool {
+        false
+    }
+    async fn resolve(&self, _did: &str) -> Result<DidResolutionResult, Self::Error> {
+        Ok(DidResolutionResult::error(
+            ResolutionError::MethodNotSupported,
+        ))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum EitherResolverError<A: std::error::Error, B: std::error::Error> {
+    #[error(transparent)]
+    First(A),
+    #[error(transparent)]
+    Second(B),
+}
+
+/// 複数のResolverを組み合わせる（先に指定したResolverを優先する）
+///
+/// # 例
+/// ```ignore
+/// let resolvers = (DidKeyResolver, (DidWebResolver::new(fetcher), DidPeerResolver));
+/// ```
+impl<A: DidResolver, B: DidResolver> DidResolver for (A, B) {
+    type Error = EitherResolverError<A::Error, B::Error>;
+    fn supports(&self, method: &str) -> bool {
+        self.0.supports(method) || self.1.supports(method)
+    }
+    async fn resolve(&self, did: &str) -> Result<DidResolutionResult, Self::Error> {
+        match did_method(did) {
+            Some(method) if self.0.supports(method) => self
+                .0
+                .resolve(did)
+                .await
+                .map_err(EitherResolverError::First),
+            _ => self
+                .1
+                .resolve(did)
+                .await
+                .map_err(EitherResolverError::Second),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DidResolverRouterError<R: std::error::Error, X: std::error::Error> {
+    #[error("failed to find identifier: {0}")]
+    Repository(R),
+    #[error("failed to resolve identifier: {0}")]
+    Resolver(X),
+    #[error("failed to resolve identifier: {did} ({error:?})")]
+    Resolution { did: String, error: ResolutionError },
+}
+
+/// DIDメソッドに応じて、DidRepositoryとResolverを切り替えるDidRepository
+///
+/// - Resolverが対応するDIDメソッドは、Resolverで解決する
+/// - それ以外のDIDメソッドの解決と、create/update/recover/deactivate操作はDidRepositoryで行う
+///
+/// DidRepositoryを実装するため、DidCommEncryptedServiceやDidVcServiceでそのまま利用できる
+pub struct DidResolverRouter<R: DidRepository, X: DidResolver> {
+    repository: R,
+    resolvers: X,
+}
+
+impl<R: DidRepository, X: DidResolver> DidResolverRouter<R, X> {
+    pub fn new(repository: R, resolvers: X) -> Self {
+        Self {
+            repository,
+            resolvers,
+        }
+    }
+
+    pub fn repository(&self) -> &R {
+        &self.repository
+    }
+
+    fn routes_to_resolver(&self, did: &str) -> bool {
+        did_method(did).is_some_and(|method| self.resolvers.supports(method))
+    }
+}
+
+impl<R: DidRepository, X: DidResolver> DidRepository for DidResolverRouter<R, X> {
+    type CreateIdentifierError = R::CreateIdentifierError;
+    type UpdateIdentifierError = R::UpdateIdentifierError;
+    type RecoverIdentifierError = R::RecoverIdentifierError;
+    type DeactivateIdentifierError = R::DeactivateIdentifierError;
+    type FindIdentifierError = DidResolverRouterError<R::FindIdentifierError, X::Error>;
+
+    async fn create_identifier<K: Into<PublicKeyPairing> + Send>(
+        &self,
+        keyring: K,
+    ) -> Result<MiaxDidResponse, Self::CreateIdentifierError> {
+        self.repository.create_identifier(keyring).await
+    }
+
+    async fn update_identifier<S: Signer + Sync>(
+        &self,
+        did: &str,
+        update_key: &S,
+        new_update_key: k256::PublicKey,
+        patches: Vec<DidAction>,
+    ) -> Result<(), Self::UpdateIdentifierError> {
+        self.repository
+            .update_identifier(did, update_key, new_update_key, patches)
+            .await
+    }
+
+    async fn recover_identifier<S: Signer + Sync, K: Into<PublicKeyPairing> + Send>(
+        &self,
+        did: &str,
+        recovery_key: &S,
+        new_keyring: K,
+    ) -> Result<(), Self::RecoverIdentifierError> {
+        self.repository
+            .recover_identifier(did, recovery_key, new_keyring)
+            .await
+    }
+
+    async fn deactivate_identifier<S: Signer + Sync>(
+        &self,
+        did: &str,
+        recovery_key: &S,
+    ) -> Result<(), Self::DeactivateIdentifierError> {
+        self.repository
+            .deactivate_identifier(did, recovery_key)
+            .await
+    }
+
+    async fn find_identifier(
+        &self,
+        did: &str,
+    ) -> Result<Option<MiaxDidResponse>, Self::FindIdentifierError> {
+        if !self.routes_to_resolver(did) {
+            return self
+                .repository
+                .find_identifier(did)
+                .await
+                .map_err(DidResolverRouterError::Repository);
+        }
+
+        let result = self.resolve(did).await?;
+        if result.is_deactivated() {
+            return Ok(None);
+        }
+        match (result.did_document, result.did_resolution_metadata.error) {
+            (Some(document), None) => {
+                let mut response = MiaxDidResponse::from(document);
+                response.did_document_metadata = Some(result.did_document_metadata);
+                Ok(Some(response))
+            }
+            (_, Some(ResolutionError::NotFound)) | (None, None) => Ok(None),
+            (_, Some(error)) => Err(DidResolverRouterError::Resolution {
+                did: did.to_string(),
+                error,
+            }),
+        }
+    }
+
+    async fn resolve(&self, did: &str) -> Result<DidResolutionResult, Self::FindIdentifierError> {
+        if self.routes_to_resolver(did) {
+            self.resolvers
+                .resolve(did)
+                .await
+                .map_err(DidResolverRouterError::Resolver)
+        } else {
+            self.repository
+                .resolve(did)
+                .await
+                .map_err(DidResolverRouterError::Repository)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::did_key::{DidKeyPublicKey, DidKeyResolver};
+    use crate::did::did_repository::{get_encrypt_key, get_sign_key, DidRepositoryImpl};
+    use crate::did::sidetree::node::LocalSidetreeNode;
+    use crate::keyring::keypair::{KeyPair, KeyPairing};
+    use futures::executor::block_on;
+    use rand_core::OsRng;
+
+    fn router() -> DidResolverRouter<DidRepositoryImpl<LocalSidetreeNode>, DidKeyResolver> {
+        DidResolverRouter::new(
+            DidRepositoryImpl::new(LocalSidetreeNode::in_memory()),
+            DidKeyResolver,
+        )
+    }
+
+    #[test]
+    fn test_did_method() {
+        assert_eq!(did_method("did:key:z6Mk"), Some("key"));
+        assert_eq!(did_method("did:miax:EiA:long"), Some("miax"));
+        assert_eq!(did_method("did:key:"), None);
+        assert_eq!(did_method("did::z6Mk"), None);
+        assert_eq!(did_method("key:z6Mk"), None);
+    }
+
+    #[test]
+    fn test_routes_by_method() {
+        let router = router();
+        let keyring = KeyPairing::create_keyring(OsRng);
+
+        // did:keyはResolverで解決する
+        let did = DidKeyPublicKey::from(keyring.sign().get_public_key()).to_did();
+        let response = block_on(router.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(response.did_document.id, did);
+        assert_eq!(
+            get_sign_key(&response.did_document).unwrap(),
+            keyring.sign().get_public_key()
+        );
+        let did = DidKeyPublicKey::X25519(keyring.encrypt().get_public_key()).to_did();
+        let response = block_on(router.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(
+            get_encrypt_key(&response.did_document).unwrap(),
+            keyring.encrypt().get_public_key()
+        );
+
+        // did:miaxはDidRepositoryで解決する
+        let created = block_on(router.create_identifier(keyring)).unwrap();
+        let found = block_on(router.find_identifier(&created.did_document.id))
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.did_document.id, created.did_document.id);
+    }
+
+    #[test]
+    fn test_resolution_errors() {
+        let router = router();
+        assert!(matches!(
+            block_on(router.find_identifier("did:key:invalid")),
+            Err(DidResolverRouterError::Resolution {
+                error: ResolutionError::InvalidDid,
+                ..
+            })
+        ));
+        let result = block_on(router.resolve("did:example:123")).unwrap();
+        assert_eq!(
+            result.did_resolution_metadata.error,
+            Some(ResolutionError::MethodNotSupported)
+        );
+    }
+
+    #[test]
+    fn test_tuple_resolver() {
+        let resolvers = ((), DidKeyResolver);
+        assert!(resolvers.supports("key"));
+        assert!(!resolvers.supports("web"));
+        let did = "did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+        let result = block_on(resolvers.resolve(did)).unwrap();
+        assert_eq!(result.did_document.unwrap().id, did);
+    }
+}
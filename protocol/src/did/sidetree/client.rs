@@ -10,6 +10,14 @@ impl SidetreeHttpClientResponse {
     pub fn new(status_code: StatusCode, body: String) -> Self {
         Self { status_code, body }
     }
+
+    pub fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
+
+    pub fn body(&self) -> &str {
+        &self.body
+    }
 }
 
 #[trait_variant::make(Send)]
@@ -24,6 +32,21 @@ pub trait SidetreeHttpClient {
         &self,
         body: &str,
     ) -> Result<SidetreeHttpClientResponse, Self::Error>;
+    /// Sidetree update operation を実行し、既存のDIDのDIDドキュメントを更新
+    async fn post_update_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error>;
+    /// Sidetree recover operation を実行し、リカバリ鍵でDIDドキュメントと更新・リカバリ用の鍵を置き換える
+    async fn post_recover_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error>;
+    /// Sidetree deactivate operation を実行し、DIDを恒久的に無効化
+    async fn post_deactivate_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error>;
     /// Sidetree resolve operation を実行し、DID識別子に対応するDIDドキュメントをSidetreeネットワークから取得
     async fn get_find_identifier(
         &self,
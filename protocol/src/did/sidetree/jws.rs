@@ -0,0 +1,105 @@
+// Sidetree操作のsigned_dataで利用するJWS（Compact Serialization）
+// VC向けの`verifiable_credentials::jws`（b64=falseの分離署名）とは異なり、ペイロードをJWS内に含める
+// 参考 : https://identity.foundation/sidetree/spec/#signed-data-compact-jws
+use data_encoding::BASE64URL_NOPAD;
+use k256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::keyring::{keypair::SignPublicKey, signer::Signer};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SidetreeJwsHeader {
+    alg: String,
+}
+
+#[derive(Debug, Error)]
+pub enum SidetreeJwsError {
+    #[error("SignatureError : {0:?}")]
+    SignatureError(#[from] k256::ecdsa::Error),
+    #[error("CanonicalizeError : {0:?}")]
+    CanonicalizeError(#[from] serde_json::Error),
+    #[error("DecodeError: {0:?}")]
+    DecodeError(#[from] data_encoding::DecodeError),
+    #[error("InvalidAlgorithm: {0}")]
+    InvalidAlgorithm(String),
+    #[error("InvalidJws : {0}")]
+    InvalidJws(String),
+    #[error("SignerError : {0}")]
+    SignerError(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// Compact JWSを header, payload, signature に分割する
+fn split(jws: &str) -> Result<(&str, &str, &str), SidetreeJwsError> {
+    let mut parts = jws.split('.');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(header), Some(payload), Some(signature), None) => Ok((header, payload, signature)),
+        _ => Err(SidetreeJwsError::InvalidJws(jws.to_string())),
+    }
+}
+
+/// Sidetreeの操作に署名する鍵の公開鍵。SidetreeはES256Kのみに対応するため、secp256k1以外の鍵はエラーとする
+pub fn es256k_public_key<S: Signer + ?Sized>(
+    signer: &S,
+) -> Result<k256::PublicKey, SidetreeJwsError> {
+    match signer.public_key() {
+        SignPublicKey::Secp256k1(public_key) => Ok(public_key),
+        public_key => Err(SidetreeJwsError::InvalidAlgorithm(format!(
+            "{:?}",
+            public_key.key_type()
+        ))),
+    }
+}
+
+/// ペイロードをJCSで正規化し、ES256Kで署名したCompact JWSを生成する
+pub fn sign<T: Serialize, S: Signer + ?Sized>(
+    payload: &T,
+    signer: &S,
+) -> Result<String, SidetreeJwsError> {
+    es256k_public_key(signer)?;
+    let header = SidetreeJwsHeader {
+        alg: "ES256K".to_string(),
+    };
+    let header = BASE64URL_NOPAD.encode(serde_jcs::to_string(&header)?.as_bytes());
+    let payload = BASE64URL_NOPAD.encode(serde_jcs::to_string(payload)?.as_bytes());
+
+    let message = [header.as_str(), payload.as_str()].join(".");
+
+    let signature = signer
+        .sign(message.as_bytes())
+        .map_err(|e| SidetreeJwsError::SignerError(Box::new(e)))?;
+    let signature = BASE64URL_NOPAD.encode(&signature);
+
+    Ok([message, signature].join("."))
+}
+
+/// 署名を検証せずにペイロードを取り出す
+/// signed_dataに含まれる公開鍵を取得する用途に限定し、取り出した値は`verify`で検証してから利用すること
+pub fn decode_payload<T: DeserializeOwned>(jws: &str) -> Result<T, SidetreeJwsError> {
+    let (_, payload, _) = split(jws)?;
+    let payload = BASE64URL_NOPAD.decode(payload.as_bytes())?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+/// 公開鍵で署名を検証し、ペイロードを取り出す
+pub fn verify<T: DeserializeOwned>(
+    jws: &str,
+    public_key: &k256::PublicKey,
+) -> Result<T, SidetreeJwsError> {
+    let (header, payload, signature) = split(jws)?;
+
+    let decoded = BASE64URL_NOPAD.decode(header.as_bytes())?;
+    let decoded = serde_json::from_slice::<SidetreeJwsHeader>(&decoded)?;
+    if decoded.alg != "ES256K" {
+        return Err(SidetreeJwsError::InvalidAlgorithm(decoded.alg));
+    }
+
+    let signature = BASE64URL_NOPAD.decode(signature.as_bytes())?;
+    let signature = Signature::from_slice(&signature)?;
+
+    let message = [header, payload].join(".");
+    let verify_key = VerifyingKey::from(public_key);
+    verify_key.verify(message.as_bytes(), &signature)?;
+
+    decode_payload(jws)
+}
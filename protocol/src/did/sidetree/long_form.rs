@@ -0,0 +1,248 @@
+// Sidetreeのlong-form DID（did:miax:<suffix>:<initial-state>）
+// create操作の内容をDIDに埋め込むことで、アンカリングの完了を待たずにDIDを解決できるようにする
+//
+// 参考 : https://identity.foundation/sidetree/spec/#long-form-did-uris
+use data_encoding::{BASE64URL_NOPAD, BASE64_NOPAD};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::node::{create_state, to_did_document};
+use super::payload::{
+    canon, did_create_payload, DidCreatePayloadError, DidPatchDocument, DidPayload,
+    MiaxDidResponse, DID_METHOD,
+};
+
+#[derive(Debug, Error)]
+pub enum LongFormDidError {
+    #[error("Invalid long-form DID: {0}")]
+    InvalidDid(String),
+    #[error("Failed to build operation payload: {0}")]
+    PayloadBuildFailed(#[from] DidCreatePayloadError),
+    #[error("Failed to decode initial state: {0}")]
+    Decode(#[from] data_encoding::DecodeError),
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("Invalid initial state: {0}")]
+    InvalidInitialState(String),
+    #[error("DID suffix does not match initial state")]
+    DidSuffixMismatch,
+}
+
+// long-form DIDに埋め込むcreate操作の内容
+// deltaとsuffix_dataは、エンコード前のJSONオブジェクトとして保持する
+#[derive(Debug, Serialize, Deserialize)]
+struct InitialState {
+    delta: serde_json::Value,
+    suffix_data: serde_json::Value,
+}
+
+/// long-form DIDを、short-form DIDのsuffixと埋め込まれた初期状態に分割する
+/// long-form DIDでない場合はNoneを返す
+pub fn split_long_form(did: &str) -> Option<(&str, &str)> {
+    let prefix = format!("did:{}:", DID_METHOD);
+    let (suffix, initial_state) = did.strip_prefix(&prefix)?.split_once(':')?;
+    if suffix.is_empty() || initial_state.is_empty() {
+        return None;
+    }
+    Some((suffix, initial_state))
+}
+
+/// long-form DIDからshort-form DID（did:miax:<suffix>）を取り出す
+/// long-form DIDでない場合は、そのまま返す
+pub fn short_form(did: &str) -> String {
+    match split_long_form(did) {
+        Some((suffix, _)) => format!("did:{}:{}", DID_METHOD, suffix),
+        None => did.to_string(),
+    }
+}
+
+/// create操作のペイロード（did_create_payloadの戻り値）からlong-form DIDを生成する
+pub fn long_form_did_from_payload(create_payload: &str) -> Result<String, LongFormDidError> {
+    let DidPayload::Create { delta, suffix_data } = serde_json::from_str(create_payload)? else {
+        return Err(LongFormDidError::InvalidInitialState(
+            "not a create operation".to_string(),
+        ));
+    };
+    let (suffix, _) = create_state(&delta, &suffix_data)
+        .map_err(|e| LongFormDidError::InvalidInitialState(e.to_string()))?;
+
+    let initial_state = InitialState {
+        delta: serde_json::from_slice(&BASE64_NOPAD.decode(delta.as_bytes())?)?,
+        suffix_data: serde_json::from_slice(&BASE64_NOPAD.decode(suffix_data.as_bytes())?)?,
+    };
+    let encoded = BASE64URL_NOPAD.encode(&canon(&initial_state)?);
+    Ok(format!("did:{}:{}:{}", DID_METHOD, suffix, encoded))
+}
+
+/// DIDドキュメントの内容と更新・リカバリ用の公開鍵からlong-form DIDを生成する
+/// 同じ内容でcreate操作を送信すると、short-form DIDとして登録される
+pub fn long_form_did(
+    replace_payload: DidPatchDocument,
+    update_key: k256::PublicKey,
+    recovery_key: k256::PublicKey,
+) -> Result<String, LongFormDidError> {
+    let payload = did_create_payload(replace_payload, update_key, recovery_key)?;
+    long_form_did_from_payload(&payload)
+}
+
+/// long-form DIDに埋め込まれた初期状態から、Sidetreeに問い合わせずにDIDドキュメントを生成する
+/// DIDドキュメントのidは、long-form DIDのままとなる
+pub fn resolve_long_form(did: &str) -> Result<MiaxDidResponse, LongFormDidError> {
+    let (suffix, encoded) =
+        split_long_form(did).ok_or_else(|| LongFormDidError::InvalidDid(did.to_string()))?;
+    let initial_state: InitialState =
+        serde_json::from_slice(&BASE64URL_NOPAD.decode(encoded.as_bytes())?)?;
+
+    // create操作と同じ形式に戻し、ノードと同じ手順で検証する
+    // JCSで正規化したJSONは再度正規化しても同じバイト列となるため、delta_hashは一致する
+    let delta = BASE64_NOPAD.encode(&canon(&initial_state.delta)?);
+    let suffix_data = BASE64_NOPAD.encode(&canon(&initial_state.suffix_data)?);
+    let (did_suffix, state) = create_state(&delta, &suffix_data)
+        .map_err(|e| LongFormDidError::InvalidInitialState(e.to_string()))?;
+    if did_suffix != suffix {
+        return Err(LongFormDidError::DidSuffixMismatch);
+    }
+
+    Ok(MiaxDidResponse {
+        did_document: to_did_document(did, &state.document),
+        did_document_metadata: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::did_repository::{long_form_identifier, DidRepository, DidRepositoryImpl};
+    use crate::did::sidetree::node::LocalSidetreeNode;
+    use crate::keyring::keypair::KeyPairing;
+    use futures::executor::block_on;
+    use rand_core::OsRng;
+
+    fn public_key_ids(response: &MiaxDidResponse) -> Vec<String> {
+        response
+            .did_document
+            .public_key
+            .iter()
+            .flatten()
+            .map(|pk| pk.id.clone())
+            .collect()
+    }
+
+    /// 埋め込まれた初期状態を書き換えたlong-form DIDを返す
+    fn tamper(did: &str, f: impl FnOnce(&mut InitialState)) -> String {
+        let (suffix, encoded) = split_long_form(did).unwrap();
+        let mut initial_state: InitialState =
+            serde_json::from_slice(&BASE64URL_NOPAD.decode(encoded.as_bytes()).unwrap()).unwrap();
+        f(&mut initial_state);
+        let encoded = BASE64URL_NOPAD.encode(&canon(&initial_state).unwrap());
+        format!("did:{}:{}:{}", DID_METHOD, suffix, encoded)
+    }
+
+    #[test]
+    fn test_split_long_form() {
+        assert_eq!(
+            split_long_form("did:miax:EiAsuffix:eyJzdGF0ZSI6MX0"),
+            Some(("EiAsuffix", "eyJzdGF0ZSI6MX0"))
+        );
+        assert_eq!(split_long_form("did:miax:EiAsuffix"), None);
+        assert_eq!(split_long_form("did:miax::eyJzdGF0ZSI6MX0"), None);
+        assert_eq!(split_long_form("did:miax:EiAsuffix:"), None);
+        assert_eq!(split_long_form("did:key:EiAsuffix:eyJzdGF0ZSI6MX0"), None);
+
+        assert_eq!(
+            short_form("did:miax:EiAsuffix:eyJzdGF0ZSI6MX0"),
+            "did:miax:EiAsuffix"
+        );
+        assert_eq!(short_form("did:miax:EiAsuffix"), "did:miax:EiAsuffix");
+    }
+
+    #[test]
+    fn test_resolve_long_form() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = long_form_identifier(&keyring).unwrap();
+
+        let response = resolve_long_form(&did).unwrap();
+        assert_eq!(response.did_document.id, did);
+        assert_eq!(public_key_ids(&response), ["#signingKey", "#encryptionKey"]);
+
+        // 同じ鍵ペアから生成したlong-form DIDは一致する
+        assert_eq!(long_form_identifier(&keyring).unwrap(), did);
+    }
+
+    #[test]
+    fn test_suffix_matches_create_operation() {
+        let node = LocalSidetreeNode::in_memory();
+        let repository = DidRepositoryImpl::new(node);
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = long_form_identifier(&keyring).unwrap();
+
+        let created = block_on(repository.create_identifier(keyring)).unwrap();
+        assert_eq!(created.did_document.id, short_form(&did));
+    }
+
+    #[test]
+    fn test_reject_invalid_long_form() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = long_form_identifier(&keyring).unwrap();
+
+        assert!(matches!(
+            resolve_long_form(&short_form(&did)),
+            Err(LongFormDidError::InvalidDid(_))
+        ));
+        assert!(matches!(
+            resolve_long_form(&format!("{}!", did)),
+            Err(LongFormDidError::Decode(_))
+        ));
+
+        // deltaを書き換えた場合は、suffix_dataのdelta_hashと一致しない
+        let tampered = tamper(&did, |state| {
+            state.delta["patches"] = serde_json::json!([]);
+        });
+        assert!(matches!(
+            resolve_long_form(&tampered),
+            Err(LongFormDidError::InvalidInitialState(_))
+        ));
+
+        // suffix_dataを書き換えた場合は、suffixと一致しない
+        let tampered = tamper(&did, |state| {
+            state.suffix_data["recovery_commitment"] =
+                serde_json::json!(state.delta["update_commitment"].clone());
+        });
+        assert!(matches!(
+            resolve_long_form(&tampered),
+            Err(LongFormDidError::DidSuffixMismatch)
+        ));
+
+        // 別のDIDのsuffixを用いた場合も、suffixと一致しない
+        let other = long_form_identifier(&KeyPairing::create_keyring(OsRng)).unwrap();
+        let (_, encoded) = split_long_form(&did).unwrap();
+        let (other_suffix, _) = split_long_form(&other).unwrap();
+        assert!(matches!(
+            resolve_long_form(&format!("did:miax:{}:{}", other_suffix, encoded)),
+            Err(LongFormDidError::DidSuffixMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_find_identifier_prefers_registered_state() {
+        let node = LocalSidetreeNode::in_memory();
+        let repository = DidRepositoryImpl::new(node);
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = long_form_identifier(&keyring).unwrap();
+
+        // 未登録の場合は、埋め込まれた初期状態から解決する
+        let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(found.did_document.id, did);
+
+        // 登録後は、Sidetreeに登録された状態を返す
+        block_on(repository.create_identifier(&keyring)).unwrap();
+        let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(found.did_document.id, short_form(&did));
+
+        // 無効化後は、初期状態に戻らずに存在しないDIDとして扱う
+        block_on(repository.deactivate_identifier(&short_form(&did), keyring.recovery())).unwrap();
+        assert!(block_on(repository.find_identifier(&did))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1,3 +1,7 @@
 pub mod client;
+pub mod jws;
+pub mod long_form;
 pub mod multihash;
+pub mod node;
+pub mod patch;
 pub mod payload;
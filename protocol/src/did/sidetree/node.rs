@@ -0,0 +1,950 @@
+// Sidetreeノードのローカル実装
+// SidetreeHttpClientを実装し、外部のSidetreeネットワークに接続せずにDIDの作成・更新・解決を行う
+// テストやCI、オフライン環境でDidRepositoryImplやDIDCommを動作させることを想定している
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use chrono::Utc;
+use data_encoding::BASE64_NOPAD;
+use futures::lock::Mutex as AsyncMutex;
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+
+use super::client::{SidetreeHttpClient, SidetreeHttpClientResponse};
+use super::jws::{self, SidetreeJwsError};
+use super::long_form;
+use super::multihash;
+use super::patch::DidPatchError;
+use super::payload::{
+    commitment_scheme, reveal_value, suffix_data_hash, DidDeactivateSignedDataObject,
+    DidDeltaObject, DidDocument, DidPatchDocument, DidPayload, DidPublicKey,
+    DidRecoverSignedDataObject, DidSuffixObject, DidUpdateSignedDataObject, MiaxDidResponse,
+    DID_METHOD,
+};
+use crate::did::resolution::DidDocumentMetadata;
+use crate::keyring::jwk::{Jwk, JwkToK256Error};
+
+/// ノードが保持するDIDごとの状態
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DidState {
+    pub(crate) document: DidPatchDocument,
+    // 無効化されたDIDは、以降の操作を受け付けないためコミットメントを持たない
+    pub(crate) update_commitment: Option<String>,
+    pub(crate) recovery_commitment: Option<String>,
+    pub(crate) deactivated: bool,
+    // 作成・更新日時と、最後に適用した操作のハッシュ値（versionId）
+    #[serde(default)]
+    pub(crate) metadata: DidDocumentMetadata,
+}
+
+/// DIDの状態を永続化するストアのインターフェース
+#[trait_variant::make(Send)]
+pub trait SidetreeNodeStore: Sync {
+    type Error: std::error::Error + Send + Sync;
+    async fn load(&self, did_suffix: &str) -> Result<Option<DidState>, Self::Error>;
+    async fn save(&self, did_suffix: &str, state: &DidState) -> Result<(), Self::Error>;
+}
+
+/// メモリ上のストア実装（プロセス終了時に破棄される）
+#[derive(Default)]
+pub struct MemoryNodeStore {
+    states: Mutex<HashMap<String, DidState>>,
+}
+
+impl SidetreeNodeStore for MemoryNodeStore {
+    type Error = std::convert::Infallible;
+
+    async fn load(&self, did_suffix: &str) -> Result<Option<DidState>, Self::Error> {
+        Ok(self.states.lock().unwrap().get(did_suffix).cloned())
+    }
+
+    async fn save(&self, did_suffix: &str, state: &DidState) -> Result<(), Self::Error> {
+        self.states
+            .lock()
+            .unwrap()
+            .insert(did_suffix.to_string(), state.clone());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FileNodeStoreError {
+    #[error("failed to access store file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("failed serialize/deserialize: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// JSONファイルに状態を書き出すストア実装
+/// 全ての状態をメモリ上に保持し、保存の度にファイル全体を書き換える（開発・テスト用途の小規模なデータを想定）
+pub struct FileNodeStore {
+    path: PathBuf,
+    states: Mutex<HashMap<String, DidState>>,
+}
+
+impl FileNodeStore {
+    /// ストアファイルを開く（存在しない場合は、最初の保存時に作成される）
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FileNodeStoreError> {
+        let path = path.as_ref().to_path_buf();
+        let states = if path.exists() {
+            serde_json::from_slice(&fs::read(&path)?)?
+        } else {
+            HashMap::new()
+        };
+        Ok(Self {
+            path,
+            states: Mutex::new(states),
+        })
+    }
+
+    fn write(&self, states: &HashMap<String, DidState>) -> Result<(), FileNodeStoreError> {
+        // 書き込み途中で中断されてもストアファイルが壊れないように、一時ファイルに書き出してから置き換える
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(states)?)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl SidetreeNodeStore for FileNodeStore {
+    type Error = FileNodeStoreError;
+
+    async fn load(&self, did_suffix: &str) -> Result<Option<DidState>, Self::Error> {
+        Ok(self.states.lock().unwrap().get(did_suffix).cloned())
+    }
+
+    async fn save(&self, did_suffix: &str, state: &DidState) -> Result<(), Self::Error> {
+        let mut states = self.states.lock().unwrap();
+        states.insert(did_suffix.to_string(), state.clone());
+        self.write(&states)
+    }
+}
+
+/// 受け付けた操作が不正な場合のエラー（HTTPレスポンスとしてクライアントに返却される）
+#[derive(Debug, Error)]
+pub(crate) enum OperationError {
+    #[error("failed to decode: {0}")]
+    Decode(#[from] data_encoding::DecodeError),
+    #[error("failed serialize/deserialize: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid signed data: {0}")]
+    Jws(#[from] SidetreeJwsError),
+    #[error("invalid public key: {0}")]
+    Jwk(#[from] JwkToK256Error),
+    #[error("invalid patch: {0}")]
+    Patch(#[from] DidPatchError),
+    #[error("delta hash mismatch")]
+    DeltaHashMismatch,
+    #[error("reveal value mismatch")]
+    RevealValueMismatch,
+    #[error("commitment mismatch")]
+    CommitmentMismatch,
+    #[error("did suffix mismatch")]
+    DidSuffixMismatch,
+    #[error("did not found: {0}")]
+    NotFound(String),
+    #[error("did is deactivated: {0}")]
+    Deactivated(String),
+}
+
+impl OperationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            OperationError::NotFound(_) => StatusCode::NOT_FOUND,
+            OperationError::Deactivated(_) => StatusCode::GONE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+enum NodeError<E> {
+    Operation(OperationError),
+    Store(E),
+}
+
+impl<E, T: Into<OperationError>> From<T> for NodeError<E> {
+    fn from(value: T) -> Self {
+        NodeError::Operation(value.into())
+    }
+}
+
+fn decode_delta(delta: &str) -> Result<(DidDeltaObject, String), OperationError> {
+    let delta = BASE64_NOPAD.decode(delta.as_bytes())?;
+    let delta_hash = multihash::hash_encode(&delta);
+    Ok((serde_json::from_slice(&delta)?, delta_hash))
+}
+
+/// 公開されたリビール値・鍵が、前回の操作で登録されたコミットメントに対応しているかを検証する
+fn check_commitment(
+    key: &Jwk,
+    revealed: &str,
+    commitment: &Option<String>,
+) -> Result<(), OperationError> {
+    if reveal_value(key)? != revealed {
+        return Err(OperationError::RevealValueMismatch);
+    }
+    if commitment.as_deref() != Some(commitment_scheme(key)?.as_str()) {
+        return Err(OperationError::CommitmentMismatch);
+    }
+    Ok(())
+}
+
+/// patch actionを検証したうえで順に適用する
+fn apply_delta(
+    document: &mut DidPatchDocument,
+    delta: &DidDeltaObject,
+) -> Result<(), DidPatchError> {
+    let mut patched = document.clone();
+    for patch in &delta.patches {
+        patch.validate()?;
+        patch.apply(&mut patched)?;
+    }
+    *document = patched;
+    Ok(())
+}
+
+/// create操作の内容から、DIDのsuffixと初期状態を生成する
+pub(crate) fn create_state(
+    delta: &str,
+    suffix_data: &str,
+) -> Result<(String, DidState), OperationError> {
+    let suffix_data = BASE64_NOPAD.decode(suffix_data.as_bytes())?;
+    let suffix_data: DidSuffixObject = serde_json::from_slice(&suffix_data)?;
+    let (delta, delta_hash) = decode_delta(delta)?;
+    if delta_hash != suffix_data.delta_hash {
+        return Err(OperationError::DeltaHashMismatch);
+    }
+
+    let mut document = DidPatchDocument::default();
+    apply_delta(&mut document, &delta)?;
+
+    let did_suffix = suffix_data_hash(&suffix_data)?;
+    let state = DidState {
+        document,
+        update_commitment: Some(delta.update_commitment),
+        recovery_commitment: Some(suffix_data.recovery_commitment),
+        deactivated: false,
+        metadata: DidDocumentMetadata::default(),
+    };
+    Ok((did_suffix, state))
+}
+
+/// ノードが保持する状態を、DID Resolutionの結果として返すDIDドキュメントに変換する
+pub(crate) fn to_did_document(did: &str, document: &DidPatchDocument) -> DidDocument {
+    let public_key = document
+        .public_keys
+        .iter()
+        .map(|pk| DidPublicKey {
+            id: format!("#{}", pk.id),
+            controller: did.to_string(),
+            r#type: pk.r#type.clone(),
+            public_key_jwk: pk.jwk.clone(),
+        })
+        .collect();
+    let authentication = document
+        .public_keys
+        .iter()
+        .filter(|pk| {
+            pk.purpose
+                .iter()
+                .any(|p| p == "auth" || p == "authentication")
+        })
+        .map(|pk| format!("#{}", pk.id))
+        .collect();
+    DidDocument {
+        id: did.to_string(),
+        public_key: Some(public_key),
+        authentication: Some(authentication),
+    }
+}
+
+fn json_response(status_code: StatusCode, body: &impl Serialize) -> SidetreeHttpClientResponse {
+    // シリアライズ対象はノード内部で組み立てた値のため、失敗することはない
+    let body = serde_json::to_string(body).expect("failed to serialize response");
+    SidetreeHttpClientResponse::new(status_code, body)
+}
+
+fn error_response(error: &OperationError) -> SidetreeHttpClientResponse {
+    json_response(
+        error.status_code(),
+        &json!({
+            "code": error.status_code().as_u16(),
+            "message": error.to_string(),
+        }),
+    )
+}
+
+fn did_response(did_suffix: &str, state: &DidState) -> SidetreeHttpClientResponse {
+    let did = format!("did:{}:{}", DID_METHOD, did_suffix);
+    let response = MiaxDidResponse {
+        did_document: to_did_document(&did, &state.document),
+        did_document_metadata: Some(state.metadata.clone()),
+    };
+    json_response(StatusCode::OK, &response)
+}
+
+struct Inner<S> {
+    store: S,
+    // load → 検証 → save の間に他の操作が割り込まないように、操作を直列化する
+    lock: AsyncMutex<()>,
+}
+
+/// ローカルで動作するSidetreeノード
+///
+/// create/update/recover/deactivateの各操作について、コミットメントと署名を検証したうえで状態を更新する
+/// cloneしたインスタンス同士は状態を共有するため、複数のDidRepositoryImplから同じノードを利用できる
+///
+/// # 例
+/// ```ignore
+/// let node = LocalSidetreeNode::in_memory();
+/// let alice = DidRepositoryImpl::new(node.clone());
+/// let bob = DidRepositoryImpl::new(node);
+/// ```
+pub struct LocalSidetreeNode<S: SidetreeNodeStore = MemoryNodeStore> {
+    inner: Arc<Inner<S>>,
+}
+
+impl<S: SidetreeNodeStore> Clone for LocalSidetreeNode<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl LocalSidetreeNode<MemoryNodeStore> {
+    pub fn in_memory() -> Self {
+        Self::new(MemoryNodeStore::default())
+    }
+}
+
+impl LocalSidetreeNode<FileNodeStore> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, FileNodeStoreError> {
+        Ok(Self::new(FileNodeStore::open(path)?))
+    }
+}
+
+impl<S: SidetreeNodeStore> LocalSidetreeNode<S> {
+    pub fn new(store: S) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                store,
+                lock: AsyncMutex::new(()),
+            }),
+        }
+    }
+
+    async fn load_active(&self, did_suffix: &str) -> Result<DidState, NodeError<S::Error>> {
+        let state = self
+            .inner
+            .store
+            .load(did_suffix)
+            .await
+            .map_err(NodeError::Store)?
+            .ok_or_else(|| OperationError::NotFound(did_suffix.to_string()))?;
+        if state.deactivated {
+            return Err(OperationError::Deactivated(did_suffix.to_string()).into());
+        }
+        Ok(state)
+    }
+
+    async fn apply_operation(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, NodeError<S::Error>> {
+        let payload: DidPayload = serde_json::from_str(body)?;
+        let _guard = self.inner.lock.lock().await;
+        let now = Utc::now();
+        let version_id = multihash::hash_encode(body.as_bytes());
+
+        let (did_suffix, mut state) = match payload {
+            DidPayload::Create { delta, suffix_data } => {
+                let (did_suffix, mut state) = create_state(&delta, &suffix_data)?;
+                // 同じ内容のcreate操作は、既存のDIDを返す
+                if let Some(existing) = self
+                    .inner
+                    .store
+                    .load(&did_suffix)
+                    .await
+                    .map_err(NodeError::Store)?
+                {
+                    return Ok(did_response(&did_suffix, &existing));
+                }
+                state.metadata.created = Some(now);
+                (did_suffix, state)
+            }
+            DidPayload::Update {
+                delta,
+                did_suffix,
+                reveal_value,
+                signed_data,
+            } => {
+                let mut state = self.load_active(&did_suffix).await?;
+                let signed: DidUpdateSignedDataObject = jws::decode_payload(&signed_data)?;
+                check_commitment(&signed.update_key, &reveal_value, &state.update_commitment)?;
+                jws::verify::<DidUpdateSignedDataObject>(
+                    &signed_data,
+                    &signed.update_key.clone().try_into()?,
+                )?;
+
+                let (delta, delta_hash) = decode_delta(&delta)?;
+                if delta_hash != signed.delta_hash {
+                    return Err(OperationError::DeltaHashMismatch.into());
+                }
+                apply_delta(&mut state.document, &delta)?;
+                state.update_commitment = Some(delta.update_commitment);
+                state.metadata.updated = Some(now);
+                (did_suffix, state)
+            }
+            DidPayload::Recover {
+                delta,
+                did_suffix,
+                reveal_value,
+                signed_data,
+            } => {
+                let mut state = self.load_active(&did_suffix).await?;
+                let signed: DidRecoverSignedDataObject = jws::decode_payload(&signed_data)?;
+                check_commitment(
+                    &signed.recovery_key,
+                    &reveal_value,
+                    &state.recovery_commitment,
+                )?;
+                jws::verify::<DidRecoverSignedDataObject>(
+                    &signed_data,
+                    &signed.recovery_key.clone().try_into()?,
+                )?;
+
+                let (delta, delta_hash) = decode_delta(&delta)?;
+                if delta_hash != signed.delta_hash {
+                    return Err(OperationError::DeltaHashMismatch.into());
+                }
+                // recover操作では、既存のドキュメントを破棄して新しい内容で置き換える
+                let mut document = DidPatchDocument::default();
+                apply_delta(&mut document, &delta)?;
+                state.document = document;
+                state.update_commitment = Some(delta.update_commitment);
+                state.recovery_commitment = Some(signed.recovery_commitment);
+                state.metadata.updated = Some(now);
+                (did_suffix, state)
+            }
+            DidPayload::Deactivate {
+                did_suffix,
+                reveal_value,
+                signed_data,
+            } => {
+                let mut state = self.load_active(&did_suffix).await?;
+                let signed: DidDeactivateSignedDataObject = jws::decode_payload(&signed_data)?;
+                if signed.did_suffix != did_suffix {
+                    return Err(OperationError::DidSuffixMismatch.into());
+                }
+                check_commitment(
+                    &signed.recovery_key,
+                    &reveal_value,
+                    &state.recovery_commitment,
+                )?;
+                jws::verify::<DidDeactivateSignedDataObject>(
+                    &signed_data,
+                    &signed.recovery_key.clone().try_into()?,
+                )?;
+
+                state.document = DidPatchDocument::default();
+                state.update_commitment = None;
+                state.recovery_commitment = None;
+                state.deactivated = true;
+                state.metadata.updated = Some(now);
+                state.metadata.version_id = Some(version_id);
+                state.metadata.deactivated = Some(true);
+                self.inner
+                    .store
+                    .save(&did_suffix, &state)
+                    .await
+                    .map_err(NodeError::Store)?;
+                return Ok(SidetreeHttpClientResponse::new(
+                    StatusCode::OK,
+                    String::new(),
+                ));
+            }
+        };
+        state.metadata.version_id = Some(version_id);
+
+        self.inner
+            .store
+            .save(&did_suffix, &state)
+            .await
+            .map_err(NodeError::Store)?;
+        Ok(did_response(&did_suffix, &state))
+    }
+
+    /// Sidetreeの操作（create/update/recover/deactivate）を検証し、適用する
+    /// 不正な操作はエラーとせず、4xxのレスポンスとして返す（ストアへのアクセスに失敗した場合のみエラーとなる）
+    pub async fn handle_operation(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, S::Error> {
+        match self.apply_operation(body).await {
+            Ok(response) => Ok(response),
+            Err(NodeError::Operation(e)) => Ok(error_response(&e)),
+            Err(NodeError::Store(e)) => Err(e),
+        }
+    }
+
+    /// DIDを解決し、DIDドキュメントを返す
+    /// 存在しないDIDは404、無効化されたDIDは410のレスポンスとなる
+    /// 未登録のlong-form DIDは、埋め込まれた初期状態から解決する
+    pub async fn resolve(&self, did: &str) -> Result<SidetreeHttpClientResponse, S::Error> {
+        let prefix = format!("did:{}:", DID_METHOD);
+        let long_form = long_form::split_long_form(did);
+        let Some(did_suffix) = long_form
+            .map(|(suffix, _)| suffix)
+            .or_else(|| did.strip_prefix(&prefix))
+        else {
+            return Ok(json_response(
+                StatusCode::BAD_REQUEST,
+                &json!({
+                    "code": StatusCode::BAD_REQUEST.as_u16(),
+                    "message": format!("invalid did: {}", did),
+                }),
+            ));
+        };
+        match self.load_active(did_suffix).await {
+            Ok(state) => Ok(did_response(did_suffix, &state)),
+            // 無効化されたDIDは、メタデータを含めて410のレスポンスとする
+            Err(NodeError::Operation(e @ OperationError::Deactivated(_))) => {
+                let metadata = self
+                    .inner
+                    .store
+                    .load(did_suffix)
+                    .await?
+                    .map(|state| state.metadata)
+                    .unwrap_or_default();
+                Ok(json_response(
+                    e.status_code(),
+                    &json!({
+                        "code": e.status_code().as_u16(),
+                        "message": e.to_string(),
+                        "did_document_metadata": metadata,
+                    }),
+                ))
+            }
+            Err(NodeError::Operation(OperationError::NotFound(_))) if long_form.is_some() => {
+                match long_form::resolve_long_form(did) {
+                    Ok(response) => Ok(json_response(StatusCode::OK, &response)),
+                    Err(e) => Ok(json_response(
+                        StatusCode::BAD_REQUEST,
+                        &json!({
+                            "code": StatusCode::BAD_REQUEST.as_u16(),
+                            "message": e.to_string(),
+                        }),
+                    )),
+                }
+            }
+            Err(NodeError::Operation(e)) => Ok(error_response(&e)),
+            Err(NodeError::Store(e)) => Err(e),
+        }
+    }
+}
+
+impl<S: SidetreeNodeStore> SidetreeHttpClient for LocalSidetreeNode<S> {
+    type Error = S::Error;
+
+    async fn post_create_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+        self.handle_operation(body).await
+    }
+
+    async fn post_update_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+        self.handle_operation(body).await
+    }
+
+    async fn post_recover_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+        self.handle_operation(body).await
+    }
+
+    async fn post_deactivate_identifier(
+        &self,
+        body: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+        self.handle_operation(body).await
+    }
+
+    async fn get_find_identifier(
+        &self,
+        did: &str,
+    ) -> Result<SidetreeHttpClientResponse, Self::Error> {
+        self.resolve(did).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::did_repository::{DidRepository, DidRepositoryImpl};
+    use crate::did::sidetree::patch::DidPatchBuilder;
+    use crate::did::sidetree::payload::{
+        did_deactivate_payload, did_recover_payload, did_update_payload, ServiceEndpoint,
+        ToPublicKey,
+    };
+    use crate::keyring::keypair::{KeyPair, KeyPairing};
+    use data_encoding::BASE64URL_NOPAD;
+    use futures::executor::block_on;
+    use rand_core::OsRng;
+
+    fn public_key_ids(response: &MiaxDidResponse) -> Vec<String> {
+        response
+            .did_document
+            .public_key
+            .iter()
+            .flatten()
+            .map(|pk| pk.id.clone())
+            .collect()
+    }
+
+    fn suffix(did: &str) -> &str {
+        did.rsplit(':').next().unwrap()
+    }
+
+    /// DIDを作成し、ノード・DID・作成時の鍵ペアを返す
+    fn create(node: &LocalSidetreeNode) -> (String, KeyPairing) {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let repository = DidRepositoryImpl::new(node.clone());
+        let response = block_on(repository.create_identifier(&keyring)).unwrap();
+        (response.did_document.id, keyring)
+    }
+
+    fn post(node: &LocalSidetreeNode, body: &str) -> SidetreeHttpClientResponse {
+        block_on(node.handle_operation(body)).unwrap()
+    }
+
+    fn resolve(node: &LocalSidetreeNode, did: &str) -> SidetreeHttpClientResponse {
+        block_on(node.resolve(did)).unwrap()
+    }
+
+    /// JSONのフィールドを書き換えたペイロードを返す
+    fn tamper(payload: &str, field: &str, value: &str) -> String {
+        let mut payload: serde_json::Value = serde_json::from_str(payload).unwrap();
+        payload[field] = serde_json::Value::String(value.to_string());
+        payload.to_string()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let node = LocalSidetreeNode::in_memory();
+        let repository = DidRepositoryImpl::new(node.clone());
+        let (did, keyring) = create(&node);
+
+        let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(found.did_document.id, did);
+        assert_eq!(public_key_ids(&found), ["#signingKey", "#encryptionKey"]);
+
+        // update: 公開鍵とサービスを追加する
+        let next = KeyPairing::create_keyring(OsRng);
+        let new_key = next
+            .sign()
+            .get_public_key()
+            .to_public_key(
+                "EcdsaSecp256k1VerificationKey2019".to_string(),
+                "signingKey2".to_string(),
+                vec!["auth".to_string()],
+            )
+            .unwrap();
+        let patches = DidPatchBuilder::new()
+            .add_public_keys(vec![new_key])
+            .unwrap()
+            .add_services(vec![ServiceEndpoint {
+                id: "didcomm".to_string(),
+                r#type: "DIDCommMessaging".to_string(),
+                service_endpoint: "https://example.com/didcomm".to_string(),
+                description: None,
+            }])
+            .unwrap()
+            .build();
+        block_on(repository.update_identifier(
+            &did,
+            keyring.update(),
+            next.update().get_public_key(),
+            patches,
+        ))
+        .unwrap();
+        let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(
+            public_key_ids(&found),
+            ["#signingKey", "#encryptionKey", "#signingKey2"]
+        );
+
+        // 次の更新には、前回コミットした更新鍵を用いる
+        let patches = DidPatchBuilder::new()
+            .remove_public_keys(vec!["signingKey2".to_string()])
+            .unwrap()
+            .build();
+        block_on(repository.update_identifier(
+            &did,
+            next.update(),
+            keyring.update().get_public_key(),
+            patches,
+        ))
+        .unwrap();
+        let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(public_key_ids(&found), ["#signingKey", "#encryptionKey"]);
+
+        // recover: 新しい鍵ペアでドキュメント全体を置き換える
+        let recovered = KeyPairing::create_keyring(OsRng);
+        block_on(repository.recover_identifier(&did, keyring.recovery(), &recovered)).unwrap();
+        let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        let jwk: Jwk = recovered.sign().get_public_key().try_into().unwrap();
+        assert_eq!(
+            serde_json::to_value(&found.did_document.public_key.unwrap()[0].public_key_jwk)
+                .unwrap(),
+            serde_json::to_value(&jwk).unwrap()
+        );
+        // recover後は、新しい更新鍵のみが有効となる
+        assert!(block_on(repository.update_identifier(
+            &did,
+            keyring.update(),
+            keyring.update().get_public_key(),
+            vec![],
+        ))
+        .is_err());
+        block_on(repository.update_identifier(
+            &did,
+            recovered.update(),
+            recovered.update().get_public_key(),
+            vec![],
+        ))
+        .unwrap();
+
+        // deactivate: 以降は解決できない
+        block_on(repository.deactivate_identifier(&did, recovered.recovery())).unwrap();
+        assert!(block_on(repository.find_identifier(&did))
+            .unwrap()
+            .is_none());
+        assert_eq!(resolve(&node, &did).status_code, StatusCode::GONE);
+    }
+
+    #[test]
+    fn test_create_is_idempotent() {
+        let node = LocalSidetreeNode::in_memory();
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let repository = DidRepositoryImpl::new(node.clone());
+        let first = block_on(repository.create_identifier(&keyring)).unwrap();
+        let second = block_on(repository.create_identifier(keyring)).unwrap();
+        assert_eq!(first.did_document.id, second.did_document.id);
+    }
+
+    #[test]
+    fn test_reject_wrong_reveal_value() {
+        let node = LocalSidetreeNode::in_memory();
+        let (did, keyring) = create(&node);
+        let other = KeyPairing::create_keyring(OsRng);
+
+        // 登録されていない更新鍵で署名した操作は、コミットメントと一致しない
+        let payload = did_update_payload(
+            vec![],
+            suffix(&did),
+            other.update(),
+            other.update().get_public_key(),
+        )
+        .unwrap();
+        let response = post(&node, &payload);
+        assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+        assert!(response.body.contains("commitment mismatch"));
+
+        // 正しい鍵で署名していても、リビール値が異なる場合は受け付けない
+        let payload = did_update_payload(
+            vec![],
+            suffix(&did),
+            keyring.update(),
+            keyring.update().get_public_key(),
+        )
+        .unwrap();
+        let other_jwk: Jwk = other.update().get_public_key().try_into().unwrap();
+        let payload = tamper(&payload, "reveal_value", &reveal_value(&other_jwk).unwrap());
+        let response = post(&node, &payload);
+        assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+        assert!(response.body.contains("reveal value mismatch"));
+
+        // リカバリ鍵以外で署名したrecover操作も受け付けない
+        let payload = did_recover_payload(
+            DidPatchDocument::default(),
+            suffix(&did),
+            keyring.update(),
+            other.update().get_public_key(),
+            other.recovery().get_public_key(),
+        )
+        .unwrap();
+        assert_eq!(post(&node, &payload).status_code, StatusCode::BAD_REQUEST);
+
+        // 拒否された操作は、DIDの状態を変更しない
+        assert_eq!(resolve(&node, &did).status_code, StatusCode::OK);
+        let payload = did_update_payload(
+            vec![],
+            suffix(&did),
+            keyring.update(),
+            keyring.update().get_public_key(),
+        )
+        .unwrap();
+        assert_eq!(post(&node, &payload).status_code, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_reject_bad_jws() {
+        let node = LocalSidetreeNode::in_memory();
+        let (did, keyring) = create(&node);
+        let payload = did_update_payload(
+            vec![],
+            suffix(&did),
+            keyring.update(),
+            keyring.update().get_public_key(),
+        )
+        .unwrap();
+        let signed_data = serde_json::from_str::<serde_json::Value>(&payload).unwrap()
+            ["signed_data"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let parts: Vec<&str> = signed_data.split('.').collect();
+
+        // 署名を改ざんしたJWS
+        let mut signature = BASE64URL_NOPAD.decode(parts[2].as_bytes()).unwrap();
+        signature[10] ^= 0x01;
+        let tampered = format!(
+            "{}.{}.{}",
+            parts[0],
+            parts[1],
+            BASE64URL_NOPAD.encode(&signature)
+        );
+        let response = post(&node, &tamper(&payload, "signed_data", &tampered));
+        assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+        assert!(response.body.contains("invalid signed data"));
+
+        // 形式が不正なJWS
+        let response = post(&node, &tamper(&payload, "signed_data", "not-a-jws"));
+        assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+
+        // 署名対象と異なる変更内容（delta）
+        let other = did_update_payload(
+            DidPatchBuilder::new()
+                .remove_services(vec!["didcomm".to_string()])
+                .unwrap()
+                .build(),
+            suffix(&did),
+            keyring.update(),
+            keyring.update().get_public_key(),
+        )
+        .unwrap();
+        let other_delta = serde_json::from_str::<serde_json::Value>(&other).unwrap()["delta"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let response = post(&node, &tamper(&payload, "delta", &other_delta));
+        assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+        assert!(response.body.contains("delta hash mismatch"));
+
+        assert_eq!(post(&node, &payload).status_code, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_reject_operation_on_deactivated_did() {
+        let node = LocalSidetreeNode::in_memory();
+        let (did, keyring) = create(&node);
+        let payload = did_deactivate_payload(suffix(&did), keyring.recovery()).unwrap();
+        assert_eq!(post(&node, &payload).status_code, StatusCode::OK);
+
+        let payload = did_update_payload(
+            vec![],
+            suffix(&did),
+            keyring.update(),
+            keyring.update().get_public_key(),
+        )
+        .unwrap();
+        assert_eq!(post(&node, &payload).status_code, StatusCode::GONE);
+
+        let payload = did_recover_payload(
+            DidPatchDocument::default(),
+            suffix(&did),
+            keyring.recovery(),
+            keyring.update().get_public_key(),
+            keyring.recovery().get_public_key(),
+        )
+        .unwrap();
+        assert_eq!(post(&node, &payload).status_code, StatusCode::GONE);
+
+        let payload = did_deactivate_payload(suffix(&did), keyring.recovery()).unwrap();
+        assert_eq!(post(&node, &payload).status_code, StatusCode::GONE);
+    }
+
+    #[test]
+    fn test_reject_deactivate_for_other_did() {
+        let node = LocalSidetreeNode::in_memory();
+        let (did, keyring) = create(&node);
+        let (other_did, _) = create(&node);
+
+        // 他のDIDに対して署名したdeactivate操作は、対象のDIDに適用できない
+        let payload = did_deactivate_payload(suffix(&other_did), keyring.recovery()).unwrap();
+        let payload = tamper(&payload, "did_suffix", suffix(&did));
+        let response = post(&node, &payload);
+        assert_eq!(response.status_code, StatusCode::BAD_REQUEST);
+        assert!(response.body.contains("did suffix mismatch"));
+        assert_eq!(resolve(&node, &did).status_code, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_resolve_unknown_did() {
+        let node = LocalSidetreeNode::in_memory();
+        assert_eq!(
+            resolve(&node, "did:miax:unknown").status_code,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            resolve(&node, "did:example:unknown").status_code,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn test_file_store_persists_state() {
+        let path = std::env::temp_dir().join(format!(
+            "miax-sidetree-node-test-{}.json",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = {
+            let node = LocalSidetreeNode::open(&path).unwrap();
+            let repository = DidRepositoryImpl::new(node);
+            block_on(repository.create_identifier(&keyring))
+                .unwrap()
+                .did_document
+                .id
+        };
+
+        let node = LocalSidetreeNode::open(&path).unwrap();
+        let repository = DidRepositoryImpl::new(node);
+        assert!(block_on(repository.find_identifier(&did))
+            .unwrap()
+            .is_some());
+        block_on(repository.deactivate_identifier(&did, keyring.recovery())).unwrap();
+
+        let node = LocalSidetreeNode::open(&path).unwrap();
+        assert_eq!(resolve_file(&node, &did), StatusCode::GONE);
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn resolve_file(node: &LocalSidetreeNode<FileNodeStore>, did: &str) -> StatusCode {
+        block_on(node.resolve(did)).unwrap().status_code
+    }
+}
@@ -0,0 +1,580 @@
+// Sidetreeのpatch action（DIDドキュメントへの差分変更）の組み立てと検証
+// 参考 : https://identity.foundation/sidetree/spec/#did-state-patches
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use super::payload::{DidAction, DidPatchDocument, PublicKeyPayload, ServiceEndpoint};
+
+// Sidetreeの仕様上、鍵IDやサービスIDはBase64URLの文字種で50文字以内
+const MAX_ID_LENGTH: usize = 50;
+// Sidetreeの仕様上、サービスのtypeは30文字以内
+const MAX_SERVICE_TYPE_LENGTH: usize = 30;
+
+/// 公開鍵のpurposeとして許容される値
+/// "auth", "general"は既存のDIDで利用している旧仕様の値のため、互換性維持のために許容する
+const ALLOWED_PURPOSES: [&str; 7] = [
+    "authentication",
+    "assertionMethod",
+    "capabilityInvocation",
+    "capabilityDelegation",
+    "keyAgreement",
+    "auth",
+    "general",
+];
+
+/// RFC 6902 (JSON Patch) の操作
+/// ietf-json-patch actionで、DIDドキュメントに対して任意の変更を適用する際に利用する
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "op")]
+pub enum JsonPatchOperation {
+    #[serde(rename = "add")]
+    Add { path: String, value: Value },
+    #[serde(rename = "remove")]
+    Remove { path: String },
+    #[serde(rename = "replace")]
+    Replace { path: String, value: Value },
+    #[serde(rename = "move")]
+    Move { from: String, path: String },
+    #[serde(rename = "copy")]
+    Copy { from: String, path: String },
+    #[serde(rename = "test")]
+    Test { path: String, value: Value },
+}
+
+#[derive(Debug, Error)]
+pub enum DidPatchError {
+    #[error("invalid id: {0}")]
+    InvalidId(String),
+    #[error("duplicate id: {0}")]
+    DuplicateId(String),
+    #[error("invalid purpose: {0}")]
+    InvalidPurpose(String),
+    #[error("duplicate purpose: {0}")]
+    DuplicatePurpose(String),
+    #[error("public key jwk must not contain private key: {0}")]
+    PrivateKeyJwk(String),
+    #[error("invalid service type: {0}")]
+    InvalidServiceType(String),
+    #[error("invalid service endpoint: {0}")]
+    InvalidServiceEndpoint(String),
+    #[error("invalid json pointer: {0}")]
+    InvalidJsonPointer(String),
+    #[error("empty patch: {0}")]
+    Empty(&'static str),
+    #[error("failed to apply json patch: {0}")]
+    JsonPatchFailed(String),
+    #[error("failed serialize/deserialize: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// 鍵ID・サービスIDの形式を検証する（Base64URLの文字種・50文字以内）
+fn validate_id(id: &str) -> Result<(), DidPatchError> {
+    let is_valid = !id.is_empty()
+        && id.len() <= MAX_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if is_valid {
+        Ok(())
+    } else {
+        Err(DidPatchError::InvalidId(id.to_string()))
+    }
+}
+
+/// IDの形式と重複を検証する
+fn validate_ids<'a>(ids: impl IntoIterator<Item = &'a str>) -> Result<(), DidPatchError> {
+    let mut seen = HashSet::new();
+    for id in ids {
+        validate_id(id)?;
+        if !seen.insert(id) {
+            return Err(DidPatchError::DuplicateId(id.to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn validate_public_keys(public_keys: &[PublicKeyPayload]) -> Result<(), DidPatchError> {
+    validate_ids(public_keys.iter().map(|pk| pk.id.as_str()))?;
+    for pk in public_keys {
+        // 秘密鍵（d）を含むJWKをDIDドキュメントに公開しない
+        if pk.jwk.is_private() {
+            return Err(DidPatchError::PrivateKeyJwk(pk.id.clone()));
+        }
+        let mut seen = HashSet::new();
+        for purpose in &pk.purpose {
+            if !ALLOWED_PURPOSES.contains(&purpose.as_str()) {
+                return Err(DidPatchError::InvalidPurpose(purpose.clone()));
+            }
+            if !seen.insert(purpose.as_str()) {
+                return Err(DidPatchError::DuplicatePurpose(purpose.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_services(services: &[ServiceEndpoint]) -> Result<(), DidPatchError> {
+    validate_ids(services.iter().map(|s| s.id.as_str()))?;
+    for service in services {
+        if service.r#type.is_empty() || service.r#type.len() > MAX_SERVICE_TYPE_LENGTH {
+            return Err(DidPatchError::InvalidServiceType(service.r#type.clone()));
+        }
+        // URIとして最低限の形式（scheme:...）を満たしているかを確認
+        let has_scheme = service
+            .service_endpoint
+            .split_once(':')
+            .map(|(scheme, rest)| {
+                !scheme.is_empty()
+                    && !rest.is_empty()
+                    && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                    && scheme
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+            })
+            .unwrap_or(false);
+        if !has_scheme {
+            return Err(DidPatchError::InvalidServiceEndpoint(
+                service.service_endpoint.clone(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// JSON Pointer (RFC 6901) の形式を検証する
+fn validate_json_pointer(pointer: &str) -> Result<(), DidPatchError> {
+    if pointer.is_empty() || pointer.starts_with('/') {
+        Ok(())
+    } else {
+        Err(DidPatchError::InvalidJsonPointer(pointer.to_string()))
+    }
+}
+
+fn validate_json_patch(patches: &[JsonPatchOperation]) -> Result<(), DidPatchError> {
+    for patch in patches {
+        match patch {
+            JsonPatchOperation::Add { path, .. }
+            | JsonPatchOperation::Remove { path }
+            | JsonPatchOperation::Replace { path, .. }
+            | JsonPatchOperation::Test { path, .. } => validate_json_pointer(path)?,
+            JsonPatchOperation::Move { from, path } | JsonPatchOperation::Copy { from, path } => {
+                validate_json_pointer(from)?;
+                validate_json_pointer(path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl DidAction {
+    /// patch actionの内容がSidetreeの仕様を満たしているかを検証する
+    pub fn validate(&self) -> Result<(), DidPatchError> {
+        match self {
+            DidAction::Replace { document } => {
+                validate_public_keys(&document.public_keys)?;
+                validate_services(&document.service_endpoints)
+            }
+            DidAction::AddPublicKeys { public_keys } => {
+                if public_keys.is_empty() {
+                    return Err(DidPatchError::Empty("add-public-keys"));
+                }
+                validate_public_keys(public_keys)
+            }
+            DidAction::RemovePublicKeys { ids } => {
+                if ids.is_empty() {
+                    return Err(DidPatchError::Empty("remove-public-keys"));
+                }
+                validate_ids(ids.iter().map(String::as_str))
+            }
+            DidAction::AddServices { services } => {
+                if services.is_empty() {
+                    return Err(DidPatchError::Empty("add-services"));
+                }
+                validate_services(services)
+            }
+            DidAction::RemoveServices { ids } => {
+                if ids.is_empty() {
+                    return Err(DidPatchError::Empty("remove-services"));
+                }
+                validate_ids(ids.iter().map(String::as_str))
+            }
+            DidAction::IetfJsonPatch { patches } => {
+                if patches.is_empty() {
+                    return Err(DidPatchError::Empty("ietf-json-patch"));
+                }
+                validate_json_patch(patches)
+            }
+        }
+    }
+}
+
+// JSON Pointerの最後の要素をエスケープ解除する（"~1" -> "/", "~0" -> "~"）
+fn unescape_token(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+fn json_patch_failed(op: &str, path: &str) -> DidPatchError {
+    DidPatchError::JsonPatchFailed(format!("{} {}", op, path))
+}
+
+fn json_patch_add(document: &mut Value, path: &str, value: Value) -> Result<(), DidPatchError> {
+    let Some((parent, token)) = path.rsplit_once('/') else {
+        // ルート自体を置き換える
+        *document = value;
+        return Ok(());
+    };
+    let token = unescape_token(token);
+    match document.pointer_mut(parent) {
+        Some(Value::Object(map)) => {
+            map.insert(token, value);
+            Ok(())
+        }
+        Some(Value::Array(array)) if token == "-" => {
+            array.push(value);
+            Ok(())
+        }
+        Some(Value::Array(array)) => match token.parse::<usize>() {
+            Ok(index) if index <= array.len() => {
+                array.insert(index, value);
+                Ok(())
+            }
+            _ => Err(json_patch_failed("add", path)),
+        },
+        _ => Err(json_patch_failed("add", path)),
+    }
+}
+
+fn json_patch_remove(document: &mut Value, path: &str) -> Result<Value, DidPatchError> {
+    let (parent, token) = path
+        .rsplit_once('/')
+        .ok_or_else(|| json_patch_failed("remove", path))?;
+    let token = unescape_token(token);
+    match document.pointer_mut(parent) {
+        Some(Value::Object(map)) => map
+            .remove(&token)
+            .ok_or_else(|| json_patch_failed("remove", path)),
+        Some(Value::Array(array)) => match token.parse::<usize>() {
+            Ok(index) if index < array.len() => Ok(array.remove(index)),
+            _ => Err(json_patch_failed("remove", path)),
+        },
+        _ => Err(json_patch_failed("remove", path)),
+    }
+}
+
+/// RFC 6902 (JSON Patch) の操作を順に適用する
+/// いずれかの操作が失敗した場合はエラーを返し、`document`は変更されない
+fn apply_json_patch(
+    document: &mut Value,
+    patches: &[JsonPatchOperation],
+) -> Result<(), DidPatchError> {
+    let mut patched = document.clone();
+    for patch in patches {
+        match patch {
+            JsonPatchOperation::Add { path, value } => {
+                json_patch_add(&mut patched, path, value.clone())?
+            }
+            JsonPatchOperation::Remove { path } => {
+                json_patch_remove(&mut patched, path)?;
+            }
+            JsonPatchOperation::Replace { path, value } => {
+                let target = patched
+                    .pointer_mut(path)
+                    .ok_or_else(|| json_patch_failed("replace", path))?;
+                *target = value.clone();
+            }
+            JsonPatchOperation::Move { from, path } => {
+                let value = json_patch_remove(&mut patched, from)?;
+                json_patch_add(&mut patched, path, value)?;
+            }
+            JsonPatchOperation::Copy { from, path } => {
+                let value = patched
+                    .pointer(from)
+                    .cloned()
+                    .ok_or_else(|| json_patch_failed("copy", from))?;
+                json_patch_add(&mut patched, path, value)?;
+            }
+            JsonPatchOperation::Test { path, value } => {
+                if patched.pointer(path) != Some(value) {
+                    return Err(json_patch_failed("test", path));
+                }
+            }
+        }
+    }
+    *document = patched;
+    Ok(())
+}
+
+impl DidAction {
+    /// patch actionをDIDドキュメントの内容に適用する
+    /// 同じIDの公開鍵・サービスを追加した場合は上書きし、存在しないIDの削除は無視する
+    pub fn apply(&self, document: &mut DidPatchDocument) -> Result<(), DidPatchError> {
+        match self {
+            DidAction::Replace { document: replaced } => {
+                *document = replaced.clone();
+            }
+            DidAction::AddPublicKeys { public_keys } => {
+                for public_key in public_keys {
+                    document.public_keys.retain(|pk| pk.id != public_key.id);
+                    document.public_keys.push(public_key.clone());
+                }
+            }
+            DidAction::RemovePublicKeys { ids } => {
+                document.public_keys.retain(|pk| !ids.contains(&pk.id));
+            }
+            DidAction::AddServices { services } => {
+                for service in services {
+                    document.service_endpoints.retain(|s| s.id != service.id);
+                    document.service_endpoints.push(service.clone());
+                }
+            }
+            DidAction::RemoveServices { ids } => {
+                document.service_endpoints.retain(|s| !ids.contains(&s.id));
+            }
+            DidAction::IetfJsonPatch { patches } => {
+                let mut value = serde_json::to_value(&*document)?;
+                apply_json_patch(&mut value, patches)?;
+                let patched: DidPatchDocument = serde_json::from_value(value)?;
+                // JSON Patchでは任意の変更が可能なため、適用後のドキュメント全体を検証する
+                validate_public_keys(&patched.public_keys)?;
+                validate_services(&patched.service_endpoints)?;
+                *document = patched;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// update操作で送信するpatch actionの一覧を組み立てるビルダー
+///
+/// 各actionは追加時に検証されるため、`build`で得られるpatchは全て仕様を満たしている
+///
+/// # 例
+/// ```ignore
+/// let patches = DidPatchBuilder::new()
+///     .remove_public_keys(vec!["signingKey".to_string()])?
+///     .add_public_keys(vec![new_signing_key])?
+///     .build();
+/// ```
+#[derive(Debug, Default)]
+pub struct DidPatchBuilder {
+    patches: Vec<DidAction>,
+}
+
+impl DidPatchBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(mut self, action: DidAction) -> Result<Self, DidPatchError> {
+        action.validate()?;
+        self.patches.push(action);
+        Ok(self)
+    }
+
+    /// DIDドキュメント全体を置き換える
+    pub fn replace(self, document: DidPatchDocument) -> Result<Self, DidPatchError> {
+        self.push(DidAction::Replace { document })
+    }
+
+    /// 公開鍵を追加する（同じIDの公開鍵が既に存在する場合は上書きされる）
+    pub fn add_public_keys(
+        self,
+        public_keys: Vec<PublicKeyPayload>,
+    ) -> Result<Self, DidPatchError> {
+        self.push(DidAction::AddPublicKeys { public_keys })
+    }
+
+    /// 指定したIDの公開鍵を削除する
+    pub fn remove_public_keys(self, ids: Vec<String>) -> Result<Self, DidPatchError> {
+        self.push(DidAction::RemovePublicKeys { ids })
+    }
+
+    /// サービスを追加する（同じIDのサービスが既に存在する場合は上書きされる）
+    pub fn add_services(self, services: Vec<ServiceEndpoint>) -> Result<Self, DidPatchError> {
+        self.push(DidAction::AddServices { services })
+    }
+
+    /// 指定したIDのサービスを削除する
+    pub fn remove_services(self, ids: Vec<String>) -> Result<Self, DidPatchError> {
+        self.push(DidAction::RemoveServices { ids })
+    }
+
+    /// RFC 6902 (JSON Patch) によりDIDドキュメントを変更する
+    pub fn ietf_json_patch(self, patches: Vec<JsonPatchOperation>) -> Result<Self, DidPatchError> {
+        self.push(DidAction::IetfJsonPatch { patches })
+    }
+
+    pub fn build(self) -> Vec<DidAction> {
+        self.patches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public_key(id: &str, purpose: &[&str]) -> PublicKeyPayload {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "type": "EcdsaSecp256k1VerificationKey2019",
+            "jwk": {
+                "kty": "EC",
+                "crv": "secp256k1",
+                "x": "7KEKZa5xJPh7WVqHJyUpb2MgEe3nA8Rk7eUlXsmBl-M",
+                "y": "3zIgl_ml4RhapyEm5J7lvU-4f5jiBvZr4KgxUjEhl9o",
+            },
+            "purpose": purpose,
+        }))
+        .unwrap()
+    }
+
+    fn service(id: &str, r#type: &str, endpoint: &str) -> ServiceEndpoint {
+        ServiceEndpoint {
+            id: id.to_string(),
+            r#type: r#type.to_string(),
+            service_endpoint: endpoint.to_string(),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_builder() {
+        let patches = DidPatchBuilder::new()
+            .remove_public_keys(vec!["signingKey".to_string()])
+            .unwrap()
+            .add_public_keys(vec![public_key("signingKey2", &["auth", "general"])])
+            .unwrap()
+            .add_services(vec![service(
+                "didcomm",
+                "DIDCommMessaging",
+                "https://example.com/didcomm",
+            )])
+            .unwrap()
+            .remove_services(vec!["old-service".to_string()])
+            .unwrap()
+            .ietf_json_patch(vec![JsonPatchOperation::Remove {
+                path: "/service/0".to_string(),
+            }])
+            .unwrap()
+            .build();
+
+        let actions: Vec<String> = patches
+            .iter()
+            .map(|p| serde_json::to_value(p).unwrap()["action"].to_string())
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                "\"remove-public-keys\"",
+                "\"add-public-keys\"",
+                "\"add-services\"",
+                "\"remove-services\"",
+                "\"ietf-json-patch\"",
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&patches[4]).unwrap(),
+            serde_json::json!({
+                "action": "ietf-json-patch",
+                "patches": [{ "op": "remove", "path": "/service/0" }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_duplicate_key_id() {
+        let result = DidPatchBuilder::new().add_public_keys(vec![
+            public_key("signingKey", &["auth"]),
+            public_key("signingKey", &["general"]),
+        ]);
+        assert!(matches!(result, Err(DidPatchError::DuplicateId(id)) if id == "signingKey"));
+    }
+
+    #[test]
+    fn test_invalid_id() {
+        for id in ["", "has space", "has#hash", &"a".repeat(MAX_ID_LENGTH + 1)] {
+            let result = DidPatchBuilder::new().remove_public_keys(vec![id.to_string()]);
+            assert!(
+                matches!(result, Err(DidPatchError::InvalidId(_))),
+                "id: {:?}",
+                id
+            );
+        }
+        assert!(DidPatchBuilder::new()
+            .remove_public_keys(vec!["a".repeat(MAX_ID_LENGTH)])
+            .is_ok());
+    }
+
+    #[test]
+    fn test_invalid_purpose() {
+        let result = DidPatchBuilder::new().add_public_keys(vec![public_key("key", &["signing"])]);
+        assert!(matches!(result, Err(DidPatchError::InvalidPurpose(p)) if p == "signing"));
+
+        let result = DidPatchBuilder::new()
+            .add_public_keys(vec![public_key("key", &["keyAgreement", "keyAgreement"])]);
+        assert!(matches!(result, Err(DidPatchError::DuplicatePurpose(_))));
+    }
+
+    #[test]
+    fn test_private_key_jwk() {
+        let mut private_key = public_key("key", &["auth"]);
+        let json = serde_json::json!({
+            "kty": "EC",
+            "crv": "secp256k1",
+            "x": "7KEKZa5xJPh7WVqHJyUpb2MgEe3nA8Rk7eUlXsmBl-M",
+            "y": "3zIgl_ml4RhapyEm5J7lvU-4f5jiBvZr4KgxUjEhl9o",
+            "d": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
+        });
+        private_key.jwk = serde_json::from_value(json).unwrap();
+        let result = DidPatchBuilder::new().add_public_keys(vec![private_key]);
+        assert!(matches!(result, Err(DidPatchError::PrivateKeyJwk(id)) if id == "key"));
+    }
+
+    #[test]
+    fn test_invalid_service() {
+        let result = DidPatchBuilder::new().add_services(vec![service(
+            "svc",
+            &"t".repeat(MAX_SERVICE_TYPE_LENGTH + 1),
+            "https://example.com",
+        )]);
+        assert!(matches!(result, Err(DidPatchError::InvalidServiceType(_))));
+
+        for endpoint in ["", "example.com", "://example.com", "1http://example.com"] {
+            let result =
+                DidPatchBuilder::new().add_services(vec![service("svc", "Type", endpoint)]);
+            assert!(
+                matches!(result, Err(DidPatchError::InvalidServiceEndpoint(_))),
+                "endpoint: {:?}",
+                endpoint
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_json_pointer() {
+        let result = DidPatchBuilder::new().ietf_json_patch(vec![JsonPatchOperation::Move {
+            from: "service/0".to_string(),
+            path: "/service/1".to_string(),
+        }]);
+        assert!(matches!(result, Err(DidPatchError::InvalidJsonPointer(p)) if p == "service/0"));
+    }
+
+    #[test]
+    fn test_empty_action() {
+        assert!(matches!(
+            DidPatchBuilder::new().add_public_keys(vec![]),
+            Err(DidPatchError::Empty("add-public-keys"))
+        ));
+        assert!(matches!(
+            DidPatchBuilder::new().remove_services(vec![]),
+            Err(DidPatchError::Empty("remove-services"))
+        ));
+        assert!(matches!(
+            DidPatchBuilder::new().ietf_json_patch(vec![]),
+            Err(DidPatchError::Empty("ietf-json-patch"))
+        ));
+    }
+}
@@ -1,9 +1,12 @@
+use crate::did::resolution::DidDocumentMetadata;
 use crate::keyring::jwk::Jwk;
+use crate::keyring::signer::Signer;
 use data_encoding::BASE64_NOPAD;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::did::sidetree::multihash;
+use crate::did::sidetree::patch::JsonPatchOperation;
+use crate::did::sidetree::{jws, multihash};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ServiceEndpoint {
@@ -106,7 +109,7 @@ where
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct DidPatchDocument {
     #[serde(rename = "public_keys")]
     pub public_keys: Vec<PublicKeyPayload>,
@@ -115,7 +118,7 @@ pub struct DidPatchDocument {
     pub service_endpoints: Vec<ServiceEndpoint>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(tag = "action")]
 pub enum DidAction {
     #[serde(rename = "replace")]
@@ -125,28 +128,77 @@ pub enum DidAction {
         #[serde(rename = "public_keys")]
         public_keys: Vec<PublicKeyPayload>,
     },
+    #[serde(rename = "remove-public-keys")]
+    RemovePublicKeys {
+        #[serde(rename = "ids")]
+        ids: Vec<String>,
+    },
+    #[serde(rename = "add-services")]
+    AddServices {
+        #[serde(rename = "services")]
+        services: Vec<ServiceEndpoint>,
+    },
+    #[serde(rename = "remove-services")]
+    RemoveServices {
+        #[serde(rename = "ids")]
+        ids: Vec<String>,
+    },
+    #[serde(rename = "ietf-json-patch")]
+    IetfJsonPatch {
+        #[serde(rename = "patches")]
+        patches: Vec<JsonPatchOperation>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct DidDeltaObject {
+    pub(crate) patches: Vec<DidAction>,
+    pub(crate) update_commitment: String,
+}
+
+// update操作で署名対象となるデータ（signed_dataのペイロード）
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct DidUpdateSignedDataObject {
+    pub(crate) update_key: Jwk,
+    pub(crate) delta_hash: String,
+}
+
+// recover操作で署名対象となるデータ（signed_dataのペイロード）
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct DidRecoverSignedDataObject {
+    pub(crate) recovery_commitment: String,
+    pub(crate) recovery_key: Jwk,
+    pub(crate) delta_hash: String,
 }
 
+// deactivate操作で署名対象となるデータ（signed_dataのペイロード）
 #[derive(Serialize, Deserialize, Debug)]
-struct DidDeltaObject {
-    patches: Vec<DidAction>,
-    update_commitment: String,
+pub(crate) struct DidDeactivateSignedDataObject {
+    pub(crate) did_suffix: String,
+    pub(crate) recovery_key: Jwk,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-struct DidSuffixObject {
-    delta_hash: String,
-    recovery_commitment: String,
+pub(crate) struct DidSuffixObject {
+    pub(crate) delta_hash: String,
+    pub(crate) recovery_commitment: String,
 }
 
+/// DIDのメソッド名（did:miax:<suffix>）
+pub const DID_METHOD: &str = "miax";
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MiaxDidResponse {
     pub did_document: DidDocument,
+
+    // 作成・更新日時などのメタデータ（Sidetreeノードが返す場合のみ）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub did_document_metadata: Option<DidDocumentMetadata>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
-enum DidPayload {
+pub(crate) enum DidPayload {
     #[serde(rename = "create")]
     Create { delta: String, suffix_data: String },
     #[serde(rename = "update")]
@@ -154,6 +206,27 @@ enum DidPayload {
         delta: String,
         #[serde(rename = "did_suffix")]
         did_suffix: String,
+        #[serde(rename = "reveal_value")]
+        reveal_value: String,
+        #[serde(rename = "signed_data")]
+        signed_data: String,
+    },
+    #[serde(rename = "recover")]
+    Recover {
+        delta: String,
+        #[serde(rename = "did_suffix")]
+        did_suffix: String,
+        #[serde(rename = "reveal_value")]
+        reveal_value: String,
+        #[serde(rename = "signed_data")]
+        signed_data: String,
+    },
+    #[serde(rename = "deactivate")]
+    Deactivate {
+        #[serde(rename = "did_suffix")]
+        did_suffix: String,
+        #[serde(rename = "reveal_value")]
+        reveal_value: String,
         #[serde(rename = "signed_data")]
         signed_data: String,
     },
@@ -165,11 +238,47 @@ pub enum DidCreatePayloadError {
     SerdeJsonError(#[from] serde_json::Error),
     #[error("Failed to convert to JWK: {0}")]
     Jwk(#[from] crate::keyring::jwk::K256ToJwkError),
+    #[error("Failed to convert to JWK: {0}")]
+    SignKeyJwk(#[from] crate::keyring::jwk::SignPublicKeyToJwkError),
+    #[error("Failed to decode suffix data: {0}")]
+    Decode(#[from] data_encoding::DecodeError),
+    #[error("not a create operation")]
+    NotCreateOperation,
+}
+
+#[derive(Debug, Error)]
+pub enum DidUpdatePayloadError {
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("Failed to convert to JWK: {0}")]
+    Jwk(#[from] crate::keyring::jwk::K256ToJwkError),
+    #[error("Failed to sign: {0}")]
+    Sign(#[from] jws::SidetreeJwsError),
+}
+
+#[derive(Debug, Error)]
+pub enum DidRecoverPayloadError {
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("Failed to convert to JWK: {0}")]
+    Jwk(#[from] crate::keyring::jwk::K256ToJwkError),
+    #[error("Failed to sign: {0}")]
+    Sign(#[from] jws::SidetreeJwsError),
+}
+
+#[derive(Debug, Error)]
+pub enum DidDeactivatePayloadError {
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("Failed to convert to JWK: {0}")]
+    Jwk(#[from] crate::keyring::jwk::K256ToJwkError),
+    #[error("Failed to sign: {0}")]
+    Sign(#[from] jws::SidetreeJwsError),
 }
 
 // JCSを利用してJSON値を正規化し、バイト列に変換
 #[inline]
-fn canon<T>(value: &T) -> Result<Vec<u8>, serde_json::Error>
+pub(crate) fn canon<T>(value: &T) -> Result<Vec<u8>, serde_json::Error>
 where
     T: ?Sized + Serialize,
 {
@@ -193,10 +302,35 @@ where
 /// # 参考文献
 /// - [Sidetree Specification - Hashing Process](https://identity.foundation/sidetree/spec/#hashing-process)
 #[inline]
-fn commitment_scheme(value: &Jwk) -> Result<String, serde_json::Error> {
+pub(crate) fn commitment_scheme(value: &Jwk) -> Result<String, serde_json::Error> {
     Ok(multihash::double_hash_encode(&canon(value)?))
 }
 
+/// suffix dataからDIDのsuffixを生成
+///
+/// suffixは、正規化したsuffix data（delta_hashとrecovery_commitment）のハッシュ値であり、
+/// create操作の内容からDIDを一意に決定するために利用される
+///
+/// # 参考文献
+/// - [Sidetree Specification - DID URI Composition](https://identity.foundation/sidetree/spec/#did-uri-composition)
+#[inline]
+pub(crate) fn suffix_data_hash(suffix: &DidSuffixObject) -> Result<String, serde_json::Error> {
+    Ok(multihash::hash_encode(&canon(suffix)?))
+}
+
+/// 公開鍵からリビール値を生成
+///
+/// リビール値は、前回の操作で登録したコミットメントの元となる公開鍵のハッシュ値（一重ハッシュ）であり、
+/// update/recover/deactivate操作の際に、コミットメントに対応する鍵を保持していることを示すために利用される
+/// （リビール値をさらにSHA-256でハッシュ化すると、コミットメントと一致する）
+///
+/// # 参考文献
+/// - [Sidetree Specification - Commitment Schemes](https://identity.foundation/sidetree/spec/#commitment-schemes)
+#[inline]
+pub(crate) fn reveal_value(value: &Jwk) -> Result<String, serde_json::Error> {
+    Ok(multihash::hash_encode(&canon(value)?))
+}
+
 // 参考 : https://identity.foundation/sidetree/spec/
 pub fn did_create_payload(
     replace_payload: DidPatchDocument, // 新しいDIDドキュメントの内容
@@ -242,3 +376,323 @@ pub fn did_create_payload(
     // Sidetreeプロトコルに準拠したJSON形式のペイロードを返す
     Ok(serde_jcs::to_string(&payload)?)
 }
+
+/// create操作のペイロード（did_create_payloadの戻り値）から、登録されるDIDのsuffixを計算する
+///
+/// suffixはsuffix dataのハッシュ値であるため、Sidetreeノードに問い合わせずに決定できる
+/// ノードが返したDIDがこの値と一致しない場合、送信した鍵が別のDIDに紐付けられている
+///
+/// # 参考文献
+/// - [Sidetree Specification - DID URI Composition](https://identity.foundation/sidetree/spec/#did-uri-composition)
+pub fn did_create_suffix(create_payload: &str) -> Result<String, DidCreatePayloadError> {
+    let DidPayload::Create { suffix_data, .. } = serde_json::from_str(create_payload)? else {
+        return Err(DidCreatePayloadError::NotCreateOperation);
+    };
+    let suffix_data = BASE64_NOPAD.decode(suffix_data.as_bytes())?;
+    let suffix_data: DidSuffixObject = serde_json::from_slice(&suffix_data)?;
+    Ok(suffix_data_hash(&suffix_data)?)
+}
+
+// 参考 : https://identity.foundation/sidetree/spec/#update
+pub fn did_update_payload<S: Signer + ?Sized>(
+    patches: Vec<DidAction>,         // DIDドキュメントへの変更内容
+    did_suffix: &str,                // 更新対象のDIDのsuffix
+    update_key: &S,                  // 現在の更新鍵（前回のupdate_commitmentに対応する鍵）
+    new_update_key: k256::PublicKey, // 次回の更新用の公開鍵
+) -> Result<String, DidUpdatePayloadError> {
+    let update_public_key: Jwk = jws::es256k_public_key(update_key)?.try_into()?;
+
+    // 現在の更新鍵のリビール値と、次回の更新用のコミットメントを生成
+    let reveal_value = reveal_value(&update_public_key)?;
+    let update_commitment = commitment_scheme(&new_update_key.try_into()?)?;
+
+    // 変更内容を作成してエンコード
+    let delta = DidDeltaObject {
+        patches,
+        update_commitment,
+    };
+    let delta = canon(&delta)?;
+    let delta_hash = multihash::hash_encode(&delta);
+
+    // 現在の更新鍵で、変更内容のハッシュ値に署名
+    let signed_data = DidUpdateSignedDataObject {
+        update_key: update_public_key,
+        delta_hash,
+    };
+    let signed_data = jws::sign(&signed_data, update_key)?;
+
+    let payload = DidPayload::Update {
+        delta: BASE64_NOPAD.encode(&delta),
+        did_suffix: did_suffix.to_string(),
+        reveal_value,
+        signed_data,
+    };
+
+    Ok(serde_jcs::to_string(&payload)?)
+}
+
+// 参考 : https://identity.foundation/sidetree/spec/#recover
+pub fn did_recover_payload<S: Signer + ?Sized>(
+    replace_payload: DidPatchDocument, // 置き換え後のDIDドキュメントの内容
+    did_suffix: &str,                  // リカバリ対象のDIDのsuffix
+    recovery_key: &S,                  // 現在のリカバリ鍵（前回のrecovery_commitmentに対応する鍵）
+    new_update_key: k256::PublicKey,   // 次回の更新用の公開鍵
+    new_recovery_key: k256::PublicKey, // 次回のリカバリ用の公開鍵
+) -> Result<String, DidRecoverPayloadError> {
+    let recovery_public_key: Jwk = jws::es256k_public_key(recovery_key)?.try_into()?;
+
+    // 現在のリカバリ鍵のリビール値と、次回の更新・リカバリ用のコミットメントを生成
+    let reveal_value = reveal_value(&recovery_public_key)?;
+    let update_commitment = commitment_scheme(&new_update_key.try_into()?)?;
+    let recovery_commitment = commitment_scheme(&new_recovery_key.try_into()?)?;
+
+    // recover操作では、DIDドキュメント全体を置き換える
+    let patch = DidAction::Replace {
+        document: replace_payload,
+    };
+
+    let delta = DidDeltaObject {
+        patches: vec![patch],
+        update_commitment,
+    };
+    let delta = canon(&delta)?;
+    let delta_hash = multihash::hash_encode(&delta);
+
+    // 現在のリカバリ鍵で、変更内容のハッシュ値と次回のリカバリ用コミットメントに署名
+    let signed_data = DidRecoverSignedDataObject {
+        recovery_commitment,
+        recovery_key: recovery_public_key,
+        delta_hash,
+    };
+    let signed_data = jws::sign(&signed_data, recovery_key)?;
+
+    let payload = DidPayload::Recover {
+        delta: BASE64_NOPAD.encode(&delta),
+        did_suffix: did_suffix.to_string(),
+        reveal_value,
+        signed_data,
+    };
+
+    Ok(serde_jcs::to_string(&payload)?)
+}
+
+// 参考 : https://identity.foundation/sidetree/spec/#deactivate
+pub fn did_deactivate_payload<S: Signer + ?Sized>(
+    did_suffix: &str, // 無効化対象のDIDのsuffix
+    recovery_key: &S, // 現在のリカバリ鍵（前回のrecovery_commitmentに対応する鍵）
+) -> Result<String, DidDeactivatePayloadError> {
+    let recovery_public_key: Jwk = jws::es256k_public_key(recovery_key)?.try_into()?;
+    let reveal_value = reveal_value(&recovery_public_key)?;
+
+    // 無効化対象のDIDをリカバリ鍵で署名し、他のDIDへの操作として再利用されることを防ぐ
+    let signed_data = DidDeactivateSignedDataObject {
+        did_suffix: did_suffix.to_string(),
+        recovery_key: recovery_public_key,
+    };
+    let signed_data = jws::sign(&signed_data, recovery_key)?;
+
+    let payload = DidPayload::Deactivate {
+        did_suffix: did_suffix.to_string(),
+        reveal_value,
+        signed_data,
+    };
+
+    Ok(serde_jcs::to_string(&payload)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyring::keypair::{KeyPair, KeyPairing};
+    use data_encoding::BASE64URL_NOPAD;
+    use k256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+    use rand_core::OsRng;
+
+    fn decode_delta(delta: &str) -> (Vec<u8>, serde_json::Value) {
+        let bytes = BASE64_NOPAD.decode(delta.as_bytes()).unwrap();
+        let value = serde_json::from_slice(&bytes).unwrap();
+        (bytes, value)
+    }
+
+    /// Compact JWSの署名を検証し、ペイロードを返す
+    fn verify_jws(jws: &str, public_key: &k256::PublicKey) -> serde_json::Value {
+        let parts: Vec<&str> = jws.split('.').collect();
+        assert_eq!(parts.len(), 3);
+        let header: serde_json::Value =
+            serde_json::from_slice(&BASE64URL_NOPAD.decode(parts[0].as_bytes()).unwrap()).unwrap();
+        assert_eq!(header, serde_json::json!({ "alg": "ES256K" }));
+
+        let signature = BASE64URL_NOPAD.decode(parts[2].as_bytes()).unwrap();
+        let signature = Signature::from_slice(&signature).unwrap();
+        let message = format!("{}.{}", parts[0], parts[1]);
+        VerifyingKey::from(public_key)
+            .verify(message.as_bytes(), &signature)
+            .unwrap();
+
+        serde_json::from_slice(&BASE64URL_NOPAD.decode(parts[1].as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_reveal_value_matches_commitment() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let jwk: Jwk = keyring.update().get_public_key().try_into().unwrap();
+
+        // リビール値のハッシュ部分をさらにハッシュ化すると、コミットメントと一致する
+        let reveal = BASE64URL_NOPAD
+            .decode(reveal_value(&jwk).unwrap().as_bytes())
+            .unwrap();
+        assert_eq!(&reveal[..2], &[0x12, 0x20]);
+        assert_eq!(
+            multihash::hash_encode(&reveal[2..]),
+            commitment_scheme(&jwk).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_did_update_payload() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let next = KeyPairing::create_keyring(OsRng);
+        let update_key = keyring.update().get_public_key();
+        let patches = vec![DidAction::AddPublicKeys {
+            public_keys: vec![next
+                .sign()
+                .get_public_key()
+                .to_public_key(
+                    "EcdsaSecp256k1VerificationKey2019".to_string(),
+                    "signingKey2".to_string(),
+                    vec!["auth".to_string()],
+                )
+                .unwrap()],
+        }];
+
+        let payload = did_update_payload(
+            patches,
+            "suffix",
+            keyring.update(),
+            next.update().get_public_key(),
+        )
+        .unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["type"], "update");
+        assert_eq!(payload["did_suffix"], "suffix");
+
+        let update_jwk: Jwk = update_key.try_into().unwrap();
+        assert_eq!(payload["reveal_value"], reveal_value(&update_jwk).unwrap());
+
+        // 変更内容と、次回の更新鍵のコミットメント
+        let (delta_bytes, delta) = decode_delta(payload["delta"].as_str().unwrap());
+        assert_eq!(delta["patches"][0]["action"], "add-public-keys");
+        assert_eq!(delta["patches"][0]["public_keys"][0]["id"], "signingKey2");
+        let next_jwk: Jwk = next.update().get_public_key().try_into().unwrap();
+        assert_eq!(
+            delta["update_commitment"],
+            commitment_scheme(&next_jwk).unwrap()
+        );
+
+        // signed_dataは現在の更新鍵で署名され、変更内容のハッシュ値を含む
+        let signed = verify_jws(payload["signed_data"].as_str().unwrap(), &update_key);
+        assert_eq!(signed["delta_hash"], multihash::hash_encode(&delta_bytes));
+        assert_eq!(
+            signed["update_key"],
+            serde_json::to_value(&update_jwk).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_did_update_payload_signature_rejects_other_key() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let other = KeyPairing::create_keyring(OsRng);
+
+        let payload = did_update_payload(
+            vec![],
+            "suffix",
+            keyring.update(),
+            other.update().get_public_key(),
+        )
+        .unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        let signed_data = payload["signed_data"].as_str().unwrap();
+
+        let parts: Vec<&str> = signed_data.split('.').collect();
+        let signature = BASE64URL_NOPAD.decode(parts[2].as_bytes()).unwrap();
+        let signature = Signature::from_slice(&signature).unwrap();
+        let message = format!("{}.{}", parts[0], parts[1]);
+        assert!(VerifyingKey::from(&other.update().get_public_key())
+            .verify(message.as_bytes(), &signature)
+            .is_err());
+    }
+
+    #[test]
+    fn test_did_recover_payload() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let next = KeyPairing::create_keyring(OsRng);
+        let recovery_key = keyring.recovery().get_public_key();
+        let document = DidPatchDocument {
+            public_keys: vec![],
+            service_endpoints: vec![],
+        };
+
+        let payload = did_recover_payload(
+            document,
+            "suffix",
+            keyring.recovery(),
+            next.update().get_public_key(),
+            next.recovery().get_public_key(),
+        )
+        .unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["type"], "recover");
+        assert_eq!(payload["did_suffix"], "suffix");
+
+        let recovery_jwk: Jwk = recovery_key.try_into().unwrap();
+        assert_eq!(
+            payload["reveal_value"],
+            reveal_value(&recovery_jwk).unwrap()
+        );
+
+        // recover操作はDIDドキュメント全体を置き換え、次回の更新鍵にコミットする
+        let (delta_bytes, delta) = decode_delta(payload["delta"].as_str().unwrap());
+        assert_eq!(delta["patches"].as_array().unwrap().len(), 1);
+        assert_eq!(delta["patches"][0]["action"], "replace");
+        let next_update: Jwk = next.update().get_public_key().try_into().unwrap();
+        assert_eq!(
+            delta["update_commitment"],
+            commitment_scheme(&next_update).unwrap()
+        );
+
+        // signed_dataは現在のリカバリ鍵で署名され、次回のリカバリ鍵のコミットメントを含む
+        let signed = verify_jws(payload["signed_data"].as_str().unwrap(), &recovery_key);
+        assert_eq!(signed["delta_hash"], multihash::hash_encode(&delta_bytes));
+        let next_recovery: Jwk = next.recovery().get_public_key().try_into().unwrap();
+        assert_eq!(
+            signed["recovery_commitment"],
+            commitment_scheme(&next_recovery).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_did_deactivate_payload() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let recovery_key = keyring.recovery().get_public_key();
+
+        let payload = did_deactivate_payload("suffix", keyring.recovery()).unwrap();
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload["type"], "deactivate");
+        assert_eq!(payload["did_suffix"], "suffix");
+        assert!(payload.get("delta").is_none());
+
+        let recovery_jwk: Jwk = recovery_key.try_into().unwrap();
+        assert_eq!(
+            payload["reveal_value"],
+            reveal_value(&recovery_jwk).unwrap()
+        );
+
+        // 他のDIDへの操作として再利用されないよう、対象のsuffixに署名する
+        let signed = verify_jws(payload["signed_data"].as_str().unwrap(), &recovery_key);
+        assert_eq!(signed["did_suffix"], "suffix");
+        assert_eq!(
+            signed["recovery_key"],
+            serde_json::to_value(&recovery_jwk).unwrap()
+        );
+    }
+}
@@ -1,8 +1,10 @@
-use crate::did::did_repository::{get_encrypt_key, get_sign_key, DidRepository, GetPublicKeyError};
+use crate::did::did_repository::{
+    get_encrypt_key, get_verification_key, DidRepository, GetPublicKeyError,
+};
+use crate::did::did_url::{DidUrl, DidUrlError};
 use crate::did::sidetree::payload::DidDocument;
 use crate::didcomm::types::{DidCommMessage, FindSenderError};
-use crate::keyring::keypair::KeyPair;
-use crate::keyring::keypair::KeyPairing;
+use crate::keyring::signer::{KeyAgreement, Keyring};
 use crate::verifiable_credentials::credential_signer::CredentialSigner;
 use crate::verifiable_credentials::{
     credential_signer::CredentialSignerVerifyError,
@@ -19,24 +21,26 @@ use thiserror::Error;
 pub trait DidCommEncryptedService: Sync {
     type GenerateError: std::error::Error;
     type VerifyError: std::error::Error;
-    async fn generate(
+    async fn generate<K: Keyring + Sync>(
         &self,
         model: VerifiableCredentials,
-        from_keyring: &KeyPairing,
+        from_keyring: &K,
         to_did: &str,
         metadata: Option<&Value>,
     ) -> Result<DidCommMessage, Self::GenerateError>;
 
-    async fn verify(
+    /// 受信したメッセージを復号し、VCを検証する
+    /// 復号には暗号化鍵のみを利用するため、鍵のローテーション後も猶予期間中の暗号化鍵で復号できる
+    async fn verify<K: KeyAgreement + Sync>(
         &self,
-        my_keyring: &KeyPairing,
+        my_key_agreement: &K,
         message: &DidCommMessage,
     ) -> Result<VerifiedContainer, Self::VerifyError>;
 }
 
-fn didcomm_generate<R: DidRepository, V: DidVcService>(
+fn didcomm_generate<R: DidRepository, V: DidVcService, K: Keyring>(
     body: &VerifiableCredentials,
-    from_keyring: &KeyPairing,
+    from_keyring: &K,
     to_doc: &DidDocument,
     metadata: Option<&Value>,
     attachment_link: Option<&str>,
@@ -72,21 +76,23 @@ fn didcomm_generate<R: DidRepository, V: DidVcService>(
     let public_key = get_encrypt_key(to_doc)?.as_bytes().to_vec();
     let public_key = Some(public_key);
 
+    // didcomm-rsは内部で鍵交換を行うため、秘密鍵のバイト列を渡す必要がある
+    let secret_key = from_keyring
+        .key_agreement()
+        .export_secret_key()
+        .ok_or(DidCommEncryptedServiceGenerateError::KeyNotExportable)?;
     let seal_message = message
         .as_jwe(&CryptoAlgorithm::XC20P, public_key.clone())
-        .seal(
-            from_keyring.encrypt.get_secret_key().as_bytes(),
-            Some(vec![public_key]),
-        )?;
+        .seal(secret_key.as_ref(), Some(vec![public_key]))?;
 
     Ok(serde_json::from_str::<DidCommMessage>(&seal_message)?)
 }
 
-async fn generate<R: DidRepository, V: DidVcService>(
+async fn generate<R: DidRepository, V: DidVcService, K: Keyring + Sync>(
     did_repository: &R,
     vc_service: &V,
     model: VerifiableCredentials,
-    from_keyring: &KeyPairing,
+    from_keyring: &K,
     to_did: &str,
     metadata: Option<&Value>,
     attachment_link: Option<&str>,
@@ -95,7 +101,7 @@ async fn generate<R: DidRepository, V: DidVcService>(
     DidCommEncryptedServiceGenerateError<R::FindIdentifierError, V::GenerateError>,
 > {
     let body = vc_service
-        .generate(model, from_keyring)
+        .generate(model, from_keyring.signer())
         .map_err(DidCommEncryptedServiceGenerateError::VcService)?;
     let to_doc = did_repository
         .find_identifier(to_did)
@@ -106,20 +112,24 @@ async fn generate<R: DidRepository, V: DidVcService>(
         ))?
         .did_document;
 
-    didcomm_generate::<R, V>(&body, from_keyring, &to_doc, metadata, attachment_link)
+    didcomm_generate::<R, V, K>(&body, from_keyring, &to_doc, metadata, attachment_link)
 }
 
-fn didcomm_verify<R: DidRepository>(
+fn didcomm_verify<R: DidRepository, K: KeyAgreement>(
     from_doc: &DidDocument,
-    my_keyring: &KeyPairing,
+    my_key_agreement: &K,
     message: &DidCommMessage,
 ) -> Result<VerifiedContainer, DidCommEncryptedServiceVerifyError<R::FindIdentifierError>> {
     let public_key = get_encrypt_key(from_doc)?.as_bytes().to_vec();
     let public_key = Some(public_key);
 
+    // didcomm-rsは内部で鍵交換を行うため、秘密鍵のバイト列を渡す必要がある
+    let secret_key = my_key_agreement
+        .export_secret_key()
+        .ok_or(DidCommEncryptedServiceVerifyError::KeyNotExportable)?;
     let message = Message::receive(
         &serde_json::to_string(&message)?,
-        Some(my_keyring.encrypt.get_secret_key().as_bytes().as_ref()),
+        Some(secret_key.as_ref()),
         public_key,
         None,
     )?;
@@ -152,9 +162,9 @@ fn didcomm_verify<R: DidRepository>(
     }
 }
 
-async fn verify<R: DidRepository>(
+async fn verify<R: DidRepository, K: KeyAgreement + Sync>(
     did_repository: &R,
-    my_keyring: &KeyPairing,
+    my_key_agreement: &K,
     message: &DidCommMessage,
 ) -> Result<VerifiedContainer, DidCommEncryptedServiceVerifyError<R::FindIdentifierError>> {
     let other_did = message.find_sender()?;
@@ -162,13 +172,25 @@ async fn verify<R: DidRepository>(
         .find_identifier(&other_did)
         .await
         .map_err(DidCommEncryptedServiceVerifyError::SidetreeFindRequestFailed)?
-        .ok_or(DidCommEncryptedServiceVerifyError::DidDocNotFound(
-            other_did,
-        ))?
+        .ok_or_else(|| DidCommEncryptedServiceVerifyError::DidDocNotFound(other_did.clone()))?
         .did_document;
-    let mut container = didcomm_verify::<R>(&other_doc, my_keyring, message)?;
+    let mut container = didcomm_verify::<R, K>(&other_doc, my_key_agreement, message)?;
     // for performance, call low level api
-    let public_key = get_sign_key(&other_doc)?;
+    let proof = container
+        .message
+        .proof
+        .as_ref()
+        .ok_or(CredentialSignerVerifyError::ProofNotFound)?;
+    // 送信者以外の公開鍵で署名されたVCは受け付けない
+    let did_url = DidUrl::parse(&proof.verification_method)?;
+    if did_url.did != other_did {
+        return Err(DidCommEncryptedServiceVerifyError::SenderMismatch {
+            sender: other_did,
+            verification_method: proof.verification_method.clone(),
+        });
+    }
+    let public_key =
+        get_verification_key(&other_doc, &proof.verification_method, &proof.proof_purpose)?;
     let body = CredentialSigner::verify(container.message, &public_key)?;
     container.message = body;
     Ok(container)
@@ -190,6 +212,8 @@ where
     SidetreeFindRequestFailed(FindIdentifierError),
     #[error("failed to encrypt message with error: {0}")]
     EncryptFailed(#[from] didcomm_rs::Error),
+    #[error("key agreement secret key is not exportable")]
+    KeyNotExportable,
     #[error("failed serialize/deserialize: {0}")]
     Json(#[from] serde_json::Error),
 }
@@ -201,21 +225,21 @@ where
     type GenerateError =
         DidCommEncryptedServiceGenerateError<R::FindIdentifierError, R::GenerateError>;
     type VerifyError = DidCommEncryptedServiceVerifyError<R::FindIdentifierError>;
-    async fn generate(
+    async fn generate<K: Keyring + Sync>(
         &self,
         model: VerifiableCredentials,
-        from_keyring: &KeyPairing,
+        from_keyring: &K,
         to_did: &str,
         metadata: Option<&Value>,
     ) -> Result<DidCommMessage, Self::GenerateError> {
-        generate::<R, R>(self, self, model, from_keyring, to_did, metadata, None).await
+        generate::<R, R, K>(self, self, model, from_keyring, to_did, metadata, None).await
     }
-    async fn verify(
+    async fn verify<K: KeyAgreement + Sync>(
         &self,
-        my_keyring: &KeyPairing,
+        my_key_agreement: &K,
         message: &DidCommMessage,
     ) -> Result<VerifiedContainer, Self::VerifyError> {
-        verify(self, my_keyring, message).await
+        verify(self, my_key_agreement, message).await
     }
 }
 
@@ -246,14 +270,14 @@ where
     type GenerateError =
         DidCommEncryptedServiceGenerateError<R::FindIdentifierError, R::GenerateError>;
     type VerifyError = DidCommEncryptedServiceVerifyError<R::FindIdentifierError>;
-    async fn generate(
+    async fn generate<K: Keyring + Sync>(
         &self,
         model: VerifiableCredentials,
-        from_keyring: &KeyPairing,
+        from_keyring: &K,
         to_did: &str,
         metadata: Option<&Value>,
     ) -> Result<DidCommMessage, Self::GenerateError> {
-        generate::<R, R>(
+        generate::<R, R, K>(
             &self.vc_service,
             &self.vc_service,
             model,
@@ -264,12 +288,12 @@ where
         )
         .await
     }
-    async fn verify(
+    async fn verify<K: KeyAgreement + Sync>(
         &self,
-        my_keyring: &KeyPairing,
+        my_key_agreement: &K,
         message: &DidCommMessage,
     ) -> Result<VerifiedContainer, Self::VerifyError> {
-        verify(&self.vc_service, my_keyring, message).await
+        verify(&self.vc_service, my_key_agreement, message).await
     }
 }
 
@@ -285,10 +309,19 @@ pub enum DidCommEncryptedServiceVerifyError<FindIdentifierError: std::error::Err
     DidPublicKeyNotFound(#[from] GetPublicKeyError),
     #[error("failed to decrypt message: {0:?}")]
     DecryptFailed(#[from] didcomm_rs::Error),
+    #[error("key agreement secret key is not exportable")]
+    KeyNotExportable,
     #[error("failed to get body: {0:?}")]
     MetadataBodyNotFound(Option<didcomm_rs::Error>),
     #[error("failed serialize/deserialize: {0}")]
     Json(#[from] serde_json::Error),
     #[error("failed to find sender did: {0}")]
     FindSender(#[from] FindSenderError),
+    #[error("invalid verification method: {0}")]
+    InvalidVerificationMethod(#[from] DidUrlError),
+    #[error("verification method is not controlled by sender. sender: {sender}, verification method: {verification_method}")]
+    SenderMismatch {
+        sender: String,
+        verification_method: String,
+    },
 }
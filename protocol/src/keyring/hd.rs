@@ -0,0 +1,413 @@
+// シードから鍵ペアを決定的に導出する（HD鍵）
+//
+// BIP-39のニーモニック（24単語）から生成したシードを元に、SLIP-0010の強化導出（hardened derivation）で
+// 署名鍵・更新鍵・リカバリ鍵・暗号化鍵を導出する。ニーモニックをバックアップしておけば、全ての鍵を復元できる
+//
+// 導出パス : m / 0x6d696178' (="miax") / 鍵の用途' / インデックス'
+// - 鍵の用途 : 署名鍵 0、更新鍵 1、リカバリ鍵 2、暗号化鍵 3
+// - インデックス : 鍵を更新（ローテーション）するたびに1つ進める
+//
+// 参考 : https://github.com/bitcoin/bips/blob/master/bip-0039.mediawiki
+//        https://github.com/satoshilabs/slips/blob/master/slip-0010.md
+use bip39::Mnemonic;
+use hmac::{Hmac, Mac};
+use k256::elliptic_curve::{CurveArithmetic, Field, FieldBytes, PrimeField};
+use rand_core::{CryptoRng, RngCore};
+use sha2::Sha512;
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::keypair::{
+    Ed25519KeyPair, K256KeyPair, KeyPairing, P256KeyPair, SignKeyPair, SignKeyType, X25519KeyPair,
+};
+use super::secret::Secret;
+
+/// エクスポートするニーモニックの単語数
+pub const MNEMONIC_WORD_COUNT: usize = 24;
+
+// 24単語のニーモニックに対応するエントロピーのバイト数
+const ENTROPY_LEN: usize = 32;
+
+const HARDENED: u32 = 0x8000_0000;
+const PURPOSE: u32 = 0x6d69_6178;
+
+const SIGN_ROLE: u32 = 0;
+const UPDATE_ROLE: u32 = 1;
+const RECOVERY_ROLE: u32 = 2;
+const ENCRYPT_ROLE: u32 = 3;
+
+#[derive(Error, Debug)]
+pub enum HdKeyringError {
+    #[error("invalid mnemonic: {0}")]
+    Mnemonic(#[from] bip39::Error),
+    #[error("invalid derivation index: {0}")]
+    InvalidIndex(u32),
+}
+
+// SLIP-0010で定義された曲線
+#[derive(Clone, Copy)]
+enum Curve {
+    Secp256k1,
+    P256,
+    Ed25519,
+    Curve25519,
+}
+
+impl Curve {
+    // マスター鍵の導出に利用するHMACの鍵
+    fn seed_key(self) -> &'static [u8] {
+        match self {
+            Curve::Secp256k1 => b"Bitcoin seed",
+            Curve::P256 => b"Nist256p1 seed",
+            Curve::Ed25519 => b"ed25519 seed",
+            Curve::Curve25519 => b"curve25519 seed",
+        }
+    }
+
+    // HMACの出力（IL）から鍵を求める。鍵として利用できない場合はNone
+    fn tweak(self, il: &[u8], parent: Option<&[u8; 32]>) -> Option<Zeroizing<[u8; 32]>> {
+        match self {
+            Curve::Secp256k1 => add_scalar::<k256::Secp256k1>(il, parent),
+            Curve::P256 => add_scalar::<p256::NistP256>(il, parent),
+            // Ed25519・X25519は、ILをそのまま秘密鍵とする
+            Curve::Ed25519 | Curve::Curve25519 => {
+                let mut key = Zeroizing::new([0u8; 32]);
+                key.copy_from_slice(il);
+                Some(key)
+            }
+        }
+    }
+}
+
+// secp256k1・P-256の子鍵 : (IL + 親の秘密鍵) mod n
+// ILが位数n以上の場合や、結果が0となる場合は鍵として利用できない
+fn add_scalar<C>(il: &[u8], parent: Option<&[u8; 32]>) -> Option<Zeroizing<[u8; 32]>>
+where
+    C: CurveArithmetic,
+    C::Scalar: PrimeField<Repr = FieldBytes<C>>,
+{
+    let scalar = |bytes: &[u8]| -> Option<C::Scalar> {
+        C::Scalar::from_repr(FieldBytes::<C>::clone_from_slice(bytes)).into()
+    };
+    let mut key = scalar(il)?;
+    if let Some(parent) = parent {
+        key += scalar(parent)?;
+    }
+    if bool::from(key.is_zero()) {
+        return None;
+    }
+    let mut bytes = Zeroizing::new([0u8; 32]);
+    bytes.copy_from_slice(&key.to_repr());
+    Some(bytes)
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> Zeroizing<[u8; 64]> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for data in data {
+        mac.update(data);
+    }
+    let mut output = Zeroizing::new([0u8; 64]);
+    output.copy_from_slice(&mac.finalize().into_bytes());
+    output
+}
+
+// 秘密鍵とチェーンコード
+struct ExtendedKey {
+    key: Zeroizing<[u8; 32]>,
+    chain_code: Zeroizing<[u8; 32]>,
+}
+
+impl ExtendedKey {
+    fn new(key: Zeroizing<[u8; 32]>, i: &[u8; 64]) -> Self {
+        let mut chain_code = Zeroizing::new([0u8; 32]);
+        chain_code.copy_from_slice(&i[32..]);
+        ExtendedKey { key, chain_code }
+    }
+
+    fn master(curve: Curve, seed: &[u8]) -> Self {
+        let mut i = hmac_sha512(curve.seed_key(), &[seed]);
+        loop {
+            if let Some(key) = curve.tweak(&i[..32], None) {
+                return Self::new(key, &i);
+            }
+            i = hmac_sha512(curve.seed_key(), &[i.as_slice()]);
+        }
+    }
+
+    // 強化導出で子鍵を求める
+    fn child(&self, curve: Curve, index: u32) -> Self {
+        let index = (index | HARDENED).to_be_bytes();
+        let mut i = hmac_sha512(
+            self.chain_code.as_slice(),
+            &[&[0x00], self.key.as_slice(), &index],
+        );
+        loop {
+            if let Some(key) = curve.tweak(&i[..32], Some(&self.key)) {
+                return Self::new(key, &i);
+            }
+            i = hmac_sha512(self.chain_code.as_slice(), &[&[0x01], &i[32..], &index]);
+        }
+    }
+}
+
+/// ニーモニックから鍵ペアを導出するHD鍵
+///
+/// シードを保持するため、Clone・Debugは実装しない
+pub struct HdKeyring {
+    mnemonic: Mnemonic,
+    seed: Secret<[u8; 64]>,
+}
+
+impl HdKeyring {
+    /// 新しいニーモニック（24単語）を生成する
+    pub fn generate<T: RngCore + CryptoRng>(mut csprng: T) -> Self {
+        let mut entropy = Zeroizing::new([0u8; ENTROPY_LEN]);
+        csprng.fill_bytes(entropy.as_mut_slice());
+        let mnemonic =
+            Mnemonic::from_entropy(entropy.as_slice()).expect("32 bytes is valid entropy length");
+        Self::new(mnemonic, "")
+    }
+
+    /// ニーモニックから復元する
+    ///
+    /// `passphrase`はBIP-39のパスフレーズ（未使用の場合は空文字列）
+    pub fn from_mnemonic(phrase: &str, passphrase: &str) -> Result<Self, HdKeyringError> {
+        let mnemonic = Mnemonic::parse(phrase)?;
+        Ok(Self::new(mnemonic, passphrase))
+    }
+
+    fn new(mnemonic: Mnemonic, passphrase: &str) -> Self {
+        let seed = Secret::new(mnemonic.to_seed(passphrase));
+        HdKeyring { mnemonic, seed }
+    }
+
+    /// バックアップ用にニーモニックをエクスポートする
+    pub fn mnemonic(&self) -> Zeroizing<String> {
+        Zeroizing::new(self.mnemonic.to_string())
+    }
+
+    fn derive(
+        &self,
+        curve: Curve,
+        role: u32,
+        index: u32,
+    ) -> Result<Zeroizing<[u8; 32]>, HdKeyringError> {
+        if index >= HARDENED {
+            return Err(HdKeyringError::InvalidIndex(index));
+        }
+        let key = [PURPOSE, role, index].into_iter().fold(
+            ExtendedKey::master(curve, self.seed.expose().as_slice()),
+            |key, i| key.child(curve, i),
+        );
+        Ok(key.key)
+    }
+
+    fn derive_k256(&self, role: u32, index: u32) -> Result<K256KeyPair, HdKeyringError> {
+        let key = self.derive(Curve::Secp256k1, role, index)?;
+        let secret_key = k256::SecretKey::from_bytes(key.as_ref().into())
+            .expect("derived key is a valid scalar");
+        Ok(K256KeyPair::new(secret_key))
+    }
+
+    /// 署名鍵を導出する
+    pub fn derive_sign(
+        &self,
+        key_type: SignKeyType,
+        index: u32,
+    ) -> Result<SignKeyPair, HdKeyringError> {
+        Ok(match key_type {
+            SignKeyType::Secp256k1 => self.derive_k256(SIGN_ROLE, index)?.into(),
+            SignKeyType::P256 => {
+                let key = self.derive(Curve::P256, SIGN_ROLE, index)?;
+                let secret_key = p256::SecretKey::from_bytes(key.as_ref().into())
+                    .expect("derived key is a valid scalar");
+                P256KeyPair::new(secret_key).into()
+            }
+            SignKeyType::Ed25519 => {
+                let key = self.derive(Curve::Ed25519, SIGN_ROLE, index)?;
+                Ed25519KeyPair::new(ed25519_dalek::SigningKey::from_bytes(&key)).into()
+            }
+        })
+    }
+
+    /// 更新鍵を導出する
+    pub fn derive_update(&self, index: u32) -> Result<K256KeyPair, HdKeyringError> {
+        self.derive_k256(UPDATE_ROLE, index)
+    }
+
+    /// リカバリ鍵を導出する
+    pub fn derive_recovery(&self, index: u32) -> Result<K256KeyPair, HdKeyringError> {
+        self.derive_k256(RECOVERY_ROLE, index)
+    }
+
+    /// 暗号化鍵を導出する
+    pub fn derive_encrypt(&self, index: u32) -> Result<X25519KeyPair, HdKeyringError> {
+        let key = self.derive(Curve::Curve25519, ENCRYPT_ROLE, index)?;
+        Ok(X25519KeyPair::new(x25519_dalek::StaticSecret::from(*key)))
+    }
+
+    /// 署名鍵にsecp256k1を利用する鍵ペアを導出する
+    pub fn derive_keyring(&self, index: u32) -> Result<KeyPairing, HdKeyringError> {
+        self.derive_keyring_with_sign_key(SignKeyType::Secp256k1, index)
+    }
+
+    /// 指定した種類の署名鍵を利用する鍵ペアを導出する
+    pub fn derive_keyring_with_sign_key(
+        &self,
+        sign_key_type: SignKeyType,
+        index: u32,
+    ) -> Result<KeyPairing, HdKeyringError> {
+        Ok(KeyPairing::new(
+            self.derive_sign(sign_key_type, index)?,
+            self.derive_update(index)?,
+            self.derive_recovery(index)?,
+            self.derive_encrypt(index)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // SLIP-0010のテストベクター : https://github.com/satoshilabs/slips/blob/master/slip-0010.md
+    // (導出パス（強化導出のインデックス）, チェーンコード, 秘密鍵)
+    type Vector = (&'static [u32], &'static str, &'static str);
+
+    const SEED: &str = "000102030405060708090a0b0c0d0e0f";
+
+    fn assert_vectors(curve: Curve, seed: &str, vectors: &[Vector]) {
+        let seed = hex::decode(seed).unwrap();
+        for (path, chain_code, key) in vectors {
+            let derived = path
+                .iter()
+                .fold(ExtendedKey::master(curve, &seed), |key, i| {
+                    key.child(curve, *i)
+                });
+            assert_eq!(
+                hex::encode(derived.chain_code.as_slice()),
+                *chain_code,
+                "{:?}",
+                path
+            );
+            assert_eq!(hex::encode(derived.key.as_slice()), *key, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn test_slip10_secp256k1() {
+        assert_vectors(
+            Curve::Secp256k1,
+            SEED,
+            &[
+                (
+                    &[],
+                    "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508",
+                    "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+                ),
+                (
+                    &[0],
+                    "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141",
+                    "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_slip10_nist256p1() {
+        assert_vectors(
+            Curve::P256,
+            SEED,
+            &[
+                (
+                    &[],
+                    "beeb672fe4621673f722f38529c07392fecaa61015c80c34f29ce8b41b3cb6ea",
+                    "612091aaa12e22dd2abef664f8a01a82cae99ad7441b7ef8110424915c268bc2",
+                ),
+                (
+                    &[0],
+                    "3460cea53e6a6bb5fb391eeef3237ffd8724bf0a40e94943c98b83825342ee11",
+                    "6939694369114c67917a182c59ddb8cafc3004e63ca5d3b84403ba8613debc0c",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_slip10_nist256p1_derivation_retry() {
+        assert_vectors(
+            Curve::P256,
+            SEED,
+            &[(
+                &[28578],
+                "e94c8ebe30c2250a14713212f6449b20f3329105ea15b652ca5bdfc68f6c65c2",
+                "06f0db126f023755d0b8d86d4591718a5210dd8d024e3e14b6159d63f53aa669",
+            )],
+        );
+    }
+
+    #[test]
+    fn test_slip10_nist256p1_seed_retry() {
+        assert_vectors(
+            Curve::P256,
+            "a7305bc8df8d0951f0cb224c0e95d7707cbdf2c6ce7e8d481fec69c7ff5e9446",
+            &[(
+                &[],
+                "7762f9729fed06121fd13f326884c82f59aa95c57ac492ce8c9654e60efd130c",
+                "3b8c18469a4634517d6d0b65448f8e6c62091b45540a1743c5846be55d47d88f",
+            )],
+        );
+    }
+
+    #[test]
+    fn test_slip10_ed25519() {
+        assert_vectors(
+            Curve::Ed25519,
+            SEED,
+            &[
+                (
+                    &[],
+                    "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb",
+                    "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+                ),
+                (
+                    &[0],
+                    "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69",
+                    "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+                ),
+                (
+                    &[0, 1],
+                    "a320425f77d1b5c2505a6b1b27382b37368ee640e3557c315416801243552f14",
+                    "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2",
+                ),
+                (
+                    &[0, 1, 2],
+                    "2e69929e00b5ab250f49c3fb1c12f252de4fed2c1db88387094a0f8c4c9ccd6c",
+                    "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9",
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_derive_from_mnemonic() {
+        let keyring = HdKeyring::generate(rand_core::OsRng);
+        let restored = HdKeyring::from_mnemonic(&keyring.mnemonic(), "").unwrap();
+        assert_eq!(keyring.mnemonic().split(' ').count(), MNEMONIC_WORD_COUNT);
+        for index in [0, 1] {
+            assert_eq!(
+                keyring.derive_keyring(index).unwrap().public_keys(),
+                restored.derive_keyring(index).unwrap().public_keys()
+            );
+        }
+        assert_ne!(
+            keyring.derive_keyring(0).unwrap().public_keys(),
+            keyring.derive_keyring(1).unwrap().public_keys()
+        );
+        assert!(matches!(
+            keyring.derive_update(HARDENED),
+            Err(HdKeyringError::InvalidIndex(_))
+        ));
+    }
+}
@@ -3,13 +3,18 @@
 use std::convert::{From, Into, TryFrom, TryInto};
 
 pub use data_encoding;
-use data_encoding::{DecodeError, DecodePartial, BASE64URL_NOPAD, BASE64_NOPAD};
+use data_encoding::{DecodeError, DecodePartial, BASE64URL_NOPAD};
 use k256::elliptic_curve::sec1::ToEncodedPoint;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::keypair::{K256KeyPair, KeyPair, SignPublicKey, X25519KeyPair};
 
 /// DID DocumentやSidetreeペイロードで利用される形式
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// 秘密鍵（d）を含む場合は、DIDドキュメントなどに公開する前に`to_public`で取り除くこと
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Jwk {
     #[serde(rename = "kty")] // key type. example: "EC", "OKP", "RSA"...
     kty: String,
@@ -23,12 +28,176 @@ pub struct Jwk {
     #[serde(rename = "y", skip_serializing_if = "Option::is_none")]
     // 楕円曲線上のy座標（kty="EC"かつ曲線次第では省略可）
     y: Option<String>,
+
+    #[serde(rename = "d", default, skip_serializing_if = "Option::is_none")]
+    // 秘密鍵(エンコード済)。秘密鍵のJWKの場合のみ
+    d: Option<String>,
+
+    #[serde(rename = "kid", default, skip_serializing_if = "Option::is_none")]
+    // key ID. JWK Set内で鍵を識別するID
+    kid: Option<String>,
+
+    #[serde(rename = "use", default, skip_serializing_if = "Option::is_none")]
+    // 公開鍵の用途. "sig"（署名）or "enc"（暗号化）
+    r#use: Option<String>,
+
+    #[serde(rename = "alg", default, skip_serializing_if = "Option::is_none")]
+    // 鍵を利用するアルゴリズム. example: "ES256K", "EdDSA"...
+    alg: Option<String>,
+}
+
+// 秘密鍵がログなどに出力されないよう、dは伏せて表示する
+impl std::fmt::Debug for Jwk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Jwk")
+            .field("kty", &self.kty)
+            .field("crv", &self.crv)
+            .field("x", &self.x)
+            .field("y", &self.y)
+            .field("d", &self.d.as_ref().map(|_| "<redacted>"))
+            .field("kid", &self.kid)
+            .field("use", &self.r#use)
+            .field("alg", &self.alg)
+            .finish()
+    }
+}
+
+impl Jwk {
+    fn new(kty: String, crv: String, x: String, y: Option<String>) -> Self {
+        Jwk {
+            kty,
+            crv,
+            x,
+            y,
+            d: None,
+            kid: None,
+            r#use: None,
+            alg: None,
+        }
+    }
+
+    pub fn kty(&self) -> &str {
+        &self.kty
+    }
+
+    pub fn crv(&self) -> &str {
+        &self.crv
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    pub fn key_use(&self) -> Option<&str> {
+        self.r#use.as_deref()
+    }
+
+    pub fn alg(&self) -> Option<&str> {
+        self.alg.as_deref()
+    }
+
+    /// 秘密鍵（d）を含むか
+    pub fn is_private(&self) -> bool {
+        self.d.is_some()
+    }
+
+    /// 秘密鍵（d）を取り除いた、公開鍵のJWKを返す
+    pub fn to_public(&self) -> Jwk {
+        Jwk {
+            d: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn with_kid(mut self, kid: impl Into<String>) -> Self {
+        self.kid = Some(kid.into());
+        self
+    }
+
+    pub fn with_use(mut self, key_use: impl Into<String>) -> Self {
+        self.r#use = Some(key_use.into());
+        self
+    }
+
+    pub fn with_alg(mut self, alg: impl Into<String>) -> Self {
+        self.alg = Some(alg.into());
+        self
+    }
+
+    /// RFC7638のJWK Thumbprint（SHA-256、Base64URLエンコード）を計算する
+    ///
+    /// ktyごとの必須メンバーのみから計算するため、秘密鍵・kid・use・algの有無によらず同じ値となる
+    pub fn thumbprint(&self) -> Result<String, JwkThumbprintError> {
+        let members = match self.kty.as_str() {
+            "EC" => {
+                let y = self.y.as_ref().ok_or(JwkThumbprintError::MissingY)?;
+                serde_json::json!({ "crv": self.crv, "kty": self.kty, "x": self.x, "y": y })
+            }
+            "OKP" => serde_json::json!({ "crv": self.crv, "kty": self.kty, "x": self.x }),
+            kty => return Err(JwkThumbprintError::UnsupportedKty(kty.to_string())),
+        };
+        // JCSは空白を含まず、メンバーを辞書順に並べるため、RFC7638の形式と一致する
+        let members = serde_jcs::to_string(&members)?;
+        let digest = Sha256::digest(members.as_bytes());
+        Ok(BASE64URL_NOPAD.encode(&digest))
+    }
+
+    /// 同じ公開鍵を表すJWKであるかを判定する
+    /// 鍵の値（kty・crv・x・y）のみを比較し、秘密鍵やkidなどの鍵の値以外のメンバーは比較しない
+    pub fn is_same_key(&self, other: &Jwk) -> bool {
+        self.kty == other.kty && self.crv == other.crv && self.x == other.x && self.y == other.y
+    }
+
+    /// kidにJWK Thumbprintを設定する
+    pub fn with_thumbprint_kid(self) -> Result<Self, JwkThumbprintError> {
+        let kid = self.thumbprint()?;
+        Ok(self.with_kid(kid))
+    }
+}
+
+/// JWK Set（RFC7517 Section 5）
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JwkSet {
+    #[serde(rename = "keys")]
+    pub keys: Vec<Jwk>,
+}
+
+impl JwkSet {
+    pub fn new(keys: Vec<Jwk>) -> Self {
+        JwkSet { keys }
+    }
+
+    /// kidに一致するJWKを返す
+    pub fn find(&self, kid: &str) -> Option<&Jwk> {
+        self.keys.iter().find(|jwk| jwk.kid() == Some(kid))
+    }
+
+    /// 全てのJWKから秘密鍵（d）を取り除いた、公開用のJWK Setを返す
+    pub fn to_public(&self) -> JwkSet {
+        JwkSet {
+            keys: self.keys.iter().map(Jwk::to_public).collect(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum JwkThumbprintError {
+    #[error("missing y")]
+    MissingY,
+    #[error("unsupported kty: {0}")]
+    UnsupportedKty(String),
+    #[error("failed to serialize: {0}")]
+    Json(#[from] serde_json::Error),
 }
 
 #[derive(Error, Debug)]
 pub enum JwkToK256Error {
     #[error("missing y")]
     MissingY,
+    #[error("missing d")]
+    MissingD,
+    #[error("public key does not match private key")]
+    KeyMismatch,
     #[error("decode error")]
     Decode(DecodePartial),
     #[error("different crv")]
@@ -37,12 +206,38 @@ pub enum JwkToK256Error {
     Crypt(#[from] k256::elliptic_curve::Error),
 }
 
+#[derive(Error, Debug)]
+pub enum JwkToP256Error {
+    #[error("missing y")]
+    MissingY,
+    #[error("decode error")]
+    Decode(DecodePartial),
+    #[error("different crv")]
+    DifferentCrv,
+    #[error("crypt error: {0}")]
+    Crypt(#[from] p256::elliptic_curve::Error),
+}
+
 #[derive(Error, Debug)]
 pub enum JwkToX25519Error {
     #[error("decode error: {0:?}")]
     Decode(Option<DecodeError>),
     #[error("different crv")]
     DifferentCrv,
+    #[error("missing d")]
+    MissingD,
+    #[error("public key does not match private key")]
+    KeyMismatch,
+}
+
+#[derive(Error, Debug)]
+pub enum JwkToEd25519Error {
+    #[error("decode error: {0:?}")]
+    Decode(Option<DecodeError>),
+    #[error("different crv")]
+    DifferentCrv,
+    #[error("invalid key: {0}")]
+    InvalidKey(#[from] ed25519_dalek::SignatureError),
 }
 
 #[derive(Error, Debug)]
@@ -51,13 +246,38 @@ pub enum K256ToJwkError {
     PointsInvalid,
 }
 
+#[derive(Error, Debug)]
+pub enum P256ToJwkError {
+    #[error("points are invalid")]
+    PointsInvalid,
+}
+
+#[derive(Error, Debug)]
+pub enum JwkToSignPublicKeyError {
+    #[error("unsupported crv: {0}")]
+    UnsupportedCrv(String),
+    #[error(transparent)]
+    K256(#[from] JwkToK256Error),
+    #[error(transparent)]
+    P256(#[from] JwkToP256Error),
+    #[error(transparent)]
+    Ed25519(#[from] JwkToEd25519Error),
+}
+
+#[derive(Error, Debug)]
+pub enum SignPublicKeyToJwkError {
+    #[error(transparent)]
+    K256(#[from] K256ToJwkError),
+    #[error(transparent)]
+    P256(#[from] P256ToJwkError),
+}
+
+// secp256k1・P-256の座標（32バイト）をデコードする
 fn decode_base64url(
     s: &str,
-) -> Result<k256::elliptic_curve::FieldBytes<k256::Secp256k1>, JwkToK256Error> {
+) -> Result<k256::elliptic_curve::FieldBytes<k256::Secp256k1>, DecodePartial> {
     let mut result = k256::elliptic_curve::FieldBytes::<k256::Secp256k1>::default();
-    BASE64_NOPAD
-        .decode_mut(s.as_bytes(), &mut result)
-        .map_err(JwkToK256Error::Decode)?;
+    BASE64URL_NOPAD.decode_mut(s.as_bytes(), &mut result)?;
     Ok(result)
 }
 
@@ -71,8 +291,8 @@ impl TryFrom<Jwk> for k256::PublicKey {
         }
         if let Some(y) = value.y {
             // Base64URLエンコードされた座標をエンコード
-            let x = decode_base64url(&value.x)?;
-            let y = decode_base64url(&y)?;
+            let x = decode_base64url(&value.x).map_err(JwkToK256Error::Decode)?;
+            let y = decode_base64url(&y).map_err(JwkToK256Error::Decode)?;
 
             // 座標から公開鍵を構築
             let pk = k256::EncodedPoint::from_affine_coordinates(&x, &y, false);
@@ -94,13 +314,47 @@ impl TryFrom<k256::PublicKey> for Jwk {
             k256::elliptic_curve::sec1::Coordinates::Uncompressed { x, y } => {
                 let x = BASE64URL_NOPAD.encode(x);
                 let y = Some(BASE64URL_NOPAD.encode(y));
-                Ok(Jwk { kty, crv, x, y })
+                Ok(Jwk::new(kty, crv, x, y))
             }
             _ => Err(K256ToJwkError::PointsInvalid),
         }
     }
 }
 
+/// JWK から p256::PublicKey への変換処理
+impl TryFrom<Jwk> for p256::PublicKey {
+    type Error = JwkToP256Error;
+    fn try_from(value: Jwk) -> Result<Self, Self::Error> {
+        if value.crv != "P-256" {
+            return Err(JwkToP256Error::DifferentCrv);
+        }
+        let y = value.y.ok_or(JwkToP256Error::MissingY)?;
+        let x = decode_base64url(&value.x).map_err(JwkToP256Error::Decode)?;
+        let y = decode_base64url(&y).map_err(JwkToP256Error::Decode)?;
+
+        let pk = p256::EncodedPoint::from_affine_coordinates(&x, &y, false);
+        Ok(p256::PublicKey::from_sec1_bytes(pk.as_bytes())?)
+    }
+}
+
+/// p256::PublicKey から JWK への変換処理
+impl TryFrom<p256::PublicKey> for Jwk {
+    type Error = P256ToJwkError;
+    fn try_from(value: p256::PublicKey) -> Result<Self, Self::Error> {
+        let value = value.to_encoded_point(false);
+        let kty = "EC".to_string();
+        let crv = "P-256".to_string();
+        match value.coordinates() {
+            p256::elliptic_curve::sec1::Coordinates::Uncompressed { x, y } => {
+                let x = BASE64URL_NOPAD.encode(x);
+                let y = Some(BASE64URL_NOPAD.encode(y));
+                Ok(Jwk::new(kty, crv, x, y))
+            }
+            _ => Err(P256ToJwkError::PointsInvalid),
+        }
+    }
+}
+
 impl TryFrom<Jwk> for x25519_dalek::PublicKey {
     type Error = JwkToX25519Error;
     fn try_from(value: Jwk) -> Result<Self, Self::Error> {
@@ -120,13 +374,336 @@ impl From<x25519_dalek::PublicKey> for Jwk {
         let x = BASE64URL_NOPAD.encode(value.as_bytes());
         let kty = "OKP".to_string();
         let crv = "X25519".to_string();
-        Jwk {
-            kty,
-            crv,
-            x,
-            y: None,
+        Jwk::new(kty, crv, x, None)
+    }
+}
+
+impl TryFrom<Jwk> for ed25519_dalek::VerifyingKey {
+    type Error = JwkToEd25519Error;
+    fn try_from(value: Jwk) -> Result<Self, Self::Error> {
+        if value.crv != "Ed25519" {
+            return Err(JwkToEd25519Error::DifferentCrv);
+        }
+        let pk = BASE64URL_NOPAD
+            .decode(value.x.as_bytes())
+            .map_err(|e| JwkToEd25519Error::Decode(Some(e)))?;
+        let pk: [u8; 32] = pk.try_into().map_err(|_| JwkToEd25519Error::Decode(None))?;
+        Ok(ed25519_dalek::VerifyingKey::from_bytes(&pk)?)
+    }
+}
+
+impl From<ed25519_dalek::VerifyingKey> for Jwk {
+    fn from(value: ed25519_dalek::VerifyingKey) -> Self {
+        let x = BASE64URL_NOPAD.encode(value.as_bytes());
+        let kty = "OKP".to_string();
+        let crv = "Ed25519".to_string();
+        Jwk::new(kty, crv, x, None)
+    }
+}
+
+/// K256KeyPair から 秘密鍵を含むJWK への変換処理
+impl TryFrom<&K256KeyPair> for Jwk {
+    type Error = K256ToJwkError;
+    fn try_from(value: &K256KeyPair) -> Result<Self, Self::Error> {
+        let mut jwk = Jwk::try_from(value.get_public_key())?;
+        let d = Zeroizing::new(value.get_secret_key().to_bytes());
+        jwk.d = Some(BASE64URL_NOPAD.encode(&d[..]));
+        Ok(jwk)
+    }
+}
+
+/// 秘密鍵を含むJWK から K256KeyPair への変換処理
+/// JWKの公開鍵（x, y）は、秘密鍵から導出した公開鍵と一致する必要がある
+impl TryFrom<Jwk> for K256KeyPair {
+    type Error = JwkToK256Error;
+    fn try_from(value: Jwk) -> Result<Self, Self::Error> {
+        let d = value.d.as_deref().ok_or(JwkToK256Error::MissingD)?;
+        let d = Zeroizing::new(decode_base64url(d).map_err(JwkToK256Error::Decode)?);
+        let keypair = K256KeyPair::new(k256::SecretKey::from_bytes(&d)?);
+        if k256::PublicKey::try_from(value)? != keypair.get_public_key() {
+            return Err(JwkToK256Error::KeyMismatch);
         }
+        Ok(keypair)
+    }
+}
+
+/// X25519KeyPair から 秘密鍵を含むJWK への変換処理
+impl From<&X25519KeyPair> for Jwk {
+    fn from(value: &X25519KeyPair) -> Self {
+        let mut jwk = Jwk::from(value.get_public_key());
+        let d = Zeroizing::new(value.get_secret_key().to_bytes());
+        jwk.d = Some(BASE64URL_NOPAD.encode(d.as_slice()));
+        jwk
     }
 }
 
-// TODO: テストコード
+/// 秘密鍵を含むJWK から X25519KeyPair への変換処理
+/// JWKの公開鍵（x）は、秘密鍵から導出した公開鍵と一致する必要がある
+impl TryFrom<Jwk> for X25519KeyPair {
+    type Error = JwkToX25519Error;
+    fn try_from(value: Jwk) -> Result<Self, Self::Error> {
+        let d = value.d.as_deref().ok_or(JwkToX25519Error::MissingD)?;
+        let d = Zeroizing::new(
+            BASE64URL_NOPAD
+                .decode(d.as_bytes())
+                .map_err(|e| JwkToX25519Error::Decode(Some(e)))?,
+        );
+        let d: Zeroizing<[u8; 32]> = Zeroizing::new(
+            d.as_slice()
+                .try_into()
+                .map_err(|_| JwkToX25519Error::Decode(None))?,
+        );
+        let keypair = X25519KeyPair::new(x25519_dalek::StaticSecret::from(*d));
+        if x25519_dalek::PublicKey::try_from(value)? != keypair.get_public_key() {
+            return Err(JwkToX25519Error::KeyMismatch);
+        }
+        Ok(keypair)
+    }
+}
+
+/// JWKのcrvから署名鍵の種類を判定し、公開鍵に変換する
+impl TryFrom<Jwk> for SignPublicKey {
+    type Error = JwkToSignPublicKeyError;
+    fn try_from(value: Jwk) -> Result<Self, Self::Error> {
+        match value.crv.as_str() {
+            "secp256k1" => Ok(SignPublicKey::Secp256k1(value.try_into()?)),
+            "P-256" => Ok(SignPublicKey::P256(value.try_into()?)),
+            "Ed25519" => Ok(SignPublicKey::Ed25519(value.try_into()?)),
+            crv => Err(JwkToSignPublicKeyError::UnsupportedCrv(crv.to_string())),
+        }
+    }
+}
+
+impl TryFrom<SignPublicKey> for Jwk {
+    type Error = SignPublicKeyToJwkError;
+    fn try_from(value: SignPublicKey) -> Result<Self, Self::Error> {
+        Ok(match value {
+            SignPublicKey::Secp256k1(key) => key.try_into()?,
+            SignPublicKey::P256(key) => key.try_into()?,
+            SignPublicKey::Ed25519(key) => key.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyring::keypair::{Ed25519KeyPair, P256KeyPair};
+    use rand_core::OsRng;
+    use serde_json::json;
+
+    fn jwk(value: serde_json::Value) -> Jwk {
+        serde_json::from_value(value).unwrap()
+    }
+
+    // RFC7517 Appendix A.1
+    fn p256_jwk() -> Jwk {
+        jwk(json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "x": "MKBCTNIcKUSDii11ySs3526iDZ8AiTo7Tu6KPAqv7D4",
+            "y": "4Etl6SRW2YiLUrN5vfvVHuhp7x8PxltmWWlbbM4IFyM",
+        }))
+    }
+
+    // RFC8037 Appendix A.2
+    fn ed25519_jwk() -> Jwk {
+        jwk(json!({
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo",
+        }))
+    }
+
+    #[test]
+    fn test_thumbprint() {
+        // RFC8037 Appendix A.3
+        assert_eq!(
+            ed25519_jwk().thumbprint().unwrap(),
+            "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"
+        );
+        // SHA-256('{"crv":"P-256","kty":"EC","x":"MKBC...","y":"4Etl..."}')
+        assert_eq!(
+            p256_jwk().thumbprint().unwrap(),
+            "cn-I_WNMClehiVp51i_0VpOENW1upEerA8sEam5hn-s"
+        );
+    }
+
+    #[test]
+    fn test_thumbprint_ignores_optional_members() {
+        let key_pair = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let public = Jwk::try_from(key_pair.get_public_key()).unwrap();
+        let private = Jwk::try_from(&key_pair)
+            .unwrap()
+            .with_use("sig")
+            .with_alg("ES256K");
+        assert_eq!(public.thumbprint().unwrap(), private.thumbprint().unwrap());
+
+        let with_kid = public.clone().with_thumbprint_kid().unwrap();
+        assert_eq!(with_kid.kid(), Some(public.thumbprint().unwrap().as_str()));
+        assert_eq!(with_kid.thumbprint().unwrap(), public.thumbprint().unwrap());
+    }
+
+    #[test]
+    fn test_thumbprint_errors() {
+        let result = jwk(json!({"kty": "EC", "crv": "P-256", "x": "AAAA"})).thumbprint();
+        assert!(matches!(result, Err(JwkThumbprintError::MissingY)));
+        // RFC7638のRSAの鍵は対応していない
+        let result = jwk(json!({"kty": "RSA", "crv": "", "x": ""})).thumbprint();
+        assert!(matches!(result, Err(JwkThumbprintError::UnsupportedKty(kty)) if kty == "RSA"));
+    }
+
+    #[test]
+    fn test_private_key_jwk() {
+        let key_pair = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let private = Jwk::try_from(&key_pair).unwrap();
+        assert!(private.is_private());
+        let restored = K256KeyPair::try_from(private.clone()).unwrap();
+        assert_eq!(restored.get_secret_key(), key_pair.get_secret_key());
+
+        let public = private.to_public();
+        assert!(!public.is_private());
+        assert!(public.is_same_key(&private));
+        assert!(matches!(
+            K256KeyPair::try_from(public),
+            Err(JwkToK256Error::MissingD)
+        ));
+
+        let key_pair = X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(OsRng));
+        let private = Jwk::from(&key_pair);
+        assert!(private.is_private());
+        let restored = X25519KeyPair::try_from(private.clone()).unwrap();
+        assert_eq!(restored.get_public_key(), key_pair.get_public_key());
+        assert!(matches!(
+            X25519KeyPair::try_from(private.to_public()),
+            Err(JwkToX25519Error::MissingD)
+        ));
+    }
+
+    #[test]
+    fn test_private_key_jwk_key_mismatch() {
+        let key_pair = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let other = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let mut private = Jwk::try_from(&other).unwrap();
+        private.d = Jwk::try_from(&key_pair).unwrap().d;
+        assert!(matches!(
+            K256KeyPair::try_from(private),
+            Err(JwkToK256Error::KeyMismatch)
+        ));
+
+        let key_pair = X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(OsRng));
+        let other = X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(OsRng));
+        let mut private = Jwk::from(&other);
+        private.d = Jwk::from(&key_pair).d;
+        assert!(matches!(
+            X25519KeyPair::try_from(private),
+            Err(JwkToX25519Error::KeyMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_debug_redacts_private_key() {
+        let key_pair = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let private = Jwk::try_from(&key_pair).unwrap();
+        let debug = format!("{:?}", private);
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains(private.d.as_deref().unwrap()));
+    }
+
+    #[test]
+    fn test_serialize_optional_members() {
+        assert_eq!(
+            serde_json::to_value(ed25519_jwk()).unwrap(),
+            json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo",
+            })
+        );
+        let jwk = ed25519_jwk()
+            .with_kid("key-1")
+            .with_use("sig")
+            .with_alg("EdDSA");
+        assert_eq!(
+            serde_json::to_value(&jwk).unwrap(),
+            json!({
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo",
+                "kid": "key-1",
+                "use": "sig",
+                "alg": "EdDSA",
+            })
+        );
+        assert_eq!(jwk.key_use(), Some("sig"));
+        assert_eq!(jwk.alg(), Some("EdDSA"));
+    }
+
+    #[test]
+    fn test_is_same_key() {
+        let jwk = p256_jwk();
+        assert!(jwk.is_same_key(&jwk.clone().with_kid("key-1").with_alg("ES256")));
+        assert_ne!(jwk, jwk.clone().with_kid("key-1"));
+        assert!(!jwk.is_same_key(&ed25519_jwk()));
+
+        let mut other = jwk.clone();
+        other.y = Some("AAAA".to_string());
+        assert!(!jwk.is_same_key(&other));
+    }
+
+    #[test]
+    fn test_jwk_set() {
+        let key_pair = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let private = Jwk::try_from(&key_pair)
+            .unwrap()
+            .with_thumbprint_kid()
+            .unwrap();
+        let kid = private.kid().unwrap().to_string();
+        let set = JwkSet::new(vec![private, ed25519_jwk().with_kid("ed25519")]);
+
+        assert_eq!(set.find(&kid).unwrap().kty(), "EC");
+        assert_eq!(set.find("ed25519").unwrap().crv(), "Ed25519");
+        assert!(set.find("other").is_none());
+
+        let public = set.to_public();
+        assert!(public.keys.iter().all(|jwk| !jwk.is_private()));
+        let json = serde_json::to_value(&public).unwrap();
+        assert_eq!(json["keys"].as_array().unwrap().len(), 2);
+        assert!(json["keys"][0].get("d").is_none());
+        assert_eq!(serde_json::from_value::<JwkSet>(json).unwrap(), public);
+    }
+
+    #[test]
+    fn test_sign_public_key_jwk() {
+        for public_key in [
+            SignPublicKey::Secp256k1(
+                K256KeyPair::new(k256::SecretKey::random(&mut OsRng)).get_public_key(),
+            ),
+            SignPublicKey::P256(
+                P256KeyPair::new(p256::SecretKey::random(&mut OsRng)).get_public_key(),
+            ),
+            SignPublicKey::Ed25519(Ed25519KeyPair::random(&mut OsRng).get_public_key()),
+        ] {
+            let jwk = Jwk::try_from(public_key).unwrap();
+            assert_eq!(SignPublicKey::try_from(jwk).unwrap(), public_key);
+        }
+
+        assert_eq!(
+            p256::PublicKey::try_from(p256_jwk())
+                .map(SignPublicKey::P256)
+                .unwrap(),
+            SignPublicKey::try_from(p256_jwk()).unwrap()
+        );
+        let x25519 = Jwk::from(
+            X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(OsRng)).get_public_key(),
+        );
+        assert!(matches!(
+            SignPublicKey::try_from(x25519),
+            Err(JwkToSignPublicKeyError::UnsupportedCrv(crv)) if crv == "X25519"
+        ));
+        assert!(matches!(
+            k256::PublicKey::try_from(p256_jwk()),
+            Err(JwkToK256Error::DifferentCrv)
+        ));
+    }
+}
@@ -3,13 +3,27 @@ use k256::elliptic_curve::sec1::ToEncodedPoint;
 use rand_core::{CryptoRng, RngCore};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use zeroize::{Zeroize, ZeroizeOnDrop};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
-#[derive(Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+use super::secret::Secret;
+
+// 秘密鍵を含むため、Clone・Debugは実装しない
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
 pub struct KeyPairHex {
     // MEMO: Matching schema in MiaX config.
     public_key: String,
     secret_key: String,
+    // 署名鍵の種類。secp256k1の鍵（既存の設定ファイルの鍵を含む）では省略する
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[zeroize(skip)]
+    key_type: Option<SignKeyType>,
+}
+
+impl KeyPairHex {
+    /// 公開鍵（16進数）
+    pub fn public_key(&self) -> &str {
+        &self.public_key
+    }
 }
 
 #[derive(Error, Debug)]
@@ -20,9 +34,8 @@ pub enum KeyPairingError {
     Crypt(String),
 }
 
-#[derive(Clone)]
 pub struct K256KeyPair {
-    secret_key: k256::SecretKey,
+    secret_key: Secret<k256::SecretKey>,
     public_key: k256::PublicKey,
 }
 
@@ -31,14 +44,15 @@ impl K256KeyPair {
         let public_key = secret_key.public_key();
         K256KeyPair {
             public_key,
-            secret_key,
+            secret_key: Secret::new(secret_key),
         }
     }
 }
 
 pub trait KeyPair<S, P>: Sized {
     type Error: std::error::Error;
-    fn get_secret_key(&self) -> S;
+    /// 秘密鍵を参照する。秘密鍵を複製しないよう、参照のみを返す
+    fn get_secret_key(&self) -> &S;
     fn get_public_key(&self) -> P;
     fn to_hex_key_pair(&self) -> KeyPairHex;
     fn from_hex_key_pair(kp: &KeyPairHex) -> Result<Self, Self::Error>;
@@ -46,8 +60,8 @@ pub trait KeyPair<S, P>: Sized {
 
 impl KeyPair<k256::SecretKey, k256::PublicKey> for K256KeyPair {
     type Error = KeyPairingError;
-    fn get_secret_key(&self) -> k256::SecretKey {
-        self.secret_key.clone()
+    fn get_secret_key(&self) -> &k256::SecretKey {
+        self.secret_key.expose()
     }
 
     fn get_public_key(&self) -> k256::PublicKey {
@@ -55,18 +69,19 @@ impl KeyPair<k256::SecretKey, k256::PublicKey> for K256KeyPair {
     }
 
     fn to_hex_key_pair(&self) -> KeyPairHex {
-        let sk = self.secret_key.to_bytes();
-        let secret_key = hex::encode(sk);
+        let sk = Zeroizing::new(self.secret_key.expose().to_bytes());
+        let secret_key = hex::encode(sk.as_slice());
         let pk = self.public_key.to_encoded_point(false);
         let public_key = hex::encode(pk.as_bytes());
         KeyPairHex {
             secret_key,
             public_key,
+            key_type: None,
         }
     }
 
     fn from_hex_key_pair(kp: &KeyPairHex) -> Result<Self, Self::Error> {
-        let secret_key = hex::decode(&kp.secret_key)?;
+        let secret_key = Zeroizing::new(hex::decode(&kp.secret_key)?);
         let secret_key = k256::SecretKey::from_slice(&secret_key)
             .map_err(|e| KeyPairingError::Crypt(e.to_string()))?;
         let public_key = hex::decode(&kp.public_key)?;
@@ -74,14 +89,64 @@ impl KeyPair<k256::SecretKey, k256::PublicKey> for K256KeyPair {
             .map_err(|e| KeyPairingError::Crypt(e.to_string()))?;
         Ok(K256KeyPair {
             public_key,
+            secret_key: Secret::new(secret_key),
+        })
+    }
+}
+
+pub struct P256KeyPair {
+    secret_key: Secret<p256::SecretKey>,
+    public_key: p256::PublicKey,
+}
+
+impl P256KeyPair {
+    pub fn new(secret_key: p256::SecretKey) -> Self {
+        let public_key = secret_key.public_key();
+        P256KeyPair {
+            public_key,
+            secret_key: Secret::new(secret_key),
+        }
+    }
+}
+
+impl KeyPair<p256::SecretKey, p256::PublicKey> for P256KeyPair {
+    type Error = KeyPairingError;
+    fn get_secret_key(&self) -> &p256::SecretKey {
+        self.secret_key.expose()
+    }
+
+    fn get_public_key(&self) -> p256::PublicKey {
+        self.public_key
+    }
+
+    fn to_hex_key_pair(&self) -> KeyPairHex {
+        let sk = Zeroizing::new(self.secret_key.expose().to_bytes());
+        let secret_key = hex::encode(sk.as_slice());
+        let pk = self.public_key.to_encoded_point(false);
+        let public_key = hex::encode(pk.as_bytes());
+        KeyPairHex {
             secret_key,
+            public_key,
+            key_type: Some(SignKeyType::P256),
+        }
+    }
+
+    fn from_hex_key_pair(kp: &KeyPairHex) -> Result<Self, Self::Error> {
+        let secret_key = Zeroizing::new(hex::decode(&kp.secret_key)?);
+        let secret_key = p256::SecretKey::from_slice(&secret_key)
+            .map_err(|e| KeyPairingError::Crypt(e.to_string()))?;
+        let public_key = hex::decode(&kp.public_key)?;
+        let public_key = p256::PublicKey::from_sec1_bytes(&public_key)
+            .map_err(|e| KeyPairingError::Crypt(e.to_string()))?;
+        Ok(P256KeyPair {
+            public_key,
+            secret_key: Secret::new(secret_key),
         })
     }
 }
 
-#[derive(Clone)]
 pub struct X25519KeyPair {
-    secret_key: x25519_dalek::StaticSecret,
+    secret_key: Secret<x25519_dalek::StaticSecret>,
     public_key: x25519_dalek::PublicKey,
 }
 
@@ -90,35 +155,37 @@ impl X25519KeyPair {
         let public_key = x25519_dalek::PublicKey::from(&secret_key);
         X25519KeyPair {
             public_key,
-            secret_key,
+            secret_key: Secret::new(secret_key),
         }
     }
 }
 
 impl KeyPair<x25519_dalek::StaticSecret, x25519_dalek::PublicKey> for X25519KeyPair {
     type Error = KeyPairingError;
-    fn get_secret_key(&self) -> x25519_dalek::StaticSecret {
-        self.secret_key.clone()
+    fn get_secret_key(&self) -> &x25519_dalek::StaticSecret {
+        self.secret_key.expose()
     }
     fn get_public_key(&self) -> x25519_dalek::PublicKey {
         self.public_key
     }
     fn to_hex_key_pair(&self) -> KeyPairHex {
-        let sk = self.secret_key.to_bytes();
-        let secret_key = hex::encode(sk);
+        let sk = Zeroizing::new(self.secret_key.expose().to_bytes());
+        let secret_key = hex::encode(sk.as_slice());
         let pk = self.public_key.as_bytes();
         let public_key = hex::encode(pk);
         KeyPairHex {
             secret_key,
             public_key,
+            key_type: None,
         }
     }
     fn from_hex_key_pair(kp: &KeyPairHex) -> Result<Self, KeyPairingError> {
-        let secret_key = hex::decode(&kp.secret_key)?;
-        let secret_key: [u8; 32] = secret_key.try_into().map_err(|e: Vec<u8>| {
-            KeyPairingError::Crypt(format!("array length mismatch: {}", e.len()))
-        })?;
-        let secret_key = x25519_dalek::StaticSecret::from(secret_key);
+        let secret_key = Zeroizing::new(hex::decode(&kp.secret_key)?);
+        let secret_key =
+            Zeroizing::new(<[u8; 32]>::try_from(secret_key.as_slice()).map_err(|_| {
+                KeyPairingError::Crypt(format!("array length mismatch: {}", secret_key.len()))
+            })?);
+        let secret_key = x25519_dalek::StaticSecret::from(*secret_key);
         let public_key = hex::decode(&kp.public_key)?;
         let public_key: [u8; 32] = public_key.try_into().map_err(|e: Vec<u8>| {
             KeyPairingError::Crypt(format!("array length mismatch: {}", e.len()))
@@ -126,25 +193,216 @@ impl KeyPair<x25519_dalek::StaticSecret, x25519_dalek::PublicKey> for X25519KeyP
         let public_key = x25519_dalek::PublicKey::from(public_key);
         Ok(X25519KeyPair {
             public_key,
+            secret_key: Secret::new(secret_key),
+        })
+    }
+}
+
+pub struct Ed25519KeyPair {
+    // 公開鍵はSigningKeyが保持するため、別途保持しない
+    secret_key: Secret<ed25519_dalek::SigningKey>,
+}
+
+impl Ed25519KeyPair {
+    pub fn new(secret_key: ed25519_dalek::SigningKey) -> Self {
+        Ed25519KeyPair {
+            secret_key: Secret::new(secret_key),
+        }
+    }
+
+    pub fn random<T: RngCore + CryptoRng>(csprng: &mut T) -> Self {
+        let mut seed = [0u8; ed25519_dalek::SECRET_KEY_LENGTH];
+        csprng.fill_bytes(&mut seed);
+        let secret_key = ed25519_dalek::SigningKey::from_bytes(&seed);
+        seed.zeroize();
+        Self::new(secret_key)
+    }
+}
+
+impl KeyPair<ed25519_dalek::SigningKey, ed25519_dalek::VerifyingKey> for Ed25519KeyPair {
+    type Error = KeyPairingError;
+    fn get_secret_key(&self) -> &ed25519_dalek::SigningKey {
+        self.secret_key.expose()
+    }
+    fn get_public_key(&self) -> ed25519_dalek::VerifyingKey {
+        self.secret_key.expose().verifying_key()
+    }
+    fn to_hex_key_pair(&self) -> KeyPairHex {
+        // 秘密鍵は、RFC8032の32バイトのseedで表現する
+        let sk = Zeroizing::new(self.secret_key.expose().to_bytes());
+        let secret_key = hex::encode(sk.as_slice());
+        let pk = self.get_public_key();
+        let public_key = hex::encode(pk.as_bytes());
+        KeyPairHex {
             secret_key,
+            public_key,
+            key_type: Some(SignKeyType::Ed25519),
+        }
+    }
+    fn from_hex_key_pair(kp: &KeyPairHex) -> Result<Self, KeyPairingError> {
+        let secret_key = Zeroizing::new(hex::decode(&kp.secret_key)?);
+        let secret_key =
+            Zeroizing::new(<[u8; 32]>::try_from(secret_key.as_slice()).map_err(|_| {
+                KeyPairingError::Crypt(format!("array length mismatch: {}", secret_key.len()))
+            })?);
+        let secret_key = ed25519_dalek::SigningKey::from_bytes(&secret_key);
+        let public_key = hex::decode(&kp.public_key)?;
+        let public_key: [u8; 32] = public_key.try_into().map_err(|e: Vec<u8>| {
+            KeyPairingError::Crypt(format!("array length mismatch: {}", e.len()))
+        })?;
+        // 公開鍵は秘密鍵から導出できるため、一致することのみを確認する
+        if secret_key.verifying_key().as_bytes() != &public_key {
+            return Err(KeyPairingError::Crypt("public key mismatch".to_string()));
+        }
+        Ok(Ed25519KeyPair::new(secret_key))
+    }
+}
+
+/// 署名鍵（DIDドキュメントのsigningKey）の種類
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignKeyType {
+    #[default]
+    Secp256k1,
+    /// NIST P-256。P-256のみに対応するセキュアエレメントで利用する
+    P256,
+    Ed25519,
+}
+
+/// 署名鍵の鍵ペア
+pub enum SignKeyPair {
+    Secp256k1(K256KeyPair),
+    P256(P256KeyPair),
+    Ed25519(Ed25519KeyPair),
+}
+
+/// 署名鍵の公開鍵
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignPublicKey {
+    Secp256k1(k256::PublicKey),
+    P256(p256::PublicKey),
+    Ed25519(ed25519_dalek::VerifyingKey),
+}
+
+impl SignKeyPair {
+    pub fn random<T: RngCore + CryptoRng>(csprng: &mut T, key_type: SignKeyType) -> Self {
+        match key_type {
+            SignKeyType::Secp256k1 => {
+                SignKeyPair::Secp256k1(K256KeyPair::new(k256::SecretKey::random(csprng)))
+            }
+            SignKeyType::P256 => {
+                SignKeyPair::P256(P256KeyPair::new(p256::SecretKey::random(csprng)))
+            }
+            SignKeyType::Ed25519 => SignKeyPair::Ed25519(Ed25519KeyPair::random(csprng)),
+        }
+    }
+
+    pub fn key_type(&self) -> SignKeyType {
+        match self {
+            SignKeyPair::Secp256k1(_) => SignKeyType::Secp256k1,
+            SignKeyPair::P256(_) => SignKeyType::P256,
+            SignKeyPair::Ed25519(_) => SignKeyType::Ed25519,
+        }
+    }
+
+    pub fn get_public_key(&self) -> SignPublicKey {
+        match self {
+            SignKeyPair::Secp256k1(key) => SignPublicKey::Secp256k1(key.get_public_key()),
+            SignKeyPair::P256(key) => SignPublicKey::P256(key.get_public_key()),
+            SignKeyPair::Ed25519(key) => SignPublicKey::Ed25519(key.get_public_key()),
+        }
+    }
+
+    pub fn to_hex_key_pair(&self) -> KeyPairHex {
+        match self {
+            SignKeyPair::Secp256k1(key) => key.to_hex_key_pair(),
+            SignKeyPair::P256(key) => key.to_hex_key_pair(),
+            SignKeyPair::Ed25519(key) => key.to_hex_key_pair(),
+        }
+    }
+
+    /// KeyPairHexの鍵の種類に応じて復元する。種類が省略されている場合はsecp256k1とする
+    pub fn from_hex_key_pair(kp: &KeyPairHex) -> Result<Self, KeyPairingError> {
+        Ok(match kp.key_type.unwrap_or_default() {
+            SignKeyType::Secp256k1 => K256KeyPair::from_hex_key_pair(kp)?.into(),
+            SignKeyType::P256 => P256KeyPair::from_hex_key_pair(kp)?.into(),
+            SignKeyType::Ed25519 => Ed25519KeyPair::from_hex_key_pair(kp)?.into(),
         })
     }
 }
 
-#[derive(Clone)]
+impl From<K256KeyPair> for SignKeyPair {
+    fn from(value: K256KeyPair) -> Self {
+        SignKeyPair::Secp256k1(value)
+    }
+}
+
+impl From<P256KeyPair> for SignKeyPair {
+    fn from(value: P256KeyPair) -> Self {
+        SignKeyPair::P256(value)
+    }
+}
+
+impl From<Ed25519KeyPair> for SignKeyPair {
+    fn from(value: Ed25519KeyPair) -> Self {
+        SignKeyPair::Ed25519(value)
+    }
+}
+
+impl SignPublicKey {
+    pub fn key_type(&self) -> SignKeyType {
+        match self {
+            SignPublicKey::Secp256k1(_) => SignKeyType::Secp256k1,
+            SignPublicKey::P256(_) => SignKeyType::P256,
+            SignPublicKey::Ed25519(_) => SignKeyType::Ed25519,
+        }
+    }
+
+    /// KeyPairHexと同じ形式の公開鍵（16進数）
+    pub fn to_hex(&self) -> String {
+        match self {
+            SignPublicKey::Secp256k1(key) => hex::encode(key.to_encoded_point(false).as_bytes()),
+            SignPublicKey::P256(key) => hex::encode(key.to_encoded_point(false).as_bytes()),
+            SignPublicKey::Ed25519(key) => hex::encode(key.as_bytes()),
+        }
+    }
+}
+
+impl From<k256::PublicKey> for SignPublicKey {
+    fn from(value: k256::PublicKey) -> Self {
+        SignPublicKey::Secp256k1(value)
+    }
+}
+
+impl From<p256::PublicKey> for SignPublicKey {
+    fn from(value: p256::PublicKey) -> Self {
+        SignPublicKey::P256(value)
+    }
+}
+
+impl From<ed25519_dalek::VerifyingKey> for SignPublicKey {
+    fn from(value: ed25519_dalek::VerifyingKey) -> Self {
+        SignPublicKey::Ed25519(value)
+    }
+}
+
+/// DIDの鍵ペアの組
+///
+/// 秘密鍵を複製しないよう、Cloneは実装せず、各鍵ペアは参照で取得する
 pub struct KeyPairing {
-    pub sign: K256KeyPair,
-    pub update: K256KeyPair,
-    pub recovery: K256KeyPair,
-    pub encrypt: X25519KeyPair,
+    sign: SignKeyPair,
+    update: K256KeyPair,
+    recovery: K256KeyPair,
+    encrypt: X25519KeyPair,
 }
 
 impl KeyPairing {
-    pub fn create_keyring<T: RngCore + CryptoRng>(mut csprng: T) -> Self {
-        let sign = K256KeyPair::new(k256::SecretKey::random(&mut csprng));
-        let update = K256KeyPair::new(k256::SecretKey::random(&mut csprng));
-        let recovery = K256KeyPair::new(k256::SecretKey::random(&mut csprng));
-        let encrypt = X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(&mut csprng));
+    pub fn new(
+        sign: SignKeyPair,
+        update: K256KeyPair,
+        recovery: K256KeyPair,
+        encrypt: X25519KeyPair,
+    ) -> Self {
         KeyPairing {
             sign,
             update,
@@ -152,4 +410,197 @@ impl KeyPairing {
             encrypt,
         }
     }
+
+    /// 署名鍵
+    pub fn sign(&self) -> &SignKeyPair {
+        &self.sign
+    }
+
+    /// 更新鍵
+    pub fn update(&self) -> &K256KeyPair {
+        &self.update
+    }
+
+    /// リカバリ鍵
+    pub fn recovery(&self) -> &K256KeyPair {
+        &self.recovery
+    }
+
+    /// 暗号化鍵
+    pub fn encrypt(&self) -> &X25519KeyPair {
+        &self.encrypt
+    }
+
+    /// 署名鍵にsecp256k1を利用する鍵ペアを生成する
+    pub fn create_keyring<T: RngCore + CryptoRng>(csprng: T) -> Self {
+        Self::create_keyring_with_sign_key(csprng, SignKeyType::Secp256k1)
+    }
+
+    /// 指定した種類の署名鍵を利用する鍵ペアを生成する
+    /// 更新鍵・リカバリ鍵はSidetreeの仕様によりsecp256k1、暗号化鍵はX25519とする
+    pub fn create_keyring_with_sign_key<T: RngCore + CryptoRng>(
+        mut csprng: T,
+        sign_key_type: SignKeyType,
+    ) -> Self {
+        let sign = SignKeyPair::random(&mut csprng, sign_key_type);
+        let update = K256KeyPair::new(k256::SecretKey::random(&mut csprng));
+        let recovery = K256KeyPair::new(k256::SecretKey::random(&mut csprng));
+        let encrypt = X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(&mut csprng));
+        Self::new(sign, update, recovery, encrypt)
+    }
+
+    /// 鍵ペアの公開鍵を返す
+    pub fn public_keys(&self) -> PublicKeyPairing {
+        PublicKeyPairing {
+            sign: self.sign.get_public_key(),
+            update: self.update.get_public_key(),
+            recovery: self.recovery.get_public_key(),
+            encrypt: self.encrypt.get_public_key(),
+        }
+    }
+}
+
+/// 鍵ペアの公開鍵
+/// HSMなど秘密鍵を取り出せないキーストアの鍵でも、公開鍵のみでDIDを作成できる
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublicKeyPairing {
+    pub sign: SignPublicKey,
+    pub update: k256::PublicKey,
+    pub recovery: k256::PublicKey,
+    pub encrypt: x25519_dalek::PublicKey,
+}
+
+impl From<&KeyPairing> for PublicKeyPairing {
+    fn from(value: &KeyPairing) -> Self {
+        value.public_keys()
+    }
+}
+
+impl From<KeyPairing> for PublicKeyPairing {
+    fn from(value: KeyPairing) -> Self {
+        value.public_keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::OsRng;
+
+    // RFC 8032 7.1. TEST 1
+    const ED25519_SECRET_KEY: &str =
+        "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const ED25519_PUBLIC_KEY: &str =
+        "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+    #[test]
+    fn test_ed25519_rfc8032_vector() {
+        let key_pair = Ed25519KeyPair::from_hex_key_pair(&KeyPairHex {
+            secret_key: ED25519_SECRET_KEY.to_string(),
+            public_key: ED25519_PUBLIC_KEY.to_string(),
+            key_type: None,
+        })
+        .unwrap();
+        assert_eq!(
+            hex::encode(key_pair.get_public_key().as_bytes()),
+            ED25519_PUBLIC_KEY
+        );
+
+        let hex = key_pair.to_hex_key_pair();
+        assert_eq!(hex.secret_key, ED25519_SECRET_KEY);
+        assert_eq!(hex.public_key, ED25519_PUBLIC_KEY);
+    }
+
+    #[test]
+    fn test_ed25519_from_hex_rejects_invalid_key_pair() {
+        let other = Ed25519KeyPair::random(&mut OsRng).to_hex_key_pair();
+        assert!(matches!(
+            Ed25519KeyPair::from_hex_key_pair(&KeyPairHex {
+                secret_key: ED25519_SECRET_KEY.to_string(),
+                public_key: other.public_key.clone(),
+                key_type: None,
+            }),
+            Err(KeyPairingError::Crypt(_))
+        ));
+        assert!(matches!(
+            Ed25519KeyPair::from_hex_key_pair(&KeyPairHex {
+                secret_key: ED25519_SECRET_KEY[2..].to_string(),
+                public_key: ED25519_PUBLIC_KEY.to_string(),
+                key_type: None,
+            }),
+            Err(KeyPairingError::Crypt(_))
+        ));
+        assert!(matches!(
+            Ed25519KeyPair::from_hex_key_pair(&KeyPairHex {
+                secret_key: "zz".repeat(32),
+                public_key: ED25519_PUBLIC_KEY.to_string(),
+                key_type: None,
+            }),
+            Err(KeyPairingError::FromHex(_))
+        ));
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let keyring = KeyPairing::create_keyring(OsRng);
+
+        let sign = SignKeyPair::from_hex_key_pair(&keyring.sign.to_hex_key_pair()).unwrap();
+        assert_eq!(sign.get_public_key(), keyring.sign.get_public_key());
+        let encrypt = X25519KeyPair::from_hex_key_pair(&keyring.encrypt.to_hex_key_pair()).unwrap();
+        assert_eq!(encrypt.get_public_key(), keyring.encrypt.get_public_key());
+
+        let ed25519 = Ed25519KeyPair::random(&mut OsRng);
+        let restored = Ed25519KeyPair::from_hex_key_pair(&ed25519.to_hex_key_pair()).unwrap();
+        assert_eq!(restored.get_public_key(), ed25519.get_public_key());
+    }
+
+    #[test]
+    fn test_sign_key_pair_hex_round_trip() {
+        for key_type in [
+            SignKeyType::Secp256k1,
+            SignKeyType::P256,
+            SignKeyType::Ed25519,
+        ] {
+            let key_pair = SignKeyPair::random(&mut OsRng, key_type);
+            let restored = SignKeyPair::from_hex_key_pair(&key_pair.to_hex_key_pair()).unwrap();
+            assert_eq!(restored.key_type(), key_type);
+            assert_eq!(restored.get_public_key(), key_pair.get_public_key());
+            assert_eq!(restored.get_public_key().key_type(), key_type);
+        }
+    }
+
+    #[test]
+    fn test_key_type_is_omitted_for_secp256k1() {
+        let key_pair = SignKeyPair::random(&mut OsRng, SignKeyType::Secp256k1);
+        let json = serde_json::to_value(key_pair.to_hex_key_pair()).unwrap();
+        assert!(json.get("key_type").is_none());
+
+        let key_pair = SignKeyPair::random(&mut OsRng, SignKeyType::P256);
+        let json = serde_json::to_value(key_pair.to_hex_key_pair()).unwrap();
+        assert_eq!(json["key_type"], "p256");
+
+        // 鍵の種類を持たない既存の設定ファイルの鍵はsecp256k1として復元する
+        let legacy = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let legacy_json = serde_json::json!({
+            "public_key": hex::encode(legacy.get_public_key().to_encoded_point(false).as_bytes()),
+            "secret_key": hex::encode(legacy.get_secret_key().to_bytes()),
+        });
+        let restored =
+            SignKeyPair::from_hex_key_pair(&serde_json::from_value(legacy_json).unwrap()).unwrap();
+        assert_eq!(restored.key_type(), SignKeyType::Secp256k1);
+        assert_eq!(
+            restored.get_public_key(),
+            SignPublicKey::Secp256k1(legacy.get_public_key())
+        );
+    }
+
+    #[test]
+    fn test_create_keyring_with_sign_key() {
+        let keyring = KeyPairing::create_keyring_with_sign_key(OsRng, SignKeyType::P256);
+        assert_eq!(keyring.sign.key_type(), SignKeyType::P256);
+        assert_eq!(
+            KeyPairing::create_keyring(OsRng).sign.key_type(),
+            SignKeyType::Secp256k1
+        );
+    }
 }
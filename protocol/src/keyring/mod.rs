@@ -1,2 +1,6 @@
+pub mod hd;
 pub mod jwk;
 pub mod keypair;
+pub mod secret;
+pub mod shamir;
+pub mod signer;
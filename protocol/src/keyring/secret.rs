@@ -0,0 +1,213 @@
+// 秘密鍵などの秘密情報を保持するラッパー
+//
+// - 秘密情報はヒープ上の専用の領域に保持し、ラッパーをムーブしても秘密情報はコピーされない
+// - 破棄時に領域全体をゼロで上書きする
+// - Cloneは実装せず、暗黙の複製を防ぐ。Debugは秘密情報を出力しない
+// - mlock featureを有効にした場合（Unixのみ）は、領域をmlock(2)でロックしてスワップへの書き出しを防ぐ
+//   Linuxでは、あわせてコアダンプの対象からも除外する（MADV_DONTDUMP）
+//   mlock・munlockはページ単位で行われるため、領域はページ単位で確保し、他の値とページを共有しない
+use std::alloc::{self, Layout};
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem::{self, MaybeUninit};
+use std::ptr::{self, NonNull};
+use std::slice;
+
+use zeroize::Zeroize;
+
+/// 秘密情報のラッパー
+pub struct Secret<T> {
+    ptr: NonNull<T>,
+    layout: Layout,
+    locked: bool,
+    _marker: PhantomData<T>,
+}
+
+// SAFETY: 領域はSecretが専有し、他に参照を持つ値はない。Tの値を所有するBox<T>と同じく、
+// Tを別スレッドに移動できる場合はSecret<T>も移動できる
+unsafe impl<T: Send> Send for Secret<T> {}
+// SAFETY: 共有参照からはexposeで&Tを得ることしかできないため、Tと同じ条件でスレッド間で共有できる
+unsafe impl<T: Sync> Sync for Secret<T> {}
+
+impl<T> Secret<T> {
+    pub fn new(value: T) -> Self {
+        assert!(mem::size_of::<T>() > 0, "zero-sized secret");
+        let layout = layout::<T>();
+        // SAFETY: 大きさが0の型は上で除外しているため、layoutの大きさは0ではない
+        let ptr = NonNull::new(unsafe { alloc::alloc(layout) } as *mut T)
+            .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        // SAFETY: ptrはTの大きさ以上・Tのアラインメントの倍数で確保した未初期化の領域を指す
+        unsafe { ptr.as_ptr().write(value) };
+        let locked = lock(ptr.as_ptr() as *mut u8, layout.size());
+        Secret {
+            ptr,
+            layout,
+            locked,
+            _marker: PhantomData,
+        }
+    }
+
+    /// 秘密情報を参照する
+    pub fn expose(&self) -> &T {
+        // SAFETY: ptrはnewで初期化した値を指し、Dropまで解放されない。
+        // 可変参照を返すメソッドはないため、共有参照と可変参照は同時に存在しない
+        unsafe { self.ptr.as_ref() }
+    }
+
+    // 値を破棄し、領域全体をゼロで上書きする
+    //
+    // SAFETY: 呼び出し後の値は破棄済みのため、exposeやclearを再度呼び出してはならない
+    unsafe fn clear(&mut self) {
+        ptr::drop_in_place(self.ptr.as_ptr());
+        // 値の型が破棄時にゼロ化しない場合にも備え、パディングを含む領域全体をゼロで上書きする
+        slice::from_raw_parts_mut(
+            self.ptr.as_ptr() as *mut MaybeUninit<u8>,
+            self.layout.size(),
+        )
+        .zeroize();
+    }
+
+    // ロックを解除して領域を解放する
+    //
+    // SAFETY: 呼び出し後は領域にアクセスしてはならず、releaseを再度呼び出してもならない
+    unsafe fn release(&mut self) {
+        if self.locked {
+            unlock(self.ptr.as_ptr() as *mut u8, self.layout.size());
+        }
+        alloc::dealloc(self.ptr.as_ptr() as *mut u8, self.layout);
+    }
+}
+
+impl<T> Drop for Secret<T> {
+    fn drop(&mut self) {
+        // SAFETY: Dropは一度だけ呼ばれ、以降はselfにアクセスしないため、clear・releaseの条件を満たす
+        // ptrはnewでlayoutを指定して確保・初期化した領域を指す
+        unsafe {
+            self.clear();
+            self.release();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secret").finish_non_exhaustive()
+    }
+}
+
+#[cfg(all(unix, feature = "mlock"))]
+fn layout<T>() -> Layout {
+    // SAFETY: sysconfは引数の定数を参照するのみで、メモリを読み書きしない
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    let page = usize::try_from(size)
+        .unwrap_or(4096)
+        .max(mem::align_of::<T>());
+    Layout::from_size_align(mem::size_of::<T>().next_multiple_of(page), page)
+        .expect("page size is a power of two")
+}
+
+#[cfg(not(all(unix, feature = "mlock")))]
+fn layout<T>() -> Layout {
+    Layout::new::<T>()
+}
+
+// ロックできた場合はtrueを返す
+// RLIMIT_MEMLOCKを超える場合などはロックできないが、秘密情報はそのまま利用できるようにする
+#[cfg(all(unix, feature = "mlock"))]
+fn lock(ptr: *mut u8, len: usize) -> bool {
+    let addr = ptr as *mut libc::c_void;
+    // SAFETY: addrはページ境界で確保したlenバイトの領域を指す。madviseは領域の属性のみを変更し、
+    // 失敗してもコアダンプに含まれるだけのため結果は無視する
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::madvise(addr, len, libc::MADV_DONTDUMP);
+    }
+    // SAFETY: addrはSecretが専有するlenバイトの領域を指す。mlockは領域の内容を変更しない
+    unsafe { libc::mlock(addr, len) == 0 }
+}
+
+#[cfg(not(all(unix, feature = "mlock")))]
+fn lock(_ptr: *mut u8, _len: usize) -> bool {
+    false
+}
+
+#[cfg(all(unix, feature = "mlock"))]
+fn unlock(ptr: *mut u8, len: usize) {
+    // SAFETY: ptrはlockでロックした、解放前のlenバイトの領域を指す
+    unsafe {
+        libc::munlock(ptr as *mut libc::c_void, len);
+    }
+}
+
+#[cfg(not(all(unix, feature = "mlock")))]
+fn unlock(_ptr: *mut u8, _len: usize) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::ManuallyDrop;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // 破棄された回数を数える値
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_expose() {
+        let secret = Secret::new([0x5au8; 32]);
+        assert_eq!(secret.expose(), &[0x5au8; 32]);
+
+        // ムーブしても同じ領域を参照する
+        let ptr = secret.expose().as_ptr();
+        let moved = secret;
+        assert_eq!(moved.expose().as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_drop_runs_destructor_once() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let secret = Secret::new(DropCounter(count.clone()));
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        drop(secret);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_clear_zeroizes_memory() {
+        // 解放後の領域は読めないため、解放する前の領域がゼロで上書きされていることを確かめる
+        let mut secret = ManuallyDrop::new(Secret::new([0xa5u8; 48]));
+        let len = secret.layout.size();
+        assert!(len >= 48);
+        // SAFETY: clearの後はexposeせず、releaseで解放した後はsecretを利用しない
+        unsafe {
+            secret.clear();
+            let bytes = slice::from_raw_parts(secret.ptr.as_ptr() as *const u8, len);
+            assert!(bytes.iter().all(|&b| b == 0));
+            secret.release();
+        }
+    }
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let secret = Secret::new(String::from("correct horse battery staple"));
+        let debug = format!("{:?}", secret);
+        assert_eq!(debug, "Secret { .. }");
+        assert!(!debug.contains("horse"));
+    }
+
+    #[cfg(all(unix, feature = "mlock"))]
+    #[test]
+    fn test_page_aligned_layout() {
+        // SAFETY: sysconfは引数の定数を参照するのみ
+        let page = usize::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).unwrap();
+        let secret = Secret::new([0u8; 32]);
+        assert_eq!(secret.expose().as_ptr() as usize % page, 0);
+        assert_eq!(secret.layout.size(), page);
+    }
+}
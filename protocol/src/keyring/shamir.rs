@@ -0,0 +1,324 @@
+// Shamirの秘密分散法による鍵の分割・復元
+//
+// secp256k1の秘密鍵（32バイト）をGF(256)上でM個のシェアに分割し、そのうちN個（しきい値）から復元する
+// デバイスにリカバリ鍵を置かず、シェアを別々の管理者・保管場所に配布する運用を想定する
+//
+// シェアは `<しきい値>-<鍵ID>-<シェア>` の形式の文字列として書き出す
+// - しきい値 : 復元に必要なシェアの数（10進数）
+// - 鍵ID : 圧縮形式の公開鍵のSHA-256の先頭4バイト（16進数）。異なる鍵のシェアの混在や、復元結果の誤りを検出する
+// - シェア : x座標（1バイト）と、秘密鍵の各バイトに対応するy座標（16進数）
+//
+// 参考 : https://web.mit.edu/6.857/OldStuff/Fall03/ref/Shamir-HowToShareASecret.pdf
+use std::collections::BTreeMap;
+
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use rand_core::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use sharks::{Share, Sharks};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use super::keypair::{K256KeyPair, KeyPair};
+
+const KEY_ID_LEN: usize = 4;
+
+#[derive(Error, Debug)]
+pub enum ShamirError {
+    #[error("invalid threshold: {threshold} of {shares} shares")]
+    InvalidThreshold { threshold: u8, shares: u8 },
+    #[error("invalid share: {0}")]
+    InvalidShare(String),
+    #[error("shares belong to different keys or thresholds")]
+    MismatchedShares,
+    #[error("no shares given")]
+    NoShares,
+    #[error("not enough shares: {required} required, {given} given")]
+    NotEnoughShares { required: u8, given: usize },
+    #[error("recovered key does not match the shares")]
+    KeyMismatch,
+}
+
+/// 鍵のシェア
+///
+/// 秘密情報の一部を含むため、Clone・Debugは実装しない
+pub struct KeyShare {
+    threshold: u8,
+    key_id: [u8; KEY_ID_LEN],
+    share: Share,
+}
+
+impl KeyShare {
+    /// 復元に必要なシェアの数
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    /// シェアを文字列として書き出す
+    pub fn encode(&self) -> Zeroizing<String> {
+        let share = Zeroizing::new(Vec::from(&self.share));
+        Zeroizing::new(format!(
+            "{}-{}-{}",
+            self.threshold,
+            hex::encode(self.key_id),
+            hex::encode(share.as_slice())
+        ))
+    }
+
+    /// `encode`で書き出した文字列からシェアを読み込む
+    pub fn decode(encoded: &str) -> Result<Self, ShamirError> {
+        let invalid = |reason: &str| ShamirError::InvalidShare(reason.to_string());
+
+        let mut parts = encoded.trim().splitn(3, '-');
+        let (Some(threshold), Some(key_id), Some(share)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("expected <threshold>-<key id>-<share>"));
+        };
+        let threshold = threshold
+            .parse::<u8>()
+            .map_err(|_| invalid("threshold is not a number"))?;
+        let key_id = hex::decode(key_id)
+            .ok()
+            .and_then(|key_id| <[u8; KEY_ID_LEN]>::try_from(key_id).ok())
+            .ok_or_else(|| invalid("key id is not 4 bytes of hex"))?;
+        let share = Zeroizing::new(hex::decode(share).map_err(|_| invalid("share is not hex"))?);
+        let share = Share::try_from(share.as_slice()).map_err(invalid)?;
+        if share.x.0 == 0 {
+            return Err(invalid("x coordinate must not be zero"));
+        }
+
+        Ok(KeyShare {
+            threshold,
+            key_id,
+            share,
+        })
+    }
+}
+
+fn key_id(key: &K256KeyPair) -> [u8; KEY_ID_LEN] {
+    let digest = Sha256::digest(key.get_public_key().to_encoded_point(true).as_bytes());
+    let mut key_id = [0u8; KEY_ID_LEN];
+    key_id.copy_from_slice(&digest[..KEY_ID_LEN]);
+    key_id
+}
+
+/// 秘密鍵を`shares`個（最大255個）のシェアに分割する。復元には`threshold`個のシェアが必要
+pub fn split_key<T: RngCore + CryptoRng>(
+    mut csprng: T,
+    key: &K256KeyPair,
+    threshold: u8,
+    shares: u8,
+) -> Result<Vec<KeyShare>, ShamirError> {
+    // しきい値が1の場合は、各シェアが秘密鍵そのものとなるため認めない
+    if threshold < 2 || threshold > shares {
+        return Err(ShamirError::InvalidThreshold { threshold, shares });
+    }
+
+    let key_id = key_id(key);
+    let secret = Zeroizing::new(key.get_secret_key().to_bytes());
+    Ok(Sharks(threshold)
+        .dealer_rng(secret.as_slice(), &mut csprng)
+        .take(usize::from(shares))
+        .map(|share| KeyShare {
+            threshold,
+            key_id,
+            share,
+        })
+        .collect())
+}
+
+/// シェアから秘密鍵を復元する
+///
+/// 同じシェアが重複している場合は1つとして数える
+pub fn combine_key(shares: &[KeyShare]) -> Result<K256KeyPair, ShamirError> {
+    let Some(first) = shares.first() else {
+        return Err(ShamirError::NoShares);
+    };
+    if shares
+        .iter()
+        .any(|share| share.threshold != first.threshold || share.key_id != first.key_id)
+    {
+        return Err(ShamirError::MismatchedShares);
+    }
+
+    // x座標が同じシェアが含まれると補間できないため、重複を取り除く
+    let mut distinct = BTreeMap::new();
+    for share in shares {
+        if let Some(other) = distinct.insert(share.share.x.0, &share.share) {
+            if Zeroizing::new(Vec::from(other)) != Zeroizing::new(Vec::from(&share.share)) {
+                return Err(ShamirError::MismatchedShares);
+            }
+        }
+    }
+    if distinct.len() < usize::from(first.threshold) {
+        return Err(ShamirError::NotEnoughShares {
+            required: first.threshold,
+            given: distinct.len(),
+        });
+    }
+
+    let secret = Zeroizing::new(
+        Sharks(first.threshold)
+            .recover(distinct.into_values())
+            .map_err(|e| ShamirError::InvalidShare(e.to_string()))?,
+    );
+    let secret_key = k256::SecretKey::from_slice(&secret).map_err(|_| ShamirError::KeyMismatch)?;
+    let key = K256KeyPair::new(secret_key);
+    if key_id(&key) != first.key_id {
+        return Err(ShamirError::KeyMismatch);
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::OsRng;
+
+    fn random_key() -> K256KeyPair {
+        K256KeyPair::new(k256::SecretKey::random(&mut OsRng))
+    }
+
+    // 同じ鍵のシェアを文字列から読み込み直す
+    fn reload<'a>(shares: impl IntoIterator<Item = &'a KeyShare>) -> Vec<KeyShare> {
+        shares
+            .into_iter()
+            .map(|share| KeyShare::decode(&share.encode()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_and_combine() {
+        let key = random_key();
+        let shares = split_key(OsRng, &key, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+        assert!(shares.iter().all(|share| share.threshold() == 3));
+
+        // しきい値以上の任意のシェアから同じ鍵を復元できる
+        for subset in [&shares[..3], &shares[2..], &shares[1..4]] {
+            let combined = combine_key(&reload(subset)).unwrap();
+            assert_eq!(combined.get_public_key(), key.get_public_key());
+        }
+        let all = combine_key(&shares).unwrap();
+        assert_eq!(all.get_secret_key(), key.get_secret_key());
+    }
+
+    #[test]
+    fn test_invalid_threshold() {
+        let key = random_key();
+        for (threshold, shares) in [(0, 3), (1, 3), (4, 3)] {
+            assert!(matches!(
+                split_key(OsRng, &key, threshold, shares),
+                Err(ShamirError::InvalidThreshold { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_share() {
+        let share = split_key(OsRng, &random_key(), 2, 2).unwrap().remove(0);
+        let encoded = share.encode();
+        let (_, rest) = encoded.split_once('-').unwrap();
+        let (key_id, share) = rest.split_once('-').unwrap();
+        for invalid in [
+            String::new(),
+            "2".to_string(),
+            format!("x-{}-{}", key_id, share),
+            format!("2-00-{}", share),
+            format!("2-{}-zz", key_id),
+            format!("2-{}-00{}", key_id, &share[2..]),
+        ] {
+            assert!(
+                matches!(
+                    KeyShare::decode(&invalid),
+                    Err(ShamirError::InvalidShare(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+        // 前後の空白は無視する
+        assert!(KeyShare::decode(&format!(" {} \n", encoded.as_str())).is_ok());
+    }
+
+    #[test]
+    fn test_combine_below_threshold() {
+        let shares = split_key(OsRng, &random_key(), 3, 5).unwrap();
+        assert!(matches!(
+            combine_key(&shares[..2]),
+            Err(ShamirError::NotEnoughShares {
+                required: 3,
+                given: 2
+            })
+        ));
+        assert!(matches!(combine_key(&[]), Err(ShamirError::NoShares)));
+    }
+
+    #[test]
+    fn test_combine_mixed_key_ids() {
+        let shares = split_key(OsRng, &random_key(), 2, 3).unwrap();
+        let other = split_key(OsRng, &random_key(), 2, 3).unwrap();
+        let mut mixed = reload(&shares[..1]);
+        mixed.extend(reload(&other[1..2]));
+        assert!(matches!(
+            combine_key(&mixed),
+            Err(ShamirError::MismatchedShares)
+        ));
+
+        // 同じ鍵でも、しきい値が異なる分割のシェアは混在できない
+        let key = random_key();
+        let mut mixed = split_key(OsRng, &key, 2, 3).unwrap();
+        mixed.extend(split_key(OsRng, &key, 3, 3).unwrap());
+        assert!(matches!(
+            combine_key(&mixed),
+            Err(ShamirError::MismatchedShares)
+        ));
+    }
+
+    #[test]
+    fn test_combine_duplicate_x() {
+        let key = random_key();
+        let shares = split_key(OsRng, &key, 2, 3).unwrap();
+
+        // 同じシェアの重複は1つとして数える
+        let duplicated = reload([&shares[0], &shares[0]]);
+        assert!(matches!(
+            combine_key(&duplicated),
+            Err(ShamirError::NotEnoughShares {
+                required: 2,
+                given: 1
+            })
+        ));
+        let duplicated = reload([&shares[0], &shares[0], &shares[1]]);
+        assert_eq!(
+            combine_key(&duplicated).unwrap().get_public_key(),
+            key.get_public_key()
+        );
+
+        // 同じ鍵の別の分割で、x座標が同じで値が異なるシェアは補間できない
+        let other = split_key(OsRng, &key, 2, 3).unwrap();
+        let conflicting = reload([&shares[0], &shares[1], &other[0]]);
+        assert!(matches!(
+            combine_key(&conflicting),
+            Err(ShamirError::MismatchedShares)
+        ));
+    }
+
+    #[test]
+    fn test_combine_tampered_share() {
+        let key = random_key();
+        let shares = split_key(OsRng, &key, 2, 3).unwrap();
+        let mut encoded = shares[0].encode().to_string();
+        // シェアの最後のバイトを書き換える
+        let last = encoded.pop().unwrap();
+        encoded.push(if last == '0' { '1' } else { '0' });
+        let tampered = vec![
+            KeyShare::decode(&encoded).unwrap(),
+            KeyShare::decode(&shares[1].encode()).unwrap(),
+        ];
+        assert!(matches!(
+            combine_key(&tampered),
+            Err(ShamirError::KeyMismatch)
+        ));
+    }
+}
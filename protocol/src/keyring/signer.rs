@@ -0,0 +1,276 @@
+// 署名・鍵交換の抽象化
+// JWS・VC・DIDComm・Sidetreeの操作は秘密鍵を直接参照せず、これらのトレイトを経由して署名する
+// HSMやOSのキーストアなど、秘密鍵を取り出せない外部のキーストアの鍵でも署名できる
+// ただしDIDCommの暗号化・復号は、didcomm-rsが内部で鍵交換を行うため、秘密鍵を取り出せる鍵のみで利用できる
+use std::convert::Infallible;
+
+use k256::ecdsa::signature::Signer as _;
+use zeroize::Zeroizing;
+
+use super::keypair::{
+    Ed25519KeyPair, K256KeyPair, KeyPair, KeyPairing, P256KeyPair, SignKeyPair, SignPublicKey,
+    X25519KeyPair,
+};
+
+/// 署名鍵
+pub trait Signer {
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// 署名の検証に利用する公開鍵。署名アルゴリズムは公開鍵の種類から決まる
+    fn public_key(&self) -> SignPublicKey;
+
+    /// メッセージに署名する
+    ///
+    /// 署名はJWSの形式（ECDSAはr||sの64バイト、EdDSAは64バイト）で返す
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Self::Error>;
+}
+
+/// 鍵交換（X25519）用の鍵
+pub trait KeyAgreement {
+    fn public_key(&self) -> x25519_dalek::PublicKey;
+
+    /// 秘密鍵のバイト列を返す。秘密鍵を取り出せない鍵の場合はNone
+    ///
+    /// didcomm-rsは暗号化・復号の内部で鍵交換を行い、秘密鍵のバイト列を要求するため、
+    /// DIDCommの暗号化・復号に限り利用する。Noneを返す鍵ではDIDCommを利用できない
+    fn export_secret_key(&self) -> Option<Zeroizing<[u8; 32]>> {
+        None
+    }
+}
+
+/// DIDの操作で利用する鍵の組
+pub trait Keyring {
+    type Signer: Signer;
+    type KeyAgreement: KeyAgreement;
+
+    /// VCの署名に利用する鍵
+    fn signer(&self) -> &Self::Signer;
+
+    /// DIDCommの暗号化・復号に利用する鍵
+    fn key_agreement(&self) -> &Self::KeyAgreement;
+}
+
+impl Signer for K256KeyPair {
+    type Error = k256::ecdsa::Error;
+
+    fn public_key(&self) -> SignPublicKey {
+        SignPublicKey::Secp256k1(self.get_public_key())
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        let signing_key = k256::ecdsa::SigningKey::from(self.get_secret_key());
+        let signature: k256::ecdsa::Signature = signing_key.try_sign(message)?;
+        Ok(signature.to_vec())
+    }
+}
+
+impl Signer for P256KeyPair {
+    type Error = p256::ecdsa::Error;
+
+    fn public_key(&self) -> SignPublicKey {
+        SignPublicKey::P256(self.get_public_key())
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        let signing_key = p256::ecdsa::SigningKey::from(self.get_secret_key());
+        let signature: p256::ecdsa::Signature = signing_key.try_sign(message)?;
+        Ok(signature.to_vec())
+    }
+}
+
+impl Signer for Ed25519KeyPair {
+    type Error = Infallible;
+
+    fn public_key(&self) -> SignPublicKey {
+        SignPublicKey::Ed25519(self.get_public_key())
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        let signature: ed25519_dalek::Signature = self.get_secret_key().sign(message);
+        Ok(signature.to_bytes().to_vec())
+    }
+}
+
+impl Signer for SignKeyPair {
+    // k256・p256のエラーはいずれもsignature::Error
+    type Error = k256::ecdsa::Error;
+
+    fn public_key(&self) -> SignPublicKey {
+        self.get_public_key()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Self::Error> {
+        match self {
+            SignKeyPair::Secp256k1(key) => Signer::sign(key, message),
+            SignKeyPair::P256(key) => Signer::sign(key, message),
+            SignKeyPair::Ed25519(key) => Signer::sign(key, message).map_err(|e| match e {}),
+        }
+    }
+}
+
+impl KeyAgreement for X25519KeyPair {
+    fn public_key(&self) -> x25519_dalek::PublicKey {
+        self.get_public_key()
+    }
+
+    fn export_secret_key(&self) -> Option<Zeroizing<[u8; 32]>> {
+        Some(Zeroizing::new(self.get_secret_key().to_bytes()))
+    }
+}
+
+impl Keyring for KeyPairing {
+    type Signer = SignKeyPair;
+    type KeyAgreement = X25519KeyPair;
+
+    fn signer(&self) -> &Self::Signer {
+        self.sign()
+    }
+
+    fn key_agreement(&self) -> &Self::KeyAgreement {
+        self.encrypt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::did_repository::{DidRepository, DidRepositoryImpl};
+    use crate::did::sidetree::jws as sidetree_jws;
+    use crate::did::sidetree::node::LocalSidetreeNode;
+    use crate::did::sidetree::patch::DidPatchBuilder;
+    use crate::keyring::keypair::SignKeyType;
+    use crate::verifiable_credentials::jws;
+    use futures::executor::block_on;
+    use rand_core::OsRng;
+
+    /// 秘密鍵を取り出せない外部のキーストアの鍵を模したSigner
+    struct OpaqueSigner<'a>(&'a K256KeyPair);
+
+    impl Signer for OpaqueSigner<'_> {
+        type Error = k256::ecdsa::Error;
+
+        fn public_key(&self) -> SignPublicKey {
+            Signer::public_key(self.0)
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Self::Error> {
+            Signer::sign(self.0, message)
+        }
+    }
+
+    struct OpaqueKeyAgreement(x25519_dalek::PublicKey);
+
+    impl KeyAgreement for OpaqueKeyAgreement {
+        fn public_key(&self) -> x25519_dalek::PublicKey {
+            self.0
+        }
+    }
+
+    fn object() -> serde_json::Value {
+        serde_json::json!({"id": "did:example:123", "message": "hello"})
+    }
+
+    #[test]
+    fn test_sign_with_each_key_type() {
+        for key_type in [
+            SignKeyType::Secp256k1,
+            SignKeyType::P256,
+            SignKeyType::Ed25519,
+        ] {
+            let key_pair = SignKeyPair::random(&mut OsRng, key_type);
+            let signature = jws::sign_with(&object(), &key_pair).unwrap();
+            jws::verify_with(&object(), &signature, &Signer::public_key(&key_pair)).unwrap();
+
+            // 別の鍵では検証できない
+            let other = SignKeyPair::random(&mut OsRng, key_type);
+            assert!(jws::verify_with(&object(), &signature, &other.get_public_key()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_signer_matches_key_pair_signature() {
+        // Ed25519の署名は決定的なため、鍵ペアの署名関数と同じ署名となる
+        let key_pair = Ed25519KeyPair::random(&mut OsRng);
+        assert_eq!(
+            jws::sign_with(&object(), &key_pair).unwrap(),
+            jws::sign_eddsa(&object(), key_pair.get_secret_key()).unwrap()
+        );
+
+        let key_pair = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let signature = jws::sign_with(&object(), &OpaqueSigner(&key_pair)).unwrap();
+        jws::verify(&object(), &signature, &key_pair.get_public_key()).unwrap();
+    }
+
+    #[test]
+    fn test_sidetree_requires_secp256k1() {
+        let key_pair = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let signature = sidetree_jws::sign(&object(), &OpaqueSigner(&key_pair)).unwrap();
+        let payload: serde_json::Value =
+            sidetree_jws::verify(&signature, &key_pair.get_public_key()).unwrap();
+        assert_eq!(payload, object());
+
+        for key_type in [SignKeyType::P256, SignKeyType::Ed25519] {
+            let key_pair = SignKeyPair::random(&mut OsRng, key_type);
+            assert!(matches!(
+                sidetree_jws::sign(&object(), &key_pair),
+                Err(sidetree_jws::SidetreeJwsError::InvalidAlgorithm(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_update_identifier_with_opaque_signer() {
+        let repository = DidRepositoryImpl::new(LocalSidetreeNode::in_memory());
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = block_on(repository.create_identifier(&keyring))
+            .unwrap()
+            .did_document
+            .id;
+
+        let next = KeyPairing::create_keyring(OsRng);
+        let patches = DidPatchBuilder::new()
+            .remove_public_keys(vec!["encryptionKey".to_string()])
+            .unwrap()
+            .build();
+        block_on(repository.update_identifier(
+            &did,
+            &OpaqueSigner(keyring.update()),
+            next.update().get_public_key(),
+            patches,
+        ))
+        .unwrap();
+
+        let found = block_on(repository.find_identifier(&did)).unwrap().unwrap();
+        assert_eq!(found.did_document.public_key.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_key_agreement() {
+        let key_pair = X25519KeyPair::new(x25519_dalek::StaticSecret::random_from_rng(OsRng));
+        assert_eq!(
+            KeyAgreement::public_key(&key_pair),
+            key_pair.get_public_key()
+        );
+        assert_eq!(
+            *key_pair.export_secret_key().unwrap(),
+            key_pair.get_secret_key().to_bytes()
+        );
+
+        // 秘密鍵を取り出せない鍵は、既定でNoneを返す
+        let opaque = OpaqueKeyAgreement(key_pair.get_public_key());
+        assert!(opaque.export_secret_key().is_none());
+    }
+
+    #[test]
+    fn test_keyring() {
+        let keyring = KeyPairing::create_keyring_with_sign_key(OsRng, SignKeyType::Ed25519);
+        assert_eq!(
+            Signer::public_key(keyring.signer()),
+            keyring.sign().get_public_key()
+        );
+        assert_eq!(
+            KeyAgreement::public_key(keyring.key_agreement()),
+            keyring.encrypt().get_public_key()
+        );
+    }
+}
@@ -3,14 +3,17 @@ use thiserror::Error;
 
 use super::types::Proof;
 use crate::{
-    keyring::keypair::{K256KeyPair, KeyPair},
+    keyring::{
+        keypair::{SignKeyType, SignPublicKey},
+        signer::Signer,
+    },
     verifiable_credentials::{jws, types::VerifiableCredentials},
 };
 
-pub struct CredentialSignerSuite<'a> {
+pub struct CredentialSignerSuite<'a, S: Signer> {
     pub did: &'a str,
     pub key_id: &'a str,
-    pub context: &'a K256KeyPair,
+    pub context: &'a S,
 }
 
 #[derive(Debug, Error)]
@@ -34,15 +37,22 @@ pub enum CredentialSignerVerifyError {
 pub struct CredentialSigner {}
 
 impl CredentialSigner {
-    pub fn sign(
+    pub fn sign<S: Signer>(
         mut object: VerifiableCredentials,
-        suite: CredentialSignerSuite,
+        suite: CredentialSignerSuite<S>,
     ) -> Result<VerifiableCredentials, CredentialSignerSignError> {
-        let jws = jws::sign(&json!(object), &suite.context.get_secret_key())?;
+        let payload = json!(object);
+        // 署名鍵の種類に応じて、proofのtypeを切り替える
+        let r#type = match suite.context.public_key().key_type() {
+            SignKeyType::Secp256k1 => "EcdsaSecp256k1Signature2019",
+            SignKeyType::P256 => "JsonWebSignature2020",
+            SignKeyType::Ed25519 => "Ed25519Signature2018",
+        };
+        let jws = jws::sign_with(&payload, suite.context)?;
         let did = suite.did;
         let key_id = suite.key_id;
         object.proof = Some(Proof {
-            r#type: "EcdsaSecp256k1Signature2019".to_string(),
+            r#type: r#type.to_string(),
             proof_purpose: "authentication".to_string(),
             // Assume that object.issuance_date is correct data
             created: object.issuance_date,
@@ -57,7 +67,7 @@ impl CredentialSigner {
 
     pub fn verify(
         mut object: VerifiableCredentials,
-        public_key: &k256::PublicKey,
+        public_key: &SignPublicKey,
     ) -> Result<VerifiableCredentials, CredentialSignerVerifyError> {
         let proof = object
             .proof
@@ -65,7 +75,7 @@ impl CredentialSigner {
             .ok_or(CredentialSignerVerifyError::ProofNotFound)?;
         let jws = proof.jws;
         let payload = serde_json::to_value(&object)?;
-        jws::verify(&payload, &jws, public_key)?;
+        jws::verify_with(&payload, &jws, public_key)?;
         Ok(object)
     }
 }
@@ -1,5 +1,12 @@
-use crate::did::did_repository::{get_sign_key, DidRepository, GetPublicKeyError};
-use crate::keyring::keypair;
+use crate::did::did_key::{did_key_document, DID_KEY_METHOD};
+use crate::did::did_peer::{did_peer_document, DID_PEER_METHOD};
+use crate::did::did_repository::{
+    get_verification_key, DidRepository, GetPublicKeyError, SIGNING_KEY_ID,
+};
+use crate::did::did_url::{DidUrl, DidUrlError};
+use crate::did::resolver::did_method;
+use crate::keyring::keypair::SignPublicKey;
+use crate::keyring::signer::Signer;
 use crate::verifiable_credentials::credential_signer::CredentialSignerVerifyError;
 use crate::verifiable_credentials::types::VerifiableCredentials;
 use thiserror::Error;
@@ -12,10 +19,11 @@ use super::credential_signer::{
 pub trait DidVcService: Sync {
     type GenerateError: std::error::Error + Send + Sync;
     type VerifyError: std::error::Error + Send + Sync;
-    fn generate(
+    /// VCに署名する。署名はSignerを経由して行うため、秘密鍵を取り出せない鍵でも署名できる
+    fn generate<S: Signer>(
         &self,
         model: VerifiableCredentials,
-        from_keyring: &keypair::KeyPairing,
+        signer: &S,
     ) -> Result<VerifiableCredentials, Self::GenerateError>;
     async fn verify(
         &self,
@@ -33,23 +41,57 @@ pub enum DidVcServiceVerifyError<FindIdentifierError: std::error::Error> {
     FindIdentifier(FindIdentifierError),
     #[error("credential signer error")]
     VerifyFailed(#[from] CredentialSignerVerifyError),
+    #[error("invalid verification method: {0}")]
+    InvalidVerificationMethod(#[from] DidUrlError),
+    #[error("verification method is not controlled by issuer. issuer: {issuer}, verification method: {verification_method}")]
+    IssuerMismatch {
+        issuer: String,
+        verification_method: String,
+    },
+}
+
+/// 署名鍵のverificationMethodのid（DID URLのフラグメント）を返す
+///
+/// did:key・did:peerはDIDから生成したDIDドキュメント上のid、それ以外はDIDドキュメントに登録するid
+fn signing_key_id(did: &str, public_key: SignPublicKey) -> String {
+    let document = match did_method(did) {
+        Some(DID_KEY_METHOD) => did_key_document(did).ok(),
+        Some(DID_PEER_METHOD) => did_peer_document(did).ok(),
+        _ => None,
+    };
+    document
+        .and_then(|document| {
+            document
+                .verification_method
+                .into_iter()
+                .find(|method| {
+                    method
+                        .public_key_jwk
+                        .clone()
+                        .and_then(|jwk| SignPublicKey::try_from(jwk).ok())
+                        == Some(public_key)
+                })
+                .and_then(|method| DidUrl::parse(&method.id).ok()?.fragment)
+        })
+        .unwrap_or_else(|| SIGNING_KEY_ID.to_string())
 }
 
 impl<R: DidRepository> DidVcService for R {
     type GenerateError = CredentialSignerSignError;
     type VerifyError = DidVcServiceVerifyError<R::FindIdentifierError>;
-    fn generate(
+    fn generate<S: Signer>(
         &self,
         model: VerifiableCredentials,
-        from_keyring: &keypair::KeyPairing,
+        signer: &S,
     ) -> Result<VerifiableCredentials, Self::GenerateError> {
         let did = &model.issuer.id.clone();
+        let key_id = signing_key_id(did, signer.public_key());
         CredentialSigner::sign(
             model,
             CredentialSignerSuite {
                 did,
-                key_id: "signingkey",
-                context: &from_keyring.sign,
+                key_id: &key_id,
+                context: signer,
             },
         )
     }
@@ -57,16 +99,132 @@ impl<R: DidRepository> DidVcService for R {
         &self,
         model: VerifiableCredentials,
     ) -> Result<VerifiableCredentials, Self::VerifyError> {
+        let proof = model
+            .proof
+            .as_ref()
+            .ok_or(CredentialSignerVerifyError::ProofNotFound)?;
+        // proofで指定された公開鍵は、発行者のDIDドキュメントに登録されている必要がある
+        let did_url = DidUrl::parse(&proof.verification_method)?;
+        if did_url.did != model.issuer.id {
+            return Err(DidVcServiceVerifyError::IssuerMismatch {
+                issuer: model.issuer.id.clone(),
+                verification_method: proof.verification_method.clone(),
+            });
+        }
         let did_document = self
-            .find_identifier(&model.issuer.id)
+            .find_identifier(&did_url.did)
             .await
             .map_err(Self::VerifyError::FindIdentifier)?;
         let did_document = did_document
-            .ok_or(DidVcServiceVerifyError::DidDocNotFound(
-                model.issuer.id.clone(),
-            ))?
+            .ok_or(DidVcServiceVerifyError::DidDocNotFound(did_url.did.clone()))?
             .did_document;
-        let public_key = get_sign_key(&did_document)?;
+        let public_key = get_verification_key(
+            &did_document,
+            &proof.verification_method,
+            &proof.proof_purpose,
+        )?;
         Ok(CredentialSigner::verify(model, &public_key)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::did::did_key::DidKeyPublicKey;
+    use crate::did::did_key::DidKeyResolver;
+    use crate::did::did_repository::DidRepositoryImpl;
+    use crate::did::resolver::DidResolverRouter;
+    use crate::did::sidetree::node::LocalSidetreeNode;
+    use crate::keyring::keypair::{KeyPairing, SignKeyType};
+    use futures::executor::block_on;
+    use rand_core::OsRng;
+
+    fn credential(did: &str) -> VerifiableCredentials {
+        VerifiableCredentials::new(
+            did.to_string(),
+            serde_json::json!({"message": "hello"}),
+            chrono::Utc::now(),
+        )
+    }
+
+    #[test]
+    fn test_generate_and_verify() {
+        let repository = DidResolverRouter::new(
+            DidRepositoryImpl::new(LocalSidetreeNode::in_memory()),
+            DidKeyResolver,
+        );
+        let keyring = KeyPairing::create_keyring(OsRng);
+        let did = block_on(repository.create_identifier(&keyring))
+            .unwrap()
+            .did_document
+            .id;
+
+        let vc = repository
+            .generate(credential(&did), keyring.sign())
+            .unwrap();
+        assert_eq!(
+            vc.proof.as_ref().unwrap().verification_method,
+            format!("{}#{}", did, SIGNING_KEY_ID)
+        );
+        block_on(repository.verify(vc)).unwrap();
+
+        // did:keyは、DIDから生成したDIDドキュメント上のidでproofを生成する
+        let did_key = DidKeyPublicKey::from(keyring.sign().get_public_key()).to_did();
+        let vc = repository
+            .generate(credential(&did_key), keyring.sign())
+            .unwrap();
+        assert_eq!(
+            vc.proof.as_ref().unwrap().verification_method,
+            format!("{}#{}", did_key, &did_key[8..])
+        );
+        block_on(repository.verify(vc)).unwrap();
+    }
+
+    #[test]
+    fn test_reject_issuer_mismatch() {
+        let repository = DidRepositoryImpl::new(LocalSidetreeNode::in_memory());
+        let issuer = KeyPairing::create_keyring(OsRng);
+        let other = KeyPairing::create_keyring(OsRng);
+        let issuer_did = block_on(repository.create_identifier(issuer))
+            .unwrap()
+            .did_document
+            .id;
+        let other_did = block_on(repository.create_identifier(&other))
+            .unwrap()
+            .did_document
+            .id;
+
+        // 別のDIDの鍵で署名したproofは、発行者の署名として受け付けない
+        let mut vc = repository
+            .generate(credential(&other_did), other.sign())
+            .unwrap();
+        vc.issuer.id = issuer_did;
+        assert!(matches!(
+            block_on(repository.verify(vc)),
+            Err(DidVcServiceVerifyError::IssuerMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_generate_and_verify_with_sign_key_types() {
+        let repository = DidResolverRouter::new(
+            DidRepositoryImpl::new(LocalSidetreeNode::in_memory()),
+            DidKeyResolver,
+        );
+        for key_type in [SignKeyType::P256, SignKeyType::Ed25519] {
+            let keyring = KeyPairing::create_keyring_with_sign_key(OsRng, key_type);
+            let did = block_on(repository.create_identifier(&keyring))
+                .unwrap()
+                .did_document
+                .id;
+            let did_key = DidKeyPublicKey::from(keyring.sign().get_public_key()).to_did();
+
+            for did in [did, did_key] {
+                let vc = repository
+                    .generate(credential(&did), keyring.sign())
+                    .unwrap();
+                block_on(repository.verify(vc)).unwrap();
+            }
+        }
+    }
+}
@@ -9,6 +9,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 
+use crate::keyring::keypair::{SignKeyType, SignPublicKey};
+use crate::keyring::signer;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct JwsHeader {
     alg: String,
@@ -22,6 +25,8 @@ pub enum JwsEncodeError {
     SignatureError(#[from] k256::ecdsa::Error),
     #[error("CanonicalizeError : {0:?}")]
     CanonicalizeError(#[from] serde_json::Error),
+    #[error("SignerError : {0}")]
+    SignerError(Box<dyn std::error::Error + Send + Sync>),
 }
 
 #[derive(Debug, Error)]
@@ -46,11 +51,20 @@ pub enum JwsDecodeError {
     CryptError(#[from] k256::ecdsa::Error),
     #[error("FromUtf8Error: {0}")]
     FromUtf8Error(#[from] std::string::FromUtf8Error),
+    #[error("Ed25519Error: {0:?}")]
+    Ed25519Error(ed25519_dalek::SignatureError),
 }
 
-pub fn sign(object: &Value, secret_key: &k256::SecretKey) -> Result<String, JwsEncodeError> {
+// JWSの署名アルゴリズム
+const ES256K: &str = "ES256K";
+const ES256: &str = "ES256";
+const EDDSA: &str = "EdDSA";
+
+// ヘッダとペイロードから、署名対象のメッセージ（<header>.<payload>）を生成する
+// b64: falseのため、JWSにはペイロードを含めない（detached payload）
+fn signing_input(alg: &str, object: &Value) -> Result<(String, String), JwsEncodeError> {
     let header = JwsHeader {
-        alg: "ES256K".to_string(),
+        alg: alg.to_string(),
         b64: false,
         crit: vec!["b64".to_string()],
     };
@@ -60,20 +74,15 @@ pub fn sign(object: &Value, secret_key: &k256::SecretKey) -> Result<String, JwsE
     let payload = BASE64URL_NOPAD.encode(object.to_string().as_bytes());
 
     let message = [header.clone(), payload].join(".");
-    let message: &[u8] = message.as_bytes();
-
-    let signing_key: SigningKey = secret_key.into();
-    let signature: Signature = signing_key.try_sign(message)?;
-    let signature = BASE64URL_NOPAD.encode(&signature.to_vec());
-
-    Ok([header, "".to_string(), signature].join("."))
+    Ok((header, message))
 }
 
-pub fn verify(
+// JWSのヘッダを検証し、署名対象のメッセージと署名を返す
+fn decode_signing_input(
+    alg: &str,
     object: &Value,
     jws: &str,
-    public_key: &k256::PublicKey,
-) -> Result<(), JwsDecodeError> {
+) -> Result<(String, Vec<u8>), JwsDecodeError> {
     let split: Vec<String> = jws.split('.').map(|v| v.to_string()).collect();
 
     if split.len() != 3 {
@@ -88,14 +97,14 @@ pub fn verify(
     let decoded = String::from_utf8(decoded)?;
     let header = serde_json::from_str::<JwsHeader>(&decoded)?;
 
-    if header.alg != *"ES256K" {
+    if header.alg != alg {
         return Err(JwsDecodeError::InvalidAlgorithm(header.alg));
     }
     if header.b64 {
         return Err(JwsDecodeError::B64NotSupported);
     }
     if header.crit.iter().all(|v| v != "b64") {
-        return Err(JwsDecodeError::B64NotSupportedButContained);
+        return Err(JwsDecodeError::EmptyPayload);
     }
 
     if __payload != *"".to_string() {
@@ -109,6 +118,52 @@ pub fn verify(
     if signature.len() != 64 {
         return Err(JwsDecodeError::InvalidSignatureLength(signature.len()));
     }
+    Ok((message, signature))
+}
+
+// 署名鍵の種類に対応するJWSの署名アルゴリズム
+fn algorithm(key_type: SignKeyType) -> &'static str {
+    match key_type {
+        SignKeyType::Secp256k1 => ES256K,
+        SignKeyType::P256 => ES256,
+        SignKeyType::Ed25519 => EDDSA,
+    }
+}
+
+/// Signerで署名する。署名アルゴリズムはSignerの公開鍵の種類から決まる
+///
+/// 秘密鍵はSignerの外に取り出さないため、外部のキーストアの鍵でも署名できる
+pub fn sign_with<S: signer::Signer>(object: &Value, signer: &S) -> Result<String, JwsEncodeError> {
+    let alg = algorithm(signer.public_key().key_type());
+    let (header, message) = signing_input(alg, object)?;
+
+    let signature = signer
+        .sign(message.as_bytes())
+        .map_err(|e| JwsEncodeError::SignerError(Box::new(e)))?;
+    let signature = BASE64URL_NOPAD.encode(&signature);
+
+    Ok([header, "".to_string(), signature].join("."))
+}
+
+/// ES256K（secp256k1）で署名する
+pub fn sign(object: &Value, secret_key: &k256::SecretKey) -> Result<String, JwsEncodeError> {
+    let (header, message) = signing_input(ES256K, object)?;
+    let message: &[u8] = message.as_bytes();
+
+    let signing_key: SigningKey = secret_key.into();
+    let signature: Signature = signing_key.try_sign(message)?;
+    let signature = BASE64URL_NOPAD.encode(&signature.to_vec());
+
+    Ok([header, "".to_string(), signature].join("."))
+}
+
+/// ES256K（secp256k1）の署名を検証する
+pub fn verify(
+    object: &Value,
+    jws: &str,
+    public_key: &k256::PublicKey,
+) -> Result<(), JwsDecodeError> {
+    let (message, signature) = decode_signing_input(ES256K, object, jws)?;
     let r: &[u8; 32] = &signature[0..32].try_into().unwrap();
     let s: &[u8; 32] = &signature[32..].try_into().unwrap();
     let wrapped_signature = Signature::from_scalars(*r, *s)?;
@@ -116,3 +171,240 @@ pub fn verify(
     let verify_key = VerifyingKey::from(public_key);
     Ok(verify_key.verify(message.as_bytes(), &wrapped_signature)?)
 }
+
+/// ES256（P-256）で署名する
+pub fn sign_es256(object: &Value, secret_key: &p256::SecretKey) -> Result<String, JwsEncodeError> {
+    let (header, message) = signing_input(ES256, object)?;
+
+    let signing_key: p256::ecdsa::SigningKey = secret_key.into();
+    let signature: p256::ecdsa::Signature = signing_key.try_sign(message.as_bytes())?;
+    let signature = BASE64URL_NOPAD.encode(&signature.to_vec());
+
+    Ok([header, "".to_string(), signature].join("."))
+}
+
+/// ES256（P-256）の署名を検証する
+pub fn verify_es256(
+    object: &Value,
+    jws: &str,
+    public_key: &p256::PublicKey,
+) -> Result<(), JwsDecodeError> {
+    let (message, signature) = decode_signing_input(ES256, object, jws)?;
+    let signature = p256::ecdsa::Signature::from_slice(&signature)?;
+
+    let verify_key = p256::ecdsa::VerifyingKey::from(public_key);
+    Ok(verify_key.verify(message.as_bytes(), &signature)?)
+}
+
+/// EdDSA（Ed25519）で署名する
+pub fn sign_eddsa(
+    object: &Value,
+    secret_key: &ed25519_dalek::SigningKey,
+) -> Result<String, JwsEncodeError> {
+    let (header, message) = signing_input(EDDSA, object)?;
+
+    let signature: ed25519_dalek::Signature = secret_key.sign(message.as_bytes());
+    let signature = BASE64URL_NOPAD.encode(&signature.to_bytes());
+
+    Ok([header, "".to_string(), signature].join("."))
+}
+
+/// EdDSA（Ed25519）の署名を検証する
+pub fn verify_eddsa(
+    object: &Value,
+    jws: &str,
+    public_key: &ed25519_dalek::VerifyingKey,
+) -> Result<(), JwsDecodeError> {
+    let (message, signature) = decode_signing_input(EDDSA, object, jws)?;
+    let signature =
+        ed25519_dalek::Signature::from_slice(&signature).map_err(JwsDecodeError::Ed25519Error)?;
+
+    // verify_strictは、小位数の公開鍵や正規化されていない署名を受け付けない
+    public_key
+        .verify_strict(message.as_bytes(), &signature)
+        .map_err(JwsDecodeError::Ed25519Error)
+}
+
+/// 公開鍵の種類に応じた署名アルゴリズムで、署名を検証する
+pub fn verify_with(
+    object: &Value,
+    jws: &str,
+    public_key: &SignPublicKey,
+) -> Result<(), JwsDecodeError> {
+    match public_key {
+        SignPublicKey::Secp256k1(key) => verify(object, jws, key),
+        SignPublicKey::P256(key) => verify_es256(object, jws, key),
+        SignPublicKey::Ed25519(key) => verify_eddsa(object, jws, key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyring::keypair::{Ed25519KeyPair, K256KeyPair, KeyPair, P256KeyPair};
+    use rand_core::OsRng;
+    use serde_json::json;
+
+    fn object() -> Value {
+        json!({"id": "did:example:123", "message": "hello"})
+    }
+
+    fn header(jws: &str) -> Value {
+        let header = jws.split('.').next().unwrap();
+        serde_json::from_slice(&BASE64URL_NOPAD.decode(header.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_es256k() {
+        let key_pair = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let jws = sign(&object(), key_pair.get_secret_key()).unwrap();
+
+        assert_eq!(
+            header(&jws),
+            json!({"alg": "ES256K", "b64": false, "crit": ["b64"]})
+        );
+        // ペイロードはJWSに含めない
+        assert_eq!(jws.split('.').nth(1), Some(""));
+        verify(&object(), &jws, &key_pair.get_public_key()).unwrap();
+
+        let other = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        assert!(matches!(
+            verify(&object(), &jws, &other.get_public_key()),
+            Err(JwsDecodeError::CryptError(_))
+        ));
+        assert!(matches!(
+            verify(
+                &json!({"id": "did:example:123", "message": "tampered"}),
+                &jws,
+                &key_pair.get_public_key()
+            ),
+            Err(JwsDecodeError::CryptError(_))
+        ));
+    }
+
+    #[test]
+    fn test_es256() {
+        let key_pair = P256KeyPair::new(p256::SecretKey::random(&mut OsRng));
+        let jws = sign_es256(&object(), key_pair.get_secret_key()).unwrap();
+
+        assert_eq!(
+            header(&jws),
+            json!({"alg": "ES256", "b64": false, "crit": ["b64"]})
+        );
+        assert_eq!(jws.split('.').nth(1), Some(""));
+        verify_es256(&object(), &jws, &key_pair.get_public_key()).unwrap();
+
+        let other = P256KeyPair::new(p256::SecretKey::random(&mut OsRng));
+        assert!(matches!(
+            verify_es256(&object(), &jws, &other.get_public_key()),
+            Err(JwsDecodeError::CryptError(_))
+        ));
+        assert!(matches!(
+            verify_es256(
+                &json!({"id": "did:example:123", "message": "tampered"}),
+                &jws,
+                &key_pair.get_public_key()
+            ),
+            Err(JwsDecodeError::CryptError(_))
+        ));
+    }
+
+    #[test]
+    fn test_eddsa() {
+        let key_pair = Ed25519KeyPair::random(&mut OsRng);
+        let jws = sign_eddsa(&object(), key_pair.get_secret_key()).unwrap();
+
+        assert_eq!(
+            header(&jws),
+            json!({"alg": "EdDSA", "b64": false, "crit": ["b64"]})
+        );
+        // Ed25519の署名は決定的
+        assert_eq!(
+            sign_eddsa(&object(), key_pair.get_secret_key()).unwrap(),
+            jws
+        );
+        verify_eddsa(&object(), &jws, &key_pair.get_public_key()).unwrap();
+
+        let other = Ed25519KeyPair::random(&mut OsRng);
+        assert!(matches!(
+            verify_eddsa(&object(), &jws, &other.get_public_key()),
+            Err(JwsDecodeError::Ed25519Error(_))
+        ));
+        assert!(matches!(
+            verify_eddsa(
+                &json!({"id": "did:example:123", "message": "tampered"}),
+                &jws,
+                &key_pair.get_public_key()
+            ),
+            Err(JwsDecodeError::Ed25519Error(_))
+        ));
+    }
+
+    #[test]
+    fn test_reject_algorithm_mismatch() {
+        let k256_key_pair = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let ed25519_key_pair = Ed25519KeyPair::random(&mut OsRng);
+
+        let es256k = sign(&object(), k256_key_pair.get_secret_key()).unwrap();
+        assert!(matches!(
+            verify_eddsa(&object(), &es256k, &ed25519_key_pair.get_public_key()),
+            Err(JwsDecodeError::InvalidAlgorithm(alg)) if alg == "ES256K"
+        ));
+        let eddsa = sign_eddsa(&object(), ed25519_key_pair.get_secret_key()).unwrap();
+        assert!(matches!(
+            verify(&object(), &eddsa, &k256_key_pair.get_public_key()),
+            Err(JwsDecodeError::InvalidAlgorithm(alg)) if alg == "EdDSA"
+        ));
+
+        // ES256KとES256は同じ形式の署名だが、アルゴリズムが異なるため受け付けない
+        let p256_key_pair = P256KeyPair::new(p256::SecretKey::random(&mut OsRng));
+        assert!(matches!(
+            verify_es256(&object(), &es256k, &p256_key_pair.get_public_key()),
+            Err(JwsDecodeError::InvalidAlgorithm(alg)) if alg == "ES256K"
+        ));
+        let es256 = sign_es256(&object(), p256_key_pair.get_secret_key()).unwrap();
+        assert!(matches!(
+            verify(&object(), &es256, &k256_key_pair.get_public_key()),
+            Err(JwsDecodeError::InvalidAlgorithm(alg)) if alg == "ES256"
+        ));
+    }
+
+    #[test]
+    fn test_reject_malformed_jws() {
+        let key_pair = K256KeyPair::new(k256::SecretKey::random(&mut OsRng));
+        let public_key = key_pair.get_public_key();
+        let jws = sign(&object(), key_pair.get_secret_key()).unwrap();
+        let parts: Vec<&str> = jws.split('.').collect();
+
+        assert!(matches!(
+            verify(&object(), "header.signature", &public_key),
+            Err(JwsDecodeError::InvalidJws(_))
+        ));
+        // ペイロードを含むJWS
+        assert!(matches!(
+            verify(
+                &object(),
+                &[parts[0], "cGF5bG9hZA", parts[2]].join("."),
+                &public_key
+            ),
+            Err(JwsDecodeError::EmptyPayload)
+        ));
+        assert!(matches!(
+            verify(
+                &object(),
+                &[parts[0], "", &parts[2][..40]].join("."),
+                &public_key
+            ),
+            Err(JwsDecodeError::InvalidSignatureLength(_))
+        ));
+        let b64_header = BASE64URL_NOPAD.encode(br#"{"alg":"ES256K","b64":true,"crit":["b64"]}"#);
+        assert!(matches!(
+            verify(
+                &object(),
+                &[b64_header.as_str(), "", parts[2]].join("."),
+                &public_key
+            ),
+            Err(JwsDecodeError::B64NotSupported)
+        ));
+    }
+}